@@ -1,14 +1,22 @@
 //! AST
 //!
 //! `ast` contains types representing the (A)bstract (S)yntax (T)ree of expressions in the Monkey language.
-//! These parsed expressions may then be interpreted / compiled / otherwise processed.
-use crate::token::Token;
+//! These parsed expressions may then be interpreted / compiled / otherwise processed, or looked over with a
+//! `Visitor`.
+mod visitor;
+
 use std::fmt;
 
+pub use self::visitor::{walk_block, walk_expression, walk_program, walk_statement, Visitor};
+pub use crate::lexer::Position;
+pub use crate::token::Token;
+
 /// Represents a full parsed program of Monkey statements.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Program {
     pub statements: Vec<Statement>,
+    /// Where each statement starts in the source, or nothing if that is not known.
+    pub positions: Vec<Position>,
 }
 
 impl fmt::Display for Program {
@@ -29,6 +37,7 @@ pub enum Statement {
     Let(String, Expression),
     Return(Expression),
     Expression(Expression),
+    Assign(Expression, Expression),
 }
 
 impl fmt::Display for Statement {
@@ -37,6 +46,7 @@ impl fmt::Display for Statement {
             Statement::Let(ident, expr) => write!(f, "let {} = {};", ident, expr),
             Statement::Return(expr) => write!(f, "return {};", expr),
             Statement::Expression(expr) => write!(f, "{};", expr),
+            Statement::Assign(target, value) => write!(f, "{} = {};", target, value),
         }
     }
 }
@@ -46,6 +56,8 @@ impl fmt::Display for Statement {
 #[derive(Debug, Clone)]
 pub struct BlockStatement {
     pub statements: Vec<Statement>,
+    /// Where each statement starts in the source, or nothing if that is not known.
+    pub positions: Vec<Position>,
 }
 
 impl fmt::Display for BlockStatement {
@@ -58,20 +70,43 @@ impl fmt::Display for BlockStatement {
     }
 }
 
+/// Represents a single parameter of a function literal, optionally with a default value.
+///
+/// Default values are expressions evaluated in the scope of the called function whenever the
+/// caller omits the corresponding argument.
+#[derive(Debug, Clone)]
+pub struct Parameter {
+    pub name: String,
+    pub default: Option<Expression>,
+}
+
+impl fmt::Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.default {
+            Some(default) => write!(f, "{} = {}", self.name, default),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
 /// Represents a parsed expression in the Monkey language.
+///
+/// Operators, calls and indexing record where their operator, `(` or `[` is, if positions were recorded, so that
+/// failures can be placed.
 #[derive(Debug, Clone)]
 pub enum Expression {
     Ident(String),
     IntegerLiteral(i64),
     BooleanLiteral(bool),
     StringLiteral(String),
-    Prefix(Token, Box<Expression>),
-    Infix(Box<Expression>, Token, Box<Expression>),
+    CharLiteral(char),
+    Prefix(Token, Box<Expression>, Option<Position>),
+    Infix(Box<Expression>, Token, Box<Expression>, Option<Position>),
     If(Box<Expression>, BlockStatement, Option<BlockStatement>),
-    FunctionLiteral(Vec<String>, BlockStatement, Option<String>),
-    Call(Box<Expression>, Vec<Expression>),
+    FunctionLiteral(Vec<Parameter>, BlockStatement, Option<String>),
+    Call(Box<Expression>, Vec<Expression>, Option<Position>),
     ArrayLiteral(Vec<Expression>),
-    Index(Box<Expression>, Box<Expression>),
+    Index(Box<Expression>, Box<Expression>, Option<Position>),
     HashLiteral(Vec<(Expression, Expression)>),
 }
 
@@ -82,8 +117,9 @@ impl fmt::Display for Expression {
             Expression::IntegerLiteral(i) => write!(f, "{}", i),
             Expression::BooleanLiteral(b) => write!(f, "{}", b),
             Expression::StringLiteral(s) => write!(f, "\"{}\"", s),
-            Expression::Prefix(token, expr) => write!(f, "({}{})", token, **expr),
-            Expression::Infix(left, token, right) => {
+            Expression::CharLiteral(c) => write!(f, "'{}'", c),
+            Expression::Prefix(token, expr, _) => write!(f, "({}{})", token, **expr),
+            Expression::Infix(left, token, right, _) => {
                 write!(f, "({} {} {})", **left, token, **right)
             }
             Expression::If(condition, consequence, alternative) => {
@@ -93,10 +129,17 @@ impl fmt::Display for Expression {
                     write!(f, "if {} {}", condition, consequence)
                 }
             }
-            Expression::FunctionLiteral(parameters, body, _) => {
-                write!(f, "fn({}) {}", parameters.join(", "), body)
-            }
-            Expression::Call(function, arguments) => {
+            Expression::FunctionLiteral(parameters, body, _) => write!(
+                f,
+                "fn({}) {}",
+                parameters
+                    .iter()
+                    .map(|x| x.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", "),
+                body
+            ),
+            Expression::Call(function, arguments, _) => {
                 // Map the vector of expressions to a vector of strings so we can join them with comma.
                 write!(
                     f,
@@ -127,7 +170,7 @@ impl fmt::Display for Expression {
                     .collect::<Vec<String>>()
                     .join(", ")
             ),
-            Expression::Index(arr, idx) => write!(f, "({}[{}])", arr, idx),
+            Expression::Index(arr, idx, _) => write!(f, "({}[{}])", arr, idx),
         }
     }
 }
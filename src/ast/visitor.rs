@@ -0,0 +1,148 @@
+//! Visitor
+//!
+//! `visitor` walks syntax trees, so that code looking over programs, such as the linter, need only handle the nodes
+//! it is interested in.
+use crate::ast::{BlockStatement, Expression, Program, Statement};
+
+/// Visits the nodes of a syntax tree.
+///
+/// Each method by default visits the nodes within the one it is given, in source order, through the `walk` function
+/// of the same name. An override which should still visit those nodes calls the `walk` function itself.
+pub trait Visitor {
+    fn visit_program(&mut self, program: &Program) {
+        walk_program(self, program);
+    }
+
+    fn visit_block(&mut self, block: &BlockStatement) {
+        walk_block(self, block);
+    }
+
+    fn visit_statement(&mut self, statement: &Statement) {
+        walk_statement(self, statement);
+    }
+
+    fn visit_expression(&mut self, expr: &Expression) {
+        walk_expression(self, expr);
+    }
+}
+
+/// Visits the statements of `program`.
+pub fn walk_program<V: Visitor + ?Sized>(visitor: &mut V, program: &Program) {
+    for statement in &program.statements {
+        visitor.visit_statement(statement);
+    }
+}
+
+/// Visits the statements of `block`.
+pub fn walk_block<V: Visitor + ?Sized>(visitor: &mut V, block: &BlockStatement) {
+    for statement in &block.statements {
+        visitor.visit_statement(statement);
+    }
+}
+
+/// Visits the expressions of `statement`: for an assignment, the target and then the value.
+pub fn walk_statement<V: Visitor + ?Sized>(visitor: &mut V, statement: &Statement) {
+    match statement {
+        Statement::Let(_, value) | Statement::Return(value) | Statement::Expression(value) => {
+            visitor.visit_expression(value)
+        }
+        Statement::Assign(target, value) => {
+            visitor.visit_expression(target);
+            visitor.visit_expression(value);
+        }
+    }
+}
+
+/// Visits the expressions and blocks within `expr`. The default values of a function's parameters come before its
+/// body.
+pub fn walk_expression<V: Visitor + ?Sized>(visitor: &mut V, expr: &Expression) {
+    match expr {
+        Expression::Ident(_)
+        | Expression::IntegerLiteral(_)
+        | Expression::BooleanLiteral(_)
+        | Expression::StringLiteral(_)
+        | Expression::CharLiteral(_) => {}
+        Expression::Prefix(_, operand, _) => visitor.visit_expression(operand),
+        Expression::Infix(left, _, right, _) => {
+            visitor.visit_expression(left);
+            visitor.visit_expression(right);
+        }
+        Expression::If(condition, consequence, alternative) => {
+            visitor.visit_expression(condition);
+            visitor.visit_block(consequence);
+            if let Some(alternative) = alternative {
+                visitor.visit_block(alternative);
+            }
+        }
+        Expression::FunctionLiteral(parameters, body, _) => {
+            for parameter in parameters {
+                if let Some(default) = &parameter.default {
+                    visitor.visit_expression(default);
+                }
+            }
+            visitor.visit_block(body);
+        }
+        Expression::Call(function, arguments, _) => {
+            visitor.visit_expression(function);
+            for argument in arguments {
+                visitor.visit_expression(argument);
+            }
+        }
+        Expression::ArrayLiteral(elements) => {
+            for element in elements {
+                visitor.visit_expression(element);
+            }
+        }
+        Expression::Index(target, index, _) => {
+            visitor.visit_expression(target);
+            visitor.visit_expression(index);
+        }
+        Expression::HashLiteral(pairs) => {
+            for (key, value) in pairs {
+                visitor.visit_expression(key);
+                visitor.visit_expression(value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    // Collects the names used, in order, and counts the blocks met.
+    #[derive(Default)]
+    struct Names {
+        names: Vec<String>,
+        blocks: usize,
+    }
+
+    impl Visitor for Names {
+        fn visit_block(&mut self, block: &BlockStatement) {
+            self.blocks += 1;
+            walk_block(self, block);
+        }
+
+        fn visit_expression(&mut self, expr: &Expression) {
+            match expr {
+                Expression::Ident(name) => self.names.push(name.clone()),
+                other => walk_expression(self, other),
+            }
+        }
+    }
+
+    #[test]
+    fn visitor_test() {
+        let source = "let f = fn(x, y = a) { if (x) { g(x[b]) } else { {c: -d} } }; e = [f(1)];";
+        let program = Parser::new(Lexer::new(source)).parse_program().unwrap();
+        let mut visitor = Names::default();
+        visitor.visit_program(&program);
+        assert_eq!(
+            visitor.names,
+            vec!["a", "x", "g", "x", "b", "c", "d", "e", "f"]
+        );
+        assert_eq!(visitor.blocks, 3);
+    }
+}
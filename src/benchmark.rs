@@ -2,14 +2,19 @@ use crate::ast::Program;
 use crate::compiler;
 use crate::evaluator;
 use crate::lexer;
-use crate::object::Environment;
+use crate::object::{Environment, HashableObject, Object};
 use crate::parser;
 use crate::vm;
+use indexmap::IndexMap;
 use std::cell::RefCell;
 use std::rc::Rc;
 use std::time::Instant;
 
-pub fn start(compile: bool) {
+const HASH_SIZES: [i64; 3] = [1_000, 10_000, 100_000];
+
+/// Times a recursive computation of a Fibonacci number, compiled or interpreted. Profiling, which implies
+/// compiling, also prints to standard error the functions the time went to.
+pub fn start(compile: bool, profile: bool) {
     let input = "let fibonacci = fn(x) {
         if (x == 0) {
             0
@@ -24,11 +29,12 @@ pub fn start(compile: bool) {
     fibonacci(35);";
 
     let mut p = parser::Parser::new(lexer::Lexer::new(&input));
-    let program = p.parse_program().unwrap();
+    let mut program = p.parse_program().unwrap();
 
-    if compile {
-        benchmark_with_compiler(&program);
+    if compile || profile {
+        benchmark_with_compiler(&program, profile);
     } else {
+        evaluator::optimize(&mut program);
         benchmark_with_interpreter(&program);
     }
 }
@@ -46,14 +52,23 @@ fn benchmark_with_interpreter(program: &Program) {
     );
 }
 
-fn benchmark_with_compiler(program: &Program) {
-    let mut compiler = compiler::Compiler::new();
+fn benchmark_with_compiler(program: &Program, profile: bool) {
+    let symbol_table = Rc::new(RefCell::new(compiler::SymbolTable::new_with_builtins()));
+    let constants = Rc::default();
+    let mut compiler = compiler::Compiler::new_with_state(Rc::clone(&symbol_table), constants);
     let bytecode = compiler.compile(&program).unwrap();
 
     let mut vm = vm::Vm::new(&bytecode);
+    if profile {
+        vm.set_symbol_table(symbol_table);
+        vm.enable_profiling();
+    }
     let start = Instant::now();
     let result = vm.run().unwrap();
     let elapsed = start.elapsed();
+    if let Some(profile) = vm.take_profile() {
+        eprintln!("{}", profile);
+    }
     println!(
         "{} seconds {} nanoseconds, result: {}",
         elapsed.as_secs(),
@@ -61,3 +76,30 @@ fn benchmark_with_compiler(program: &Program) {
         result
     );
 }
+
+/// Times the display of increasingly large hashes, which is dominated by formatting their entries.
+pub fn start_hash_display() {
+    for size in HASH_SIZES.iter() {
+        let mut hash = IndexMap::new();
+        for i in 0..*size {
+            hash.insert(
+                HashableObject::Str(format!("key{}", i)),
+                Rc::new(Object::Array(vec![
+                    Rc::new(Object::Integer(i)),
+                    Rc::new(Object::Boolean(i % 2 == 0)),
+                ])),
+            );
+        }
+        let hash = Object::Hash(hash);
+        let start = Instant::now();
+        let output = hash.to_string();
+        let elapsed = start.elapsed();
+        println!(
+            "{} entries: {} seconds {} nanoseconds, output length: {}",
+            size,
+            elapsed.as_secs(),
+            elapsed.subsec_nanos(),
+            output.len()
+        );
+    }
+}
@@ -0,0 +1,78 @@
+//! Book test suite
+//!
+//! Runs the example programs from "Writing an Interpreter in Go" and "Writing a Compiler in Go" (found in the
+//! `book` directory) against both the interpreter and the compiler + virtual machine.
+//! These programs are the compatibility contract of a Monkey implementation.
+//! Enable with `cargo test --features book-suite`.
+use crate::compiler::Compiler;
+use crate::evaluator;
+use crate::lexer::Lexer;
+use crate::object::Environment;
+use crate::parser::Parser;
+use crate::vm::Vm;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const PROGRAMS: [(&str, &str, &str); 11] = [
+    (
+        "arrays",
+        include_str!("../book/arrays.monkey"),
+        "[4, 5, 1, 5, [2, 3, 4, 5]]",
+    ),
+    ("closures", include_str!("../book/closures.monkey"), "5"),
+    ("counter", include_str!("../book/counter.monkey"), "true"),
+    ("fibonacci", include_str!("../book/fibonacci.monkey"), "610"),
+    (
+        "hashes",
+        include_str!("../book/hashes.monkey"),
+        "\"Alice and Anna\"",
+    ),
+    (
+        "higher_order",
+        include_str!("../book/higher_order.monkey"),
+        "12",
+    ),
+    ("map", include_str!("../book/map.monkey"), "[2, 4, 6, 8]"),
+    ("reduce", include_str!("../book/reduce.monkey"), "15"),
+    (
+        "strings",
+        include_str!("../book/strings.monkey"),
+        "\"Hello Thorsten!\"",
+    ),
+    (
+        "unless",
+        include_str!("../book/unless.monkey"),
+        "\"greater\"",
+    ),
+    ("wrapper", include_str!("../book/wrapper.monkey"), "0"),
+];
+
+#[test]
+fn book_interpreter_test() {
+    for (name, input, want) in PROGRAMS.iter() {
+        let program = Parser::new(Lexer::new(input))
+            .parse_program()
+            .unwrap_or_else(|err| panic!("Could not parse {}: {}", name, err));
+        let env = Rc::new(RefCell::new(Environment::new()));
+        match evaluator::eval(&program, env) {
+            Ok(got) => assert_eq!(got.to_string(), *want, "Wrong output for {}!", name),
+            Err(error) => panic!("Could not evaluate {}: {}", name, error),
+        }
+    }
+}
+
+#[test]
+fn book_vm_test() {
+    for (name, input, want) in PROGRAMS.iter() {
+        let program = Parser::new(Lexer::new(input))
+            .parse_program()
+            .unwrap_or_else(|err| panic!("Could not parse {}: {}", name, err));
+        let bytecode = Compiler::new()
+            .compile(&program)
+            .unwrap_or_else(|err| panic!("Could not compile {}: {:?}", name, err));
+        match Vm::new(&bytecode).run() {
+            Ok(got) => assert_eq!(got.to_string(), *want, "Wrong output for {}!", name),
+            Err(error) => panic!("Could not run {}: {:?}", name, error),
+        }
+    }
+}
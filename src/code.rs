@@ -1,8 +1,18 @@
 //! Code
 //!
 //! `code` contains functionality relating to bytecode for the Monkey language.
+mod decode;
+mod disassembler;
+mod serialize;
+
+pub use self::decode::*;
+pub use self::disassembler::Disassembler;
+pub use self::serialize::SerializeError;
+use crate::compiler::SymbolTable;
+use crate::lexer::Position;
 use crate::object::Object;
 use num_enum::{IntoPrimitive, TryFromPrimitive};
+use std::cell::RefCell;
 use std::convert::TryFrom;
 use std::fmt;
 use std::rc::Rc;
@@ -11,11 +21,32 @@ pub type Instructions = Vec<u8>;
 pub type ReadOnlyInstructions = [u8];
 // TODO: Determine a space-efficient way of representing constants.
 pub type Constant = Object;
+/// The constants of a program. The compiler shares them with the bytecode it returns and the machines running
+/// that, so they are only copied when added to while those still hold them.
+pub type Constants = Rc<Vec<Rc<Constant>>>;
+
+/// A variable shared between a closure and the scope which created it.
+///
+/// Assigning through an upvalue is visible to every closure holding it, as well as to the creating scope.
+pub type Upvalue = Rc<RefCell<Rc<Object>>>;
 
+/// A compiled function along with the free variables it captured.
+///
+/// Closures are cloned for every call to them, so what they hold is shared rather than copied.
 #[derive(Debug, Clone)]
 pub struct Closure {
-    pub compiled_function: CompiledFunction,
-    pub free: Vec<Rc<Object>>,
+    pub compiled_function: Rc<CompiledFunction>,
+    pub free: Rc<[Upvalue]>,
+    /// The instructions of the function, decoded once for all the calls to it.
+    pub code: Rc<[DecodedOp]>,
+}
+
+/// Describes where, in the scope creating a closure, one of its free variables lives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capture {
+    Local(usize),
+    Free(usize),
+    CurrentClosure,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -23,6 +54,22 @@ pub struct CompiledFunction {
     pub instructions: Instructions,
     pub num_locals: usize,
     pub num_parameters: usize,
+    pub num_defaults: usize,
+    pub captures: Vec<Capture>,
+    /// Where in the source the statements compiled into `instructions` start, as pairs of the offset of their
+    /// first instruction and their position, ordered by offset.
+    pub positions: Vec<(usize, Position)>,
+}
+
+impl CompiledFunction {
+    /// Returns where in the source the statement which the instruction at `offset` belongs to starts, if known.
+    pub fn position(&self, offset: usize) -> Option<Position> {
+        self.positions
+            .iter()
+            .rev()
+            .find(|(start, _)| *start <= offset)
+            .map(|(_, position)| *position)
+    }
 }
 
 impl fmt::Display for CompiledFunction {
@@ -33,14 +80,17 @@ impl fmt::Display for CompiledFunction {
 
 pub struct Bytecode {
     pub instructions: Instructions,
-    pub constants: Vec<Constant>,
+    pub constants: Constants,
+    /// Where in the source the statements of the main program start, like `CompiledFunction::positions`.
+    pub positions: Vec<(usize, Position)>,
 }
 
 impl Bytecode {
     pub fn new(instructions: Instructions, constants: Vec<Constant>) -> Self {
         Bytecode {
             instructions,
-            constants,
+            constants: Rc::new(constants.into_iter().map(Rc::new).collect()),
+            positions: vec![],
         }
     }
 }
@@ -50,7 +100,7 @@ pub struct Definition {
     pub widths: Vec<usize>,
 }
 
-#[derive(IntoPrimitive, TryFromPrimitive, Debug, Eq, PartialEq)]
+#[derive(IntoPrimitive, TryFromPrimitive, Debug, Clone, Copy, Eq, PartialEq)]
 #[repr(u8)]
 pub enum OpCode {
     Null,
@@ -83,11 +133,68 @@ pub enum OpCode {
     Return,
     Closure,
     CurrentClosure,
+    JumpIfPassed,
+    SetIndex,
+    SetFree,
+    // Superinstructions, each doing what the sequence of instructions it is named after does.
+    GetLocalGetLocalAdd,
+    ConstantCall,
+    GetGlobalCall,
+    // Forms of instructions taking wider operands, for when theirs do not fit.
+    GetLocalWide,
+    SetLocalWide,
+    CallWide,
+    ConstantWide,
+    ClosureWide,
 }
 
 impl OpCode {
     pub fn definition(&self) -> Definition {
         match self {
+            OpCode::ClosureWide => Definition {
+                name: String::from("OpClosureWide"),
+                widths: vec![4, 1],
+            },
+            OpCode::ConstantWide => Definition {
+                name: String::from("OpConstantWide"),
+                widths: vec![4],
+            },
+            OpCode::CallWide => Definition {
+                name: String::from("OpCallWide"),
+                widths: vec![2],
+            },
+            OpCode::SetLocalWide => Definition {
+                name: String::from("OpSetLocalWide"),
+                widths: vec![2],
+            },
+            OpCode::GetLocalWide => Definition {
+                name: String::from("OpGetLocalWide"),
+                widths: vec![2],
+            },
+            OpCode::GetGlobalCall => Definition {
+                name: String::from("OpGetGlobalCall"),
+                widths: vec![2, 1],
+            },
+            OpCode::ConstantCall => Definition {
+                name: String::from("OpConstantCall"),
+                widths: vec![2, 1],
+            },
+            OpCode::GetLocalGetLocalAdd => Definition {
+                name: String::from("OpGetLocalGetLocalAdd"),
+                widths: vec![1, 1],
+            },
+            OpCode::SetFree => Definition {
+                name: String::from("OpSetFree"),
+                widths: vec![1],
+            },
+            OpCode::SetIndex => Definition {
+                name: String::from("OpSetIndex"),
+                widths: vec![],
+            },
+            OpCode::JumpIfPassed => Definition {
+                name: String::from("OpJumpIfPassed"),
+                widths: vec![2, 1],
+            },
             OpCode::CurrentClosure => Definition {
                 name: String::from("OpCurrentClosure"),
                 widths: vec![],
@@ -228,19 +335,44 @@ impl OpCode {
         let b = u16::to_be_bytes(operand16);
         vec![self.into(), b[0], b[1], operand8]
     }
+
+    pub fn make_u8_u8(self, first: u8, second: u8) -> Instructions {
+        vec![self.into(), first, second]
+    }
+
+    pub fn make_u32(self, operand: u32) -> Instructions {
+        let mut instruction = vec![self.into()];
+        instruction.extend(u32::to_be_bytes(operand));
+        instruction
+    }
+
+    pub fn make_u32_u8(self, operand32: u32, operand8: u8) -> Instructions {
+        let mut instruction = self.make_u32(operand32);
+        instruction.push(operand8);
+        instruction
+    }
 }
 
-pub fn read_operands(def: &Definition, instructions: &ReadOnlyInstructions) -> (Vec<u16>, usize) {
+pub fn read_operands(def: &Definition, instructions: &ReadOnlyInstructions) -> (Vec<u32>, usize) {
     let mut operands = Vec::with_capacity(def.widths.len());
     let mut offset = 0;
     for w in &def.widths {
         match w {
+            4 => {
+                let bytes = [
+                    instructions[offset],
+                    instructions[offset + 1],
+                    instructions[offset + 2],
+                    instructions[offset + 3],
+                ];
+                operands.push(u32::from_be_bytes(bytes));
+            }
             2 => {
-                operands.push(read_uint16(instructions[offset], instructions[offset + 1]));
+                operands.push(read_uint16(instructions[offset], instructions[offset + 1]) as u32);
             }
             1 => {
-                // Even though the operand is 8-bit, we convert to 16 for read-out for ease of implementation.
-                operands.push(instructions[offset] as u16)
+                // Even though the operand is narrower, we convert to 32 for read-out for ease of implementation.
+                operands.push(instructions[offset] as u32)
             }
             _ => panic!("The requested operand size was invalid!"),
         }
@@ -253,29 +385,35 @@ pub fn read_uint16(b0: u8, b1: u8) -> u16 {
     u16::from_be_bytes([b0, b1])
 }
 
+/// Returns the disassembled instructions of `bytecode`, with the constants they load and the names of the globals
+/// of `symbol_table` they refer to.
+///
+/// Compiled functions are disassembled as well, under the instructions creating their closures.
+pub(crate) fn bytecode_listing(bytecode: &Bytecode, symbol_table: &SymbolTable) -> String {
+    let mut disassembler = Disassembler::new(&bytecode.constants);
+    disassembler.set_symbol_table(symbol_table);
+    disassembler.disassemble(&bytecode.instructions)
+}
+
+/// Returns the listing of `instructions`, with jumps written as labels; see `Disassembler` for more.
 pub fn disassemble(instructions: &ReadOnlyInstructions) -> String {
-    let mut all_instructions = vec![];
-    let mut ip = 0;
-    while ip < instructions.len() {
-        let mut current_instruction = vec![];
-        current_instruction.push(format!("{:04}", ip));
-        let op = OpCode::try_from(instructions[ip]);
-        ip += 1;
-        match op {
-            Err(_) => current_instruction.push(String::from("ERROR")),
-            Ok(op) => {
-                let def = op.definition();
-                current_instruction.push(format!("{}", def.name));
-                let (operands, n) = read_operands(&def, &instructions[ip..]);
-                for o in operands {
-                    current_instruction.push(format!("{}", o));
-                }
-                ip += n;
-                all_instructions.push(current_instruction.join(" "));
+    Disassembler::new(&[]).disassemble(instructions)
+}
+
+/// Returns the human-readable form of the single instruction starting at `ip`, along with its width in bytes.
+pub fn disassemble_instruction(instructions: &ReadOnlyInstructions, ip: usize) -> (String, usize) {
+    match OpCode::try_from(instructions[ip]) {
+        Err(_) => (String::from("ERROR"), 1),
+        Ok(op) => {
+            let def = op.definition();
+            let mut current_instruction = vec![def.name.clone()];
+            let (operands, n) = read_operands(&def, &instructions[ip + 1..]);
+            for o in operands {
+                current_instruction.push(format!("{}", o));
             }
+            (current_instruction.join(" "), 1 + n)
         }
     }
-    all_instructions.join("\n")
 }
 
 #[cfg(test)]
@@ -336,7 +474,7 @@ mod tests {
             let (operands, n) = read_operands(&def, &instructions[1..]);
             assert_eq!(n, want_n);
             for (i, operand) in want_operands.iter().enumerate() {
-                assert_eq!(*operand as u16, operands[i]);
+                assert_eq!(*operand as u32, operands[i]);
             }
         }
     }
@@ -0,0 +1,115 @@
+//! Decode
+//!
+//! `decode` reads instructions once, ahead of running them, into a form the virtual machine can execute without
+//! reading operands byte by byte or checking opcodes again. The bytes remain the format bytecode is compiled to
+//! and written in.
+use crate::code::{read_operands, OpCode, ReadOnlyInstructions};
+use std::convert::TryFrom;
+
+/// An instruction with its operands read.
+///
+/// The operands of jumps are the indices of the decoded instructions they jump to, rather than byte offsets; a
+/// jump to the end of the instructions is to the index one past the last.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodedOp {
+    /// The opcode, or the byte which is not one. An instruction cut short by the end of the instructions is not
+    /// one either.
+    pub op: Result<OpCode, u8>,
+    /// The operands, in order, with any the opcode does not take left as zero.
+    pub operands: [usize; 2],
+    /// Where the instruction starts among the bytes it was decoded from.
+    pub offset: usize,
+}
+
+impl DecodedOp {
+    /// Returns the byte the instruction starts with.
+    pub fn byte(&self) -> u8 {
+        match self.op {
+            Ok(op) => op.into(),
+            Err(byte) => byte,
+        }
+    }
+}
+
+/// Returns `instructions` decoded, one `DecodedOp` for each instruction.
+pub fn decode(instructions: &ReadOnlyInstructions) -> Vec<DecodedOp> {
+    let mut decoded = vec![];
+    let mut offset = 0;
+    while offset < instructions.len() {
+        let byte = instructions[offset];
+        let mut instruction = DecodedOp {
+            op: Err(byte),
+            operands: [0; 2],
+            offset,
+        };
+        offset += 1;
+        if let Ok(op) = OpCode::try_from(byte) {
+            let def = op.definition();
+            if offset + def.widths.iter().sum::<usize>() > instructions.len() {
+                // What is left of the instructions is the start of this one.
+                decoded.push(instruction);
+                break;
+            }
+            let (operands, width) = read_operands(&def, &instructions[offset..]);
+            for (slot, operand) in instruction.operands.iter_mut().zip(operands) {
+                *slot = operand as usize;
+            }
+            instruction.op = Ok(op);
+            offset += width;
+        }
+        decoded.push(instruction);
+    }
+    // Jump targets are instruction offsets, so each is found among the decoded instructions.
+    let offsets: Vec<usize> = decoded
+        .iter()
+        .map(|instruction| instruction.offset)
+        .collect();
+    for instruction in &mut decoded {
+        if let Ok(OpCode::Jump) | Ok(OpCode::JumpNotTruthy) | Ok(OpCode::JumpIfPassed) =
+            instruction.op
+        {
+            let target = instruction.operands[0];
+            instruction.operands[0] = offsets.partition_point(|offset| *offset < target);
+        }
+    }
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_test() {
+        let instructions = [
+            OpCode::True.make(),
+            OpCode::JumpNotTruthy.make_u16(10),
+            OpCode::Constant.make_u16(65535),
+            OpCode::Jump.make_u16(11),
+            OpCode::Null.make(),
+            OpCode::JumpIfPassed.make_u16_u8(0, 2),
+            vec![255],
+            OpCode::Constant.make_u16(1)[..2].to_vec(),
+        ]
+        .concat();
+        let op = |op, operands, offset| DecodedOp {
+            op,
+            operands,
+            offset,
+        };
+        assert_eq!(
+            decode(&instructions),
+            vec![
+                op(Ok(OpCode::True), [0, 0], 0),
+                op(Ok(OpCode::JumpNotTruthy), [4, 0], 1),
+                op(Ok(OpCode::Constant), [65535, 0], 4),
+                op(Ok(OpCode::Jump), [5, 0], 7),
+                op(Ok(OpCode::Null), [0, 0], 10),
+                op(Ok(OpCode::JumpIfPassed), [0, 2], 11),
+                op(Err(255), [0, 0], 15),
+                // An instruction cut short is not decoded, and neither are its operand bytes which are there.
+                op(Err(u8::from(OpCode::Constant)), [0, 0], 16),
+            ]
+        );
+    }
+}
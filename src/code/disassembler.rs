@@ -0,0 +1,189 @@
+//! Disassembler
+//!
+//! `disassembler` lists instructions for people to read. Jumps are written with labels placed before the
+//! instructions they land on, and instructions which refer to something by index are followed by what it is: the
+//! value of a constant, the name of a built-in function or of a global, if the symbol table is given. The body of
+//! each function is listed, indented, under the instruction creating its closure.
+use crate::code::{decode, Constant, DecodedOp, OpCode, ReadOnlyInstructions};
+use crate::compiler::SymbolTable;
+use crate::object::{BuiltIn, Object};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+/// Lists instructions, along with what they refer to where that is known.
+pub struct Disassembler<'a> {
+    constants: &'a [Rc<Constant>],
+    // The names of globals, by index.
+    globals: HashMap<usize, String>,
+}
+
+impl<'a> Disassembler<'a> {
+    /// Returns a disassembler for instructions which load their constants from `constants`.
+    pub fn new(constants: &'a [Rc<Constant>]) -> Self {
+        Disassembler {
+            constants,
+            globals: HashMap::new(),
+        }
+    }
+
+    /// Names the globals the instructions refer to after the symbols of `symbol_table`.
+    pub fn set_symbol_table(&mut self, symbol_table: &SymbolTable) {
+        self.globals = symbol_table
+            .global_symbols()
+            .into_iter()
+            .map(|symbol| (symbol.index as usize, symbol.name))
+            .collect();
+    }
+
+    /// Returns the listing of `instructions`, one instruction or label to a line.
+    pub fn disassemble(&self, instructions: &ReadOnlyInstructions) -> String {
+        let mut lines = vec![];
+        self.list(instructions, "", &mut lines);
+        lines.join("\n")
+    }
+
+    fn list(&self, instructions: &ReadOnlyInstructions, indent: &str, lines: &mut Vec<String>) {
+        let code = decode(instructions);
+        // Labels are numbered in the order of the instructions they are placed before.
+        let mut targets: Vec<usize> = code.iter().filter_map(jump_target).collect();
+        targets.sort_unstable();
+        targets.dedup();
+        let label = |target: usize| format!("L{}", targets.binary_search(&target).unwrap() + 1);
+
+        for (idx, instruction) in code.iter().enumerate() {
+            if targets.binary_search(&idx).is_ok() {
+                lines.push(format!("{}{}:", indent, label(idx)));
+            }
+            let text = match instruction.op {
+                Err(_) => String::from("ERROR"),
+                Ok(op) => {
+                    let def = op.definition();
+                    let mut text = def.name;
+                    for (position, operand) in
+                        instruction.operands[..def.widths.len()].iter().enumerate()
+                    {
+                        match position == 0 && jump_target(instruction).is_some() {
+                            true => text += &format!(" {}", label(*operand)),
+                            false => text += &format!(" {}", operand),
+                        }
+                    }
+                    text
+                }
+            };
+            match self.annotation(instruction) {
+                Some(annotation) => lines.push(format!(
+                    "{}{:04} {:<24} ; {}",
+                    indent, instruction.offset, text, annotation
+                )),
+                None => lines.push(format!("{}{:04} {}", indent, instruction.offset, text)),
+            }
+            if let (Ok(OpCode::Closure), Some(Object::CompiledFunction(function)))
+            | (Ok(OpCode::ClosureWide), Some(Object::CompiledFunction(function))) = (
+                instruction.op,
+                self.constants
+                    .get(instruction.operands[0])
+                    .map(|constant| &**constant),
+            ) {
+                self.list(&function.instructions, &format!("{}    ", indent), lines);
+            }
+        }
+        // A jump past the last instruction lands at the end.
+        if targets.last() == Some(&code.len()) {
+            lines.push(format!("{}{}:", indent, label(code.len())));
+        }
+    }
+
+    // Returns what the operand of `instruction` refers to, if it refers to anything which is known.
+    fn annotation(&self, instruction: &DecodedOp) -> Option<String> {
+        let operand = instruction.operands[0];
+        match instruction.op.ok()? {
+            OpCode::Constant | OpCode::ConstantWide | OpCode::ConstantCall => self
+                .constants
+                .get(operand)
+                .map(|constant| constant.to_string()),
+            OpCode::Closure | OpCode::ClosureWide => match &**self.constants.get(operand)? {
+                Object::CompiledFunction(function) => Some(match function.num_parameters {
+                    1 => String::from("function of 1 parameter"),
+                    n => format!("function of {} parameters", n),
+                }),
+                _ => None,
+            },
+            OpCode::GetGlobal | OpCode::SetGlobal | OpCode::GetGlobalCall => {
+                self.globals.get(&operand).cloned()
+            }
+            OpCode::GetBuiltin => BuiltIn::try_from(operand as u8)
+                .ok()
+                .map(|builtin| builtin.name()),
+            _ => None,
+        }
+    }
+}
+
+// Returns the index of the decoded instruction `instruction` jumps to, if it is a jump.
+fn jump_target(instruction: &DecodedOp) -> Option<usize> {
+    match instruction.op {
+        Ok(OpCode::Jump) | Ok(OpCode::JumpNotTruthy) | Ok(OpCode::JumpIfPassed) => {
+            Some(instruction.operands[0])
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::code::CompiledFunction;
+
+    #[test]
+    fn disassembler_test() {
+        let function = CompiledFunction {
+            instructions: [
+                OpCode::GetLocal.make_u8(0),
+                OpCode::JumpNotTruthy.make_u16(11),
+                OpCode::GetBuiltin.make_u8(0),
+                OpCode::ConstantCall.make_u16_u8(0, 1),
+                OpCode::ReturnValue.make(),
+            ]
+            .concat(),
+            num_locals: 1,
+            num_parameters: 1,
+            num_defaults: 0,
+            captures: vec![],
+            positions: vec![],
+        };
+        let constants = vec![
+            Rc::new(Object::Str(String::from("a"))),
+            Rc::new(Object::CompiledFunction(function)),
+        ];
+        let instructions = [
+            OpCode::Closure.make_u16_u8(1, 0),
+            OpCode::SetGlobal.make_u16(0),
+            OpCode::GetGlobal.make_u16(1),
+            OpCode::Pop.make(),
+        ]
+        .concat();
+        let mut symbol_table = SymbolTable::new();
+        symbol_table.define(&String::from("f"));
+        let mut disassembler = Disassembler::new(&constants);
+        disassembler.set_symbol_table(&symbol_table);
+        let expected = [
+            "0000 OpClosure 1 0            ; function of 1 parameter",
+            "    0000 OpGetLocal 0",
+            "    0002 OpJumpNotTruthy L1",
+            "    0005 OpGetBuiltin 0           ; len",
+            "    0007 OpConstantCall 0 1       ; \"a\"",
+            "    L1:",
+            "    0011 OpReturnValue",
+            "0004 OpSetGlobal 0            ; f",
+            // Globals the symbol table does not know go without names.
+            "0007 OpGetGlobal 1",
+            "0010 OpPop",
+        ];
+        assert_eq!(disassembler.disassemble(&instructions), expected.join("\n"));
+        // Without constants, only jumps are made clearer.
+        assert!(Disassembler::new(&[])
+            .disassemble(&instructions)
+            .starts_with("0000 OpClosure 1 0\n"));
+    }
+}
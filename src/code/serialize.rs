@@ -0,0 +1,358 @@
+//! Serialize
+//!
+//! `serialize` converts `Bytecode` to and from the bytes of a compiled Monkey file, so that programs can be
+//! compiled ahead of time and run later.
+//!
+//! The file starts with the magic bytes `MKC`, then a format version, the main instructions and the constants.
+//! Instructions, those of the main program and of each function, are followed by the positions of the statements
+//! they were compiled from. Integers are little-endian; lengths, counts, offsets, lines and columns are `u32`s.
+use crate::code::{Bytecode, Capture, CompiledFunction, Constant, Instructions};
+use crate::lexer::Position;
+use std::convert::TryFrom;
+use std::fmt;
+
+const MAGIC: &[u8] = b"MKC";
+const VERSION: u8 = 5;
+
+const INTEGER_TAG: u8 = 0;
+const STR_TAG: u8 = 1;
+const CHAR_TAG: u8 = 2;
+const FUNCTION_TAG: u8 = 3;
+
+const LOCAL_TAG: u8 = 0;
+const FREE_TAG: u8 = 1;
+const CURRENT_CLOSURE_TAG: u8 = 2;
+
+/// Represents the reasons bytecode could not be written or read.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SerializeError {
+    // The constant, of the given type, is never produced by the compiler and so has no encoding.
+    UnsupportedConstant(String),
+    NotBytecode,
+    UnsupportedVersion(u8),
+    UnexpectedEnd,
+    BadTag(u8),
+    BadString,
+    BadChar(u32),
+    // The number of defaults of a function, and the number of parameters it has, fewer than that.
+    BadDefaults(usize, usize),
+    // The line and column of a position, one of which is 0 although both count from 1.
+    BadPosition(usize, usize),
+}
+
+impl fmt::Display for SerializeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SerializeError::UnsupportedConstant(type_name) => {
+                write!(
+                    f,
+                    "SerializeError: Cannot write a constant of type {}",
+                    type_name
+                )
+            }
+            SerializeError::NotBytecode => write!(f, "SerializeError: Not a compiled Monkey file"),
+            SerializeError::UnsupportedVersion(version) => {
+                write!(f, "SerializeError: Unsupported format version {}", version)
+            }
+            SerializeError::UnexpectedEnd => write!(f, "SerializeError: Unexpected end of file"),
+            SerializeError::BadTag(tag) => write!(f, "SerializeError: Unknown tag {}", tag),
+            SerializeError::BadString => write!(f, "SerializeError: String is not valid UTF-8"),
+            SerializeError::BadChar(value) => {
+                write!(f, "SerializeError: {} is not a valid character", value)
+            }
+            SerializeError::BadDefaults(defaults, parameters) => write!(
+                f,
+                "SerializeError: Function has {} defaults but only {} parameters",
+                defaults, parameters
+            ),
+            SerializeError::BadPosition(line, column) => {
+                write!(f, "SerializeError: Bad position {}:{}", line, column)
+            }
+        }
+    }
+}
+
+impl Bytecode {
+    /// Returns the bytes of a compiled Monkey file holding this bytecode.
+    ///
+    /// Only constants the compiler produces can be written: integers, strings, characters and compiled functions.
+    pub fn serialize(&self) -> Result<Vec<u8>, SerializeError> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        write_instructions(&mut bytes, &self.instructions);
+        write_positions(&mut bytes, &self.positions);
+        write_u32(&mut bytes, self.constants.len());
+        for constant in self.constants.iter() {
+            write_constant(&mut bytes, constant)?;
+        }
+        Ok(bytes)
+    }
+
+    /// Reads the bytecode held by a compiled Monkey file, as written by `serialize`.
+    pub fn deserialize(bytes: &[u8]) -> Result<Bytecode, SerializeError> {
+        if !bytes.starts_with(MAGIC) {
+            return Err(SerializeError::NotBytecode);
+        }
+        let mut reader = Reader {
+            bytes,
+            pos: MAGIC.len(),
+        };
+        let version = reader.u8()?;
+        if version != VERSION {
+            return Err(SerializeError::UnsupportedVersion(version));
+        }
+        let instructions = reader.instructions()?;
+        let positions = reader.positions()?;
+        let count = reader.u32()?;
+        let mut constants = vec![];
+        for _ in 0..count {
+            constants.push(reader.constant()?);
+        }
+        Ok(Bytecode {
+            positions,
+            ..Bytecode::new(instructions, constants)
+        })
+    }
+}
+
+fn write_u32(bytes: &mut Vec<u8>, value: usize) {
+    bytes.extend_from_slice(&(value as u32).to_le_bytes());
+}
+
+fn write_instructions(bytes: &mut Vec<u8>, instructions: &[u8]) {
+    write_u32(bytes, instructions.len());
+    bytes.extend_from_slice(instructions);
+}
+
+fn write_positions(bytes: &mut Vec<u8>, positions: &[(usize, Position)]) {
+    write_u32(bytes, positions.len());
+    for (offset, position) in positions {
+        write_u32(bytes, *offset);
+        write_u32(bytes, position.line);
+        write_u32(bytes, position.column);
+    }
+}
+
+fn write_constant(bytes: &mut Vec<u8>, constant: &Constant) -> Result<(), SerializeError> {
+    match constant {
+        Constant::Integer(value) => {
+            bytes.push(INTEGER_TAG);
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        Constant::Str(value) => {
+            bytes.push(STR_TAG);
+            write_u32(bytes, value.len());
+            bytes.extend_from_slice(value.as_bytes());
+        }
+        Constant::Char(value) => {
+            bytes.push(CHAR_TAG);
+            write_u32(bytes, *value as usize);
+        }
+        Constant::CompiledFunction(function) => {
+            bytes.push(FUNCTION_TAG);
+            write_instructions(bytes, &function.instructions);
+            write_positions(bytes, &function.positions);
+            write_u32(bytes, function.num_locals);
+            write_u32(bytes, function.num_parameters);
+            write_u32(bytes, function.num_defaults);
+            write_u32(bytes, function.captures.len());
+            for capture in &function.captures {
+                match capture {
+                    Capture::Local(idx) => {
+                        bytes.push(LOCAL_TAG);
+                        write_u32(bytes, *idx);
+                    }
+                    Capture::Free(idx) => {
+                        bytes.push(FREE_TAG);
+                        write_u32(bytes, *idx);
+                    }
+                    Capture::CurrentClosure => bytes.push(CURRENT_CLOSURE_TAG),
+                }
+            }
+        }
+        other => {
+            return Err(SerializeError::UnsupportedConstant(
+                other.type_name().to_string(),
+            ))
+        }
+    }
+    Ok(())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], SerializeError> {
+        if self.bytes.len() - self.pos < n {
+            return Err(SerializeError::UnexpectedEnd);
+        }
+        self.pos += n;
+        Ok(&self.bytes[self.pos - n..self.pos])
+    }
+
+    fn u8(&mut self) -> Result<u8, SerializeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<usize, SerializeError> {
+        let mut buf = [0u8; 4];
+        buf.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(buf) as usize)
+    }
+
+    fn instructions(&mut self) -> Result<Instructions, SerializeError> {
+        let len = self.u32()?;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    fn positions(&mut self) -> Result<Vec<(usize, Position)>, SerializeError> {
+        let count = self.u32()?;
+        let mut positions = vec![];
+        for _ in 0..count {
+            let offset = self.u32()?;
+            let line = self.u32()?;
+            let column = self.u32()?;
+            if line == 0 || column == 0 {
+                return Err(SerializeError::BadPosition(line, column));
+            }
+            positions.push((offset, Position { line, column }));
+        }
+        Ok(positions)
+    }
+
+    fn constant(&mut self) -> Result<Constant, SerializeError> {
+        match self.u8()? {
+            INTEGER_TAG => {
+                let mut buf = [0u8; 8];
+                buf.copy_from_slice(self.take(8)?);
+                Ok(Constant::Integer(i64::from_le_bytes(buf)))
+            }
+            STR_TAG => {
+                let len = self.u32()?;
+                match String::from_utf8(self.take(len)?.to_vec()) {
+                    Ok(value) => Ok(Constant::Str(value)),
+                    Err(_) => Err(SerializeError::BadString),
+                }
+            }
+            CHAR_TAG => {
+                let value = self.u32()? as u32;
+                match char::try_from(value) {
+                    Ok(ch) => Ok(Constant::Char(ch)),
+                    Err(_) => Err(SerializeError::BadChar(value)),
+                }
+            }
+            FUNCTION_TAG => {
+                let instructions = self.instructions()?;
+                let positions = self.positions()?;
+                let num_locals = self.u32()?;
+                let num_parameters = self.u32()?;
+                let num_defaults = self.u32()?;
+                if num_defaults > num_parameters {
+                    return Err(SerializeError::BadDefaults(num_defaults, num_parameters));
+                }
+                let count = self.u32()?;
+                let mut captures = vec![];
+                for _ in 0..count {
+                    captures.push(match self.u8()? {
+                        LOCAL_TAG => Capture::Local(self.u32()?),
+                        FREE_TAG => Capture::Free(self.u32()?),
+                        CURRENT_CLOSURE_TAG => Capture::CurrentClosure,
+                        tag => return Err(SerializeError::BadTag(tag)),
+                    });
+                }
+                Ok(Constant::CompiledFunction(CompiledFunction {
+                    instructions,
+                    num_locals,
+                    num_parameters,
+                    num_defaults,
+                    captures,
+                    positions,
+                }))
+            }
+            tag => Err(SerializeError::BadTag(tag)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::Compiler;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    #[test]
+    fn round_trip_test() {
+        let input = "let counter = fn(start) { let n = start; fn() { n = n + 1; n } };\n\
+                     let c = counter(5); c(); [c(), \"two\", 'x']";
+        let program = Parser::new(Lexer::new(input)).parse_program().unwrap();
+        let bytecode = Compiler::new().compile(&program).unwrap();
+        let bytes = bytecode.serialize().unwrap();
+        let read = Bytecode::deserialize(&bytes).unwrap();
+        assert_eq!(read.instructions, bytecode.instructions);
+        assert_eq!(read.positions, bytecode.positions);
+        assert_eq!(read.constants.len(), bytecode.constants.len());
+        for (got, want) in read.constants.iter().zip(bytecode.constants.iter()) {
+            match (&**got, &**want) {
+                (Constant::CompiledFunction(got), Constant::CompiledFunction(want)) => {
+                    assert_eq!(got, want)
+                }
+                (got, want) => assert_eq!(got.to_string(), want.to_string()),
+            }
+        }
+    }
+
+    #[test]
+    fn deserialize_errors_test() {
+        let bytecode = Bytecode::new(vec![], vec![Constant::Str("monkey".to_string())]);
+        let bytes = bytecode.serialize().unwrap();
+        let function = CompiledFunction {
+            instructions: vec![],
+            num_locals: 1,
+            num_parameters: 1,
+            num_defaults: 2,
+            captures: vec![],
+            positions: vec![],
+        };
+        let bad_defaults = Bytecode::new(vec![], vec![Constant::CompiledFunction(function)]);
+        let mut bad_position = Bytecode::new(vec![], vec![]);
+        bad_position.positions = vec![(0, Position { line: 1, column: 0 })];
+        let tests = vec![
+            (
+                bad_defaults.serialize().unwrap(),
+                SerializeError::BadDefaults(2, 1),
+            ),
+            (
+                bad_position.serialize().unwrap(),
+                SerializeError::BadPosition(1, 0),
+            ),
+            (b"puts(1)".to_vec(), SerializeError::NotBytecode),
+            (b"MKC\x09".to_vec(), SerializeError::UnsupportedVersion(9)),
+            (
+                bytes[..bytes.len() - 1].to_vec(),
+                SerializeError::UnexpectedEnd,
+            ),
+        ];
+        for (input, want) in tests {
+            match Bytecode::deserialize(&input) {
+                Err(error) => assert_eq!(error, want),
+                Ok(_) => panic!("Expected {:?}!", want),
+            }
+        }
+    }
+
+    #[test]
+    fn serialize_errors_test() {
+        let bytecode = Bytecode::new(vec![], vec![Constant::Boolean(true)]);
+        match bytecode.serialize() {
+            Err(error) => assert_eq!(
+                error,
+                SerializeError::UnsupportedConstant(String::from("BOOLEAN"))
+            ),
+            Ok(_) => panic!("Expected an unsupported constant!"),
+        }
+    }
+}
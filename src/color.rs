@@ -0,0 +1,29 @@
+//! Color
+//!
+//! `color` styles text for terminals with ANSI escape codes, for errors and values to stand out from the rest.
+
+/// The ways text is styled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    // The kind of an error, such as `ParseError`, and the caret pointing out where it is.
+    Error,
+    // Where an error is, such as `main.monkey:2:5`.
+    Location,
+    // A value shown by the REPL.
+    Value,
+}
+
+impl Style {
+    fn code(&self) -> &'static str {
+        match self {
+            Style::Error => "1;31",
+            Style::Location => "2",
+            Style::Value => "36",
+        }
+    }
+}
+
+/// Returns `text` styled with `style`.
+pub fn paint(text: &str, style: Style) -> String {
+    format!("\x1b[{}m{}\x1b[0m", style.code(), text)
+}
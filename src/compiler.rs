@@ -1,15 +1,21 @@
 #[cfg(test)]
 mod compiler_test;
+pub(crate) mod fold;
+mod peephole;
 mod symbol_table;
 
 pub use self::symbol_table::*;
 use crate::ast::{BlockStatement, Expression, Program, Statement};
-use crate::code::{Bytecode, CompiledFunction, Constant, Instructions, OpCode};
+use crate::code::{Bytecode, Capture, CompiledFunction, Constant, Constants, Instructions, OpCode};
+use crate::evaluator;
+use crate::lexer::Position;
+use crate::linter::{self, Warning};
 use crate::object::Object;
 use crate::token::Token;
 
 use std::cell::RefCell;
 use std::convert::TryFrom;
+use std::fmt;
 use std::mem;
 use std::rc::Rc;
 
@@ -17,6 +23,9 @@ pub struct CompilationScope {
     instructions: Instructions,
     last_instruction: Option<EmittedInstruction>,
     previous_instruction: Option<EmittedInstruction>,
+    positions: Vec<(usize, Position)>,
+    // Where the statement defining each local starts, by index, if known.
+    definitions: Vec<Option<Position>>,
 }
 
 impl CompilationScope {
@@ -25,6 +34,8 @@ impl CompilationScope {
             instructions: vec![],
             last_instruction: None,
             previous_instruction: None,
+            positions: vec![],
+            definitions: vec![],
         }
     }
 }
@@ -36,10 +47,14 @@ pub struct EmittedInstruction {
 }
 
 pub struct Compiler {
-    constants: Rc<RefCell<Vec<Constant>>>,
+    constants: Rc<RefCell<Constants>>,
     symbol_table: Rc<RefCell<SymbolTable>>,
     scopes: Vec<CompilationScope>,
     scope_index: usize,
+    optimize: bool,
+    // Where the statement being compiled starts, if known.
+    position: Option<Position>,
+    warnings: Vec<Warning>,
 }
 
 #[derive(Debug)]
@@ -47,38 +62,71 @@ pub enum CompileError {
     UnknownError,
     UnknownOperator,
     SymbolNotFound,
+    InvalidAssignment,
+    // There are more of something, such as the arguments of a call, than its operand can count.
+    TooMany(&'static str),
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompileError::UnknownError => write!(f, "CompileError: UnknownError"),
+            CompileError::UnknownOperator => write!(f, "CompileError: Unknown operator"),
+            CompileError::SymbolNotFound => write!(f, "CompileError: Unknown identifier"),
+            CompileError::InvalidAssignment => write!(f, "CompileError: Invalid assignment"),
+            CompileError::TooMany(what) => write!(f, "CompileError: Too many {}", what),
+        }
+    }
 }
 
 impl Compiler {
     pub fn new() -> Self {
         Compiler::new_with_state(
             Rc::new(RefCell::new(SymbolTable::new_with_builtins())),
-            Rc::new(RefCell::new(Vec::new())),
+            Rc::default(),
         )
     }
 
     pub fn new_with_state(
         symbol_table: Rc<RefCell<SymbolTable>>,
-        constants: Rc<RefCell<Vec<Constant>>>,
+        constants: Rc<RefCell<Constants>>,
     ) -> Self {
         Compiler {
             constants,
             symbol_table,
             scopes: vec![CompilationScope::new()],
             scope_index: 0,
+            optimize: true,
+            position: None,
+            warnings: vec![],
         }
     }
 
+    /// Turns the optimizations the compiler makes, such as folding constant expressions, on or off.
+    ///
+    /// They are on unless turned off.
+    // Not used within the crate outside of tests yet.
+    #[allow(dead_code)]
+    pub fn set_optimize(&mut self, optimize: bool) {
+        self.optimize = optimize;
+    }
+
     pub fn current_instructions(&self) -> &Instructions {
         &self.scopes[self.scope_index].instructions
     }
 
-    // TODO: Determine if bytecode can return a reference / take ownership.
     pub fn bytecode(&self) -> Bytecode {
-        Bytecode::new(
-            self.current_instructions().clone(),
-            self.constants.borrow().clone(),
-        )
+        let mut instructions = self.current_instructions().clone();
+        let mut positions = self.scopes[self.scope_index].positions.clone();
+        if self.optimize {
+            peephole::thread_jumps(&mut instructions);
+            peephole::fuse(&mut instructions, &mut positions);
+        }
+        Bytecode {
+            instructions,
+            constants: Rc::clone(&self.constants.borrow()),
+            positions,
+        }
     }
 
     fn enter_scope(&mut self) {
@@ -87,40 +135,125 @@ impl Compiler {
         self.scope_index += 1;
     }
 
-    fn leave_scope(&mut self) -> Result<Instructions, CompileError> {
+    fn leave_scope(&mut self) -> Result<CompilationScope, CompileError> {
         self.scope_index -= 1;
         if let Some(value) = self.scopes.pop() {
             self.symbol_table.borrow_mut().leave_scope();
-            Ok(value.instructions)
+            Ok(value)
         } else {
             Err(CompileError::UnknownError)
         }
     }
 
-    fn load_symbol(&self, symbol: &Symbol) -> Instructions {
-        match symbol.scope {
+    fn load_symbol(&self, symbol: &Symbol) -> Result<Instructions, CompileError> {
+        Ok(match symbol.scope {
             SymbolScope::Global => OpCode::GetGlobal.make_u16(symbol.index),
-            SymbolScope::Local => OpCode::GetLocal.make_u8(symbol.index as u8),
+            SymbolScope::Local => get_local(symbol.index),
             SymbolScope::BuiltIn => OpCode::GetBuiltin.make_u8(symbol.index as u8),
-            SymbolScope::Free => OpCode::GetFree.make_u8(symbol.index as u8),
+            SymbolScope::Free => {
+                OpCode::GetFree.make_u8(operand(symbol.index as usize, "free variables")?)
+            }
             SymbolScope::Function => OpCode::CurrentClosure.make(),
-        }
+        })
     }
 
     pub fn compile(&mut self, p: &Program) -> Result<Bytecode, CompileError> {
-        for statement in &p.statements {
+        self.warnings.clear();
+        // Constant expressions are folded in a copy of the program, leaving the one passed in as it was.
+        let optimized;
+        let p = match self.optimize {
+            true => {
+                let mut copy = p.clone();
+                evaluator::optimize(&mut copy);
+                optimized = copy;
+                &optimized
+            }
+            false => p,
+        };
+        for (idx, statement) in p.statements.iter().enumerate() {
+            self.mark(p.positions.get(idx).copied());
             self.compile_statement(statement)?;
         }
+        self.warnings
+            .sort_by_key(|w| (w.position.line, w.position.column));
         Ok(self.bytecode())
     }
 
+    /// Returns the warnings for the program last compiled, ordered by position: the variables and parameters of
+    /// functions which are never used. Names starting with `_` are exempt.
+    ///
+    /// Globals are left out, as they may be used by programs compiled later with the same symbol table.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
     pub fn compile_block_statement(&mut self, bs: &BlockStatement) -> Result<(), CompileError> {
-        for statement in &bs.statements {
+        let enclosing = self.position;
+        let mut statements = bs.statements.iter().enumerate();
+        for (idx, statement) in statements.by_ref() {
+            self.mark(bs.positions.get(idx).copied());
             self.compile_statement(statement)?;
+            if self.optimize && matches!(statement, Statement::Return(_)) {
+                break;
+            }
+        }
+        // Statements after a `return` can never run, so they are left out, but the names they define are still
+        // known to the rest of the scope.
+        for (idx, statement) in statements {
+            if let Statement::Let(name, _) = statement {
+                let symbol = self.symbol_table.borrow_mut().define(name).clone();
+                self.defined(&symbol, bs.positions.get(idx).copied());
+            }
         }
+        self.position = enclosing;
         Ok(())
     }
 
+    // Records that `symbol` was defined by the statement starting at `position`, if it is a local.
+    fn defined(&mut self, symbol: &Symbol, position: Option<Position>) {
+        if symbol.scope == SymbolScope::Local {
+            let definitions = &mut self.scopes[self.scope_index].definitions;
+            definitions.resize(symbol.index as usize, None);
+            definitions.push(position);
+        }
+    }
+
+    // Warns of the locals of the current scope which are never used, the first `num_parameters` being parameters.
+    fn warn_unused(&mut self, num_parameters: usize) {
+        let unused = self.symbol_table.borrow().unused_locals();
+        let definitions = &self.scopes[self.scope_index].definitions;
+        for symbol in unused.iter().filter(|symbol| !symbol.name.starts_with('_')) {
+            let idx = symbol.index as usize;
+            let message = match idx < num_parameters {
+                true => format!("parameter `{}` is never used", symbol.name),
+                false => linter::unused(&symbol.name),
+            };
+            self.warnings.push(Warning {
+                position: definitions
+                    .get(idx)
+                    .copied()
+                    .flatten()
+                    .unwrap_or(Position { line: 1, column: 1 }),
+                message,
+            });
+        }
+    }
+
+    /// Records that the instructions emitted from now on belong to the statement starting at `position`, if known.
+    fn mark(&mut self, position: Option<Position>) {
+        self.position = position;
+        if let Some(position) = position {
+            let offset = self.current_instructions().len();
+            let positions = &mut self.scopes[self.scope_index].positions;
+            // A statement which emitted no instructions has nothing to place.
+            if let Some(last) = positions.last_mut().filter(|(start, _)| *start == offset) {
+                *last = (offset, position);
+            } else {
+                positions.push((offset, position));
+            }
+        }
+    }
+
     fn compile_statement(&mut self, statement: &Statement) -> Result<(), CompileError> {
         match statement {
             Statement::Expression(expr) => {
@@ -129,10 +262,11 @@ impl Compiler {
             }
             Statement::Let(name, expr) => {
                 let symbol = self.symbol_table.borrow_mut().define(name).clone();
+                self.defined(&symbol, self.position);
                 self.compile_expression(expr)?;
                 let insts = match symbol.scope {
                     SymbolScope::Global => OpCode::SetGlobal.make_u16(symbol.index),
-                    SymbolScope::Local => OpCode::SetLocal.make_u8(symbol.index as u8),
+                    SymbolScope::Local => set_local(symbol.index),
                     _ => return Err(CompileError::UnknownError),
                 };
                 self.emit(insts);
@@ -141,18 +275,59 @@ impl Compiler {
                 self.compile_expression(value)?;
                 self.emit(OpCode::ReturnValue.make());
             }
+            Statement::Assign(target, value) => {
+                self.compile_expression(value)?;
+                self.compile_store(target)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Emits instructions storing the value on top of the stack into `target`.
+    ///
+    /// Assigning to an index produces an updated copy of the indexed collection, which is in turn stored back
+    /// into the expression it was read from.
+    fn compile_store(&mut self, target: &Expression) -> Result<(), CompileError> {
+        match target {
+            Expression::Ident(name) => {
+                let symbol = match self.symbol_table.borrow_mut().resolve_assigned(name) {
+                    Ok(symbol) => symbol,
+                    Err(_) => return Err(CompileError::SymbolNotFound),
+                };
+                let insts = match symbol.scope {
+                    SymbolScope::Global => OpCode::SetGlobal.make_u16(symbol.index),
+                    SymbolScope::Local => set_local(symbol.index),
+                    SymbolScope::Free => {
+                        OpCode::SetFree.make_u8(operand(symbol.index as usize, "free variables")?)
+                    }
+                    _ => return Err(CompileError::InvalidAssignment),
+                };
+                self.emit(insts);
+            }
+            Expression::Index(left, index, _) => {
+                self.compile_expression(left)?;
+                self.compile_expression(index)?;
+                self.emit(OpCode::SetIndex.make());
+                self.compile_store(left)?;
+            }
+            _ => return Err(CompileError::InvalidAssignment),
         }
         Ok(())
     }
 
     fn compile_expression(&mut self, expression: &Expression) -> Result<(), CompileError> {
         match expression {
-            Expression::Call(func, args) => {
+            Expression::Call(func, args, _) => {
                 self.compile_expression(func)?;
                 for expr in args {
                     self.compile_expression(expr)?;
                 }
-                self.emit(OpCode::Call.make_u8(args.len() as u8));
+                // Calls with many arguments take the wide form of the instruction.
+                let num_args = operand(args.len(), "arguments")?;
+                match u8::try_from(num_args) {
+                    Ok(num_args) => self.emit(OpCode::Call.make_u8(num_args)),
+                    Err(_) => self.emit(OpCode::CallWide.make_u16(num_args)),
+                };
             }
             Expression::FunctionLiteral(parameters, block_statement, maybe_name) => {
                 self.enter_scope();
@@ -160,33 +335,71 @@ impl Compiler {
                     self.symbol_table.borrow_mut().define_function_name(name);
                 };
                 for parameter in parameters {
-                    self.symbol_table.borrow_mut().define(parameter);
+                    let symbol = self
+                        .symbol_table
+                        .borrow_mut()
+                        .define(&parameter.name)
+                        .clone();
+                    self.defined(&symbol, self.position);
+                }
+                // Initialize omitted arguments from their defaults, skipping those the caller passed.
+                for (idx, parameter) in parameters.iter().enumerate() {
+                    if let Some(default) = &parameter.default {
+                        let param = operand(idx, "parameters before one with a default")?;
+                        let jump_pos = self.emit(OpCode::JumpIfPassed.make_u16_u8(9999, param));
+                        self.compile_expression(default)?;
+                        self.emit(set_local(operand(idx, "parameters")?));
+                        let target = self.jump_target()?;
+                        self.replace_instructions(
+                            jump_pos,
+                            OpCode::JumpIfPassed.make_u16_u8(target, param),
+                        );
+                    }
                 }
                 self.compile_block_statement(block_statement)?;
                 self.replace_last_pop_with_return();
                 if !self.last_instruction_is(OpCode::ReturnValue) {
                     self.emit(OpCode::Return.make());
                 }
+                self.warn_unused(parameters.len());
                 let free_symbols = self.symbol_table.borrow().free_symbols().clone();
                 let num_locals = self.symbol_table.borrow().num_definitions();
-                let instructions = self.leave_scope()?;
+                let mut scope = self.leave_scope()?;
+                if self.optimize {
+                    peephole::thread_jumps(&mut scope.instructions);
+                    peephole::fuse(&mut scope.instructions, &mut scope.positions);
+                }
+                // Free variables are shared with the enclosing scope rather than copied into the closure.
+                let mut captures = Vec::with_capacity(free_symbols.len());
                 for symbol in &free_symbols {
-                    self.emit(self.load_symbol(symbol));
+                    captures.push(match symbol.scope {
+                        SymbolScope::Local => Capture::Local(symbol.index as usize),
+                        SymbolScope::Free => Capture::Free(symbol.index as usize),
+                        SymbolScope::Function => Capture::CurrentClosure,
+                        _ => return Err(CompileError::UnknownError),
+                    });
                 }
                 let compiled_function = CompiledFunction {
-                    instructions,
+                    instructions: scope.instructions,
                     num_locals,
                     num_parameters: parameters.len(),
+                    num_defaults: parameters.iter().filter(|p| p.default.is_some()).count(),
+                    captures,
+                    positions: scope.positions,
+                };
+                let idx = self.add_constant(Constant::CompiledFunction(compiled_function))?;
+                let num_free = operand(free_symbols.len(), "free variables")?;
+                match u16::try_from(idx) {
+                    Ok(idx) => self.emit(OpCode::Closure.make_u16_u8(idx, num_free)),
+                    Err(_) => self.emit(OpCode::ClosureWide.make_u32_u8(idx, num_free)),
                 };
-                let idx = self.add_constant(Constant::CompiledFunction(compiled_function));
-                self.emit(OpCode::Closure.make_u16_u8(idx, free_symbols.len() as u8));
             }
             Expression::Ident(name) => {
                 // Use a separate statement to catch the result so that we can unborrow the symbol_table.
                 let symbol_result = self.symbol_table.borrow_mut().resolve(name);
                 match symbol_result {
                     Ok(symbol) => {
-                        let insts = self.load_symbol(&symbol);
+                        let insts = self.load_symbol(&symbol)?;
                         self.emit(insts);
                     }
                     Err(_) => return Err(CompileError::SymbolNotFound),
@@ -197,10 +410,15 @@ impl Compiler {
                 let jump_not_truthy_pos = self.emit(OpCode::JumpNotTruthy.make_u16(9999));
                 self.compile_block_statement(&consequence)?;
                 self.remove_last_pop();
-                let jump_pos = self.emit(OpCode::Jump.make_u16(9999));
+                // A consequence which returns never reaches the jump over the alternative, which is then left out.
+                let jump_pos = match self.optimize && returns(consequence) {
+                    true => None,
+                    false => Some(self.emit(OpCode::Jump.make_u16(9999))),
+                };
+                let target = self.jump_target()?;
                 self.replace_instructions(
                     jump_not_truthy_pos,
-                    OpCode::JumpNotTruthy.make_u16(self.current_instructions().len() as u16),
+                    OpCode::JumpNotTruthy.make_u16(target),
                 );
                 match alternative {
                     None => {
@@ -211,12 +429,14 @@ impl Compiler {
                         self.remove_last_pop();
                     }
                 }
-                self.replace_instructions(
-                    jump_pos,
-                    OpCode::Jump.make_u16(self.current_instructions().len() as u16),
-                );
+                if let Some(jump_pos) = jump_pos {
+                    let target = self.jump_target()?;
+                    self.replace_instructions(jump_pos, OpCode::Jump.make_u16(target));
+                }
+                // What follows belongs to the statement holding the `if` again, not the last one in its blocks.
+                self.mark(self.position);
             }
-            Expression::Prefix(prefix, expr) => {
+            Expression::Prefix(prefix, expr, _) => {
                 self.compile_expression(expr)?;
                 let opcode = match prefix {
                     Token::Bang => OpCode::Bang,
@@ -225,7 +445,7 @@ impl Compiler {
                 };
                 self.emit(opcode.make());
             }
-            Expression::Infix(left, infix, right) => {
+            Expression::Infix(left, infix, right, _) => {
                 match infix {
                     Token::LessThan => {
                         // Optimization to flip args and re-use GreaterThan.
@@ -252,12 +472,17 @@ impl Compiler {
             }
             Expression::IntegerLiteral(int) => {
                 let int = Object::Integer(*int);
-                let instructions = OpCode::Constant.make_u16(self.add_constant(int));
+                let instructions = constant(self.add_constant(int)?);
                 self.emit(instructions);
             }
             Expression::StringLiteral(str) => {
                 let str = Object::Str(str.clone());
-                let instructions = OpCode::Constant.make_u16(self.add_constant(str));
+                let instructions = constant(self.add_constant(str)?);
+                self.emit(instructions);
+            }
+            Expression::CharLiteral(ch) => {
+                let ch = Object::Char(*ch);
+                let instructions = constant(self.add_constant(ch)?);
                 self.emit(instructions);
             }
             Expression::BooleanLiteral(bool) => {
@@ -268,16 +493,17 @@ impl Compiler {
                 for expr in elements {
                     self.compile_expression(expr)?;
                 }
-                self.emit(OpCode::Array.make_u16(elements.len() as u16));
+                self.emit(OpCode::Array.make_u16(operand(elements.len(), "elements")?));
             }
             Expression::HashLiteral(keys_and_values) => {
                 for (key, value) in keys_and_values {
                     self.compile_expression(key)?;
                     self.compile_expression(value)?;
                 }
-                self.emit(OpCode::Hash.make_u16(2 * keys_and_values.len() as u16));
+                let num_elements = operand(2 * keys_and_values.len(), "elements")?;
+                self.emit(OpCode::Hash.make_u16(num_elements));
             }
-            Expression::Index(left, right) => {
+            Expression::Index(left, right, _) => {
                 self.compile_expression(&left)?;
                 self.compile_expression(&right)?;
                 self.emit(OpCode::Index.make());
@@ -286,9 +512,18 @@ impl Compiler {
         Ok(())
     }
 
-    fn add_constant(&mut self, constant: Constant) -> u16 {
-        self.constants.borrow_mut().push(constant);
-        return (self.constants.borrow().len() - 1) as u16;
+    fn add_constant(&mut self, constant: Constant) -> Result<u32, CompileError> {
+        // Bytecode returned earlier keeps the constants it was returned with.
+        Rc::make_mut(&mut self.constants.borrow_mut()).push(Rc::new(constant));
+        operand(self.constants.borrow().len() - 1, "constants")
+    }
+
+    // Returns the offset of the next instruction emitted, for a jump to land on.
+    fn jump_target(&self) -> Result<u16, CompileError> {
+        operand(
+            self.current_instructions().len(),
+            "instructions to jump over",
+        )
     }
 
     pub fn emit(&mut self, ins: Instructions) -> usize {
@@ -312,6 +547,43 @@ impl Compiler {
     }
 }
 
+// Returns the instruction loading the local variable at `index`, in the wide form if the index needs it.
+fn get_local(index: u16) -> Instructions {
+    match u8::try_from(index) {
+        Ok(index) => OpCode::GetLocal.make_u8(index),
+        Err(_) => OpCode::GetLocalWide.make_u16(index),
+    }
+}
+
+// Returns the instruction loading the constant at `index`, in the wide form if the index needs it.
+fn constant(index: u32) -> Instructions {
+    match u16::try_from(index) {
+        Ok(index) => OpCode::Constant.make_u16(index),
+        Err(_) => OpCode::ConstantWide.make_u32(index),
+    }
+}
+
+// Returns the instruction storing into the local variable at `index`, in the wide form if the index needs it.
+fn set_local(index: u16) -> Instructions {
+    match u8::try_from(index) {
+        Ok(index) => OpCode::SetLocal.make_u8(index),
+        Err(_) => OpCode::SetLocalWide.make_u16(index),
+    }
+}
+
+// Returns `count` as an operand, or the error saying there are too many `what` if it does not fit in one.
+fn operand<T: TryFrom<usize>>(count: usize, what: &'static str) -> Result<T, CompileError> {
+    T::try_from(count).map_err(|_| CompileError::TooMany(what))
+}
+
+/// Returns whether `block` always returns, having a `return` statement of its own.
+fn returns(block: &BlockStatement) -> bool {
+    block
+        .statements
+        .iter()
+        .any(|statement| matches!(statement, Statement::Return(_)))
+}
+
 impl CompilationScope {
     // TODO: Determine if this function can be removed entirely.
     fn add_instruction(&mut self, ins: Instructions) -> usize {
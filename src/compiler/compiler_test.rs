@@ -11,20 +11,24 @@ struct TestCase<'a> {
     expected_instructions: Vec<Instructions>,
 }
 
+// Positions are left out, for compiled functions to compare equal to those expected; `positions_test` checks them.
 fn parse(input: &str) -> Program {
     let mut p = Parser::new(Lexer::new(input));
+    p.set_positions(false);
     p.parse_program().unwrap()
 }
 
 fn test_compile(test_case: TestCase) {
     let program = parse(test_case.input);
+    // The expected instructions are those emitted as written, without constants folded.
     let mut compiler = Compiler::new();
+    compiler.set_optimize(false);
     let bytecode = match compiler.compile(&program) {
         Ok(code) => code,
         Err(_) => panic!("Compilation error!"),
     };
 
-    test_constants(test_case.expected_constants, bytecode.constants);
+    test_constants(test_case.expected_constants, &bytecode.constants);
     test_instructions(test_case.expected_instructions, bytecode.instructions);
 }
 
@@ -52,9 +56,9 @@ fn test_instructions(want: Vec<Instructions>, got: Instructions) {
     );
 }
 
-fn test_constants(want: Vec<Constant>, got: Vec<Constant>) {
+fn test_constants(want: Vec<Constant>, got: &[Rc<Constant>]) {
     for (w, g) in want.iter().zip(got.iter()) {
-        match (w, g) {
+        match (w, &**g) {
             (Constant::Integer(want), Constant::Integer(got)) => {
                 assert_eq!(want, got, "Bad integer constant!");
             }
@@ -796,7 +800,7 @@ fn closure_test() {
                 };
                 };",
             expected_constants: vec![
-                compiled_function(
+                closure_function(
                     vec![
                         OpCode::GetFree.make_u8(0),
                         OpCode::GetLocal.make_u8(0),
@@ -805,15 +809,16 @@ fn closure_test() {
                     ],
                     1,
                     1,
+                    vec![Capture::Local(0)],
                 ),
-                compiled_function(
+                closure_function(
                     vec![
-                        OpCode::GetLocal.make_u8(0),
                         OpCode::Closure.make_u16_u8(0, 1),
                         OpCode::ReturnValue.make(),
                     ],
                     1,
                     1,
+                    vec![],
                 ),
             ],
             expected_instructions: vec![OpCode::Closure.make_u16_u8(1, 0), OpCode::Pop.make()],
@@ -835,7 +840,7 @@ fn closure_test() {
                 Constant::Integer(66),
                 Constant::Integer(77),
                 Constant::Integer(88),
-                compiled_function(
+                closure_function(
                     vec![
                         OpCode::Constant.make_u16(3),
                         OpCode::SetLocal.make_u8(0),
@@ -850,29 +855,29 @@ fn closure_test() {
                     ],
                     1,
                     0,
+                    vec![Capture::Free(0), Capture::Local(0)],
                 ),
-                compiled_function(
+                closure_function(
                     vec![
                         OpCode::Constant.make_u16(2),
                         OpCode::SetLocal.make_u8(0),
-                        OpCode::GetFree.make_u8(0),
-                        OpCode::GetLocal.make_u8(0),
                         OpCode::Closure.make_u16_u8(4, 2),
                         OpCode::ReturnValue.make(),
                     ],
                     1,
                     0,
+                    vec![Capture::Local(0)],
                 ),
-                compiled_function(
+                closure_function(
                     vec![
                         OpCode::Constant.make_u16(1),
                         OpCode::SetLocal.make_u8(0),
-                        OpCode::GetLocal.make_u8(0),
                         OpCode::Closure.make_u16_u8(5, 1),
                         OpCode::ReturnValue.make(),
                     ],
                     1,
                     0,
+                    vec![],
                 ),
             ],
             expected_instructions: vec![
@@ -888,6 +893,98 @@ fn closure_test() {
     }
 }
 
+#[test]
+fn free_assignment_test() {
+    let tests = vec![TestCase {
+        input: "fn() { let a = 1; fn() { a = 2; } }",
+        expected_constants: vec![
+            Constant::Integer(1),
+            Constant::Integer(2),
+            closure_function(
+                vec![
+                    OpCode::Constant.make_u16(1),
+                    OpCode::SetFree.make_u8(0),
+                    OpCode::Return.make(),
+                ],
+                0,
+                0,
+                vec![Capture::Local(0)],
+            ),
+            closure_function(
+                vec![
+                    OpCode::Constant.make_u16(0),
+                    OpCode::SetLocal.make_u8(0),
+                    OpCode::Closure.make_u16_u8(2, 1),
+                    OpCode::ReturnValue.make(),
+                ],
+                1,
+                0,
+                vec![],
+            ),
+        ],
+        expected_instructions: vec![OpCode::Closure.make_u16_u8(3, 0), OpCode::Pop.make()],
+    }];
+    for test in tests {
+        test_compile(test);
+    }
+}
+
+#[test]
+fn index_assignment_test() {
+    let tests = vec![TestCase {
+        input: "let a = [1]; a[0] = 2;",
+        expected_constants: vec![
+            Constant::Integer(1),
+            Constant::Integer(2),
+            Constant::Integer(0),
+        ],
+        expected_instructions: vec![
+            OpCode::Constant.make_u16(0),
+            OpCode::Array.make_u16(1),
+            OpCode::SetGlobal.make_u16(0),
+            OpCode::Constant.make_u16(1),
+            OpCode::GetGlobal.make_u16(0),
+            OpCode::Constant.make_u16(2),
+            OpCode::SetIndex.make(),
+            OpCode::SetGlobal.make_u16(0),
+        ],
+    }];
+    for test in tests {
+        test_compile(test);
+    }
+}
+
+#[test]
+fn default_parameter_test() {
+    let tests = vec![TestCase {
+        input: "fn(a, b = 2) { a + b }",
+        expected_constants: vec![
+            Constant::Integer(2),
+            Constant::CompiledFunction(CompiledFunction {
+                instructions: [
+                    OpCode::JumpIfPassed.make_u16_u8(9, 1),
+                    OpCode::Constant.make_u16(0),
+                    OpCode::SetLocal.make_u8(1),
+                    OpCode::GetLocal.make_u8(0),
+                    OpCode::GetLocal.make_u8(1),
+                    OpCode::Add.make(),
+                    OpCode::ReturnValue.make(),
+                ]
+                .concat(),
+                num_locals: 2,
+                num_parameters: 2,
+                num_defaults: 1,
+                captures: vec![],
+                positions: vec![],
+            }),
+        ],
+        expected_instructions: vec![OpCode::Closure.make_u16_u8(1, 0), OpCode::Pop.make()],
+    }];
+    for test in tests {
+        test_compile(test);
+    }
+}
+
 #[test]
 fn recursive_test() {
     let tests = vec![TestCase {
@@ -923,6 +1020,367 @@ fn recursive_test() {
     }
 }
 
+#[test]
+fn positions_test() {
+    let input = "let x = 1;\nlet f = fn() {\n  x;\n  x\n};\nf()";
+    let program = Parser::new(Lexer::new(input)).parse_program().unwrap();
+    let bytecode = Compiler::new().compile(&program).unwrap();
+    let at = |line, column| Position { line, column };
+    assert_eq!(
+        bytecode.positions,
+        vec![(0, at(1, 1)), (6, at(2, 1)), (13, at(6, 1))]
+    );
+    match &*bytecode.constants[1] {
+        Constant::CompiledFunction(function) => {
+            assert_eq!(function.positions, vec![(0, at(3, 3)), (4, at(4, 3))]);
+            assert_eq!(function.position(3), Some(at(3, 3)));
+            assert_eq!(function.position(4), Some(at(4, 3)));
+        }
+        other => panic!("Expected a compiled function, got {:?}", other),
+    }
+}
+
+#[test]
+fn constant_folding_test() {
+    let tests = vec![
+        (
+            "2 * 3 + 4",
+            vec![Constant::Integer(10)],
+            vec![OpCode::Constant.make_u16(0)],
+        ),
+        (
+            "\"a\" + \"b\"",
+            vec![Constant::Str(String::from("ab"))],
+            vec![OpCode::Constant.make_u16(0)],
+        ),
+        ("!true", vec![], vec![OpCode::False.make()]),
+        ("-(1 - 3) > 1 == !false", vec![], vec![OpCode::True.make()]),
+        ("!5", vec![], vec![OpCode::False.make()]),
+        // Only the constant part of the expression is folded.
+        (
+            "fn(x) { x * (2 + 3) }",
+            vec![
+                Constant::Integer(5),
+                compiled_function(
+                    vec![
+                        OpCode::GetLocal.make_u8(0),
+                        OpCode::Constant.make_u16(0),
+                        OpCode::Mul.make(),
+                        OpCode::ReturnValue.make(),
+                    ],
+                    1,
+                    1,
+                ),
+            ],
+            vec![OpCode::Closure.make_u16_u8(1, 0)],
+        ),
+        // Expressions which fail at runtime are left for the VM to report.
+        (
+            "1 / 0",
+            vec![Constant::Integer(1), Constant::Integer(0)],
+            vec![
+                OpCode::Constant.make_u16(0),
+                OpCode::Constant.make_u16(1),
+                OpCode::Div.make(),
+            ],
+        ),
+    ];
+    for (input, expected_constants, mut expected_instructions) in tests {
+        expected_instructions.push(OpCode::Pop.make());
+        let folded = Compiler::new().compile(&parse(input)).unwrap();
+        test_constants(expected_constants, &folded.constants);
+        test_instructions(expected_instructions, folded.instructions.clone());
+
+        let mut compiler = Compiler::new();
+        compiler.set_optimize(false);
+        let unfolded = compiler.compile(&parse(input)).unwrap();
+        // Folding either shortens the instructions or leaves them be.
+        let (folded_len, unfolded_len) = (instructions_len(&folded), instructions_len(&unfolded));
+        assert!(folded_len < unfolded_len || folded.instructions == unfolded.instructions);
+    }
+}
+
+#[test]
+fn dead_code_test() {
+    let tests = vec![
+        (
+            "fn() { return 1; 2 }",
+            vec![
+                Constant::Integer(1),
+                compiled_function(
+                    vec![OpCode::Constant.make_u16(0), OpCode::ReturnValue.make()],
+                    0,
+                    0,
+                ),
+            ],
+        ),
+        // What is defined after the `return` still takes up a local.
+        (
+            "fn() { return 1; let y = 2; y }",
+            vec![
+                Constant::Integer(1),
+                compiled_function(
+                    vec![OpCode::Constant.make_u16(0), OpCode::ReturnValue.make()],
+                    1,
+                    0,
+                ),
+            ],
+        ),
+        // No jump over the (missing) alternative follows a consequence which returns.
+        (
+            "fn(x) { if (x) { return 1; 3 } 2 }",
+            vec![
+                Constant::Integer(1),
+                Constant::Integer(2),
+                compiled_function(
+                    vec![
+                        OpCode::GetLocal.make_u8(0),
+                        OpCode::JumpNotTruthy.make_u16(9),
+                        OpCode::Constant.make_u16(0),
+                        OpCode::ReturnValue.make(),
+                        OpCode::Null.make(),
+                        OpCode::Pop.make(),
+                        OpCode::Constant.make_u16(1),
+                        OpCode::ReturnValue.make(),
+                    ],
+                    1,
+                    1,
+                ),
+            ],
+        ),
+    ];
+    for (input, expected_constants) in tests {
+        let bytecode = Compiler::new().compile(&parse(input)).unwrap();
+        let function = (expected_constants.len() - 1) as u16;
+        test_constants(expected_constants, &bytecode.constants);
+        test_instructions(
+            vec![OpCode::Closure.make_u16_u8(function, 0), OpCode::Pop.make()],
+            bytecode.instructions,
+        );
+    }
+}
+
+#[test]
+fn superinstruction_test() {
+    let tests = vec![
+        (
+            "fn(a, b) { a + b }",
+            vec![compiled_function(
+                vec![
+                    OpCode::GetLocalGetLocalAdd.make_u8_u8(0, 1),
+                    OpCode::ReturnValue.make(),
+                ],
+                2,
+                2,
+            )],
+            vec![OpCode::Closure.make_u16_u8(0, 0), OpCode::Pop.make()],
+        ),
+        (
+            "let f = fn() { 1 }; f(); f(2)",
+            vec![
+                Constant::Integer(1),
+                compiled_function(
+                    vec![OpCode::Constant.make_u16(0), OpCode::ReturnValue.make()],
+                    0,
+                    0,
+                ),
+                Constant::Integer(2),
+            ],
+            vec![
+                OpCode::Closure.make_u16_u8(1, 0),
+                OpCode::SetGlobal.make_u16(0),
+                OpCode::GetGlobalCall.make_u16_u8(0, 0),
+                OpCode::Pop.make(),
+                OpCode::GetGlobal.make_u16(0),
+                OpCode::ConstantCall.make_u16_u8(2, 1),
+                OpCode::Pop.make(),
+            ],
+        ),
+        // Jumps over what was fused are moved to where their targets ended up.
+        (
+            "fn(a, b) { if (a) { a + b } else { 0 } }",
+            vec![
+                Constant::Integer(0),
+                compiled_function(
+                    vec![
+                        OpCode::GetLocal.make_u8(0),
+                        OpCode::JumpNotTruthy.make_u16(11),
+                        OpCode::GetLocalGetLocalAdd.make_u8_u8(0, 1),
+                        OpCode::Jump.make_u16(14),
+                        OpCode::Constant.make_u16(0),
+                        OpCode::ReturnValue.make(),
+                    ],
+                    2,
+                    2,
+                ),
+            ],
+            vec![OpCode::Closure.make_u16_u8(1, 0), OpCode::Pop.make()],
+        ),
+        // Nothing is fused where a jump lands in the middle.
+        (
+            "fn(a, b) { if (a) { 1 } else { a } + b }",
+            vec![
+                Constant::Integer(1),
+                compiled_function(
+                    vec![
+                        OpCode::GetLocal.make_u8(0),
+                        OpCode::JumpNotTruthy.make_u16(11),
+                        OpCode::Constant.make_u16(0),
+                        OpCode::Jump.make_u16(13),
+                        OpCode::GetLocal.make_u8(0),
+                        OpCode::GetLocal.make_u8(1),
+                        OpCode::Add.make(),
+                        OpCode::ReturnValue.make(),
+                    ],
+                    2,
+                    2,
+                ),
+            ],
+            vec![OpCode::Closure.make_u16_u8(1, 0), OpCode::Pop.make()],
+        ),
+    ];
+    for (input, expected_constants, expected_instructions) in tests {
+        let bytecode = Compiler::new().compile(&parse(input)).unwrap();
+        test_constants(expected_constants, &bytecode.constants);
+        test_instructions(expected_instructions, bytecode.instructions);
+    }
+}
+
+#[test]
+fn jump_threading_test() {
+    let input = "fn(a, b) { if (a) { if (b) { 1 } else { 2 } } else { 3 } }";
+    let expected_constants = vec![
+        Constant::Integer(1),
+        Constant::Integer(2),
+        Constant::Integer(3),
+        compiled_function(
+            vec![
+                OpCode::GetLocal.make_u8(0),
+                OpCode::JumpNotTruthy.make_u16(22),
+                OpCode::GetLocal.make_u8(1),
+                OpCode::JumpNotTruthy.make_u16(16),
+                OpCode::Constant.make_u16(0),
+                // Out of both conditionals at once, rather than to the jump out of the outer one.
+                OpCode::Jump.make_u16(25),
+                OpCode::Constant.make_u16(1),
+                OpCode::Jump.make_u16(25),
+                OpCode::Constant.make_u16(2),
+                OpCode::ReturnValue.make(),
+            ],
+            2,
+            2,
+        ),
+    ];
+    let bytecode = Compiler::new().compile(&parse(input)).unwrap();
+    test_constants(expected_constants, &bytecode.constants);
+}
+
+#[test]
+fn wide_operands_test() {
+    let names: Vec<String> = (0..300).map(|idx| format!("a{}", idx)).collect();
+    let input = format!(
+        "let f = fn({}) {{ let b = a299; b }}; f({})",
+        names.join(", "),
+        (0..300)
+            .map(|idx| idx.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    let bytecode = Compiler::new().compile(&parse(&input)).unwrap();
+    let function = match &*bytecode.constants[0] {
+        Constant::CompiledFunction(function) => disassemble(&function.instructions),
+        other => panic!("Expected a function, got {:?}!", other),
+    };
+    // Locals and calls past the reach of a byte take the wide form of their instructions.
+    assert!(function.contains("OpGetLocalWide 299\n"));
+    assert!(function.contains("OpSetLocalWide 300\n"));
+    assert!(function.contains("OpGetLocalWide 300\n"));
+    assert!(disassemble(&bytecode.instructions).contains("OpCallWide 300\n"));
+
+    // Where there is no wide form, there are too many rather than a silently wrong operand.
+    let input = format!(
+        "fn() {{ let {} = 0; fn() {{ [{}] }} }}",
+        names.join(" = 0; let "),
+        names.join(", ")
+    );
+    match Compiler::new().compile(&parse(&input)) {
+        Err(CompileError::TooMany(what)) => assert_eq!(what, "free variables"),
+        other => panic!(
+            "Expected too many free variables, got {:?}!",
+            other.map(|_| ())
+        ),
+    }
+}
+
+#[test]
+fn wide_constants_test() {
+    let literals: Vec<String> = (0..70000).map(|idx| idx.to_string()).collect();
+    let input = format!("{}; fn() {{ 1 }}", literals.join("; "));
+    let bytecode = Compiler::new().compile(&parse(&input)).unwrap();
+    let instructions = disassemble(&bytecode.instructions);
+    assert!(instructions.starts_with("0000 OpConstant 0\n"));
+    assert!(instructions.contains(" OpConstant 65535\n"));
+    assert!(instructions.contains(" OpConstantWide 65536\n"));
+    assert!(instructions.contains(" OpClosureWide 70001 0\n"));
+}
+
+#[test]
+fn shared_constants_test() {
+    let constants: Rc<RefCell<Constants>> = Rc::default();
+    let symbol_table = Rc::new(RefCell::new(SymbolTable::new_with_builtins()));
+    let mut compiler = Compiler::new_with_state(Rc::clone(&symbol_table), Rc::clone(&constants));
+    let first = compiler.compile(&parse("\"a\"")).unwrap();
+    // The bytecode holds the constants of the compiler rather than a copy.
+    assert!(Rc::ptr_eq(&first.constants, &compiler.bytecode().constants));
+    assert!(Rc::ptr_eq(&first.constants, &constants.borrow()));
+
+    // Adding to the constants while bytecode holds them leaves those of the bytecode as they were.
+    let mut compiler = Compiler::new_with_state(symbol_table, Rc::clone(&constants));
+    let second = compiler.compile(&parse("\"b\"")).unwrap();
+    assert_eq!(first.constants.len(), 1);
+    assert_eq!(second.constants.len(), 2);
+    assert!(Rc::ptr_eq(&first.constants[0], &second.constants[0]));
+}
+
+#[test]
+fn warnings_test() {
+    let input = "let unused = 1;
+let f = fn(a, b, _c) {
+    let x = 1;
+    let y = 2;
+    y = 3;
+    fn() { a }
+};
+f(1, 2, 3)";
+    let program = Parser::new(Lexer::new(input)).parse_program().unwrap();
+    let mut compiler = Compiler::new();
+    compiler.compile(&program).unwrap();
+    let warning = |line, column, message: &str| Warning {
+        position: Position { line, column },
+        message: message.to_string(),
+    };
+    // Globals may be used by later input, and assigning to a variable does not use it.
+    assert_eq!(
+        compiler.warnings(),
+        &[
+            warning(2, 1, "parameter `b` is never used"),
+            warning(3, 5, "variable `x` is never used"),
+            warning(4, 5, "variable `y` is never used"),
+        ]
+    );
+    compiler.compile(&parse("1")).unwrap();
+    assert!(compiler.warnings().is_empty());
+}
+
+// Returns the length of the instructions of `bytecode`, including those of its functions.
+fn instructions_len(bytecode: &Bytecode) -> usize {
+    let functions = bytecode.constants.iter().map(|constant| match &**constant {
+        Constant::CompiledFunction(function) => function.instructions.len(),
+        _ => 0,
+    });
+    bytecode.instructions.len() + functions.sum::<usize>()
+}
+
 fn compiled_function(
     instructions: Vec<Instructions>,
     num_locals: usize,
@@ -932,5 +1390,24 @@ fn compiled_function(
         instructions: instructions.concat(),
         num_locals,
         num_parameters,
+        num_defaults: 0,
+        captures: vec![],
+        positions: vec![],
+    })
+}
+
+fn closure_function(
+    instructions: Vec<Instructions>,
+    num_locals: usize,
+    num_parameters: usize,
+    captures: Vec<Capture>,
+) -> Constant {
+    Constant::CompiledFunction(CompiledFunction {
+        instructions: instructions.concat(),
+        num_locals,
+        num_parameters,
+        num_defaults: 0,
+        captures,
+        positions: vec![],
     })
 }
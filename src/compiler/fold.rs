@@ -0,0 +1,60 @@
+//! Fold
+//!
+//! `fold` works out the values of expressions made only of literals and operators, such as `2 * 3 + 4`,
+//! `"a" + "b"` or `!true`, so that `optimize` can put the value in their place before a program is compiled or
+//! evaluated.
+//!
+//! Values are worked out as the virtual machine would at runtime. Expressions which would fail at runtime, such as
+//! `1 / 0` or `-"a"`, are left alone so that they still fail at runtime.
+use crate::ast::Expression;
+use crate::object::Object;
+use crate::token::Token;
+
+/// Returns the value of `expression`, if it can be worked out without running the program.
+pub fn fold(expression: &Expression) -> Option<Object> {
+    match expression {
+        Expression::IntegerLiteral(value) => Some(Object::Integer(*value)),
+        Expression::StringLiteral(value) => Some(Object::Str(value.clone())),
+        Expression::BooleanLiteral(value) => Some(Object::Boolean(*value)),
+        Expression::Prefix(prefix, operand, _) => fold_prefix(prefix, fold(operand)?),
+        Expression::Infix(left, infix, right, _) => fold_infix(fold(left)?, infix, fold(right)?),
+        _ => None,
+    }
+}
+
+fn fold_prefix(prefix: &Token, operand: Object) -> Option<Object> {
+    match (prefix, operand) {
+        (Token::Bang, operand) => Some(Object::Boolean(matches!(
+            operand,
+            Object::Boolean(false) | Object::Null
+        ))),
+        (Token::Minus, Object::Integer(value)) => value.checked_neg().map(Object::Integer),
+        _ => None,
+    }
+}
+
+fn fold_infix(left: Object, infix: &Token, right: Object) -> Option<Object> {
+    match (left, right) {
+        (Object::Integer(left), Object::Integer(right)) => match infix {
+            // Overflow and division by zero are left to happen at runtime.
+            Token::Plus => left.checked_add(right).map(Object::Integer),
+            Token::Minus => left.checked_sub(right).map(Object::Integer),
+            Token::Asterisk => left.checked_mul(right).map(Object::Integer),
+            Token::Slash => left.checked_div(right).map(Object::Integer),
+            Token::Equal => Some(Object::Boolean(left == right)),
+            Token::NotEqual => Some(Object::Boolean(left != right)),
+            Token::LessThan => Some(Object::Boolean(left < right)),
+            Token::GreaterThan => Some(Object::Boolean(left > right)),
+            _ => None,
+        },
+        (Object::Boolean(left), Object::Boolean(right)) => match infix {
+            Token::Equal => Some(Object::Boolean(left == right)),
+            Token::NotEqual => Some(Object::Boolean(left != right)),
+            _ => None,
+        },
+        (Object::Str(left), Object::Str(right)) if *infix == Token::Plus => {
+            Some(Object::Str(left + &right))
+        }
+        _ => None,
+    }
+}
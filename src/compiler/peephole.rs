@@ -0,0 +1,193 @@
+//! Peephole
+//!
+//! `peephole` replaces short sequences of instructions which are common in hot code with single superinstructions
+//! doing the same, such as `OpGetLocal 0`, `OpGetLocal 1`, `OpAdd` with `OpGetLocalGetLocalAdd 0 1`, so that the
+//! virtual machine dispatches fewer instructions in tight arithmetic and recursive calls.
+//!
+//! A sequence is only fused when nothing jumps into the middle of it and no statement starts there. Jumps, and the
+//! offsets statements start at, are then moved to where their instructions ended up.
+//!
+//! Before that, jumps landing on an unconditional jump, as those out of nested conditionals do, are made to jump
+//! straight to where that one leads.
+use crate::code::{read_operands, Instructions, OpCode};
+use crate::lexer::Position;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+
+// An instruction with its operands read, at `offset` among the instructions it was read from.
+struct Instruction {
+    offset: usize,
+    op: OpCode,
+    operands: Vec<u32>,
+}
+
+/// Fuses the sequences among `instructions` which a superinstruction can replace, and moves the offsets of
+/// `positions` to match.
+///
+/// Instructions which cannot be read, which the compiler never emits, are left alone.
+pub fn fuse(instructions: &mut Instructions, positions: &mut [(usize, Position)]) {
+    let read = match read(instructions) {
+        Some(read) => read,
+        None => return,
+    };
+    // The offsets which must still start an instruction afterwards.
+    let mut labels: HashSet<usize> = positions.iter().map(|(offset, _)| *offset).collect();
+    labels.extend(read.iter().filter_map(jump_target));
+
+    let mut fused = vec![];
+    // Where each instruction kept, or the first of each sequence fused, moved to.
+    let mut moved = HashMap::new();
+    let mut idx = 0;
+    while idx < read.len() {
+        let (instruction, count) = match superinstruction(&read[idx..]) {
+            Some((instruction, count))
+                if read[idx + 1..idx + count]
+                    .iter()
+                    .all(|later| !labels.contains(&later.offset)) =>
+            {
+                (instruction, count)
+            }
+            _ => (
+                Instruction {
+                    offset: read[idx].offset,
+                    op: read[idx].op,
+                    operands: read[idx].operands.clone(),
+                },
+                1,
+            ),
+        };
+        moved.insert(instruction.offset, fused.len());
+        fused.push(instruction);
+        idx += count;
+    }
+    if fused.len() == read.len() {
+        return;
+    }
+
+    let mut offsets = HashMap::new();
+    let mut offset = 0;
+    for (idx, instruction) in fused.iter().enumerate() {
+        offsets.insert(idx, offset);
+        offset += 1 + instruction.op.definition().widths.iter().sum::<usize>();
+    }
+    offsets.insert(fused.len(), offset);
+    moved.insert(instructions.len(), fused.len());
+    let new_offset = |old: usize| offsets[&moved[&old]];
+
+    let mut rewritten = Vec::with_capacity(offset);
+    for mut instruction in fused {
+        if let Some(target) = jump_target(&instruction) {
+            instruction.operands[0] = new_offset(target) as u32;
+        }
+        rewritten.extend(make(instruction.op, &instruction.operands));
+    }
+    for (offset, _) in positions.iter_mut() {
+        *offset = new_offset(*offset);
+    }
+    *instructions = rewritten;
+}
+
+/// Makes each jump among `instructions` which lands on an unconditional jump land where that one leads instead,
+/// following chains of them.
+///
+/// Instructions which cannot be read, which the compiler never emits, are left alone.
+pub fn thread_jumps(instructions: &mut Instructions) {
+    let read = match read(instructions) {
+        Some(read) => read,
+        None => return,
+    };
+    let jumps: HashMap<usize, usize> = read
+        .iter()
+        .filter(|instruction| instruction.op == OpCode::Jump)
+        .map(|instruction| (instruction.offset, instruction.operands[0] as usize))
+        .collect();
+    for instruction in &read {
+        let mut target = match jump_target(instruction) {
+            Some(target) => target,
+            None => continue,
+        };
+        // A chain of jumps is at most as long as there are jumps, unless it loops.
+        for _ in 0..jumps.len() {
+            match jumps.get(&target) {
+                Some(next) if *next != target => target = *next,
+                _ => break,
+            }
+        }
+        let operand = instruction.offset + 1;
+        instructions[operand..operand + 2].copy_from_slice(&(target as u16).to_be_bytes());
+    }
+}
+
+// Returns the superinstruction which can replace the instructions `window` starts with, and how many it replaces.
+fn superinstruction(window: &[Instruction]) -> Option<(Instruction, usize)> {
+    let first = window.first()?;
+    let (op, operands, count) = match window {
+        [a, b, c, ..]
+            if a.op == OpCode::GetLocal && b.op == OpCode::GetLocal && c.op == OpCode::Add =>
+        {
+            (
+                OpCode::GetLocalGetLocalAdd,
+                vec![a.operands[0], b.operands[0]],
+                3,
+            )
+        }
+        [a, b, ..] if a.op == OpCode::Constant && b.op == OpCode::Call => {
+            (OpCode::ConstantCall, vec![a.operands[0], b.operands[0]], 2)
+        }
+        [a, b, ..] if a.op == OpCode::GetGlobal && b.op == OpCode::Call => {
+            (OpCode::GetGlobalCall, vec![a.operands[0], b.operands[0]], 2)
+        }
+        _ => return None,
+    };
+    Some((
+        Instruction {
+            offset: first.offset,
+            op,
+            operands,
+        },
+        count,
+    ))
+}
+
+// Returns the offset `instruction` jumps to, if it is a jump.
+fn jump_target(instruction: &Instruction) -> Option<usize> {
+    match instruction.op {
+        OpCode::Jump | OpCode::JumpNotTruthy | OpCode::JumpIfPassed => {
+            Some(instruction.operands[0] as usize)
+        }
+        _ => None,
+    }
+}
+
+// Returns each of `instructions` with its operands read, or nothing if any cannot be read.
+fn read(instructions: &Instructions) -> Option<Vec<Instruction>> {
+    let mut read = vec![];
+    let mut offset = 0;
+    while offset < instructions.len() {
+        let op = OpCode::try_from(instructions[offset]).ok()?;
+        let def = op.definition();
+        if offset + 1 + def.widths.iter().sum::<usize>() > instructions.len() {
+            return None;
+        }
+        let (operands, width) = read_operands(&def, &instructions[offset + 1..]);
+        read.push(Instruction {
+            offset,
+            op,
+            operands,
+        });
+        offset += 1 + width;
+    }
+    Some(read)
+}
+
+fn make(op: OpCode, operands: &[u32]) -> Instructions {
+    let mut instruction = vec![op.into()];
+    for (operand, width) in operands.iter().zip(op.definition().widths) {
+        match width {
+            4 => instruction.extend(operand.to_be_bytes()),
+            2 => instruction.extend((*operand as u16).to_be_bytes()),
+            _ => instruction.push(*operand as u8),
+        }
+    }
+    instruction
+}
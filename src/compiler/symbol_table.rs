@@ -21,11 +21,13 @@ pub enum SymbolError {
     NotFound,
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 struct SymbolStore {
     store: HashMap<String, Symbol>,
     pub num_definitions: u16,
     pub free_symbols: Vec<Symbol>,
+    // The name of each local defined in the scope, by index, and whether it has been used since.
+    locals: Vec<(String, bool)>,
 }
 
 impl SymbolStore {
@@ -67,6 +69,9 @@ impl SymbolStore {
                 self.num_definitions - 1
             }
         };
+        if scope == SymbolScope::Local {
+            self.locals.push((name.to_owned(), false));
+        }
 
         self.store.insert(
             name.clone(),
@@ -87,7 +92,7 @@ impl SymbolStore {
     }
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct SymbolTable {
     stores: Vec<SymbolStore>,
     store_index: usize,
@@ -126,6 +131,18 @@ impl SymbolTable {
         &self.stores[self.store_index - 1].free_symbols
     }
 
+    /// Returns the symbols defined as globals, ordered by name.
+    pub fn global_symbols(&self) -> Vec<Symbol> {
+        let mut symbols: Vec<Symbol> = self.stores[0]
+            .store
+            .values()
+            .filter(|symbol| symbol.scope == SymbolScope::Global)
+            .cloned()
+            .collect();
+        symbols.sort_by(|a, b| a.name.cmp(&b.name));
+        symbols
+    }
+
     pub fn enter_scope(&mut self) {
         self.stores.push(SymbolStore::new());
         self.store_index += 1;
@@ -145,10 +162,36 @@ impl SymbolTable {
         self.stores[self.store_index - 1].define_with_scope(name, scope, None)
     }
 
+    /// Returns the locals of the current scope which have been defined but not used since, in the order they were
+    /// defined.
+    pub fn unused_locals(&self) -> Vec<Symbol> {
+        let locals = self.stores[self.store_index - 1].locals.iter().enumerate();
+        locals
+            .filter(|(_, (_, used))| !used)
+            .map(|(idx, (name, _))| Symbol {
+                name: name.clone(),
+                scope: SymbolScope::Local,
+                index: idx as u16,
+            })
+            .collect()
+    }
+
     pub fn resolve(&mut self, name: &String) -> Result<Symbol, SymbolError> {
+        self.resolve_marking(name, true)
+    }
+
+    /// Resolves `name` like `resolve`, for a variable being assigned to, which does not count as using it.
+    pub fn resolve_assigned(&mut self, name: &String) -> Result<Symbol, SymbolError> {
+        self.resolve_marking(name, false)
+    }
+
+    fn resolve_marking(&mut self, name: &String, used: bool) -> Result<Symbol, SymbolError> {
         let current_index = self.store_index - 1;
         match self.resolve_with_index(name, current_index) {
             Ok((sym, index)) => {
+                if used && sym.scope == SymbolScope::Local {
+                    self.stores[index].locals[sym.index as usize].1 = true;
+                }
                 if index == current_index
                     || sym.scope == SymbolScope::Global
                     || sym.scope == SymbolScope::BuiltIn
@@ -345,4 +388,35 @@ mod tests {
         let out = tbl.resolve(&String::from("does_not_exist"));
         assert!(out.is_err());
     }
+
+    #[test]
+    fn resolve_nested_free_test() {
+        let mut tbl = SymbolTable::new();
+        tbl.enter_scope();
+        tbl.define(&String::from("a"));
+        tbl.enter_scope();
+        tbl.define(&String::from("b"));
+        tbl.enter_scope();
+        tbl.enter_scope();
+
+        let free = |name: &str, index| Symbol {
+            name: name.to_string(),
+            scope: SymbolScope::Free,
+            index,
+        };
+        let local = |name: &str| Symbol {
+            name: name.to_string(),
+            scope: SymbolScope::Local,
+            index: 0,
+        };
+        // Each scope between the one defining a variable and the one using it captures it, from the one outside.
+        assert_eq!(tbl.resolve(&String::from("b")).unwrap(), free("b", 0));
+        assert_eq!(tbl.resolve(&String::from("a")).unwrap(), free("a", 1));
+        assert_eq!(tbl.free_symbols(), &vec![free("b", 0), free("a", 1)]);
+        tbl.leave_scope();
+        assert_eq!(tbl.free_symbols(), &vec![local("b"), free("a", 0)]);
+        tbl.leave_scope();
+        assert_eq!(tbl.free_symbols(), &vec![local("a")]);
+        assert_eq!(tbl.resolve(&String::from("a")).unwrap(), free("a", 0));
+    }
 }
@@ -0,0 +1,495 @@
+//! Engine
+//!
+//! `engine` runs Monkey code handed to it a piece at a time, each piece seeing what the ones before it defined, as
+//! the REPL does with the lines typed into it. It is how programs embedding Monkey drive it: by evaluating source,
+//! defining values for the code to use, and reading back what the code defined. Compiled code can also be stepped
+//! through with a debugger. A `SyncEngine` does the same from any thread, and with the `serde` feature the values it
+//! hands over can be serialized.
+#[cfg(test)]
+mod engine_test;
+#[cfg(feature = "serde")]
+mod serialize;
+mod sync;
+
+use crate::ast::{walk_expression, Expression, Program, Visitor};
+use crate::code::Constants;
+use crate::compiler;
+use crate::error::Error;
+use crate::evaluator;
+use crate::lexer::{self, Position};
+use crate::object::{stdin_reader, stdout_writer, Environment, Object, SharedEnvironment};
+use crate::parser;
+use crate::prelude;
+use crate::vm;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+pub use self::sync::{SyncEngine, SyncError, SyncValue};
+pub use crate::linter::Warning;
+pub use crate::vm::{Breakpoint, Debugger, Limits, Stop};
+
+/// Runs Monkey code, interpreted or compiled, keeping the values it defines from one call of `eval` to the next.
+pub struct Engine {
+    backend: Backend,
+    prelude: bool,
+    // Raised, say by Ctrl-C, to stop the code being run without losing what has been defined.
+    interrupt: Option<Arc<AtomicBool>>,
+    limits: Limits,
+    // The number of lines evaluated so far. Pieces are numbered as continuing what came before, so that an error in
+    // code evaluated earlier, such as the body of a function, is shown from the piece it is in.
+    lines: usize,
+    // The pieces evaluated so far which define functions, by the line they start on. Only code in functions can
+    // fail after the piece it is in, so the others need not be kept.
+    pieces: BTreeMap<usize, String>,
+    // What the compiler warned of in the last piece evaluated.
+    warnings: Vec<Warning>,
+    sandboxed: bool,
+    args: Vec<String>,
+    // Where built-in functions read and write, lent to the interpreter or machine while a piece runs.
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+}
+
+/// How code is run, as the REPL, the runners of programs in `script` and engines all take it.
+pub struct Settings {
+    /// Whether code is compiled to bytecode and run on the virtual machine, rather than interpreted.
+    pub compile: bool,
+    /// Whether the functions of the Monkey prelude are defined before any code is run.
+    pub prelude: bool,
+    /// The limits code runs within, as `Engine::set_limits` describes.
+    pub limits: Limits,
+    /// Whether built-in functions that reach outside the program, such as `env`, are disallowed.
+    pub sandboxed: bool,
+    /// The arguments returned by the `args` built-in function.
+    pub args: Vec<String>,
+    /// Where the `input` built-in function reads from.
+    pub input: Box<dyn BufRead + Send>,
+    /// Where `puts`, `print` and the prompt of `input` write to.
+    pub output: Box<dyn Write + Send>,
+}
+
+impl Default for Settings {
+    /// Returns the settings the command line starts from: interpreted, after the prelude, with standard input and
+    /// output.
+    fn default() -> Self {
+        Settings {
+            compile: false,
+            prelude: true,
+            limits: Limits::default(),
+            sandboxed: false,
+            args: vec![],
+            input: stdin_reader(),
+            output: stdout_writer(),
+        }
+    }
+}
+
+/// The state kept between pieces of code by either way of running them.
+enum Backend {
+    Interpreted {
+        // Kept across pieces so that state such as the random seed carries over.
+        interpreter: evaluator::Interpreter,
+        env: SharedEnvironment,
+    },
+    Compiled {
+        symbol_table: Rc<RefCell<compiler::SymbolTable>>,
+        constants: Rc<RefCell<Constants>>,
+        globals: Rc<RefCell<Vec<Rc<Object>>>>,
+        // Made for the first piece run, and kept across pieces so that state such as the random seed carries over.
+        vm: Option<Box<vm::Vm>>,
+    },
+}
+
+impl Backend {
+    fn new(compile: bool, prelude: bool) -> Self {
+        if compile {
+            let symbol_table = Rc::new(RefCell::new(compiler::SymbolTable::new_with_builtins()));
+            let constants = Rc::default();
+            let globals = Rc::new(RefCell::new(vec![]));
+            if prelude {
+                prelude::load_compiled(&symbol_table, &constants, &globals);
+            }
+            Backend::Compiled {
+                symbol_table,
+                constants,
+                globals,
+                vm: None,
+            }
+        } else {
+            let env = Rc::new(RefCell::new(Environment::new()));
+            let mut interpreter = evaluator::Interpreter::new();
+            if prelude {
+                prelude::load_interpreted(&mut interpreter, Rc::clone(&env));
+            }
+            Backend::Interpreted { interpreter, env }
+        }
+    }
+}
+
+impl Engine {
+    /// Returns an engine running code as `settings` say.
+    pub fn new(settings: Settings) -> Self {
+        Engine {
+            backend: Backend::new(settings.compile, settings.prelude),
+            prelude: settings.prelude,
+            interrupt: None,
+            limits: settings.limits,
+            lines: 0,
+            pieces: BTreeMap::new(),
+            warnings: vec![],
+            sandboxed: settings.sandboxed,
+            args: settings.args,
+            input: settings.input,
+            output: settings.output,
+        }
+    }
+
+    /// Sets the limits code runs within, as the size of the machine when compiled; each call of `eval` gets the fuel
+    /// and time afresh.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+        self.forget_vm();
+    }
+
+    /// Makes code stop with an error once `flag` is raised, lowering it again. A flag raised while nothing is being
+    /// run is ignored.
+    pub fn set_interrupt_flag(&mut self, flag: Arc<AtomicBool>) {
+        self.interrupt = Some(flag);
+        self.forget_vm();
+    }
+
+    /// Makes the `input` built-in function read from `input` instead of standard input.
+    pub fn set_input(&mut self, input: Box<dyn BufRead + Send>) {
+        self.input = input;
+    }
+
+    /// Makes `puts`, `print` and `input` write to `output` instead of standard output.
+    pub fn set_output(&mut self, output: Box<dyn Write + Send>) {
+        self.output = output;
+    }
+
+    /// Makes `puts`, `print` and `input` write to a buffer instead of standard output, and returns a handle to read
+    /// it through.
+    pub fn capture_output(&mut self) -> CapturedOutput {
+        let captured = CapturedOutput::default();
+        self.set_output(Box::new(captured.clone()));
+        captured
+    }
+
+    /// Returns whether code is compiled rather than interpreted.
+    pub fn is_compiled(&self) -> bool {
+        matches!(self.backend, Backend::Compiled { .. })
+    }
+
+    /// Runs `source` and returns the value it evaluates to, or the error which stopped it.
+    ///
+    /// What `source` defines is kept for later calls, even if it fails after defining it.
+    pub fn eval(&mut self, source: &str) -> Result<Object, Error> {
+        let (mut program, first_line) = self.parse(source)?;
+        match &mut self.backend {
+            Backend::Interpreted { interpreter, env } => {
+                evaluator::optimize(&mut program);
+                if let Some(interrupt) = &self.interrupt {
+                    interpreter.set_interrupt_flag(Arc::clone(interrupt));
+                }
+                interpreter.set_sandboxed(self.sandboxed);
+                interpreter.set_args(self.args.clone());
+                // Each piece gets the fuel and time afresh, as the machine gives each when compiled.
+                interpreter.set_max_depth(self.limits.max_frames);
+                if let Some(fuel) = self.limits.fuel {
+                    interpreter.set_fuel(fuel);
+                }
+                if let Some(timeout) = self.limits.timeout {
+                    interpreter.set_timeout(timeout);
+                }
+                interpreter.swap_io(&mut self.input, &mut self.output);
+                let result = interpreter.eval(&program, Rc::clone(env));
+                interpreter.swap_io(&mut self.input, &mut self.output);
+                let position = interpreter.error_position();
+                result
+                    .map_err(|error| self.locate(Error::from(error), source, first_line, position))
+            }
+            Backend::Compiled { .. } => {
+                let mut vm = self.load(&program)?;
+                let result = vm.run().map_err(|error| {
+                    let error = Error::from(error);
+                    self.locate(error, source, first_line, vm.error_position())
+                        .traced(&vm)
+                });
+                self.unload(vm);
+                result
+            }
+        }
+    }
+
+    /// Runs `source` compiled, a little at a time through the debugger given to `debug`, and returns what `debug`
+    /// does.
+    ///
+    /// The debugger starts before the first instruction of `source`, which runs only as far as `debug` makes it.
+    /// Lines are numbered as in errors, continuing from the code evaluated before. What `source` defines is kept for
+    /// later calls, as with `eval`. Interpreted code cannot be debugged.
+    pub fn debug<T>(
+        &mut self,
+        source: &str,
+        debug: impl FnOnce(&mut Debugger) -> T,
+    ) -> Result<T, Error> {
+        if !self.is_compiled() {
+            return Err(Error::Usage(String::from(
+                "Only compiled code can be debugged",
+            )));
+        }
+        let (program, _) = self.parse(source)?;
+        let mut vm = self.load(&program)?;
+        let result = debug(&mut vm.debugger());
+        self.unload(vm);
+        Ok(result)
+    }
+
+    /// Returns what the compiler warned of in the code last given to `eval`, such as variables which are never used.
+    ///
+    /// Interpreted code is not compiled, and so never warned of.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// Defines `name` as a global holding `value`, for code run afterwards to use.
+    pub fn define(&mut self, name: &str, value: Object) {
+        match &mut self.backend {
+            Backend::Interpreted { env, .. } => env.borrow_mut().set(name, Rc::new(value)),
+            Backend::Compiled {
+                symbol_table,
+                globals,
+                ..
+            } => {
+                let idx = symbol_table.borrow_mut().define(&name.to_string()).index as usize;
+                let mut globals = globals.borrow_mut();
+                if globals.len() <= idx {
+                    globals.resize(idx + 1, Rc::new(Object::Null));
+                }
+                globals[idx] = Rc::new(value);
+            }
+        }
+    }
+
+    /// Returns the names defined at the top level with their current values, ordered by name.
+    pub fn bindings(&self) -> Vec<(String, Object)> {
+        match &self.backend {
+            Backend::Interpreted { env, .. } => env.borrow().bindings(),
+            Backend::Compiled {
+                symbol_table,
+                globals,
+                ..
+            } => {
+                let globals = globals.borrow();
+                symbol_table
+                    .borrow()
+                    .global_symbols()
+                    .into_iter()
+                    .map(|symbol| {
+                        let obj = globals
+                            .get(symbol.index as usize)
+                            .map_or(Object::Null, |obj| (**obj).clone());
+                        (symbol.name, obj)
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Forgets everything defined so far, leaving only the prelude if there is one.
+    pub fn reset(&mut self) {
+        self.backend = Backend::new(self.is_compiled(), self.prelude);
+        self.pieces.clear();
+    }
+
+    /// Makes code compiled or interpreted from now on, according to `compile`, defining the values defined so far
+    /// anew.
+    ///
+    /// Functions are compiled to bytecode or evaluated directly depending on how code is run, so values holding them
+    /// cannot be carried over. Returns the names of such values, other than the functions of the prelude.
+    pub fn migrate(&mut self, compile: bool) -> Vec<String> {
+        let bindings = self.bindings();
+        self.backend = Backend::new(compile, self.prelude);
+        // No function is carried over, so no piece can be failed in again.
+        self.pieces.clear();
+        let mut left_behind = vec![];
+        for (name, obj) in bindings {
+            if !holds_function(&obj) {
+                self.define(&name, obj);
+            } else if !self.prelude || !prelude::defines(&name) {
+                left_behind.push(name);
+            }
+        }
+        left_behind
+    }
+
+    /// Returns copies of the symbol table and constants code is compiled with, or nothing if it is interpreted.
+    pub(crate) fn compiler_state(&self) -> Option<(compiler::SymbolTable, Constants)> {
+        match &self.backend {
+            Backend::Compiled {
+                symbol_table,
+                constants,
+                ..
+            } => Some((symbol_table.borrow().clone(), constants.borrow().clone())),
+            Backend::Interpreted { .. } => None,
+        }
+    }
+
+    // Parses the next piece of code, keeping it if it defines functions, and returns it with the line it starts on.
+    fn parse(&mut self, source: &str) -> Result<(Program, usize), Error> {
+        self.warnings.clear();
+        let first_line = self.lines + 1;
+        // Even an empty piece takes a line, as a line entered into the REPL does.
+        self.lines += source.lines().count().max(1);
+        let mut lexer = lexer::Lexer::new(source);
+        lexer.set_first_line(first_line);
+        let mut p = parser::Parser::new(lexer);
+        let program = p.parse_program().map_err(|error| {
+            Error::from(error).at_part(None, source, first_line, p.error_position())
+        })?;
+        if defines_function(&program) {
+            self.pieces.insert(first_line, source.to_string());
+        }
+        if let Some(interrupt) = &self.interrupt {
+            interrupt.store(false, Ordering::Relaxed);
+        }
+        Ok((program, first_line))
+    }
+
+    // Places an error met running `source`, which starts on `first_line`, at its position in whichever piece that is.
+    fn locate(
+        &self,
+        error: Error,
+        source: &str,
+        first_line: usize,
+        position: Option<Position>,
+    ) -> Error {
+        let earlier = position
+            .filter(|position| position.line < first_line)
+            .and_then(|position| self.pieces.range(..=position.line).next_back());
+        match earlier {
+            Some((&line, piece)) => error.at_part(None, piece, line, position),
+            None => error.at_part(None, source, first_line, position),
+        }
+    }
+
+    // Compiles `program` and returns the machine loaded with it, lent the engine's input and output until `unload`.
+    // The machine is taken from the engine meanwhile, and made as the settings say if it has not been yet.
+    fn load(&mut self, program: &Program) -> Result<Box<vm::Vm>, Error> {
+        let (symbol_table, constants, globals, vm) = match &mut self.backend {
+            Backend::Compiled {
+                symbol_table,
+                constants,
+                globals,
+                vm,
+            } => (symbol_table, constants, globals, vm),
+            Backend::Interpreted { .. } => unreachable!("interpreted code is not loaded"),
+        };
+        let mut compiler =
+            compiler::Compiler::new_with_state(symbol_table.clone(), constants.clone());
+        let bytecode = compiler.compile(program)?;
+        self.warnings = compiler.warnings().to_vec();
+        let mut vm = match vm.take() {
+            Some(mut vm) => {
+                vm.load(&bytecode);
+                vm
+            }
+            None => {
+                let mut new = vm::Vm::new_with_limits(&bytecode, globals.clone(), self.limits);
+                new.set_symbol_table(symbol_table.clone());
+                new.set_sandboxed(self.sandboxed);
+                new.set_args(self.args.clone());
+                if let Some(interrupt) = &self.interrupt {
+                    new.set_interrupt_flag(Arc::clone(interrupt));
+                }
+                Box::new(new)
+            }
+        };
+        vm.swap_io(&mut self.input, &mut self.output);
+        Ok(vm)
+    }
+
+    // Takes back the machine and the input and output lent by `load`.
+    fn unload(&mut self, mut vm: Box<vm::Vm>) {
+        vm.swap_io(&mut self.input, &mut self.output);
+        // The machine lets go of the constants, so that the next piece can add to them without copying.
+        vm.reset();
+        if let Backend::Compiled { vm: slot, .. } = &mut self.backend {
+            *slot = Some(vm);
+        }
+    }
+
+    // Drops the machine compiled code has been running on, so that the next is made with the current settings.
+    fn forget_vm(&mut self) {
+        if let Backend::Compiled { vm, .. } = &mut self.backend {
+            *vm = None;
+        }
+    }
+}
+
+/// Returns whether `obj` is or contains a function written in Monkey.
+pub(crate) fn holds_function(obj: &Object) -> bool {
+    match obj {
+        Object::Function(..) | Object::CompiledFunction(_) | Object::Closure(_) => true,
+        Object::Array(elements) => elements.iter().any(|element| holds_function(element)),
+        Object::Hash(items) => items.values().any(|value| holds_function(value)),
+        _ => false,
+    }
+}
+
+// Returns whether `program` has a function literal anywhere within it.
+fn defines_function(program: &Program) -> bool {
+    struct Finder(bool);
+
+    impl Visitor for Finder {
+        fn visit_expression(&mut self, expr: &Expression) {
+            match expr {
+                Expression::FunctionLiteral(..) => self.0 = true,
+                other => walk_expression(self, other),
+            }
+        }
+    }
+
+    let mut finder = Finder(false);
+    finder.visit_program(program);
+    finder.0
+}
+
+/// A writer keeping what is written to it, for it to be read once it has been handed to an engine, from any thread.
+#[derive(Clone, Default)]
+pub struct CapturedOutput(Arc<Mutex<Vec<u8>>>);
+
+impl CapturedOutput {
+    /// Returns what has been written so far, with anything which is not UTF-8 replaced.
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.buffer()).to_string()
+    }
+
+    /// Returns what has been written so far, as `text` does, and forgets it.
+    pub fn take(&self) -> String {
+        let mut buffer = self.buffer();
+        let text = String::from_utf8_lossy(&buffer).to_string();
+        buffer.clear();
+        text
+    }
+
+    // Nothing panics while writing to the buffer, so it is never left half written.
+    fn buffer(&self) -> MutexGuard<'_, Vec<u8>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Write for CapturedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,170 @@
+use super::*;
+use std::thread;
+
+fn engine(compile: bool, prelude: bool) -> Engine {
+    Engine::new(Settings {
+        compile,
+        prelude,
+        ..Settings::default()
+    })
+}
+
+fn engines() -> Vec<Engine> {
+    vec![engine(false, true), engine(true, true)]
+}
+
+#[test]
+fn eval_test() {
+    for (mut engine, caret) in engines().into_iter().zip(vec![24, 22]) {
+        assert!(engine.eval("let double = fn(x) { x * 2 };").is_ok());
+        match engine.eval("map([1, 2], double)") {
+            Ok(value) => assert_eq!(value.to_string(), "[2, 4]"),
+            Err(error) => panic!("Got error {}!", error),
+        }
+        // An error in a function is shown in the piece of code defining it.
+        // The interpreter places it at the operator which failed, the virtual machine at the statement holding it.
+        let error = engine.eval("let y = 1;\ndouble(true)").unwrap_err();
+        let excerpt = format!("\nlet double = fn(x) {{ x * 2 }};\n{:>1$}", "^", caret);
+        assert!(error.to_string().contains(&excerpt), "{}", error);
+        // What was defined before the error is kept.
+        assert_eq!(engine.eval("y").unwrap().to_string(), "1");
+    }
+}
+
+#[test]
+fn define_test() {
+    for mut engine in engines() {
+        engine.define("greeting", Object::Str(String::from("hello")));
+        match engine.eval("let loud = greeting + \"!\"; loud") {
+            Ok(value) => assert_eq!(value.to_string(), "\"hello!\""),
+            Err(error) => panic!("Got error {}!", error),
+        }
+        let bindings: Vec<String> = engine
+            .bindings()
+            .iter()
+            .filter(|(name, _)| !prelude::defines(name))
+            .map(|(name, value)| format!("{} = {}", name, value))
+            .collect();
+        assert_eq!(bindings, vec!["greeting = \"hello\"", "loud = \"hello!\""]);
+        engine.reset();
+        assert!(engine.eval("greeting").is_err());
+    }
+}
+
+#[test]
+fn warnings_test() {
+    let mut engine = engine(true, false);
+    assert!(engine.eval("let f = fn(x) { 1 }; f(2)").is_ok());
+    assert_eq!(engine.warnings().len(), 1);
+    assert!(engine.eval("f(3)").is_ok());
+    assert!(engine.warnings().is_empty());
+}
+
+#[test]
+fn limits_test() {
+    for mut engine in engines() {
+        engine.set_limits(Limits {
+            fuel: Some(100),
+            ..Limits::default()
+        });
+        assert!(engine.eval("let f = fn(n) { f(n + 1) }; f(0)").is_err());
+        // Each piece of code gets the fuel afresh.
+        assert!(engine.eval("1 + 1").is_ok());
+    }
+}
+
+#[test]
+fn io_test() {
+    for mut engine in engines() {
+        engine.set_input(Box::new(io::Cursor::new("Ada\nBob\n")));
+        let output = engine.capture_output();
+        match engine.eval("let name = input(\"Name? \"); puts(\"Hi, \" + name); name") {
+            Ok(value) => assert_eq!(value.to_string(), "\"Ada\""),
+            Err(error) => panic!("Got error {}!", error),
+        }
+        assert_eq!(output.take(), "Name? Hi, Ada\n");
+        // Input and output are kept when the machine running compiled code is made anew.
+        engine.set_limits(Limits::default());
+        assert!(engine.eval("print(input(), 1)").is_ok());
+        assert_eq!(output.text(), "Bob 1");
+    }
+}
+
+#[test]
+fn held_values_test() {
+    for mut engine in engines() {
+        let counter = engine
+            .eval("let mk = fn() { let x = 5; fn() { x } }; mk()")
+            .unwrap();
+        // Enough captured variables for the garbage collector to run while the closure is held only here.
+        let cycles =
+            "let make = fn() { let f = 0; f = fn() { f }; 1 }; map(range(2000), fn(i) { make() });";
+        assert!(engine.eval(cycles).is_ok());
+        engine.define("c", counter);
+        assert_eq!(engine.eval("c()").unwrap().to_string(), "5");
+    }
+}
+
+#[test]
+fn deep_recursion_test() {
+    // Deep recursion stops at the limit on calls rather than overflowing the stack, however small it is.
+    let handle = thread::Builder::new()
+        .stack_size(2 * 1024 * 1024)
+        .spawn(|| {
+            let mut engine = engine(false, false);
+            let countdown = "let f = fn(n) { if (n == 0) { 0 } else { f(n - 1) } };";
+            assert!(engine.eval(countdown).is_ok());
+            assert_eq!(engine.eval("f(1000)").unwrap().to_string(), "0");
+            assert!(engine.eval("f(2000)").is_err());
+        })
+        .unwrap();
+    handle.join().unwrap();
+}
+
+#[test]
+fn pieces_test() {
+    for (mut engine, column) in engines().into_iter().zip(vec![5, 3]) {
+        assert!(engine.eval("let f = fn(x) {\n  x + true\n};").is_ok());
+        assert!(engine.eval("let a = 1;\nlet b = 2;").is_ok());
+        assert!(engine.eval("").is_ok());
+        // Only the code defining a function is kept to show errors from.
+        assert_eq!((engine.lines, engine.pieces.len()), (6, 1));
+        let error = engine.eval("a + b;\nf(1)").unwrap_err();
+        assert_eq!(error.position(), Some(Position { line: 2, column }));
+        let excerpt = format!("\n  x + true\n{:>1$}", "^", column);
+        assert!(error.to_string().contains(&excerpt), "{}", error);
+        let error = engine.eval("let c = 3;\nc + true").unwrap_err();
+        let column = column - 2;
+        assert_eq!(error.position(), Some(Position { line: 10, column }));
+        let excerpt = format!("\nc + true\n{:>1$}", "^", column);
+        assert!(error.to_string().contains(&excerpt), "{}", error);
+    }
+}
+
+#[test]
+fn debug_test() {
+    let mut compiled = engine(true, false);
+    let output = compiled.capture_output();
+    assert!(compiled
+        .eval("let f = fn(x) {\n  let y = x * 2;\n  y + 1\n};")
+        .is_ok());
+    let locals = compiled
+        .debug("let a = f(3);\nputs(a);\na", |debugger| {
+            debugger.set_breakpoint(Breakpoint::Line(3));
+            assert!(matches!(debugger.resume(), Ok(Stop::Breakpoint)));
+            let locals = debugger.locals(0).unwrap();
+            let locals: Vec<String> = locals.iter().map(|obj| obj.to_string()).collect();
+            assert!(matches!(
+                debugger.resume(),
+                Ok(Stop::Finished(Object::Integer(7)))
+            ));
+            locals
+        })
+        .unwrap();
+    assert_eq!(locals, ["3", "6"]);
+    assert_eq!(output.text(), "7\n");
+    assert!(matches!(compiled.eval("a"), Ok(Object::Integer(7))));
+
+    let mut interpreted = engine(false, false);
+    assert!(interpreted.debug("1", |_| ()).is_err());
+}
@@ -0,0 +1,140 @@
+//! Serialize
+//!
+//! `serialize` lets `SyncValue`s be written to and read from any format serde supports, such as JSON. Values map to
+//! the data model as plainly as they can: null to unit, arrays to sequences and hashes to maps, so that
+//! `{"step": 2}` reads as the hash it looks like.
+use crate::engine::SyncValue;
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
+use std::convert::TryFrom;
+use std::fmt;
+
+impl Serialize for SyncValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            SyncValue::Null => serializer.serialize_unit(),
+            SyncValue::Integer(value) => serializer.serialize_i64(*value),
+            SyncValue::Boolean(value) => serializer.serialize_bool(*value),
+            SyncValue::Str(value) => serializer.serialize_str(value),
+            SyncValue::Char(value) => serializer.serialize_char(*value),
+            SyncValue::Array(elements) => {
+                let mut seq = serializer.serialize_seq(Some(elements.len()))?;
+                for element in elements {
+                    seq.serialize_element(element)?;
+                }
+                seq.end()
+            }
+            SyncValue::Hash(entries) => {
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for (key, value) in entries {
+                    map.serialize_entry(key, value)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for SyncValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(SyncValueVisitor)
+    }
+}
+
+struct SyncValueVisitor;
+
+impl<'de> Visitor<'de> for SyncValueVisitor {
+    type Value = SyncValue;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "null, an integer, a boolean, a string, an array or a map"
+        )
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<SyncValue, E> {
+        Ok(SyncValue::Null)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<SyncValue, E> {
+        Ok(SyncValue::Null)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<SyncValue, D::Error> {
+        SyncValue::deserialize(deserializer)
+    }
+
+    fn visit_bool<E: de::Error>(self, value: bool) -> Result<SyncValue, E> {
+        Ok(SyncValue::Boolean(value))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<SyncValue, E> {
+        Ok(SyncValue::Integer(value))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<SyncValue, E> {
+        // Monkey's integers are signed, so the largest unsigned ones do not fit.
+        i64::try_from(value)
+            .map(SyncValue::Integer)
+            .map_err(|_| E::invalid_value(de::Unexpected::Unsigned(value), &self))
+    }
+
+    fn visit_char<E: de::Error>(self, value: char) -> Result<SyncValue, E> {
+        Ok(SyncValue::Char(value))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<SyncValue, E> {
+        Ok(SyncValue::Str(value.to_string()))
+    }
+
+    fn visit_string<E: de::Error>(self, value: String) -> Result<SyncValue, E> {
+        Ok(SyncValue::Str(value))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<SyncValue, A::Error> {
+        let mut elements = vec![];
+        while let Some(element) = seq.next_element()? {
+            elements.push(element);
+        }
+        Ok(SyncValue::Array(elements))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<SyncValue, A::Error> {
+        let mut entries = vec![];
+        while let Some(entry) = map.next_entry()? {
+            entries.push(entry);
+        }
+        Ok(SyncValue::Hash(entries))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_test() {
+        let value = SyncValue::Array(vec![
+            SyncValue::Null,
+            SyncValue::Integer(-3),
+            SyncValue::Boolean(true),
+            SyncValue::Str(String::from("monkey")),
+            SyncValue::Hash(vec![
+                (SyncValue::Str(String::from("b")), SyncValue::Array(vec![])),
+                (SyncValue::Str(String::from("a")), SyncValue::Integer(1)),
+            ]),
+        ]);
+        let json = serde_json::to_string(&value).unwrap();
+        // Entries keep their order.
+        assert_eq!(json, r#"[null,-3,true,"monkey",{"b":[],"a":1}]"#);
+        assert_eq!(serde_json::from_str::<SyncValue>(&json).unwrap(), value);
+    }
+
+    #[test]
+    fn deserialize_errors_test() {
+        for json in ["1.5", "18446744073709551615"] {
+            assert!(serde_json::from_str::<SyncValue>(json).is_err());
+        }
+    }
+}
@@ -0,0 +1,340 @@
+//! Sync
+//!
+//! `sync` runs an engine on a thread of its own, so that Monkey code can be run from any thread. Objects share their
+//! parts through reference counts which are not thread safe, so none ever leaves the engine's thread: values are
+//! copied into and out of it as `SyncValue`s, and errors as the messages they show.
+use crate::engine::{Engine, Limits, Settings};
+use crate::evaluator::EvalError;
+use crate::object::{HashableObject, Object};
+use indexmap::IndexMap;
+use std::any::Any;
+use std::fmt;
+use std::io::{BufRead, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+use std::sync::atomic::AtomicBool;
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::thread;
+
+// The size of the stack of the engine's thread. Parsing, compiling and evaluating expressions recurse into the ones
+// they contain, so deeply nested source code needs more room than a thread has by default. The interpreter moves
+// calls it nests to stacks of their own.
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+// Work for the engine's thread to do, returning whether the engine may still be used.
+type Job = Box<dyn FnOnce(&mut Engine) -> bool + Send>;
+
+/// A value copied out of or into an engine, which unlike an object can be sent between threads.
+///
+/// Only data can be copied: functions stay within the engine which made them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyncValue {
+    Null,
+    Integer(i64),
+    Boolean(bool),
+    Str(String),
+    Char(char),
+    Array(Vec<SyncValue>),
+    /// The entries of a hash, in order.
+    Hash(Vec<(SyncValue, SyncValue)>),
+}
+
+impl fmt::Display for SyncValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SyncValue::Null => write!(f, "null"),
+            SyncValue::Integer(value) => write!(f, "{}", value),
+            SyncValue::Boolean(value) => write!(f, "{}", value),
+            SyncValue::Str(value) => write!(f, "\"{}\"", value),
+            SyncValue::Char(value) => write!(f, "'{}'", value),
+            SyncValue::Array(elements) => {
+                let elements: Vec<String> = elements.iter().map(|e| e.to_string()).collect();
+                write!(f, "[{}]", elements.join(", "))
+            }
+            SyncValue::Hash(entries) => {
+                let entries: Vec<String> = entries
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", key, value))
+                    .collect();
+                write!(f, "{{{}}}", entries.join(", "))
+            }
+        }
+    }
+}
+
+impl SyncValue {
+    // Returns a copy of `obj`, or the type of the part of it which cannot be copied.
+    fn from_object(obj: &Object) -> Result<Self, &'static str> {
+        Ok(match obj {
+            Object::Null => SyncValue::Null,
+            Object::Integer(value) => SyncValue::Integer(*value),
+            Object::Boolean(value) => SyncValue::Boolean(*value),
+            Object::Str(value) => SyncValue::Str(value.clone()),
+            Object::Char(value) => SyncValue::Char(*value),
+            Object::Array(elements) => SyncValue::Array(
+                elements
+                    .iter()
+                    .map(|element| SyncValue::from_object(element))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Object::Hash(items) => {
+                let mut entries = vec![];
+                for (key, value) in items {
+                    let key = match key {
+                        HashableObject::Integer(value) => SyncValue::Integer(*value),
+                        HashableObject::Boolean(value) => SyncValue::Boolean(*value),
+                        HashableObject::Str(value) => SyncValue::Str(value.clone()),
+                        HashableObject::Char(value) => SyncValue::Char(*value),
+                    };
+                    entries.push((key, SyncValue::from_object(value)?));
+                }
+                SyncValue::Hash(entries)
+            }
+            other => return Err(other.type_name()),
+        })
+    }
+
+    // Returns the object this is a copy of, or the error met making a hash with a key which cannot be hashed.
+    fn to_object(&self) -> Result<Object, EvalError> {
+        Ok(match self {
+            SyncValue::Null => Object::Null,
+            SyncValue::Integer(value) => Object::Integer(*value),
+            SyncValue::Boolean(value) => Object::Boolean(*value),
+            SyncValue::Str(value) => Object::Str(value.clone()),
+            SyncValue::Char(value) => Object::Char(*value),
+            SyncValue::Array(elements) => Object::Array(
+                elements
+                    .iter()
+                    .map(|element| element.to_object().map(Rc::new))
+                    .collect::<Result<_, _>>()?,
+            ),
+            SyncValue::Hash(entries) => {
+                let mut items = IndexMap::new();
+                for (key, value) in entries {
+                    items.insert(
+                        key.to_object()?.to_hashable_object()?,
+                        Rc::new(value.to_object()?),
+                    );
+                }
+                Object::Hash(items)
+            }
+        })
+    }
+}
+
+/// An error which stopped code run by a `SyncEngine`, as shown to users, with the exit status it leads to when
+/// running a program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncError {
+    pub message: String,
+    pub exit_code: i32,
+}
+
+impl SyncError {
+    // The error for a job during which the engine panicked, with what the panic carried if it is a message.
+    fn panicked(payload: &(dyn Any + Send)) -> Self {
+        let message = match payload.downcast_ref::<&str>() {
+            Some(message) => message.to_string(),
+            None => payload
+                .downcast_ref::<String>()
+                .cloned()
+                .unwrap_or_default(),
+        };
+        SyncError {
+            message: format!("The engine panicked: {}", message),
+            exit_code: crate::error::RUNTIME_EXIT_CODE,
+        }
+    }
+
+    // The error for every job after a panic.
+    fn stopped() -> Self {
+        SyncError {
+            message: String::from("The engine has stopped, after panicking"),
+            exit_code: crate::error::RUNTIME_EXIT_CODE,
+        }
+    }
+}
+
+impl fmt::Display for SyncError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Runs Monkey code like an `Engine` does, on a thread of its own, so that it can be used from any thread.
+///
+/// Calls from several threads take turns. Should the engine panic, the call it panicked in fails, and so does every
+/// call after it, as the engine may have been left part way through changing what it holds.
+pub struct SyncEngine {
+    jobs: Sender<Job>,
+}
+
+impl SyncEngine {
+    /// Starts an engine made by `Engine::new(settings)` on a thread of its own, which ends once this is dropped.
+    pub fn new(settings: Settings) -> Self {
+        let (jobs, queue) = mpsc::channel::<Job>();
+        thread::Builder::new()
+            .name(String::from("monkey-engine"))
+            .stack_size(STACK_SIZE)
+            .spawn(move || {
+                let mut engine = Engine::new(settings);
+                for job in queue {
+                    if !job(&mut engine) {
+                        break;
+                    }
+                }
+            })
+            .expect("could not start the engine's thread");
+        SyncEngine { jobs }
+    }
+
+    /// Runs `source` and returns a copy of the value it evaluates to, or the error which stopped it, as
+    /// `Engine::eval` does. A value holding a function cannot be copied, which is an error too.
+    pub fn eval(&self, source: &str) -> Result<SyncValue, SyncError> {
+        let source = source.to_string();
+        self.run(move |engine| match engine.eval(&source) {
+            Ok(value) => SyncValue::from_object(&value).map_err(|type_name| SyncError {
+                message: format!(
+                    "The value cannot leave the engine, as it holds a {}",
+                    type_name
+                ),
+                exit_code: crate::error::RUNTIME_EXIT_CODE,
+            }),
+            Err(error) => Err(SyncError {
+                message: error.to_string(),
+                exit_code: error.exit_code(),
+            }),
+        })?
+    }
+
+    /// Defines `name` as a global holding a copy of `value`, as `Engine::define` does, unless `value` is a hash with
+    /// a key which cannot be hashed.
+    pub fn define(&self, name: &str, value: SyncValue) -> Result<(), SyncError> {
+        let name = name.to_string();
+        self.run(move |engine| {
+            let value = value.to_object().map_err(|error| SyncError {
+                message: error.to_string(),
+                exit_code: crate::error::RUNTIME_EXIT_CODE,
+            })?;
+            engine.define(&name, value);
+            Ok(())
+        })?
+    }
+
+    /// Sets the limits code runs within, as `Engine::set_limits` does.
+    pub fn set_limits(&self, limits: Limits) -> Result<(), SyncError> {
+        self.run(move |engine| engine.set_limits(limits))
+    }
+
+    /// Makes the `input` built-in function read from `input` instead of standard input, as `Engine::set_input` does.
+    pub fn set_input(&self, input: Box<dyn BufRead + Send>) -> Result<(), SyncError> {
+        self.run(move |engine| engine.set_input(input))
+    }
+
+    /// Makes `puts`, `print` and `input` write to `output` instead of standard output, as `Engine::set_output` does.
+    pub fn set_output(&self, output: Box<dyn Write + Send>) -> Result<(), SyncError> {
+        self.run(move |engine| engine.set_output(output))
+    }
+
+    /// Makes code stop with an error once `flag` is raised, as `Engine::set_interrupt_flag` does. The flag can be
+    /// raised from any thread, to stop code another is waiting on.
+    pub fn set_interrupt_flag(&self, flag: Arc<AtomicBool>) -> Result<(), SyncError> {
+        self.run(move |engine| engine.set_interrupt_flag(flag))
+    }
+
+    // Runs `job` on the engine's thread and returns its result, once the jobs sent before it are done, or an error if
+    // the engine panicked, then or before.
+    fn run<T: Send + 'static>(
+        &self,
+        job: impl FnOnce(&mut Engine) -> T + Send + 'static,
+    ) -> Result<T, SyncError> {
+        let (reply, result) = mpsc::channel();
+        let job: Job = Box::new(move |engine| {
+            let outcome = panic::catch_unwind(AssertUnwindSafe(|| job(engine)));
+            let alive = outcome.is_ok();
+            // The caller only stops waiting if it panicked, in which case there is no one to tell.
+            let _ = reply.send(outcome.map_err(|payload| SyncError::panicked(&*payload)));
+            alive
+        });
+        if self.jobs.send(job).is_err() {
+            return Err(SyncError::stopped());
+        }
+        result.recv().unwrap_or_else(|_| Err(SyncError::stopped()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::CapturedOutput;
+
+    #[test]
+    fn sync_engine_test() {
+        let engine = Arc::new(SyncEngine::new(Settings {
+            compile: true,
+            ..Settings::default()
+        }));
+        assert!(engine
+            .define(
+                "config",
+                SyncValue::Hash(vec![(
+                    SyncValue::Str(String::from("step")),
+                    SyncValue::Integer(2)
+                )])
+            )
+            .is_ok());
+        let definitions =
+            "let count = 0; let step = fn() { count = count + config[\"step\"]; count }; count";
+        assert_eq!(engine.eval(definitions), Ok(SyncValue::Integer(0)));
+        // Each thread's calls run one after another on the engine's thread, so no step is lost.
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                let engine = Arc::clone(&engine);
+                thread::spawn(move || {
+                    for _ in 0..10 {
+                        engine.eval("step()").unwrap();
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        assert_eq!(engine.eval("count"), Ok(SyncValue::Integer(80)));
+        assert_eq!(
+            engine.eval("[1, {'a': true}]").unwrap().to_string(),
+            "[1, {'a': true}]"
+        );
+
+        let error = engine.eval("step").unwrap_err();
+        assert_eq!(
+            error.message,
+            "The value cannot leave the engine, as it holds a FUNCTION"
+        );
+        assert!(engine.eval("1 + true").is_err());
+        let unhashable = SyncValue::Hash(vec![(SyncValue::Array(vec![]), SyncValue::Null)]);
+        assert!(engine.define("bad", unhashable).is_err());
+    }
+
+    #[test]
+    fn captured_output_test() {
+        let engine = SyncEngine::new(Settings::default());
+        let output = CapturedOutput::default();
+        assert!(engine.set_output(Box::new(output.clone())).is_ok());
+        assert!(engine.eval("puts(1 + 2)").is_ok());
+        assert_eq!(output.take(), "3\n");
+        assert_eq!(output.text(), "");
+    }
+
+    #[test]
+    fn panic_test() {
+        let engine = SyncEngine::new(Settings::default());
+        assert!(engine.eval("let x = 1;").is_ok());
+        let error = engine.run(|_| panic!("at the disco")).unwrap_err();
+        assert_eq!(error.message, "The engine panicked: at the disco");
+        // The engine is not used again, but calls fail rather than panic.
+        assert_eq!(engine.eval("x"), Err(SyncError::stopped()));
+        assert!(engine.set_limits(Limits::default()).is_err());
+    }
+}
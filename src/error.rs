@@ -0,0 +1,293 @@
+//! Error
+//!
+//! `error` contains the error type returned by the entry points of the binary, which brings together the errors of
+//! each stage of running a Monkey program and says which exit status each should lead to.
+#[cfg(test)]
+mod error_test;
+
+use crate::code::SerializeError;
+use crate::color::{self, Style};
+use crate::compiler::CompileError;
+use crate::evaluator::EvalError;
+use crate::lexer::Position;
+use crate::parser::ParseError;
+use crate::vm::{json_string, Backtrace, Vm, VmError};
+use std::fmt;
+use std::io;
+
+/// The exit status after a runtime error, or when a check such as `lint` fails.
+pub const RUNTIME_EXIT_CODE: i32 = 1;
+/// The exit status after the command line itself was not understood.
+pub const USAGE_EXIT_CODE: i32 = 2;
+/// The exit status after a program could not be parsed.
+pub const PARSE_EXIT_CODE: i32 = 3;
+/// The exit status after a program could not be compiled.
+pub const COMPILE_EXIT_CODE: i32 = 4;
+/// The exit status after a file could not be read or written.
+pub const IO_EXIT_CODE: i32 = 5;
+
+/// Represents any error which stops a Monkey program, or a tool working on one, from completing.
+#[derive(Debug)]
+pub enum Error {
+    Usage(String),
+    // What was being attempted, such as reading a named file, and why it failed.
+    Io(String, io::Error),
+    Parse(ParseError),
+    Compile(CompileError),
+    Eval(EvalError),
+    Vm(VmError),
+    Serialize(SerializeError),
+    // One of the errors above, with the file it is in and where, when known, and the offending line of source with
+    // a caret underneath.
+    Located {
+        error: Box<Error>,
+        name: Option<String>,
+        position: Option<Position>,
+        excerpt: Option<String>,
+    },
+    // One of the errors above, met inside a function of a compiled program, and the calls which led to it.
+    Traced {
+        error: Box<Error>,
+        backtrace: Backtrace,
+    },
+}
+
+impl Error {
+    /// Returns the error, placed at `position` (when known) of `source`.
+    ///
+    /// The error is said to be in `name`, if given, such as the path of the file `source` was read from.
+    pub(crate) fn at(self, name: Option<&str>, source: &str, position: Option<Position>) -> Self {
+        self.at_part(name, source, 1, position)
+    }
+
+    /// Returns the error, placed at `position` (when known) of `source`, which is part of some longer code and
+    /// starts on its line `first_line`.
+    pub(crate) fn at_part(
+        self,
+        name: Option<&str>,
+        source: &str,
+        first_line: usize,
+        position: Option<Position>,
+    ) -> Self {
+        let excerpt = position.map(|position| {
+            let line = (position.line + 1).saturating_sub(first_line);
+            annotate(source, Position { line, ..position })
+        });
+        Error::Located {
+            error: Box::new(self),
+            name: name.map(String::from),
+            position,
+            excerpt,
+        }
+    }
+
+    /// Returns the error for `error`, which stopped `vm` running `source`, placed where it happened.
+    ///
+    /// A failure inside a function also shows the calls which led to it; one in the main program needs no more than
+    /// its place. The error is said to be in `name`, if given.
+    pub(crate) fn vm(error: VmError, vm: &Vm, name: Option<&str>, source: &str) -> Self {
+        Error::from(error)
+            .at(name, source, vm.error_position())
+            .traced(vm)
+    }
+
+    /// Returns the error, which stopped `vm`, along with the calls which led to it if it was met inside a function.
+    pub(crate) fn traced(self, vm: &Vm) -> Self {
+        match vm.backtrace() {
+            Some(backtrace) if backtrace.frames.len() > 1 => Error::Traced {
+                error: Box::new(self),
+                backtrace: backtrace.clone(),
+            },
+            _ => self,
+        }
+    }
+
+    /// Returns the kind of error, as a single lowercase word.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Usage(_) => "usage",
+            Error::Io(..) => "io",
+            Error::Parse(_) => "parse",
+            Error::Compile(_) => "compile",
+            Error::Eval(_) | Error::Vm(_) => "runtime",
+            Error::Serialize(_) => "bytecode",
+            Error::Located { error, .. } | Error::Traced { error, .. } => error.kind(),
+        }
+    }
+
+    /// Returns the error as a single-line JSON object, for tools to read.
+    ///
+    /// The object holds the `kind` and `message` of the error, and the `file`, `line`, `column` and offending
+    /// source line (`snippet`) it concerns, each of which is `null` when not known.
+    pub fn to_json(&self) -> String {
+        if let Error::Traced { error, .. } = self {
+            return error.to_json();
+        }
+        let (message, name, position, snippet) = match self {
+            Error::Located {
+                error,
+                name,
+                excerpt,
+                ..
+            } => (
+                error.message(),
+                name.as_deref(),
+                self.position(),
+                // The caret is left out, as the column says the same.
+                excerpt
+                    .as_deref()
+                    .and_then(|excerpt| excerpt.lines().next()),
+            ),
+            other => (other.message(), None, None, None),
+        };
+        let string_or_null = |s: Option<&str>| s.map_or(String::from("null"), json_string);
+        let number_or_null = |n: Option<usize>| n.map_or(String::from("null"), |n| n.to_string());
+        format!(
+            "{{\"kind\": {}, \"message\": {}, \"file\": {}, \"line\": {}, \"column\": {}, \"snippet\": {}}}",
+            json_string(self.kind()),
+            json_string(&message),
+            string_or_null(name),
+            number_or_null(position.map(|position| position.line)),
+            number_or_null(position.map(|position| position.column)),
+            string_or_null(snippet)
+        )
+    }
+
+    /// Returns the error as `Display` writes it, but colored for a terminal: the kind of error and the caret under
+    /// the offending source in red, and where the error is dimmed.
+    pub fn to_colored_string(&self) -> String {
+        self.render(true)
+    }
+
+    fn render(&self, color: bool) -> String {
+        if let Error::Traced { error, backtrace } = self {
+            return format!("{}\n{}", error.render(color), backtrace);
+        }
+        let paint = |text: &str, style| match color {
+            true => color::paint(text, style),
+            false => text.to_string(),
+        };
+        let message = self.message();
+        // The errors of each stage say which they are, as in `VmError: Stack overflow`.
+        let message = match message.find(": ") {
+            Some(idx) if message[..idx].ends_with("Error") => {
+                paint(&message[..=idx], Style::Error) + &message[idx + 1..]
+            }
+            _ => message,
+        };
+        let mut text = match self.location() {
+            Some(location) => paint(&(location + ":"), Style::Location) + " " + &message,
+            None => message,
+        };
+        if let Error::Located {
+            excerpt: Some(excerpt),
+            ..
+        } = self
+        {
+            let (line, caret) = excerpt.split_once('\n').unwrap_or((excerpt, ""));
+            text = format!("{}\n{}\n{}", text, line, paint(caret, Style::Error));
+        }
+        text
+    }
+
+    // Returns where in its source the error is, when known.
+    pub(crate) fn position(&self) -> Option<Position> {
+        match self {
+            Error::Located { position, .. } => *position,
+            Error::Traced { error, .. } => error.position(),
+            _ => None,
+        }
+    }
+
+    // Returns what went wrong, without saying where.
+    pub(crate) fn message(&self) -> String {
+        match self {
+            Error::Usage(usage) => usage.clone(),
+            Error::Io(attempt, error) => format!("{}: {}", attempt, error),
+            Error::Parse(error) => error.to_string(),
+            Error::Compile(error) => error.to_string(),
+            Error::Eval(error) => error.to_string(),
+            Error::Vm(error) => error.to_string(),
+            Error::Serialize(error) => error.to_string(),
+            Error::Located { error, .. } | Error::Traced { error, .. } => error.message(),
+        }
+    }
+
+    // Returns where the error is, as `name:line:column`, if known.
+    fn location(&self) -> Option<String> {
+        match self {
+            Error::Located {
+                name: Some(name),
+                position,
+                ..
+            } => match position {
+                Some(position) => Some(format!("{}:{}:{}", name, position.line, position.column)),
+                None => Some(name.clone()),
+            },
+            _ => None,
+        }
+    }
+
+    /// Returns the status the process should exit with because of this error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Usage(_) => USAGE_EXIT_CODE,
+            Error::Io(..) | Error::Serialize(_) => IO_EXIT_CODE,
+            Error::Parse(_) => PARSE_EXIT_CODE,
+            Error::Compile(_) => COMPILE_EXIT_CODE,
+            Error::Eval(_) | Error::Vm(_) => RUNTIME_EXIT_CODE,
+            Error::Located { error, .. } | Error::Traced { error, .. } => error.exit_code(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.render(false))
+    }
+}
+
+/// Returns the line of `source` at `position`, with a caret underneath the position.
+pub(crate) fn annotate(source: &str, position: Position) -> String {
+    let line = source
+        .lines()
+        .nth(position.line.saturating_sub(1))
+        .unwrap_or("");
+    // Tabs are kept so that the caret lines up with the line above it.
+    let indent: String = line
+        .chars()
+        .take(position.column.saturating_sub(1))
+        .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+        .collect();
+    format!("{}\n{}^", line.trim_end(), indent)
+}
+
+impl From<ParseError> for Error {
+    fn from(error: ParseError) -> Self {
+        Error::Parse(error)
+    }
+}
+
+impl From<CompileError> for Error {
+    fn from(error: CompileError) -> Self {
+        Error::Compile(error)
+    }
+}
+
+impl From<EvalError> for Error {
+    fn from(error: EvalError) -> Self {
+        Error::Eval(error)
+    }
+}
+
+impl From<VmError> for Error {
+    fn from(error: VmError) -> Self {
+        Error::Vm(error)
+    }
+}
+
+impl From<SerializeError> for Error {
+    fn from(error: SerializeError) -> Self {
+        Error::Serialize(error)
+    }
+}
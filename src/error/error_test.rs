@@ -0,0 +1,160 @@
+use super::*;
+
+use crate::compiler::Compiler;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+
+#[test]
+fn annotate_test() {
+    let tests = vec![
+        ("let = 1;", 1, 5, "let = 1;\n    ^"),
+        (
+            "let x = 1;\nlet y = (2 + 3;\n",
+            2,
+            15,
+            "let y = (2 + 3;\n              ^",
+        ),
+        ("\tlet = 1;", 1, 6, "\tlet = 1;\n\t    ^"),
+        ("1 +\n", 2, 1, "\n^"),
+        // Positions count from 1, but those read from a file might not.
+        ("1 +", 0, 0, "1 +\n^"),
+    ];
+    for (source, line, column, want) in tests {
+        assert_eq!(annotate(source, Position { line, column }), want);
+    }
+}
+
+#[test]
+fn parse_error_test() {
+    let source = "let x = 1;\nlet = 2;\n";
+    let mut p = Parser::new(Lexer::new(source));
+    let error = p.parse_program().unwrap_err();
+    let position = p.error_position();
+    let named = Error::from(error.clone()).at(Some("main.monkey"), source, position);
+    assert!(named.to_string().starts_with("main.monkey:2:5: "));
+    assert!(named.to_string().ends_with("\nlet = 2;\n    ^"));
+    let unnamed = Error::from(error.clone()).at(None, source, position);
+    assert_eq!(unnamed.to_string(), format!("{}\nlet = 2;\n    ^", error));
+}
+
+#[test]
+fn located_test() {
+    let source = "let f = fn() { f() };\nlet x = f();";
+    let error = Error::from(VmError::StackOverflow).at(
+        Some("main.monkey"),
+        source,
+        Some(Position { line: 2, column: 1 }),
+    );
+    assert_eq!(
+        error.to_string(),
+        "main.monkey:2:1: VmError: Stack overflow\nlet x = f();\n^"
+    );
+    assert_eq!(error.kind(), "runtime");
+    assert_eq!(error.exit_code(), RUNTIME_EXIT_CODE);
+    // Without a position there is no excerpt, but the file is still named.
+    let error = Error::from(VmError::StackOverflow).at(Some("main.monkey"), source, None);
+    assert_eq!(error.to_string(), "main.monkey: VmError: Stack overflow");
+}
+
+#[test]
+fn vm_test() {
+    let source = "let f = fn(x) {\n  x + true\n};\nf(1)";
+    let program = Parser::new(Lexer::new(source)).parse_program().unwrap();
+    let bytecode = Compiler::new().compile(&program).unwrap();
+    let mut vm = Vm::new(&bytecode);
+    let error = vm.run().unwrap_err();
+    let traced = Error::vm(error, &vm, Some("main.monkey"), source);
+    let text = traced.to_string();
+    assert!(text.starts_with("main.monkey:2:3: VmError: Unsupported operands for OpAdd: INTEGER and BOOLEAN\n  x + true\n  ^\n"));
+    assert!(text.contains("\nBacktrace (innermost call first):\n  0: constant 0 at 2:3, ip "));
+    assert_eq!(traced.exit_code(), RUNTIME_EXIT_CODE);
+    assert!(!traced.to_json().contains("Backtrace"));
+
+    // Failures in the main program are only placed.
+    let program = Parser::new(Lexer::new("1 + true")).parse_program().unwrap();
+    let mut vm = Vm::new(&Compiler::new().compile(&program).unwrap());
+    let error = vm.run().unwrap_err();
+    assert_eq!(
+        Error::vm(error, &vm, None, "1 + true").to_string(),
+        "VmError: Unsupported operands for OpAdd: INTEGER and BOOLEAN\n1 + true\n^"
+    );
+}
+
+#[test]
+fn to_colored_string_test() {
+    let source = "let = 1;";
+    let mut p = Parser::new(Lexer::new(source));
+    let error = p.parse_program().unwrap_err();
+    let parse = Error::from(error).at(Some("main.monkey"), source, p.error_position());
+    let colored = parse.to_colored_string();
+    assert!(colored.starts_with("\x1b[2mmain.monkey:1:5:\x1b[0m \x1b[1;31mParseError:\x1b[0m "));
+    assert!(colored.ends_with("\nlet = 1;\n\x1b[1;31m    ^\x1b[0m"));
+    // Other than the colors, the text is the same.
+    let plain = colored
+        .split('\x1b')
+        .enumerate()
+        .map(|(idx, part)| match idx {
+            0 => part,
+            _ => &part[part.find('m').unwrap() + 1..],
+        })
+        .collect::<String>();
+    assert_eq!(plain, parse.to_string());
+    let usage = Error::Usage(String::from("Usage: orangutan lint <file>"));
+    assert_eq!(usage.to_colored_string(), usage.to_string());
+}
+
+#[test]
+fn to_json_test() {
+    let source = "let x = 1;\nlet = \"2\";\n";
+    let mut p = Parser::new(Lexer::new(source));
+    let error = p.parse_program().unwrap_err();
+    let message = json_string(&error.to_string());
+    let parse = Error::from(error).at(Some("main.monkey"), source, p.error_position());
+    assert_eq!(
+        parse.to_json(),
+        format!(
+            "{{\"kind\": \"parse\", \"message\": {}, \"file\": \"main.monkey\", \"line\": 2, \"column\": 5, \"snippet\": \"let = \\\"2\\\";\"}}",
+            message
+        )
+    );
+    assert_eq!(
+        Error::from(VmError::StackOverflow).to_json(),
+        "{\"kind\": \"runtime\", \"message\": \"VmError: Stack overflow\", \"file\": null, \"line\": null, \"column\": null, \"snippet\": null}"
+    );
+}
+
+#[test]
+fn exit_code_test() {
+    let tests = vec![
+        (
+            Error::Usage(String::from("Usage: orangutan")),
+            USAGE_EXIT_CODE,
+        ),
+        (
+            Error::Io(
+                String::from("Could not read `a.monkey`"),
+                io::Error::from(io::ErrorKind::NotFound),
+            ),
+            IO_EXIT_CODE,
+        ),
+        (
+            Error::from(CompileError::UnknownOperator),
+            COMPILE_EXIT_CODE,
+        ),
+        (Error::from(VmError::StackOverflow), RUNTIME_EXIT_CODE),
+        (Error::from(SerializeError::NotBytecode), IO_EXIT_CODE),
+    ];
+    for (error, want) in tests {
+        assert_eq!(error.exit_code(), want);
+    }
+    let codes = [
+        RUNTIME_EXIT_CODE,
+        USAGE_EXIT_CODE,
+        PARSE_EXIT_CODE,
+        COMPILE_EXIT_CODE,
+        IO_EXIT_CODE,
+    ];
+    for (idx, code) in codes.iter().enumerate() {
+        assert!(*code != 0 && !codes[..idx].contains(code));
+    }
+}
@@ -1,206 +1,636 @@
 //! Evaluator
 //!
 //! `evaluator` contains functions for evaluating parsed expressions in the Monkey language.
-//! The public interface is simply the `eval` function.
+//! The public interface is the `eval` function, or the `Interpreter` type it is built on. Programs can first be
+//! rewritten by `optimize` to be evaluated faster.
 mod eval_error;
 #[cfg(test)]
 mod evaluator_test;
+mod optimize;
 pub use self::eval_error::EvalError;
-use crate::ast::{BlockStatement, Expression, Program, Statement};
-use crate::object::{get_built_in, Object, SharedEnvironment};
+pub use self::optimize::optimize;
+use crate::ast::{BlockStatement, Expression, Parameter, Program, Statement};
+use crate::lexer::{Lexer, Position};
+use crate::object::{
+    get_built_in, stdin_reader, stdout_writer, Environment, Object, Rng, Runtime, SharedEnvironment,
+};
+use crate::parser::Parser;
 use crate::token::Token;
+use crate::vm::SMALL_INTEGERS;
+use indexmap::IndexMap;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::mem;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// The depth calls may be nested to unless told otherwise, as many as the virtual machine allows by default.
+const DEFAULT_MAX_DEPTH: usize = 1024;
+// The room on the stack below which a call is evaluated on a newly allocated stack, and the size of that stack.
+// A call takes tens of kilobytes in an unoptimized build.
+#[cfg(not(target_arch = "wasm32"))]
+const STACK_RED_ZONE: usize = 256 * 1024;
+#[cfg(not(target_arch = "wasm32"))]
+const STACK_SEGMENT: usize = 8 * 1024 * 1024;
 
 /// Returns the result of evaluating the input program.
 ///
 /// The input `p` is the primary input consisting of the abstract syntax tree of a Monkey program.
 /// The input `env` contains any saved state (environment variables) to be used, and may be modified.
 pub fn eval(p: &Program, env: SharedEnvironment) -> Result<Object, EvalError> {
-    let mut result = Object::Null;
-    for statement in &p.statements {
-        result = eval_statement(statement, Rc::clone(&env))?;
-        if let Object::Return(value) = result {
-            // We *do* unwrap the returned object from its `Return`.
-            return Ok(*value);
+    Interpreter::new().eval(p, env)
+}
+
+/// Evaluates Monkey programs by walking their abstract syntax tree.
+///
+/// Values are shared while they are evaluated, as on the virtual machine, so that passing an array or hash around
+/// does not copy it.
+///
+/// The interpreter is also the `Runtime` through which built-in functions call back into Monkey code.
+pub struct Interpreter {
+    rng: Rng,
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+    sandboxed: bool,
+    args: Vec<String>,
+    // The environment of the innermost call expression being evaluated, in which `eval` runs its source.
+    caller_env: Option<SharedEnvironment>,
+    interrupt: Option<Arc<AtomicBool>>,
+    // The number of statements left to evaluate, if limited.
+    fuel: Option<usize>,
+    // When evaluation must stop by, if limited.
+    deadline: Option<Instant>,
+    // The number of calls to Monkey functions being evaluated, one within the next.
+    depth: usize,
+    max_depth: usize,
+    // Where in the source the last evaluation failed, if it did and that is known.
+    error_position: Option<Position>,
+    true_obj: Rc<Object>,
+    false_obj: Rc<Object>,
+    null_obj: Rc<Object>,
+    // The objects for `SMALL_INTEGERS`, in order.
+    small_integers: Vec<Rc<Object>>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Interpreter {
+            rng: Rng::default(),
+            input: stdin_reader(),
+            output: stdout_writer(),
+            sandboxed: false,
+            args: vec![],
+            caller_env: None,
+            interrupt: None,
+            fuel: None,
+            deadline: None,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            error_position: None,
+            true_obj: Rc::new(Object::Boolean(true)),
+            false_obj: Rc::new(Object::Boolean(false)),
+            null_obj: Rc::new(Object::Null),
+            small_integers: SMALL_INTEGERS
+                .map(|value| Rc::new(Object::Integer(value)))
+                .collect(),
         }
     }
-    return Ok(result);
 }
 
-// TODO: This function could be merged with `eval` if we merge the `BlockStatement` and `Program` types.
-fn eval_block_statement(bs: &BlockStatement, env: SharedEnvironment) -> Result<Object, EvalError> {
-    let mut result = Object::Null;
-    for statement in &bs.statements {
-        result = eval_statement(statement, Rc::clone(&env))?;
-        if let Object::Return(_) = result {
-            // We do *not* unwrap the returned object from its `Return`.
-            return Ok(result);
-        }
+impl Runtime for Interpreter {
+    fn call(&mut self, func: &Object, args: Vec<Object>) -> Result<Object, EvalError> {
+        let args: Vec<Rc<Object>> = args.into_iter().map(Rc::new).collect();
+        self.apply_function(func, &args).map(unshare)
+    }
+
+    fn rng(&mut self) -> &mut Rng {
+        &mut self.rng
+    }
+
+    fn input(&mut self) -> &mut dyn BufRead {
+        &mut *self.input
+    }
+
+    fn output(&mut self) -> &mut dyn Write {
+        &mut *self.output
+    }
+
+    fn sandboxed(&self) -> bool {
+        self.sandboxed
+    }
+
+    fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    fn eval_source(&mut self, source: &str) -> Result<Object, EvalError> {
+        let program = Parser::new(Lexer::new(source))
+            .parse_program()
+            .map_err(EvalError::ParseError)?;
+        let env = match &self.caller_env {
+            Some(env) => Rc::clone(env),
+            None => Rc::new(RefCell::new(Environment::new())),
+        };
+        // Positions within `source` mean nothing in the program calling `eval`, where the failure is placed instead.
+        let result = self.eval(&program, env);
+        self.error_position = None;
+        result
     }
-    return Ok(result);
 }
 
-fn eval_statement(s: &Statement, env: SharedEnvironment) -> Result<Object, EvalError> {
-    match s {
-        Statement::Expression(expr) => eval_expression(&expr, env),
-        Statement::Return(expr) => Ok(Object::Return(Box::new(eval_expression(&expr, env)?))),
-        Statement::Let(ident, expr) => {
-            let result = eval_expression(&expr, Rc::clone(&env));
-            match result {
-                Err(_) => result,
-                Ok(object) => {
-                    // Ugly, unsafe Rust, what to do?
-                    env.borrow_mut().set(ident, object);
-                    Ok(Object::Null)
-                }
+impl Interpreter {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Makes the `input` built-in function read from `input` instead of standard input.
+    pub fn set_input(&mut self, input: Box<dyn BufRead>) {
+        self.input = input;
+    }
+
+    /// Makes `puts`, `print` and `input` write to `output` instead of standard output.
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = output;
+    }
+
+    /// Exchanges the reader and writer built-in functions use with `input` and `output`, so that an engine driving
+    /// this one can lend it its own for a while.
+    pub(crate) fn swap_io(&mut self, input: &mut Box<dyn BufRead>, output: &mut Box<dyn Write>) {
+        mem::swap(&mut self.input, input);
+        mem::swap(&mut self.output, output);
+    }
+
+    /// Disallows built-in functions that reach outside the program, such as `env`.
+    pub fn set_sandboxed(&mut self, sandboxed: bool) {
+        self.sandboxed = sandboxed;
+    }
+
+    /// Makes evaluation stop with `EvalError::Interrupted` once `flag` is raised, lowering it again.
+    pub fn set_interrupt_flag(&mut self, flag: Arc<AtomicBool>) {
+        self.interrupt = Some(flag);
+    }
+
+    /// Makes evaluation stop with `EvalError::OutOfFuel` once `fuel` more statements have been evaluated.
+    ///
+    /// Statements of functions, including those called by built-in functions and code run by `eval`, count too.
+    pub fn set_fuel(&mut self, fuel: usize) {
+        self.fuel = Some(fuel);
+    }
+
+    /// Makes evaluation stop with `EvalError::TimedOut` once `timeout` has passed from now, as checked before each
+    /// statement.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.deadline = Some(Instant::now() + timeout);
+    }
+
+    /// Makes evaluation stop with `EvalError::StackOverflow` rather than nest calls to Monkey functions deeper than
+    /// `max_depth`; 1024 by default.
+    ///
+    /// Calls are evaluated on stacks allocated as the one of the thread evaluating them runs out, so the limit
+    /// need not suit the size of that thread's stack. WebAssembly is the exception, as its stack cannot be switched.
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    /// Sets the script arguments returned by the `args` built-in function.
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.args = args;
+    }
+
+    /// Returns the result of evaluating the input program, like the free function `eval`.
+    pub fn eval(&mut self, p: &Program, env: SharedEnvironment) -> Result<Object, EvalError> {
+        self.error_position = None;
+        let mut result = Rc::clone(&self.null_obj);
+        for (idx, statement) in p.statements.iter().enumerate() {
+            result = self
+                .eval_statement(statement, Rc::clone(&env))
+                .map_err(|error| self.locate(error, p.positions.get(idx)))?;
+            if let Object::Return(value) = &*result {
+                // We *do* unwrap the returned object from its `Return`.
+                result = Rc::clone(value);
+                break;
             }
         }
+        return Ok(unshare(result));
     }
-}
 
-fn eval_expressions(
-    exprs: &[Expression],
-    env: SharedEnvironment,
-) -> Result<Vec<Object>, EvalError> {
-    let mut results = vec![];
-    for expr in exprs {
-        results.push(eval_expression(expr, Rc::clone(&env))?);
+    // TODO: This function could be merged with `eval` if we merge the `BlockStatement` and `Program` types.
+    fn eval_block_statement(
+        &mut self,
+        bs: &BlockStatement,
+        env: SharedEnvironment,
+    ) -> Result<Rc<Object>, EvalError> {
+        let mut result = Rc::clone(&self.null_obj);
+        for (idx, statement) in bs.statements.iter().enumerate() {
+            result = self
+                .eval_statement(statement, Rc::clone(&env))
+                .map_err(|error| self.locate(error, bs.positions.get(idx)))?;
+            if let Object::Return(_) = *result {
+                // We do *not* unwrap the returned object from its `Return`.
+                return Ok(result);
+            }
+        }
+        return Ok(result);
+    }
+
+    /// Returns where in the source the last call to `eval` failed, if it did.
+    ///
+    /// This is where the innermost operator, call or index expression being evaluated is, or otherwise the start of
+    /// the innermost statement whose position is known.
+    pub fn error_position(&self) -> Option<Position> {
+        self.error_position
+    }
+
+    // Passes on `error`, met evaluating the statement or expression at `position`, noting the position unless the
+    // error was already placed within an inner one.
+    fn locate(&mut self, error: EvalError, position: Option<&Position>) -> EvalError {
+        if self.error_position.is_none() {
+            self.error_position = position.copied();
+        }
+        error
     }
-    Ok(results)
-}
 
-fn eval_expression(e: &Expression, env: SharedEnvironment) -> Result<Object, EvalError> {
-    match e {
-        Expression::IntegerLiteral(value) => Ok(Object::Integer(*value)),
-        Expression::StringLiteral(value) => Ok(Object::Str(value.clone())),
-        Expression::BooleanLiteral(value) => Ok(Object::Boolean(*value)),
-        Expression::Prefix(operator, expr) => eval_prefix_expression(operator, expr, env),
-        Expression::Infix(left, operator, right) => {
-            eval_infix_expression(left, operator, right, env)
+    fn eval_statement(
+        &mut self,
+        s: &Statement,
+        env: SharedEnvironment,
+    ) -> Result<Rc<Object>, EvalError> {
+        if let Some(flag) = &self.interrupt {
+            if flag.swap(false, Ordering::Relaxed) {
+                return Err(EvalError::Interrupted);
+            }
+        }
+        if let Some(fuel) = &mut self.fuel {
+            if *fuel == 0 {
+                return Err(EvalError::OutOfFuel);
+            }
+            *fuel -= 1;
         }
-        Expression::If(condition, consequence, alternative) => {
-            eval_if_expression(condition, consequence, alternative, env)
+        if let Some(deadline) = self.deadline {
+            if Instant::now() >= deadline {
+                return Err(EvalError::TimedOut);
+            }
         }
-        Expression::Ident(name) => eval_identifier(name, env),
-        Expression::FunctionLiteral(parameters, body, _) => Ok(Object::Function(
-            parameters.clone(),
-            body.clone(),
-            env.clone(),
-        )),
-        Expression::Call(expr, arguments) => {
-            let function = eval_expression(&**expr, Rc::clone(&env))?;
-            let args = eval_expressions(arguments, env)?;
-            apply_function(&function, &args)
+        match s {
+            Statement::Expression(expr) => self.eval_expression(&expr, env),
+            Statement::Return(expr) => {
+                Ok(Rc::new(Object::Return(self.eval_expression(&expr, env)?)))
+            }
+            Statement::Let(ident, expr) => {
+                let result = self.eval_expression(&expr, Rc::clone(&env));
+                match result {
+                    Err(_) => result,
+                    Ok(object) => {
+                        // Ugly, unsafe Rust, what to do?
+                        env.borrow_mut().set(ident, object);
+                        Ok(Rc::clone(&self.null_obj))
+                    }
+                }
+            }
+            Statement::Assign(target, value) => self.eval_assign_statement(target, value, env),
         }
-        Expression::ArrayLiteral(items) => {
-            let elements = eval_expressions(items, env)?;
-            Ok(Object::Array(elements))
+    }
+
+    fn eval_assign_statement(
+        &mut self,
+        target: &Expression,
+        value: &Expression,
+        env: SharedEnvironment,
+    ) -> Result<Rc<Object>, EvalError> {
+        let value = self.eval_expression(value, Rc::clone(&env))?;
+        // Walk down to the variable being indexed, evaluating the indices from the outside in.
+        let mut indices = vec![];
+        let mut root = target;
+        while let Expression::Index(left, index, _) = root {
+            indices.push(&**index);
+            root = left;
         }
-        Expression::Index(left, right) => {
-            let obj = eval_expression(&**left, Rc::clone(&env))?;
-            let idx = eval_expression(&**right, env)?;
-            eval_index_expression(&obj, &idx)
+        let mut evaluated_indices = vec![];
+        for index in indices.iter().rev() {
+            evaluated_indices.push(self.eval_expression(index, Rc::clone(&env))?);
         }
-        Expression::HashLiteral(items) => {
-            let mut hash = HashMap::new();
-            for (key, value) in items.iter() {
-                let evaluated_key = eval_expression(&key, Rc::clone(&env))?;
-                let evaluated_value = eval_expression(&value, Rc::clone(&env))?;
-                hash.insert(evaluated_key.to_hashable_object()?, evaluated_value);
+        let name = match root {
+            Expression::Ident(name) => name,
+            _ => return Err(EvalError::UnknownError),
+        };
+        // The variable (or element of it) is modified in place inside the environment defining it.
+        let result = env.borrow_mut().update(name, |obj| {
+            let last = match evaluated_indices.pop() {
+                Some(last) => last,
+                None => {
+                    *obj = value;
+                    return Ok(());
+                }
+            };
+            let mut obj = Rc::make_mut(obj);
+            for index in evaluated_indices {
+                obj = obj.index_mut((*index).clone())?;
             }
-            Ok(Object::Hash(hash))
+            obj.set_index((*last).clone(), value)
+        });
+        match result {
+            Some(result) => result.map(|_| Rc::clone(&self.null_obj)),
+            None => Err(EvalError::UnknownIdentifier(name.clone())),
         }
     }
-}
 
-fn eval_index_expression(obj: &Object, index: &Object) -> Result<Object, EvalError> {
-    match (&obj, &index) {
-        (Object::Array(arr), Object::Integer(idx)) => match arr.get(*idx as usize) {
-            Some(obj) => Ok(obj.clone()),
-            None => Ok(Object::Null),
-        },
-        (Object::Hash(items), _) => {
-            let key = index.clone().to_hashable_object()?;
-            match items.get(&key) {
-                Some(result) => Ok(result.clone()),
-                None => Ok(Object::Null),
+    fn eval_expressions(
+        &mut self,
+        exprs: &[Expression],
+        env: SharedEnvironment,
+    ) -> Result<Vec<Rc<Object>>, EvalError> {
+        let mut results = vec![];
+        for expr in exprs {
+            results.push(self.eval_expression(expr, Rc::clone(&env))?);
+        }
+        Ok(results)
+    }
+
+    fn eval_expression(
+        &mut self,
+        e: &Expression,
+        env: SharedEnvironment,
+    ) -> Result<Rc<Object>, EvalError> {
+        match e {
+            Expression::IntegerLiteral(value) => Ok(self.integer(*value)),
+            Expression::StringLiteral(value) => Ok(Rc::new(Object::Str(value.clone()))),
+            Expression::CharLiteral(value) => Ok(Rc::new(Object::Char(*value))),
+            Expression::BooleanLiteral(value) => Ok(self.boolean(*value)),
+            Expression::Prefix(operator, expr, position) => self
+                .eval_prefix_expression(operator, expr, env)
+                .map_err(|error| self.locate(error, position.as_ref())),
+            Expression::Infix(left, operator, right, position) => self
+                .eval_infix_expression(left, operator, right, env)
+                .map_err(|error| self.locate(error, position.as_ref())),
+            Expression::If(condition, consequence, alternative) => {
+                self.eval_if_expression(condition, consequence, alternative, env)
+            }
+            Expression::Ident(name) => eval_identifier(name, env),
+            Expression::FunctionLiteral(parameters, body, _) => Ok(Rc::new(Object::Function(
+                parameters.clone(),
+                body.clone(),
+                env.clone(),
+            ))),
+            Expression::Call(expr, arguments, position) => self
+                .eval_call_expression(expr, arguments, env)
+                .map_err(|error| self.locate(error, position.as_ref())),
+            Expression::ArrayLiteral(items) => {
+                let elements = self.eval_expressions(items, env)?;
+                Ok(Rc::new(Object::Array(elements)))
+            }
+            Expression::Index(left, right, position) => {
+                let result = self
+                    .eval_expression(&**left, Rc::clone(&env))
+                    .and_then(|obj| {
+                        let idx = self.eval_expression(&**right, env)?;
+                        self.eval_index_expression(&obj, &idx)
+                    });
+                result.map_err(|error| self.locate(error, position.as_ref()))
+            }
+            Expression::HashLiteral(items) => {
+                let mut hash = IndexMap::new();
+                for (key, value) in items.iter() {
+                    let evaluated_key = self.eval_expression(&key, Rc::clone(&env))?;
+                    let evaluated_value = self.eval_expression(&value, Rc::clone(&env))?;
+                    hash.insert(
+                        (*evaluated_key).clone().to_hashable_object()?,
+                        evaluated_value,
+                    );
+                }
+                Ok(Rc::new(Object::Hash(hash)))
             }
         }
-        _ => Err(EvalError::UnknownError),
     }
-}
 
-fn eval_identifier(name: &String, env: SharedEnvironment) -> Result<Object, EvalError> {
-    if let Some(obj) = env.borrow().get(name) {
-        return Ok(obj.clone());
+    fn eval_if_expression(
+        &mut self,
+        condition: &Expression,
+        consequence: &BlockStatement,
+        alternative: &Option<BlockStatement>,
+        env: SharedEnvironment,
+    ) -> Result<Rc<Object>, EvalError> {
+        if self
+            .eval_expression(condition, Rc::clone(&env))?
+            .is_truthy()
+        {
+            return self.eval_block_statement(consequence, env);
+        }
+        if let Some(bs) = alternative {
+            return self.eval_block_statement(bs, env);
+        }
+        return Ok(Rc::clone(&self.null_obj));
     }
-    if let Some(obj) = get_built_in(name) {
-        return Ok(obj.clone());
-    } else {
-        Err(EvalError::UnknownIdentifier(name.clone()))
+
+    fn eval_call_expression(
+        &mut self,
+        expr: &Expression,
+        arguments: &[Expression],
+        env: SharedEnvironment,
+    ) -> Result<Rc<Object>, EvalError> {
+        let function = self.eval_expression(expr, Rc::clone(&env))?;
+        let args = self.eval_expressions(arguments, Rc::clone(&env))?;
+        let outer_env = self.caller_env.replace(env);
+        let result = self.apply_function(&function, &args);
+        self.caller_env = outer_env;
+        result
     }
-}
 
-fn eval_if_expression(
-    condition: &Expression,
-    consequence: &BlockStatement,
-    alternative: &Option<BlockStatement>,
-    env: SharedEnvironment,
-) -> Result<Object, EvalError> {
-    if eval_expression(condition, Rc::clone(&env))?.is_truthy() {
-        return eval_block_statement(consequence, env);
+    fn eval_prefix_expression(
+        &mut self,
+        prefix: &Token,
+        right: &Expression,
+        env: SharedEnvironment,
+    ) -> Result<Rc<Object>, EvalError> {
+        let obj = self.eval_expression(right, env)?;
+        match prefix {
+            Token::Bang => Ok(self.boolean(!obj.is_truthy())),
+            Token::Minus => {
+                // Optional: Could choose to return Null for non-integral type.
+                match *obj {
+                    Object::Integer(value) => match value.checked_neg() {
+                        Some(value) => Ok(self.integer(value)),
+                        None => Err(EvalError::IntegerOverflow),
+                    },
+                    _ => Err(EvalError::PrefixTypeMismatch(Token::Minus, obj)),
+                }
+            }
+            other => Err(EvalError::UnknownPrefixOperator(other.clone())),
+        }
     }
-    if let Some(bs) = alternative {
-        return eval_block_statement(bs, env);
+
+    fn eval_infix_expression(
+        &mut self,
+        left: &Expression,
+        op: &Token,
+        right: &Expression,
+        env: SharedEnvironment,
+    ) -> Result<Rc<Object>, EvalError> {
+        let left_obj = self.eval_expression(left, Rc::clone(&env))?;
+        let right_obj = self.eval_expression(right, Rc::clone(&env))?;
+
+        let result = match (&*left_obj, &*right_obj) {
+            (Object::Integer(left), Object::Integer(right)) => {
+                eval_integer_infix_expression(*left, op, *right)
+            }
+            (Object::Boolean(left), Object::Boolean(right)) => {
+                eval_boolean_infix_expression(*left, op, *right)
+            }
+            (Object::Char(left), Object::Char(right)) => {
+                eval_char_infix_expression(*left, op, *right)
+            }
+            (Object::Str(left), Object::Str(right)) => {
+                if *op != Token::Plus {
+                    Err(EvalError::UnknownInfixOperator(op.clone()))
+                } else {
+                    Ok(Object::Str(format!("{}{}", left, right)))
+                }
+            }
+            _ => Err(EvalError::InfixTypeMismatch(
+                left_obj,
+                op.clone(),
+                right_obj,
+            )),
+        };
+        result.map(|obj| self.share(obj))
     }
-    return Ok(Object::Null);
-}
 
-fn eval_prefix_expression(
-    prefix: &Token,
-    right: &Expression,
-    env: SharedEnvironment,
-) -> Result<Object, EvalError> {
-    let obj = eval_expression(right, env)?;
-    match prefix {
-        Token::Bang => Ok(Object::Boolean(!obj.is_truthy())),
-        Token::Minus => {
-            // Optional: Could choose to return Null for non-integral type.
-            match obj {
-                Object::Integer(value) => Ok(Object::Integer(-value)),
-                other => Err(EvalError::PrefixTypeMismatch(Token::Minus, other)),
+    fn apply_function(
+        &mut self,
+        function: &Object,
+        args: &[Rc<Object>],
+    ) -> Result<Rc<Object>, EvalError> {
+        match function {
+            Object::Function(parameters, body, env) => {
+                let num_required = parameters.iter().filter(|p| p.default.is_none()).count();
+                if args.len() < num_required || args.len() > parameters.len() {
+                    return Err(EvalError::WrongNumberOfArguments(
+                        parameters.len() as u32,
+                        args.len() as u32,
+                    ));
+                }
+                // Defaults are evaluated within the call, so they count towards its depth too.
+                if self.depth >= self.max_depth {
+                    return Err(EvalError::StackOverflow);
+                }
+                self.depth += 1;
+                let result = grow_stack(|| self.eval_call(parameters, body, env, args));
+                self.depth -= 1;
+                result
+            }
+            Object::BuiltIn(built_in_function) => {
+                // TODO: Remove this clone and figure out references here.
+                let args = args.iter().map(|arg| (**arg).clone()).collect();
+                let result = built_in_function(self, args)?;
+                Ok(self.share(result))
             }
+            // TODO: Make this a more specific error.
+            _ => Err(EvalError::UnknownError),
         }
-        other => Err(EvalError::UnknownPrefixOperator(other.clone())),
     }
-}
 
-fn eval_infix_expression(
-    left: &Expression,
-    op: &Token,
-    right: &Expression,
-    env: SharedEnvironment,
-) -> Result<Object, EvalError> {
-    let left_obj = eval_expression(left, Rc::clone(&env))?;
-    let right_obj = eval_expression(right, Rc::clone(&env))?;
-
-    match (left_obj, right_obj) {
-        (Object::Integer(left), Object::Integer(right)) => {
-            eval_integer_infix_expression(left, op, right)
+    // Evaluates the body of a function with `args` bound to its parameters, in an environment enclosed by `env`.
+    fn eval_call(
+        &mut self,
+        parameters: &[Parameter],
+        body: &BlockStatement,
+        env: &SharedEnvironment,
+        args: &[Rc<Object>],
+    ) -> Result<Rc<Object>, EvalError> {
+        // Build environment for function.
+        let extended_env = Rc::new(RefCell::new(Environment::new_enclosed(Rc::clone(env))));
+        for (p, a) in parameters.iter().zip(args) {
+            extended_env.borrow_mut().set(&p.name, Rc::clone(a))
+        }
+        // Fill in any omitted arguments from their defaults, which may refer to earlier parameters.
+        for p in &parameters[args.len()..] {
+            if let Some(default) = &p.default {
+                let value = self.eval_expression(default, Rc::clone(&extended_env))?;
+                extended_env.borrow_mut().set(&p.name, value);
+            }
         }
-        (Object::Boolean(left), Object::Boolean(right)) => {
-            eval_boolean_infix_expression(left, op, right)
+        // Evaluate the function with this environment.
+        let obj = self.eval_block_statement(body, Rc::clone(&extended_env))?;
+        match &*obj {
+            Object::Return(value) => Ok(Rc::clone(value)),
+            _ => Ok(obj),
         }
-        (Object::Str(left), Object::Str(right)) => {
-            if *op != Token::Plus {
-                Err(EvalError::UnknownInfixOperator(op.clone()))
-            } else {
-                Ok(Object::Str(format!("{}{}", left, right)))
+    }
+
+    fn eval_index_expression(&self, obj: &Object, index: &Object) -> Result<Rc<Object>, EvalError> {
+        match (&obj, &index) {
+            (Object::Array(arr), Object::Integer(idx)) => match arr.get(*idx as usize) {
+                Some(obj) => Ok(Rc::clone(obj)),
+                None => Ok(Rc::clone(&self.null_obj)),
+            },
+            (Object::Str(string), Object::Integer(idx)) => {
+                match string.chars().nth(*idx as usize) {
+                    Some(ch) => Ok(Rc::new(Object::Char(ch))),
+                    None => Ok(Rc::clone(&self.null_obj)),
+                }
+            }
+            (Object::Hash(items), _) => {
+                let key = index.clone().to_hashable_object()?;
+                match items.get(&key) {
+                    Some(result) => Ok(Rc::clone(result)),
+                    None => Ok(Rc::clone(&self.null_obj)),
+                }
             }
+            _ => Err(EvalError::UnknownError),
+        }
+    }
+
+    // Returns `obj` ready to hand on, reusing the interpreter's own object for it if it has one.
+    fn share(&self, obj: Object) -> Rc<Object> {
+        match obj {
+            Object::Integer(value) => self.integer(value),
+            Object::Boolean(value) => self.boolean(value),
+            Object::Null => Rc::clone(&self.null_obj),
+            other => Rc::new(other),
+        }
+    }
+
+    // Returns an object for the integer `value`, which is shared if the integer is small.
+    fn integer(&self, value: i64) -> Rc<Object> {
+        match SMALL_INTEGERS.contains(&value) {
+            true => Rc::clone(&self.small_integers[(value - SMALL_INTEGERS.start()) as usize]),
+            false => Rc::new(Object::Integer(value)),
+        }
+    }
+
+    fn boolean(&self, value: bool) -> Rc<Object> {
+        match value {
+            true => Rc::clone(&self.true_obj),
+            false => Rc::clone(&self.false_obj),
         }
-        (a, b) => Err(EvalError::InfixTypeMismatch(a, op.clone(), b)),
+    }
+}
+
+// Runs `f`, on a newly allocated stack if the current one is running out.
+#[cfg(not(target_arch = "wasm32"))]
+fn grow_stack<T>(f: impl FnOnce() -> T) -> T {
+    stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT, f)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn grow_stack<T>(f: impl FnOnce() -> T) -> T {
+    f()
+}
+
+// Returns `obj` on its own, copying it only if it is still shared.
+fn unshare(obj: Rc<Object>) -> Object {
+    Rc::try_unwrap(obj).unwrap_or_else(|obj| (*obj).clone())
+}
+
+fn eval_identifier(name: &String, env: SharedEnvironment) -> Result<Rc<Object>, EvalError> {
+    if let Some(obj) = env.borrow().get(name) {
+        return Ok(obj);
+    }
+    if let Some(obj) = get_built_in(name) {
+        return Ok(Rc::new(obj));
+    } else {
+        Err(EvalError::UnknownIdentifier(name.clone()))
     }
 }
 
@@ -215,16 +645,12 @@ fn eval_boolean_infix_expression(left: bool, op: &Token, right: bool) -> Result<
     Ok(obj)
 }
 
-fn eval_integer_infix_expression(left: i64, op: &Token, right: i64) -> Result<Object, EvalError> {
+fn eval_char_infix_expression(left: char, op: &Token, right: char) -> Result<Object, EvalError> {
     let obj = match op {
         Token::Equal => Object::Boolean(left == right),
         Token::NotEqual => Object::Boolean(left != right),
         Token::LessThan => Object::Boolean(left < right),
         Token::GreaterThan => Object::Boolean(left > right),
-        Token::Plus => Object::Integer(left + right),
-        Token::Minus => Object::Integer(left - right),
-        Token::Asterisk => Object::Integer(left * right),
-        Token::Slash => Object::Integer(left / right),
         other => {
             return Err(EvalError::UnknownInfixOperator(other.clone()));
         }
@@ -232,31 +658,25 @@ fn eval_integer_infix_expression(left: i64, op: &Token, right: i64) -> Result<Ob
     Ok(obj)
 }
 
-fn apply_function(function: &Object, args: &Vec<Object>) -> Result<Object, EvalError> {
-    match function {
-        Object::Function(parameters, body, env) => {
-            if parameters.len() != args.len() {
-                return Err(EvalError::WrongNumberOfArguments(
-                    parameters.len() as u32,
-                    args.len() as u32,
-                ));
-            }
-            // Build environment for function.
-            let extended_env = Rc::new(RefCell::new(env.borrow().clone()));
-            for (p, a) in parameters.iter().zip(args) {
-                extended_env.borrow_mut().set(p, a.clone())
-            }
-            // Evaluate the function with this environment.
-            match eval_block_statement(body, Rc::clone(&extended_env)) {
-                Ok(Object::Return(value)) => Ok(*value),
-                other => other,
-            }
+fn eval_integer_infix_expression(left: i64, op: &Token, right: i64) -> Result<Object, EvalError> {
+    let obj = match op {
+        Token::Equal => Object::Boolean(left == right),
+        Token::NotEqual => Object::Boolean(left != right),
+        Token::LessThan => Object::Boolean(left < right),
+        Token::GreaterThan => Object::Boolean(left > right),
+        Token::Slash if right == 0 => return Err(EvalError::DivisionByZero),
+        Token::Plus | Token::Minus | Token::Asterisk | Token::Slash => {
+            let value = match op {
+                Token::Plus => left.checked_add(right),
+                Token::Minus => left.checked_sub(right),
+                Token::Asterisk => left.checked_mul(right),
+                _ => left.checked_div(right),
+            };
+            Object::Integer(value.ok_or(EvalError::IntegerOverflow)?)
         }
-        Object::BuiltIn(built_in_function) => {
-            // TODO: Remove this clone and figure out references here.
-            built_in_function(args.clone())
+        other => {
+            return Err(EvalError::UnknownInfixOperator(other.clone()));
         }
-        // TODO: Make this a more specific error.
-        _ => Err(EvalError::UnknownError),
-    }
+    };
+    Ok(obj)
 }
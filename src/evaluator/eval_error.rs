@@ -2,24 +2,47 @@
 //!
 //! `eval_error` contains an enum type representing errors encountered while evaluating Monkey statements.
 use crate::object::Object;
+use crate::parser::ParseError;
 use crate::token::Token;
 use std::fmt;
+use std::rc::Rc;
 
 /// Represents errors encountered during evaluation of the Monkey language.
 ///
 /// In most cases the error is specific and self-explanatory.
 /// However, in some cases we fallback to a generic error to make implementation less cumbersome.
+///
+/// The values an error concerns are shared rather than held directly, so that errors stay small to return.
 #[derive(Debug)]
 pub enum EvalError {
     UnknownError,
     UnknownPrefixOperator(Token),
     UnknownInfixOperator(Token),
     UnknownIdentifier(String),
-    InfixTypeMismatch(Object, Token, Object),
-    PrefixTypeMismatch(Token, Object),
+    InfixTypeMismatch(Rc<Object>, Token, Rc<Object>),
+    PrefixTypeMismatch(Token, Rc<Object>),
     WrongNumberOfArguments(u32, u32),
     UnsupportedInputToBuiltIn,
-    HashError(Object),
+    UnsupportedArgument(String, Rc<Object>),
+    IoError(String),
+    AssertionFailed(Option<String>),
+    Sandboxed(String),
+    ParseError(ParseError),
+    Interrupted,
+    // The number of statements evaluation was allowed was used up.
+    OutOfFuel,
+    // Evaluation took longer than it was allowed.
+    TimedOut,
+    // Calls were nested deeper than evaluation was allowed.
+    StackOverflow,
+    HashError(Rc<Object>),
+    IndexOutOfBounds(Rc<Object>),
+    IndexTypeMismatch(Rc<Object>, Rc<Object>),
+    DivisionByZero,
+    // The result of an operation on integers does not fit in one.
+    IntegerOverflow,
+    // A range was asked for with more integers than it may hold.
+    RangeTooLarge(u64),
 }
 
 impl fmt::Display for EvalError {
@@ -31,14 +54,16 @@ impl fmt::Display for EvalError {
             EvalError::UnknownInfixOperator(token) => {
                 write!(f, "EvalError: Unknown infix operator `{}`", token)
             }
-            EvalError::InfixTypeMismatch(_, token, _) => {
-                write!(f, "EvalError: Type mismatch for infix operator `{}`", token)
-            }
-            EvalError::PrefixTypeMismatch(token, _) => write!(
+            EvalError::InfixTypeMismatch(left, token, right) => write!(
                 f,
-                "EvalError: Type mismatch for prefix operator `{}`",
-                token
+                "EvalError: Type mismatch: {} {} {}",
+                left.type_name(),
+                token,
+                right.type_name()
             ),
+            EvalError::PrefixTypeMismatch(token, obj) => {
+                write!(f, "EvalError: Type mismatch: {}{}", token, obj.type_name())
+            }
             EvalError::UnknownIdentifier(name) => {
                 write!(f, "EvalError: Unknown identifier `{}`", name)
             }
@@ -51,7 +76,42 @@ impl fmt::Display for EvalError {
             EvalError::UnsupportedInputToBuiltIn => {
                 write!(f, "EvalError: Unsupported input to built-in function")
             }
-            EvalError::HashError(obj) => write!(f, "{} is not hashable!", obj),
+            EvalError::UnsupportedArgument(name, obj) => write!(
+                f,
+                "EvalError: Unsupported argument `{}` of type {} to built-in function `{}`",
+                obj,
+                obj.type_name(),
+                name
+            ),
+            EvalError::IoError(message) => write!(f, "EvalError: I/O error: {}", message),
+            EvalError::AssertionFailed(None) => write!(f, "EvalError: Assertion failed"),
+            EvalError::AssertionFailed(Some(message)) => {
+                write!(f, "EvalError: Assertion failed: {}", message)
+            }
+            EvalError::Sandboxed(name) => write!(
+                f,
+                "EvalError: Built-in function `{}` is not available in a sandbox",
+                name
+            ),
+            EvalError::ParseError(error) => write!(f, "EvalError: {}", error),
+            EvalError::Interrupted => write!(f, "EvalError: Interrupted"),
+            EvalError::OutOfFuel => write!(f, "EvalError: Out of fuel"),
+            EvalError::TimedOut => write!(f, "EvalError: Timed out"),
+            EvalError::StackOverflow => write!(f, "EvalError: Stack overflow"),
+            EvalError::HashError(obj) => {
+                write!(f, "{} is not hashable (type {})!", obj, obj.type_name())
+            }
+            EvalError::IndexOutOfBounds(index) => {
+                write!(f, "EvalError: Index `{}` is out of bounds", index)
+            }
+            EvalError::IndexTypeMismatch(obj, index) => {
+                write!(f, "EvalError: Cannot index `{}` with `{}`", obj, index)
+            }
+            EvalError::DivisionByZero => write!(f, "EvalError: Division by zero"),
+            EvalError::IntegerOverflow => write!(f, "EvalError: Integer overflow"),
+            EvalError::RangeTooLarge(length) => {
+                write!(f, "EvalError: A range of {} integers is too large", length)
+            }
         }
     }
 }
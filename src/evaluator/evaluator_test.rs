@@ -91,6 +91,36 @@ fn eval_string_expression_test() {
     }
 }
 
+#[test]
+fn char_test() {
+    let tests = vec![
+        ("'a'", "'a'"),
+        ("'a' == 'a'", "true"),
+        ("'a' != 'a'", "false"),
+        ("'a' < 'b'", "true"),
+        ("'b' > 'c'", "false"),
+        ("\"abc\"[1]", "'b'"),
+        ("\"abc\"[0] == 'a'", "true"),
+        ("\"abc\"[3]", "null"),
+        ("let s = \"héllo\"; s[len(s) - 1]", "'o'"),
+        ("{'a': 1}['a']", "1"),
+        ("str('a') + \"b\"", "\"ab\""),
+        ("str(12)", "\"12\""),
+    ];
+
+    for (input, want) in tests {
+        match eval_test(input) {
+            Ok(obj) => assert_eq!(obj.to_string(), want),
+            Err(error) => panic!("Got error {} on input {}!", error, input),
+        }
+    }
+
+    match eval_test("'a' + 'b'") {
+        Err(EvalError::UnknownInfixOperator(Token::Plus)) => {}
+        other => panic!("Expected an unknown operator error, got {:?}!", other),
+    }
+}
+
 #[test]
 fn bang_operator_test() {
     let tests = vec![
@@ -166,15 +196,33 @@ fn return_test() {
 #[test]
 fn errors_test() {
     let tests = vec![
+        ("5 + true;", "EvalError: Type mismatch: INTEGER + BOOLEAN"),
+        ("5 + true; 5", "EvalError: Type mismatch: INTEGER + BOOLEAN"),
+        ("-true;", "EvalError: Type mismatch: -BOOLEAN"),
+        ("{[1]: 2}", "[1] is not hashable (type ARRAY)!"),
+        ("5 / 0", "EvalError: Division by zero"),
+        ("let z = 1; 5 / (z - 1)", "EvalError: Division by zero"),
+        (
+            "let m = -9223372036854775807 - 1; m / -1",
+            "EvalError: Integer overflow",
+        ),
+        (
+            "let x = 9223372036854775807; x + 1",
+            "EvalError: Integer overflow",
+        ),
+        ("-9223372036854775807 - 2", "EvalError: Integer overflow"),
         (
-            "5 + true;",
-            "EvalError: Type mismatch for infix operator `+`",
+            "let x = 4611686018427387904; x * 2",
+            "EvalError: Integer overflow",
         ),
         (
-            "5 + true; 5",
-            "EvalError: Type mismatch for infix operator `+`",
+            "let m = -9223372036854775807 - 1; -m",
+            "EvalError: Integer overflow",
+        ),
+        (
+            "fn(a, b) { a + b }(9223372036854775807, 1)",
+            "EvalError: Integer overflow",
         ),
-        ("-true;", "EvalError: Type mismatch for prefix operator `-`"),
     ];
 
     for (input, want) in tests {
@@ -213,7 +261,14 @@ fn function_test() {
         match evaluated {
             Ok(Object::Function(parameters, body, _)) => {
                 assert_eq!(parameters.len(), want_len);
-                assert_eq!(parameters.join(", "), want_parameters);
+                assert_eq!(
+                    parameters
+                        .iter()
+                        .map(|p| p.to_string())
+                        .collect::<Vec<String>>()
+                        .join(", "),
+                    want_parameters
+                );
                 assert_eq!(body.to_string(), want_body);
             }
             _ => panic!("Did not get Object::Function!"),
@@ -245,13 +300,45 @@ fn function_application_test() {
     }
 }
 
+#[test]
+fn default_parameter_test() {
+    let tests = vec![
+        ("let add = fn(x, y = 10) { x + y; }; add(5);", 15),
+        ("let add = fn(x, y = 10) { x + y; }; add(5, 1);", 6),
+        ("let f = fn(x = 1, y = x * 2) { x + y; }; f();", 3),
+        ("let f = fn(x = 1, y = x * 2) { x + y; }; f(2);", 6),
+    ];
+
+    for (input, want) in tests {
+        let evaluated = eval_test(input);
+        match evaluated {
+            Ok(Object::Integer(got)) => assert_eq!(got, want),
+            _ => panic!("Did not get Object::Integer!"),
+        }
+    }
+
+    let errors = vec![
+        "let add = fn(x, y = 10) { x + y; }; add();",
+        "let add = fn(x, y = 10) { x + y; }; add(1, 2, 3);",
+    ];
+    for input in errors {
+        match eval_test(input) {
+            Err(EvalError::WrongNumberOfArguments(_, _)) => {}
+            _ => panic!("Did not get EvalError::WrongNumberOfArguments!"),
+        }
+    }
+}
+
 #[test]
 fn builtin_function_test() {
     let tests = vec![
         ("len(\"\")", 0),
         ("len(\"four\")", 4),
         ("len(\"hello world\")", 11),
+        ("len(\"héllo\")", 5),
         ("len([1, 2, 3+3])", 3),
+        ("len({})", 0),
+        ("len({1: 2, \"a\": 3})", 2),
         ("magic_number(1,2,3)", 42),
         ("first([3, 2, 1])", 3),
         ("first([])", -1),
@@ -269,6 +356,892 @@ fn builtin_function_test() {
     }
 }
 
+#[test]
+fn membership_test() {
+    let tests = vec![
+        ("contains([1, 2, 3], 2)", "true"),
+        ("contains([1, 2, 3], 4)", "false"),
+        ("contains([[1], \"a\"], [1])", "true"),
+        ("contains({\"a\": 1}, \"a\")", "true"),
+        ("contains({\"a\": 1}, 1)", "false"),
+        ("contains(\"monkey\", \"key\")", "true"),
+        ("contains(\"monkey\", 'z')", "false"),
+        ("has_key({1: 2, true: 3}, true)", "true"),
+        ("has_key({}, \"a\")", "false"),
+    ];
+
+    for (input, want) in tests {
+        match eval_test(input) {
+            Ok(obj) => assert_eq!(obj.to_string(), want),
+            Err(error) => panic!("Got error {} on input {}!", error, input),
+        }
+    }
+
+    let errors = vec!["contains(1, 1)", "has_key([1], 0)", "has_key({}, [])"];
+    for input in errors {
+        if let Ok(obj) = eval_test(input) {
+            panic!("Expected an error on input {}, got {}!", input, obj);
+        }
+    }
+}
+
+#[test]
+fn delete_test() {
+    let tests = vec![
+        (
+            "delete({\"a\": 1, \"b\": 2, \"c\": 3}, \"b\")",
+            "{\"a\": 1, \"c\": 3}",
+        ),
+        ("delete({1: 2}, 3)", "{1: 2}"),
+        (
+            "let h = {1: 2}; let g = delete(h, 1); [h, g]",
+            "[{1: 2}, {}]",
+        ),
+        ("let h = {1: 2, 3: 4}; h = delete(h, 1); h", "{3: 4}"),
+    ];
+
+    for (input, want) in tests {
+        match eval_test(input) {
+            Ok(obj) => assert_eq!(obj.to_string(), want),
+            Err(error) => panic!("Got error {} on input {}!", error, input),
+        }
+    }
+
+    let errors = vec!["delete([1], 0)", "delete({}, [])", "delete({})"];
+    for input in errors {
+        if let Ok(obj) = eval_test(input) {
+            panic!("Expected an error on input {}, got {}!", input, obj);
+        }
+    }
+}
+
+#[test]
+fn type_test() {
+    let tests = vec![
+        ("type(1)", "\"INTEGER\""),
+        ("type(true)", "\"BOOLEAN\""),
+        ("type(\"a\")", "\"STRING\""),
+        ("type('a')", "\"CHAR\""),
+        ("type([])", "\"ARRAY\""),
+        ("type({})", "\"HASH\""),
+        ("type(fn() {})", "\"FUNCTION\""),
+        ("type(len)", "\"BUILTIN\""),
+        ("type(if (false) { 1 })", "\"NULL\""),
+    ];
+
+    for (input, want) in tests {
+        match eval_test(input) {
+            Ok(obj) => assert_eq!(obj.to_string(), want),
+            Err(error) => panic!("Got error {} on input {}!", error, input),
+        }
+    }
+}
+
+#[test]
+fn split_join_test() {
+    let tests = vec![
+        ("split(\"a,b,c\", \",\")", "[\"a\", \"b\", \"c\"]"),
+        ("split(\"a, b\", \", \")", "[\"a\", \"b\"]"),
+        ("split(\"a-b\", '-')", "[\"a\", \"b\"]"),
+        ("split(\"abc\", \"\")", "[\"a\", \"b\", \"c\"]"),
+        ("split(\"\", \",\")", "[\"\"]"),
+        ("join([\"a\", \"b\"], \"-\")", "\"a-b\""),
+        ("join([1, 'x', true], \", \")", "\"1, x, true\""),
+        ("join([], \",\")", "\"\""),
+        ("join(split(\"a b c\", \" \"), \"\")", "\"abc\""),
+    ];
+
+    for (input, want) in tests {
+        match eval_test(input) {
+            Ok(obj) => assert_eq!(obj.to_string(), want),
+            Err(error) => panic!("Got error {} on input {}!", error, input),
+        }
+    }
+
+    let errors = vec!["split(1, \",\")", "split(\"a\", 1)", "join(\"a\", \",\")"];
+    for input in errors {
+        if let Ok(obj) = eval_test(input) {
+            panic!("Expected an error on input {}, got {}!", input, obj);
+        }
+    }
+}
+
+#[test]
+fn slice_test() {
+    let tests = vec![
+        ("slice(\"monkey\", 1, 3)", "\"on\""),
+        ("slice(\"monkey\", 3)", "\"key\""),
+        ("slice(\"monkey\", -3)", "\"key\""),
+        ("slice(\"monkey\", 0, -3)", "\"mon\""),
+        ("slice(\"monkey\", -100, 100)", "\"monkey\""),
+        ("slice(\"monkey\", 4, 2)", "\"\""),
+        ("slice([1, 2, 3, 4], 1, 3)", "[2, 3]"),
+        ("slice([1, 2, 3, 4], -1)", "[4]"),
+    ];
+
+    for (input, want) in tests {
+        match eval_test(input) {
+            Ok(obj) => assert_eq!(obj.to_string(), want),
+            Err(error) => panic!("Got error {} on input {}!", error, input),
+        }
+    }
+
+    let errors = vec!["slice(\"a\")", "slice(\"a\", \"0\")", "slice(1, 0, 1)"];
+    for input in errors {
+        if let Ok(obj) = eval_test(input) {
+            panic!("Expected an error on input {}, got {}!", input, obj);
+        }
+    }
+}
+
+#[test]
+fn sort_test() {
+    let tests = vec![
+        ("sort([3, 1, 2])", "[1, 2, 3]"),
+        ("sort([])", "[]"),
+        ("sort([\"b\", \"c\", \"a\"])", "[\"a\", \"b\", \"c\"]"),
+        ("sort(['b', 'a'])", "['a', 'b']"),
+        ("sort([3, 1, 2], fn(a, b) { a > b })", "[3, 2, 1]"),
+        ("sort([3, 1, 2], fn(a, b) { b - a })", "[3, 2, 1]"),
+        (
+            "sort([[2, \"x\"], [1, \"y\"], [2, \"a\"], [1, \"b\"]], fn(a, b) { a[0] < b[0] })",
+            "[[1, \"y\"], [1, \"b\"], [2, \"x\"], [2, \"a\"]]",
+        ),
+        (
+            "let by = fn(k) { fn(a, b) { a[k] < b[k] } }; sort([{\"n\": 2}, {\"n\": 1}], by(\"n\"))",
+            "[{\"n\": 1}, {\"n\": 2}]",
+        ),
+        ("sort([[3, 1], [2]], fn(a, b) { len(sort(a)) < len(b) })", "[[2], [3, 1]]"),
+    ];
+
+    for (input, want) in tests {
+        match eval_test(input) {
+            Ok(obj) => assert_eq!(obj.to_string(), want),
+            Err(error) => panic!("Got error {} on input {}!", error, input),
+        }
+    }
+
+    let errors = vec![
+        "sort([1, \"a\"])",
+        "sort(1)",
+        "sort([1, 2], fn(a) { true })",
+        "sort([1, 2], fn(a, b) { \"a\" })",
+        "sort([1, 2], fn(a, b) { a + true })",
+    ];
+    for input in errors {
+        if let Ok(obj) = eval_test(input) {
+            panic!("Expected an error on input {}, got {}!", input, obj);
+        }
+    }
+}
+
+#[test]
+fn higher_order_builtin_test() {
+    let tests = vec![
+        ("map([1, 2, 3], fn(x) { x * 2 })", "[2, 4, 6]"),
+        ("map([], fn(x) { x })", "[]"),
+        ("map([\"a\"], len)", "[1]"),
+        ("filter([1, 2, 3, 4], fn(x) { x > 2 })", "[3, 4]"),
+        ("filter([1, 2], fn(x) { if (x == 1) { 0 } })", "[1]"),
+        ("reduce([1, 2, 3, 4], 0, fn(acc, x) { acc + x })", "10"),
+        ("reduce([], 5, fn(acc, x) { acc + x })", "5"),
+        (
+            "let total = 0; map([1, 2], fn(x) { total = total + x }); total",
+            "3",
+        ),
+        (
+            "reduce(map(filter([1, 2, 3], fn(x) { x != 2 }), fn(x) { x * 10 }), [], push)",
+            "[10, 30]",
+        ),
+    ];
+
+    for (input, want) in tests {
+        match eval_test(input) {
+            Ok(obj) => assert_eq!(obj.to_string(), want),
+            Err(error) => panic!("Got error {} on input {}!", error, input),
+        }
+    }
+
+    let errors = vec![
+        "map(1, fn(x) { x })",
+        "map([1], 1)",
+        "filter([1], fn() { true })",
+        "reduce([1], fn(a, x) { a })",
+    ];
+    for input in errors {
+        if let Ok(obj) = eval_test(input) {
+            panic!("Expected an error on input {}, got {}!", input, obj);
+        }
+    }
+}
+
+#[test]
+fn range_test() {
+    let tests = vec![
+        ("range(5)", "[0, 1, 2, 3, 4]"),
+        ("range(0)", "[]"),
+        ("range(-3)", "[]"),
+        ("range(2, 5)", "[2, 3, 4]"),
+        ("range(2, 10, 2)", "[2, 4, 6, 8]"),
+        ("range(2, 9, 2)", "[2, 4, 6, 8]"),
+        ("range(5, 0, -2)", "[5, 3, 1]"),
+        ("range(5, 0)", "[]"),
+        (
+            "range(9223372036854775800, 9223372036854775807, 10)",
+            "[9223372036854775800]",
+        ),
+        (
+            "range(-9223372036854775807 + 2, -9223372036854775807 - 1, -2)",
+            "[-9223372036854775805, -9223372036854775807]",
+        ),
+    ];
+
+    for (input, want) in tests {
+        match eval_test(input) {
+            Ok(obj) => assert_eq!(obj.to_string(), want),
+            Err(error) => panic!("Got error {} on input {}!", error, input),
+        }
+    }
+
+    let errors = vec![
+        "range()",
+        "range(1, 2, 3, 4)",
+        "range(\"5\")",
+        "range(0, 5, 0)",
+        "range(1000000000000)",
+    ];
+    for input in errors {
+        if let Ok(obj) = eval_test(input) {
+            panic!("Expected an error on input {}, got {}!", input, obj);
+        }
+    }
+}
+
+#[test]
+fn reverse_test() {
+    let tests = vec![
+        ("reverse([1, 2, 3])", "[3, 2, 1]"),
+        ("reverse([])", "[]"),
+        ("reverse(\"monkey\")", "\"yeknom\""),
+        ("let a = [1, 2]; reverse(a); a", "[1, 2]"),
+    ];
+
+    for (input, want) in tests {
+        match eval_test(input) {
+            Ok(obj) => assert_eq!(obj.to_string(), want),
+            Err(error) => panic!("Got error {} on input {}!", error, input),
+        }
+    }
+
+    if let Ok(obj) = eval_test("reverse(1)") {
+        panic!("Expected an error, got {}!", obj);
+    }
+}
+
+#[test]
+fn numeric_builtin_test() {
+    let tests = vec![
+        ("min(3, 1, 2)", "1"),
+        ("min([3, 1, 2])", "1"),
+        ("min(7)", "7"),
+        ("min([])", "null"),
+        ("max(3, 1, 2)", "3"),
+        ("max([-3, -1])", "-1"),
+        ("sum([1, 2, 3])", "6"),
+        ("sum(1, 2)", "3"),
+        ("sum([])", "0"),
+        ("abs(-5)", "5"),
+        ("abs(5)", "5"),
+    ];
+
+    for (input, want) in tests {
+        match eval_test(input) {
+            Ok(obj) => assert_eq!(obj.to_string(), want),
+            Err(error) => panic!("Got error {} on input {}!", error, input),
+        }
+    }
+
+    let errors = vec![
+        (
+            "min(1, \"a\")",
+            "EvalError: Unsupported argument `\"a\"` of type STRING to built-in function `min`",
+        ),
+        (
+            "sum([[1]])",
+            "EvalError: Unsupported argument `[1]` of type ARRAY to built-in function `sum`",
+        ),
+        (
+            "abs(true)",
+            "EvalError: Unsupported argument `true` of type BOOLEAN to built-in function `abs`",
+        ),
+        ("sum(9223372036854775807, 1)", "EvalError: Integer overflow"),
+        ("pow(2, 64)", "EvalError: Integer overflow"),
+        ("pow(2, 4294967296)", "EvalError: Integer overflow"),
+        (
+            "abs(-9223372036854775807 - 1)",
+            "EvalError: Integer overflow",
+        ),
+    ];
+    for (input, want) in errors {
+        match eval_test(input) {
+            Err(error) => assert_eq!(error.to_string(), want),
+            Ok(obj) => panic!("Expected an error on input {}, got {}!", input, obj),
+        }
+    }
+}
+
+#[test]
+fn math_builtin_test() {
+    let tests = vec![
+        ("sqrt(16)", "4"),
+        ("sqrt(17)", "4"),
+        ("sqrt(0)", "0"),
+        ("sqrt(9223372036854775807)", "3037000499"),
+        ("pow(2, 10)", "1024"),
+        ("pow(-3, 3)", "-27"),
+        ("pow(5, 0)", "1"),
+        ("pow(1, 4294967296)", "1"),
+        ("pow(-1, 4294967297)", "-1"),
+        ("floor(7)", "7"),
+        ("ceil(-7)", "-7"),
+        ("round(7)", "7"),
+    ];
+
+    for (input, want) in tests {
+        match eval_test(input) {
+            Ok(obj) => assert_eq!(obj.to_string(), want),
+            Err(error) => panic!("Got error {} on input {}!", error, input),
+        }
+    }
+
+    let errors = vec![
+        "sqrt(-1)",
+        "sqrt(\"4\")",
+        "pow(2, -1)",
+        "pow(2, 64)",
+        "pow(\"2\", 2)",
+        "floor(true)",
+        "round(1, 2)",
+    ];
+    for input in errors {
+        if let Ok(obj) = eval_test(input) {
+            panic!("Expected an error on input {}, got {}!", input, obj);
+        }
+    }
+}
+
+#[test]
+fn random_test() {
+    let tests = vec![
+        ("seed(7); let a = rand(); seed(7); a == rand()", "true"),
+        (
+            "seed(7); let a = rand_int(1, 6); seed(7); a == rand_int(1, 6)",
+            "true",
+        ),
+        ("rand_int(3, 3)", "3"),
+        ("rand() < 0", "false"),
+        (
+            "let r = map(range(100), fn(i) { rand_int(-2, 2) }); [min(r), max(r)]",
+            "[-2, 2]",
+        ),
+    ];
+
+    for (input, want) in tests {
+        match eval_test(input) {
+            Ok(obj) => assert_eq!(obj.to_string(), want),
+            Err(error) => panic!("Got error {} on input {}!", error, input),
+        }
+    }
+
+    // The numbers only depend on the seed, so both engines produce the same ones.
+    let mut rng = Rng::new(42);
+    let want = format!("[{}, {}]", rng.range(0, 1000), rng.range(0, 1000));
+    match eval_test("seed(42); [rand_int(0, 1000), rand_int(0, 1000)]") {
+        Ok(obj) => assert_eq!(obj.to_string(), want),
+        Err(error) => panic!("Got error {}!", error),
+    }
+
+    let errors = vec![
+        "rand(1)",
+        "rand_int(2, 1)",
+        "rand_int(1, \"2\")",
+        "seed(true)",
+    ];
+    for input in errors {
+        if let Ok(obj) = eval_test(input) {
+            panic!("Expected an error on input {}, got {}!", input, obj);
+        }
+    }
+}
+
+#[test]
+fn input_test() {
+    let program = "let a = input(); let b = input(); let c = input(); [a, b, c]";
+    let env = Rc::new(RefCell::new(Environment::new()));
+    let mut interpreter = Interpreter::new();
+    interpreter.set_input(Box::new("first\nsecond\r\n".as_bytes()));
+    let program = Parser::new(Lexer::new(program)).parse_program().unwrap();
+    match interpreter.eval(&program, env) {
+        Ok(obj) => assert_eq!(obj.to_string(), "[\"first\", \"second\", null]"),
+        Err(error) => panic!("Got error {}!", error),
+    }
+}
+
+#[test]
+fn assert_test() {
+    let tests = vec![
+        ("assert(true)", "null"),
+        ("assert(1 < 2, \"math works\")", "null"),
+        ("assert([])", "null"),
+    ];
+    for (input, want) in tests {
+        match eval_test(input) {
+            Ok(obj) => assert_eq!(obj.to_string(), want),
+            Err(error) => panic!("Got error {} on input {}!", error, input),
+        }
+    }
+
+    let errors = vec![
+        ("assert(false)", "EvalError: Assertion failed"),
+        (
+            "let x = 3; assert(x == 4, \"x is \" + str(x)); 5",
+            "EvalError: Assertion failed: x is 3",
+        ),
+        (
+            "assert(if (false) { 1 }, 'c')",
+            "EvalError: Assertion failed: c",
+        ),
+    ];
+    for (input, want) in errors {
+        match eval_test(input) {
+            Err(error) => assert_eq!(error.to_string(), want),
+            Ok(obj) => panic!("Expected an error on input {}, got {}!", input, obj),
+        }
+    }
+}
+
+#[test]
+fn code_point_test() {
+    let tests = vec![
+        ("ord('a')", "97"),
+        ("ord(\"A\")", "65"),
+        ("chr(98)", "'b'"),
+        ("chr(ord('x') + 1)", "'y'"),
+        ("char_at(\"monkey\", 2)", "'n'"),
+        ("char_at(\"monkey\", 6)", "null"),
+        ("char_at(\"monkey\", -1)", "null"),
+        (
+            "let shift = fn(s) { join(map(split(s, \"\"), fn(c) { chr(ord(c) + 1) }), \"\") }; shift(\"HAL\")",
+            "\"IBM\"",
+        ),
+    ];
+
+    for (input, want) in tests {
+        match eval_test(input) {
+            Ok(obj) => assert_eq!(obj.to_string(), want),
+            Err(error) => panic!("Got error {} on input {}!", error, input),
+        }
+    }
+
+    let errors = vec![
+        "ord(\"ab\")",
+        "ord(1)",
+        "chr(-1)",
+        "chr(55296)",
+        "char_at('a', 0)",
+    ];
+    for input in errors {
+        if let Ok(obj) = eval_test(input) {
+            panic!("Expected an error on input {}, got {}!", input, obj);
+        }
+    }
+}
+
+/// A writer whose contents remain readable after it has been handed to an engine.
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl std::io::Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn print_test() {
+    let program = "puts(\"a\", 1); print(\"b\", 'c', [2]); print(); print(3)";
+    let env = Rc::new(RefCell::new(Environment::new()));
+    let output = SharedBuffer::default();
+    let mut interpreter = Interpreter::new();
+    interpreter.set_output(Box::new(output.clone()));
+    let program = Parser::new(Lexer::new(program)).parse_program().unwrap();
+    match interpreter.eval(&program, env) {
+        Ok(obj) => assert_eq!(obj.to_string(), "null"),
+        Err(error) => panic!("Got error {}!", error),
+    }
+    assert_eq!(
+        String::from_utf8_lossy(&output.0.borrow()),
+        "a\n1\nb c [2]3"
+    );
+}
+
+#[test]
+fn deep_copy_test() {
+    let tests = vec![
+        ("deep_copy(5)", "5"),
+        (
+            "let a = [[1], 2]; let b = deep_copy(a); b[0][0] = 3; [a, b]",
+            "[[[1], 2], [[3], 2]]",
+        ),
+        (
+            "let h = {\"k\": [1]}; let c = deep_copy(h); c[\"k\"][0] = 2; [h, c]",
+            "[{\"k\": [1]}, {\"k\": [2]}]",
+        ),
+    ];
+    for (input, want) in tests {
+        match eval_test(input) {
+            Ok(obj) => assert_eq!(obj.to_string(), want),
+            Err(error) => panic!("Got error {} for {}!", error, input),
+        }
+    }
+    if let Ok(obj) = eval_test("deep_copy()") {
+        panic!("Expected error, got {}!", obj);
+    }
+}
+
+#[test]
+fn env_test() {
+    std::env::set_var("ORANGUTAN_EVALUATOR_ENV_TEST", "monkey");
+    let tests = vec![
+        ("env(\"ORANGUTAN_EVALUATOR_ENV_TEST\")", "\"monkey\""),
+        ("env(\"ORANGUTAN_EVALUATOR_ENV_TEST_UNSET\")", "null"),
+        ("env()[\"ORANGUTAN_EVALUATOR_ENV_TEST\"]", "\"monkey\""),
+    ];
+    for (input, want) in tests {
+        match eval_test(input) {
+            Ok(obj) => assert_eq!(obj.to_string(), want),
+            Err(error) => panic!("Got error {} for {}!", error, input),
+        }
+    }
+
+    let program = Parser::new(Lexer::new("env(\"ORANGUTAN_EVALUATOR_ENV_TEST\")"))
+        .parse_program()
+        .unwrap();
+    let mut interpreter = Interpreter::new();
+    interpreter.set_sandboxed(true);
+    match interpreter.eval(&program, Rc::new(RefCell::new(Environment::new()))) {
+        Err(EvalError::Sandboxed(name)) => assert_eq!(name, "env"),
+        other => panic!("Expected sandbox error, got {:?}!", other),
+    }
+}
+
+#[test]
+fn args_test() {
+    match eval_test("args()") {
+        Ok(obj) => assert_eq!(obj.to_string(), "[]"),
+        Err(error) => panic!("Got error {}!", error),
+    }
+
+    let program = Parser::new(Lexer::new("let a = args(); [len(a), a[1]]"))
+        .parse_program()
+        .unwrap();
+    let mut interpreter = Interpreter::new();
+    interpreter.set_args(vec![String::from("-v"), String::from("file.txt")]);
+    match interpreter.eval(&program, Rc::new(RefCell::new(Environment::new()))) {
+        Ok(obj) => assert_eq!(obj.to_string(), "[2, \"file.txt\"]"),
+        Err(error) => panic!("Got error {}!", error),
+    }
+}
+
+#[test]
+fn eval_builtin_test() {
+    let tests = vec![
+        ("eval(\"1 + 2\")", "3"),
+        ("let x = 5; eval(\"x * 2\")", "10"),
+        ("eval(\"let y = 7;\"); y", "7"),
+        ("let f = fn(a) { eval(\"a + 1\") }; f(41)", "42"),
+    ];
+    for (input, want) in tests {
+        match eval_test(input) {
+            Ok(obj) => assert_eq!(obj.to_string(), want),
+            Err(error) => panic!("Got error {} for {}!", error, input),
+        }
+    }
+    match eval_test("eval(\"let = 1\")") {
+        Err(EvalError::ParseError(_)) => {}
+        other => panic!("Expected parse error, got {:?}!", other),
+    }
+}
+
+#[test]
+fn replace_test() {
+    let tests = vec![
+        ("replace(\"a-b-c\", \"-\", \", \")", "\"a, b, c\""),
+        ("replace(\"banana\", 'a', 'o')", "\"bonono\""),
+        ("replace(\"banana\", \"x\", \"y\")", "\"banana\""),
+        ("replace_first(\"banana\", \"an\", \"AN\")", "\"bANana\""),
+        ("replace_first(\"\", \"a\", \"b\")", "\"\""),
+    ];
+    for (input, want) in tests {
+        match eval_test(input) {
+            Ok(obj) => assert_eq!(obj.to_string(), want),
+            Err(error) => panic!("Got error {} for {}!", error, input),
+        }
+    }
+    for input in [
+        "replace(\"a\", \"b\")",
+        "replace('a', \"a\", \"b\")",
+        "replace(\"a\", 1, \"b\")",
+    ] {
+        if let Ok(obj) = eval_test(input) {
+            panic!("Expected error for {}, got {}!", input, obj);
+        }
+    }
+}
+
+#[test]
+fn index_of_test() {
+    let tests = vec![
+        ("index_of([1, 2, 3], 2)", "1"),
+        ("index_of([[1], [2]], [2])", "1"),
+        ("index_of([1, 2, 3], 4)", "null"),
+        ("index_of(\"hello\", \"ll\")", "2"),
+        ("index_of(\"hello\", 'o')", "4"),
+        ("index_of(\"hello\", \"x\")", "null"),
+        ("find([1, 2, 3, 4], fn(x) { x > 2 })", "3"),
+        ("find([1, 2], fn(x) { x > 2 })", "null"),
+    ];
+    for (input, want) in tests {
+        match eval_test(input) {
+            Ok(obj) => assert_eq!(obj.to_string(), want),
+            Err(error) => panic!("Got error {} for {}!", error, input),
+        }
+    }
+    for input in ["index_of(1, 1)", "find(\"abc\", fn(x) { true })"] {
+        if let Ok(obj) = eval_test(input) {
+            panic!("Expected error for {}, got {}!", input, obj);
+        }
+    }
+}
+
+#[test]
+fn flatten_test() {
+    let tests = vec![
+        ("flatten([1, [2, 3], [[4]], []])", "[1, 2, 3, [4]]"),
+        ("flatten([1, [2, [3, [4]]]], 2)", "[1, 2, 3, [4]]"),
+        ("flatten([[1], [2]], 0)", "[[1], [2]]"),
+        ("flatten([])", "[]"),
+    ];
+    for (input, want) in tests {
+        match eval_test(input) {
+            Ok(obj) => assert_eq!(obj.to_string(), want),
+            Err(error) => panic!("Got error {} for {}!", error, input),
+        }
+    }
+    for input in ["flatten(1)", "flatten([1], -1)", "flatten()"] {
+        if let Ok(obj) = eval_test(input) {
+            panic!("Expected error for {}, got {}!", input, obj);
+        }
+    }
+}
+
+#[test]
+fn set_operations_test() {
+    let tests = vec![
+        ("unique([1, 2, 1, 3, 2])", "[1, 2, 3]"),
+        ("unique([\"a\", 'a', \"a\"])", "[\"a\", 'a']"),
+        ("union([1, 2, 2], [3, 1])", "[1, 2, 3]"),
+        ("intersect([1, 2, 3, 2], [2, 3, 4])", "[2, 3]"),
+        ("difference([1, 2, 3, 1], [2])", "[1, 3]"),
+        ("intersect([], [1])", "[]"),
+    ];
+    for (input, want) in tests {
+        match eval_test(input) {
+            Ok(obj) => assert_eq!(obj.to_string(), want),
+            Err(error) => panic!("Got error {} for {}!", error, input),
+        }
+    }
+    for input in [
+        "unique([[1]])",
+        "union([1], 2)",
+        "difference([1], [fn(x) { x }])",
+    ] {
+        if let Ok(obj) = eval_test(input) {
+            panic!("Expected error for {}, got {}!", input, obj);
+        }
+    }
+}
+
+#[test]
+fn starts_with_test() {
+    let tests = vec![
+        ("starts_with(\"monkey\", \"mon\")", "true"),
+        ("starts_with(\"monkey\", \"key\")", "false"),
+        ("starts_with(\"monkey\", 'm')", "true"),
+        ("starts_with(\"\", \"\")", "true"),
+        ("ends_with(\"monkey\", \"key\")", "true"),
+        ("ends_with(\"monkey\", 'm')", "false"),
+        ("starts_with([1, 2, 3], [1, 2])", "true"),
+        ("starts_with([1], [1, 2])", "false"),
+        ("ends_with([1, 2, 3], [2, 3])", "true"),
+        ("ends_with([1, 2, 3], [])", "true"),
+    ];
+    for (input, want) in tests {
+        match eval_test(input) {
+            Ok(obj) => assert_eq!(obj.to_string(), want),
+            Err(error) => panic!("Got error {} for {}!", error, input),
+        }
+    }
+    if let Ok(obj) = eval_test("starts_with([1], 1)") {
+        panic!("Expected error, got {}!", obj);
+    }
+}
+
+#[test]
+fn chars_test() {
+    let tests = vec![
+        ("chars(\"abc\")", "[\"a\", \"b\", \"c\"]"),
+        ("chars(\"\")", "[]"),
+        (
+            "join(map(chars(\"abc\"), fn(c) { c + c }), \"\")",
+            "\"aabbcc\"",
+        ),
+    ];
+    for (input, want) in tests {
+        match eval_test(input) {
+            Ok(obj) => assert_eq!(obj.to_string(), want),
+            Err(error) => panic!("Got error {} for {}!", error, input),
+        }
+    }
+    if let Ok(obj) = eval_test("chars([1])") {
+        panic!("Expected error, got {}!", obj);
+    }
+}
+
+#[test]
+fn interrupt_test() {
+    let program = Parser::new(Lexer::new("let x = 1; x + 1"))
+        .parse_program()
+        .unwrap();
+    let flag = Arc::new(AtomicBool::new(true));
+    let mut interpreter = Interpreter::new();
+    interpreter.set_interrupt_flag(Arc::clone(&flag));
+    let env = Rc::new(RefCell::new(Environment::new()));
+    match interpreter.eval(&program, Rc::clone(&env)) {
+        Err(EvalError::Interrupted) => {}
+        other => panic!("Expected interruption, got {:?}!", other),
+    }
+    // The flag is lowered again, so the next program runs normally.
+    assert!(!flag.load(Ordering::Relaxed));
+    match interpreter.eval(&program, env) {
+        Ok(obj) => assert_eq!(obj.to_string(), "2"),
+        Err(error) => panic!("Got error {}!", error),
+    }
+}
+
+#[test]
+fn error_position_test() {
+    let tests = vec![
+        ("let a = 1;\nlet b = a + true;", 2, 11),
+        ("let f = fn(x) {\n  let y = x;\n  y + true\n};\nf(1)", 3, 5),
+        ("let t = true;\nmap([t], fn(x) {\n    -x\n})", 3, 5),
+        ("if (true) { 1 } else { 2 } + true", 1, 28),
+        ("let a = 1;\neval(\"1 + true\")", 2, 5),
+        ("let f = fn(x) { x };\nlet x = f(1) + f(true + 1);", 2, 23),
+        ("let f = fn(x) { x };\nf()", 2, 2),
+        ("let a = [1];\na[true]", 2, 2),
+        ("let a = 1;\nlet b = c * 2;", 2, 11),
+        ("let a = 1;\nmap([1], fn(a, b) { a })", 2, 4),
+    ];
+    for (input, line, column) in tests {
+        let program = Parser::new(Lexer::new(input)).parse_program().unwrap();
+        let env = Rc::new(RefCell::new(Environment::new()));
+        let mut interpreter = Interpreter::new();
+        assert!(interpreter.eval(&program, env).is_err(), "{}", input);
+        assert_eq!(
+            interpreter.error_position(),
+            Some(Position { line, column }),
+            "{}",
+            input
+        );
+    }
+
+    let program = Parser::new(Lexer::new("let x = 1;"))
+        .parse_program()
+        .unwrap();
+    let mut interpreter = Interpreter::new();
+    assert!(interpreter
+        .eval(&program, Rc::new(RefCell::new(Environment::new())))
+        .is_ok());
+    assert_eq!(interpreter.error_position(), None);
+}
+
+#[test]
+fn fuel_test() {
+    let countdown = "let f = fn(n) { if (n == 0) { 0 } else { f(n - 1) } }; f(20)";
+    let program = Parser::new(Lexer::new(countdown)).parse_program().unwrap();
+    let run_with = |fuel: usize| {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_fuel(fuel);
+        interpreter.eval(&program, Rc::new(RefCell::new(Environment::new())))
+    };
+    match run_with(1_000) {
+        Ok(obj) => assert_eq!(obj.to_string(), "0"),
+        Err(error) => panic!("Got error {}!", error),
+    }
+    match run_with(20) {
+        Err(EvalError::OutOfFuel) => {}
+        other => panic!("Expected to run out of fuel, got {:?}!", other),
+    }
+}
+
+#[test]
+fn timeout_test() {
+    let input = "let f = fn(n) { if (n == 0) { 0 } else { f(n - 1) + f(n - 1) } }; f(30)";
+    let program = Parser::new(Lexer::new(input)).parse_program().unwrap();
+    let mut interpreter = Interpreter::new();
+    interpreter.set_timeout(Duration::from_millis(10));
+    match interpreter.eval(&program, Rc::new(RefCell::new(Environment::new()))) {
+        Err(EvalError::TimedOut) => {}
+        other => panic!("Expected to time out, got {:?}!", other),
+    }
+}
+
+#[test]
+fn max_depth_test() {
+    let countdown = "let f = fn(n) { if (n == 0) { 0 } else { f(n - 1) } };";
+    let mut interpreter = Interpreter::new();
+    interpreter.set_max_depth(20);
+    let mut run = |n: usize| {
+        let input = format!("{} f({})", countdown, n);
+        let program = Parser::new(Lexer::new(&input)).parse_program().unwrap();
+        interpreter.eval(&program, Rc::new(RefCell::new(Environment::new())))
+    };
+    match run(19) {
+        Ok(obj) => assert_eq!(obj.to_string(), "0"),
+        Err(error) => panic!("Got error {}!", error),
+    }
+    match run(20) {
+        Err(EvalError::StackOverflow) => {}
+        other => panic!("Expected a stack overflow, got {:?}!", other),
+    }
+    // The calls cut short by the overflow no longer count.
+    assert!(run(19).is_ok());
+
+    // Defaults are evaluated within the call they are for.
+    let program = Parser::new(Lexer::new("let f = fn(x = f()) { x }; f()"))
+        .parse_program()
+        .unwrap();
+    match interpreter.eval(&program, Rc::new(RefCell::new(Environment::new()))) {
+        Err(EvalError::StackOverflow) => {}
+        other => panic!("Expected a stack overflow, got {:?}!", other),
+    }
+}
+
 #[test]
 fn rest_test() {
     let tests = vec![("rest([1, 2, 3])", "[2, 3]"), ("rest([])", "")];
@@ -325,7 +1298,14 @@ fn array_test() {
 
 #[test]
 fn hash_test() {
-    let tests = vec![("{1: 2*2, \"a\": len(\"bcd\")}", "{\"a\": 3, 1: 4}")];
+    let tests = vec![
+        ("{1: 2*2, \"a\": len(\"bcd\")}", "{1: 4, \"a\": 3}"),
+        (
+            "{\"b\": 1, \"a\": 2, true: 3}",
+            "{\"b\": 1, \"a\": 2, true: 3}",
+        ),
+        ("{3: 1, 2: 2, 3: 3}", "{3: 3, 2: 2}"),
+    ];
 
     for (input, want) in tests {
         let evaluated = eval_test(input);
@@ -435,3 +1415,150 @@ fn sum_function_test() {
         _ => panic!("Did not get Object::Integer!"),
     }
 }
+
+#[test]
+fn mutable_closure_test() {
+    let tests = vec![
+        (
+            "let counter = fn() { let count = 0; fn() { count = count + 1; count } };
+            let c = counter(); c(); c(); c()",
+            "3",
+        ),
+        (
+            "let make = fn() { let n = 0; [fn() { n = n + 1 }, fn() { n }] };
+            let fs = make(); fs[0](); fs[0](); fs[1]()",
+            "2",
+        ),
+        (
+            "let f = fn() { let x = 1; let set = fn(v) { x = v }; set(5); x }; f()",
+            "5",
+        ),
+        (
+            "let f = fn(a) { fn() { fn() { a = a * 2; a } } }; let g = f(3)(); g(); g()",
+            "12",
+        ),
+        (
+            "let f = fn() { let a = [1]; let g = fn() { a[0] = 2 }; g(); a }; f()",
+            "[2]",
+        ),
+        ("let x = 1; let f = fn() { x = 2 }; f(); x", "2"),
+        ("let x = 1; x = x + 1; x", "2"),
+    ];
+
+    for (input, want) in tests {
+        match eval_test(input) {
+            Ok(obj) => assert_eq!(obj.to_string(), want),
+            Err(error) => panic!("Got error {} on input {}!", error, input),
+        }
+    }
+
+    match eval_test("let f = fn() { y = 1 }; f()") {
+        Err(error) => assert_eq!(error.to_string(), "EvalError: Unknown identifier `y`"),
+        Ok(obj) => panic!("Expected an error, got {}!", obj),
+    }
+}
+
+#[test]
+fn outer_environment_test() {
+    // Functions see their defining environment as it is when they are called, not a copy of it as it was when they
+    // were created, and each call gets an environment of its own.
+    let tests = vec![
+        ("let f = fn() { g() }; let g = fn() { 5 }; f()", "5"),
+        (
+            "let f = fn() { let g = fn() { y }; let y = 3; g() }; f()",
+            "3",
+        ),
+        (
+            "let make = fn(n) { fn() { n } }; let a = make(1); let b = make(2); a() + b() * 10",
+            "21",
+        ),
+        (
+            "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(15)",
+            "610",
+        ),
+    ];
+
+    for (input, want) in tests {
+        match eval_test(input) {
+            Ok(obj) => assert_eq!(obj.to_string(), want),
+            Err(error) => panic!("Got error {} on input {}!", error, input),
+        }
+    }
+}
+
+#[test]
+fn shared_values_test() {
+    // Variables, elements and arguments holding the same array share it, until one of them is changed.
+    let input = "let a = [[1, 2], 3]; let b = a; let c = a[0]; let id = fn(x) { x }; let d = id(a); d[1] = 4;";
+    let program = Parser::new(Lexer::new(input)).parse_program().unwrap();
+    let env = Rc::new(RefCell::new(Environment::new()));
+    assert!(eval(&program, Rc::clone(&env)).is_ok());
+    let get = |name| env.borrow().get(name).unwrap();
+    assert!(Rc::ptr_eq(&get("a"), &get("b")));
+    match (&*get("a"), &*get("d")) {
+        (Object::Array(a), Object::Array(d)) => {
+            assert!(Rc::ptr_eq(&a[0], &get("c")));
+            assert!(Rc::ptr_eq(&a[0], &d[0]));
+            assert_eq!(a[1].to_string(), "3");
+        }
+        other => panic!("Expected arrays, got {:?}!", other),
+    }
+}
+
+#[test]
+fn index_assignment_test() {
+    let tests = vec![
+        ("let a = [1, 2, 3]; a[0] = 5; a", "[5, 2, 3]"),
+        (
+            "let a = [1, 2, 3]; let i = 1; a[i + 1] = a[i]; a",
+            "[1, 2, 2]",
+        ),
+        ("let a = [[1, 2], [3]]; a[0][1] = 7; a", "[[1, 7], [3]]"),
+        (
+            "let h = {\"a\": 1}; h[\"a\"] = 2; h[\"b\"] = 3; h",
+            "{\"a\": 2, \"b\": 3}",
+        ),
+        ("let h = {\"a\": [1]}; h[\"a\"][0] = 2; h", "{\"a\": [2]}"),
+        ("let a = [1]; let b = a; b[0] = 2; a", "[1]"),
+        // Elements are shared between copies, but copied before they are changed.
+        ("let a = [[1], 2]; let b = a; b[0][0] = 5; a", "[[1], 2]"),
+        ("let a = [[1], 2]; let c = a[0]; c[0] = 7; a", "[[1], 2]"),
+        (
+            "let h = {\"x\": [1]}; let g = h; g[\"x\"][0] = 3; h",
+            "{\"x\": [1]}",
+        ),
+    ];
+
+    for (input, want) in tests {
+        match eval_test(input) {
+            Ok(obj) => assert_eq!(obj.to_string(), want),
+            Err(error) => panic!("Got error {} on input {}!", error, input),
+        }
+    }
+
+    let errors = vec![
+        (
+            "let a = [1]; a[1] = 2;",
+            "EvalError: Index `1` is out of bounds",
+        ),
+        (
+            "let a = [1]; a[-1] = 2;",
+            "EvalError: Index `-1` is out of bounds",
+        ),
+        (
+            "let h = {}; h[1][2] = 2;",
+            "EvalError: Index `1` is out of bounds",
+        ),
+        (
+            "let s = \"s\"; s[0] = 1;",
+            "EvalError: Cannot index `\"s\"` with `0`",
+        ),
+        ("b[0] = 1;", "EvalError: Unknown identifier `b`"),
+    ];
+    for (input, want) in errors {
+        match eval_test(input) {
+            Err(got) => assert_eq!(got.to_string(), want),
+            _ => panic!("Did not get EvalError!"),
+        }
+    }
+}
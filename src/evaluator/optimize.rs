@@ -0,0 +1,118 @@
+//! Optimize
+//!
+//! `optimize` rewrites parsed programs before they are evaluated, replacing each expression made only of literals
+//! and operators, such as `60 * 60 * 24` or `"a" + "b"`, with its value. The tree-walking interpreter would
+//! otherwise work such expressions out again each time it reaches them, as on every call of a function.
+//!
+//! Values are worked out as the compiler works them out, so expressions which would fail, such as `1 / 0`, are left
+//! to fail when evaluated. Only the literal parts of other expressions are folded: `x + 2 * 3` becomes `x + 6`.
+use crate::ast::{BlockStatement, Expression, Program, Statement};
+use crate::compiler::fold::fold;
+use crate::object::Object;
+
+/// Folds the constant expressions throughout `program`.
+pub fn optimize(program: &mut Program) {
+    statements(&mut program.statements);
+}
+
+fn statements(statements: &mut [Statement]) {
+    for statement in statements {
+        match statement {
+            Statement::Let(_, value) | Statement::Return(value) | Statement::Expression(value) => {
+                expression(value)
+            }
+            Statement::Assign(target, value) => {
+                expression(target);
+                expression(value);
+            }
+        }
+    }
+}
+
+fn block(block: &mut BlockStatement) {
+    statements(&mut block.statements);
+}
+
+fn expression(expr: &mut Expression) {
+    match expr {
+        Expression::Ident(_)
+        | Expression::IntegerLiteral(_)
+        | Expression::BooleanLiteral(_)
+        | Expression::StringLiteral(_)
+        | Expression::CharLiteral(_) => {}
+        Expression::Prefix(_, operand, _) => expression(operand),
+        Expression::Infix(left, _, right, _) => {
+            expression(left);
+            expression(right);
+        }
+        Expression::If(condition, consequence, alternative) => {
+            expression(condition);
+            block(consequence);
+            if let Some(alternative) = alternative {
+                block(alternative);
+            }
+        }
+        Expression::FunctionLiteral(parameters, body, _) => {
+            for default in parameters.iter_mut().filter_map(|p| p.default.as_mut()) {
+                expression(default);
+            }
+            block(body);
+        }
+        Expression::Call(function, arguments, _) => {
+            expression(function);
+            arguments.iter_mut().for_each(expression);
+        }
+        Expression::ArrayLiteral(elements) => elements.iter_mut().for_each(expression),
+        Expression::Index(target, index, _) => {
+            expression(target);
+            expression(index);
+        }
+        Expression::HashLiteral(pairs) => {
+            for (key, value) in pairs {
+                expression(key);
+                expression(value);
+            }
+        }
+    }
+    // The operands are literals by now, if they can be.
+    if let Expression::Prefix(..) | Expression::Infix(..) = expr {
+        match fold(expr) {
+            Some(Object::Integer(value)) => *expr = Expression::IntegerLiteral(value),
+            Some(Object::Boolean(value)) => *expr = Expression::BooleanLiteral(value),
+            Some(Object::Str(value)) => *expr = Expression::StringLiteral(value),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    #[test]
+    fn optimize_test() {
+        let tests = vec![
+            ("60 * 60 * 24", "86400;"),
+            ("\"a\" + \"b\"", "\"ab\";"),
+            ("!(1 < 2)", "false;"),
+            ("x + 2 * 3", "(x + 6);"),
+            (
+                "let f = fn(a = -1 + 0) { [a * (2 - 1)] };",
+                "let f = fn(a = -1) { [(a * 1)]; };",
+            ),
+            // Expressions which would fail are left to fail when evaluated.
+            (
+                "if (1 > 2) { 1 / 0 } else { {\"k\": 1 + 1} }",
+                "if false { (1 / 0); } else { {\"k\": 2}; };",
+            ),
+            ("-9223372036854775807 - 2", "(-9223372036854775807 - 2);"),
+        ];
+        for (input, expected) in tests {
+            let mut program = Parser::new(Lexer::new(input)).parse_program().unwrap();
+            optimize(&mut program);
+            assert_eq!(program.statements[0].to_string(), expected);
+        }
+    }
+}
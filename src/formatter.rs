@@ -0,0 +1,330 @@
+//! Formatter
+//!
+//! `formatter` prints parsed Monkey programs back as source code in a canonical layout:
+//! blocks are indented by four spaces, operators are spaced, parentheses appear only where precedence needs them,
+//! and statements end with semicolons (except for `if` expressions, and the final expression of a block).
+//!
+//! Anything which fits in the given width is kept on one line; otherwise blocks are broken over several lines,
+//! as are the elements of arrays, hashes and argument lists.
+#[cfg(test)]
+mod formatter_test;
+
+use crate::ast::{BlockStatement, Expression, Parameter, Program, Statement};
+use crate::parser::{token_precedence, Precedence};
+
+/// The width programs are formatted to, unless told otherwise.
+pub const DEFAULT_WIDTH: usize = 80;
+
+const INDENT: &str = "    ";
+
+/// Returns the source of `program`, formatted to fit within `width` columns where possible.
+pub fn format_program(program: &Program, width: usize) -> String {
+    let printer = Printer { width };
+    let mut out = String::new();
+    for statement in &program.statements {
+        out.push_str(&printer.statement(statement, 0, false));
+        out.push('\n');
+    }
+    out
+}
+
+struct Printer {
+    width: usize,
+}
+
+impl Printer {
+    // Returns `statement` as it appears at indentation `level`, without the indentation itself.
+    fn statement(&self, statement: &Statement, level: usize, last_in_block: bool) -> String {
+        let column = level * INDENT.len();
+        match statement {
+            Statement::Let(name, value) => {
+                let prefix = format!("let {} = ", name);
+                let value = self.expression(value, level, column + prefix.len());
+                format!("{}{};", prefix, value)
+            }
+            Statement::Return(value) => {
+                format!("return {};", self.expression(value, level, column + 7))
+            }
+            Statement::Assign(target, value) => {
+                let target = self.expression(target, level, column);
+                let value = self.expression(value, level, end_column(&target, column) + 3);
+                format!("{} = {};", target, value)
+            }
+            Statement::Expression(expr) => {
+                let text = self.expression(expr, level, column);
+                if last_in_block || matches!(expr, Expression::If(..)) {
+                    text
+                } else {
+                    text + ";"
+                }
+            }
+        }
+    }
+
+    // Returns `expr` starting at `column`, where lines it is broken into are indented by `level`.
+    fn expression(&self, expr: &Expression, level: usize, column: usize) -> String {
+        match flat(expr) {
+            Some(text) if column + text.len() <= self.width => text,
+            _ => self.broken(expr, level, column),
+        }
+    }
+
+    fn broken(&self, expr: &Expression, level: usize, column: usize) -> String {
+        match expr {
+            Expression::Prefix(token, operand, _) => {
+                let prefix = token.to_string();
+                let operand = self.operand(operand, Precedence::Prefix, level, column + 1);
+                prefix + &operand
+            }
+            Expression::Infix(left, token, right, _) => {
+                let precedence = token_precedence(token);
+                let left = self.operand(left, precedence, level, column);
+                let op = format!(" {} ", token);
+                let column = end_column(&left, column) + op.len();
+                let right = match needs_parentheses(right, &token_precedence(token), true) {
+                    true => format!("({})", self.expression(right, level, column + 1)),
+                    false => self.expression(right, level, column),
+                };
+                left + &op + &right
+            }
+            Expression::If(condition, consequence, alternative) => {
+                let condition = self.expression(condition, level, column + 4);
+                let mut text = format!("if ({}) {}", condition, self.block(consequence, level));
+                if let Some(alternative) = alternative {
+                    text.push_str(" else ");
+                    text.push_str(&self.block(alternative, level));
+                }
+                text
+            }
+            Expression::FunctionLiteral(parameters, body, _) => {
+                format!(
+                    "fn({}) {}",
+                    parameter_list(parameters),
+                    self.block(body, level)
+                )
+            }
+            Expression::Call(function, arguments, _) => {
+                let function = self.operand(function, Precedence::Call, level, column);
+                let column = end_column(&function, column);
+                function + &self.list("(", arguments, ")", level, column)
+            }
+            Expression::Index(target, index, _) => {
+                let target = self.operand(target, Precedence::Call, level, column);
+                let index = self.expression(index, level, end_column(&target, column) + 1);
+                format!("{}[{}]", target, index)
+            }
+            Expression::ArrayLiteral(elements) => self.list("[", elements, "]", level, column),
+            Expression::HashLiteral(pairs) => {
+                let inner = INDENT.repeat(level + 1);
+                let lines: Vec<String> = pairs
+                    .iter()
+                    .map(|(key, value)| {
+                        let key = self.expression(key, level + 1, inner.len());
+                        let column = end_column(&key, inner.len()) + 2;
+                        format!(
+                            "{}{}: {}",
+                            inner,
+                            key,
+                            self.expression(value, level + 1, column)
+                        )
+                    })
+                    .collect();
+                format!("{{\n{}\n{}}}", lines.join(",\n"), INDENT.repeat(level))
+            }
+            // Names and literals cannot be broken.
+            other => flat(other).unwrap_or_default(),
+        }
+    }
+
+    // Returns `expr` as the operand of an operator binding with `precedence`, in parentheses if need be.
+    fn operand(
+        &self,
+        expr: &Expression,
+        precedence: Precedence,
+        level: usize,
+        column: usize,
+    ) -> String {
+        if needs_parentheses(expr, &precedence, false) {
+            format!("({})", self.expression(expr, level, column + 1))
+        } else {
+            self.expression(expr, level, column)
+        }
+    }
+
+    // Returns `elements` between `open` and `close`.
+    //
+    // All but the last element are kept on the first line if they fit, in which case the last one may be broken;
+    // `f(xs, fn(x) { ... })` thus keeps its shape. Otherwise every element gets a line of its own.
+    fn list(
+        &self,
+        open: &str,
+        elements: &[Expression],
+        close: &str,
+        level: usize,
+        column: usize,
+    ) -> String {
+        if let Some((last, init)) = elements.split_last() {
+            let init: Option<Vec<String>> = init.iter().map(flat).collect();
+            if let Some(init) = init {
+                let mut head = open.to_string();
+                for element in init {
+                    head.push_str(&element);
+                    head.push_str(", ");
+                }
+                if column + head.len() < self.width {
+                    let last = self.expression(last, level, column + head.len());
+                    let first_line = last.lines().next().unwrap_or("");
+                    if column + head.len() + first_line.len() <= self.width {
+                        return head + &last + close;
+                    }
+                }
+            }
+        }
+        let inner = INDENT.repeat(level + 1);
+        let lines: Vec<String> = elements
+            .iter()
+            .map(|element| inner.clone() + &self.expression(element, level + 1, inner.len()))
+            .collect();
+        format!(
+            "{}\n{}\n{}{}",
+            open,
+            lines.join(",\n"),
+            INDENT.repeat(level),
+            close
+        )
+    }
+
+    fn block(&self, block: &BlockStatement, level: usize) -> String {
+        if block.statements.is_empty() {
+            return String::from("{}");
+        }
+        let inner = INDENT.repeat(level + 1);
+        let last = block.statements.len() - 1;
+        let lines: Vec<String> = block
+            .statements
+            .iter()
+            .enumerate()
+            .map(|(idx, statement)| {
+                inner.clone() + &self.statement(statement, level + 1, idx == last)
+            })
+            .collect();
+        format!("{{\n{}\n{}}}", lines.join("\n"), INDENT.repeat(level))
+    }
+}
+
+// Returns `expr` on a single line, or nothing if it holds a block of more than one statement.
+fn flat(expr: &Expression) -> Option<String> {
+    Some(match expr {
+        Expression::Ident(name) => name.clone(),
+        Expression::IntegerLiteral(value) => value.to_string(),
+        Expression::BooleanLiteral(value) => value.to_string(),
+        Expression::StringLiteral(value) => format!("\"{}\"", value),
+        Expression::CharLiteral(value) => format!("'{}'", value),
+        Expression::Prefix(token, operand, _) => {
+            format!("{}{}", token, flat_operand(operand, &Precedence::Prefix)?)
+        }
+        Expression::Infix(left, token, right, _) => {
+            let precedence = token_precedence(token);
+            let right = match needs_parentheses(right, &precedence, true) {
+                true => format!("({})", flat(right)?),
+                false => flat(right)?,
+            };
+            format!("{} {} {}", flat_operand(left, &precedence)?, token, right)
+        }
+        Expression::If(condition, consequence, alternative) => {
+            let mut text = format!("if ({}) {}", flat(condition)?, flat_block(consequence)?);
+            if let Some(alternative) = alternative {
+                text.push_str(" else ");
+                text.push_str(&flat_block(alternative)?);
+            }
+            text
+        }
+        Expression::FunctionLiteral(parameters, body, _) => {
+            format!("fn({}) {}", parameter_list(parameters), flat_block(body)?)
+        }
+        Expression::Call(function, arguments, _) => format!(
+            "{}({})",
+            flat_operand(function, &Precedence::Call)?,
+            flat_list(arguments)?
+        ),
+        Expression::Index(target, index, _) => format!(
+            "{}[{}]",
+            flat_operand(target, &Precedence::Call)?,
+            flat(index)?
+        ),
+        Expression::ArrayLiteral(elements) => format!("[{}]", flat_list(elements)?),
+        Expression::HashLiteral(pairs) => {
+            let pairs: Option<Vec<String>> = pairs
+                .iter()
+                .map(|(key, value)| Some(format!("{}: {}", flat(key)?, flat(value)?)))
+                .collect();
+            format!("{{{}}}", pairs?.join(", "))
+        }
+    })
+}
+
+fn flat_operand(expr: &Expression, precedence: &Precedence) -> Option<String> {
+    if needs_parentheses(expr, precedence, false) {
+        Some(format!("({})", flat(expr)?))
+    } else {
+        flat(expr)
+    }
+}
+
+fn flat_list(elements: &[Expression]) -> Option<String> {
+    let elements: Option<Vec<String>> = elements.iter().map(flat).collect();
+    Some(elements?.join(", "))
+}
+
+fn flat_block(block: &BlockStatement) -> Option<String> {
+    match block.statements.as_slice() {
+        [] => Some(String::from("{}")),
+        [statement] => {
+            let text = match statement {
+                Statement::Let(name, value) => format!("let {} = {};", name, flat(value)?),
+                Statement::Return(value) => format!("return {};", flat(value)?),
+                Statement::Assign(target, value) => {
+                    format!("{} = {};", flat(target)?, flat(value)?)
+                }
+                Statement::Expression(expr) => flat(expr)?,
+            };
+            Some(format!("{{ {} }}", text))
+        }
+        _ => None,
+    }
+}
+
+fn parameter_list(parameters: &[Parameter]) -> String {
+    parameters
+        .iter()
+        .map(|parameter| match &parameter.default {
+            Some(default) => format!(
+                "{} = {}",
+                parameter.name,
+                flat(default).unwrap_or_else(|| default.to_string())
+            ),
+            None => parameter.name.clone(),
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+// Returns whether `expr` must be parenthesized as an operand of an operator binding with `precedence`.
+//
+// Operators associate to the left, so a right operand of equal precedence needs parentheses too.
+fn needs_parentheses(expr: &Expression, precedence: &Precedence, right: bool) -> bool {
+    let own = match expr {
+        Expression::Infix(_, token, _, _) => token_precedence(token),
+        Expression::Prefix(..) => Precedence::Prefix,
+        _ => return false,
+    };
+    own < *precedence || (right && own == *precedence)
+}
+
+// Returns the column at which `text`, starting at `column`, ends.
+fn end_column(text: &str, column: usize) -> usize {
+    match text.rfind('\n') {
+        Some(idx) => text.len() - idx - 1,
+        None => column + text.len(),
+    }
+}
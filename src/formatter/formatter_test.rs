@@ -0,0 +1,81 @@
+use super::*;
+
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+
+fn format(input: &str, width: usize) -> String {
+    let program = Parser::new(Lexer::new(input)).parse_program().unwrap();
+    format_program(&program, width)
+}
+
+#[test]
+fn format_program_test() {
+    let tests = vec![
+        ("let   x=1+2*3;", "let x = 1 + 2 * 3;\n"),
+        (
+            "(1 + 2) * 3; 1 - (2 - 3); (1 - 2) - 3",
+            "(1 + 2) * 3;\n1 - (2 - 3);\n1 - 2 - 3;\n",
+        ),
+        ("-(a + b); !true; -x * y", "-(a + b);\n!true;\n-x * y;\n"),
+        (
+            "let f = fn(x, y = 2) { x * y };",
+            "let f = fn(x, y = 2) { x * y };\n",
+        ),
+        (
+            "if (x > 1) { 1 } else { 2 }",
+            "if (x > 1) { 1 } else { 2 }\n",
+        ),
+        (
+            "let f = fn(x) { let y = x; return y; };",
+            "let f = fn(x) {\n    let y = x;\n    return y;\n};\n",
+        ),
+        (
+            "a[0](1); f(x)[0]; {\"a\": [1, 'b']}",
+            "a[0](1);\nf(x)[0];\n{\"a\": [1, 'b']};\n",
+        ),
+        ("x = 1; a[0] = fn() {}", "x = 1;\na[0] = fn() {};\n"),
+    ];
+    for (input, want) in tests {
+        assert_eq!(format(input, DEFAULT_WIDTH), want, "formatting {}", input);
+    }
+}
+
+#[test]
+fn line_width_test() {
+    let tests = vec![
+        (
+            "map(numbers, fn(x) { x * 2 })",
+            20,
+            "map(numbers, fn(x) {\n    x * 2\n});\n",
+        ),
+        (
+            "let xs = [100, 200, 300];",
+            16,
+            "let xs = [\n    100,\n    200,\n    300\n];\n",
+        ),
+        (
+            "let h = {\"one\": 1, \"two\": 2};",
+            20,
+            "let h = {\n    \"one\": 1,\n    \"two\": 2\n};\n",
+        ),
+    ];
+    for (input, width, want) in tests {
+        assert_eq!(format(input, width), want, "formatting {}", input);
+    }
+}
+
+#[test]
+fn round_trip_test() {
+    let input = "let reduce = fn(arr, initial, f) { let iter = fn(arr, result) { if (len(arr) == 0) { result } \
+                 else { iter(rest(arr), f(result, first(arr))) } }; iter(arr, initial); }; \
+                 let total = reduce([1, 2, 3, 4, 5], 0, fn(a, b) { a + b }); \
+                 puts({\"total\": total, \"negated\": -total, \"check\": !(total > 10)});";
+    for width in [10, 40, DEFAULT_WIDTH] {
+        let formatted = format(input, width);
+        // Formatting is idempotent, and keeps the meaning of the program.
+        assert_eq!(format(&formatted, width), formatted);
+        let original = Parser::new(Lexer::new(input)).parse_program().unwrap();
+        let reparsed = Parser::new(Lexer::new(&formatted)).parse_program().unwrap();
+        assert_eq!(reparsed.to_string(), original.to_string());
+    }
+}
@@ -1,7 +1,8 @@
 //! Lexer
 //!
 //! `lexer` contains functionality for lexing raw input, i.e., converting input strings to a sequence of Monkey tokens.
-//! The public interface is simply the `Lexer` type, which performs all the heavy lifting.
+//! The public interface is simply the `Lexer` type, which performs all the heavy lifting, plus `tokenize` for when
+//! only the tokens are wanted.
 
 use crate::token::lookup_ident;
 use crate::token::Token;
@@ -17,20 +18,55 @@ fn is_valid_name_start_symbol(ch: &char) -> bool {
     ch.is_alphabetic() || *ch == '_'
 }
 
+/// A place in the input, as a line and a column (in characters) both counted from 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Returns the tokens of `input`, up to but not including the end of the input.
+pub fn tokenize(input: &str) -> Vec<Token> {
+    Lexer::new(input).map(|(token, _)| token).collect()
+}
+
 /// A struct wrapping a raw input string for lexing.
+///
+/// Iterating over a lexer yields the tokens left in the input along with where each starts, up to but not including
+/// the end of the input.
 pub struct Lexer<'a> {
     input: Peekable<Chars<'a>>,
     peek_buffer: Token,
+    // Where the next character of the input is.
+    next_position: Position,
+    // Where the buffered token starts.
+    peek_position: Position,
+    // Where the token most recently returned by `next_token` or `peek_token` starts.
+    token_position: Position,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(input: &str) -> Lexer {
+        let start = Position { line: 1, column: 1 };
         Lexer {
             input: input.chars().peekable(),
             peek_buffer: Token::Null,
+            next_position: start,
+            peek_position: start,
+            token_position: start,
         }
     }
 
+    /// Makes the input count as starting on `line`, as when it continues earlier input.
+    pub fn set_first_line(&mut self, line: usize) {
+        self.next_position.line = line;
+    }
+
+    /// Returns where the token most recently returned by `next_token` or `peek_token` starts.
+    pub fn position(&self) -> Position {
+        self.token_position
+    }
+
     /// Returns a reference to the next token to be lexed from the input stream.
     ///
     /// Calling `peek_token` does not advance to the next token, so calling it twice in a row returns the same result.
@@ -39,7 +75,9 @@ impl<'a> Lexer<'a> {
         // Otherwise, we must populate the buffer.
         if self.peek_buffer == Token::Null {
             self.peek_buffer = self.next_token_from_input();
+            self.peek_position = self.token_position;
         }
+        self.token_position = self.peek_position;
         &self.peek_buffer
     }
 
@@ -51,16 +89,34 @@ impl<'a> Lexer<'a> {
         // If so, the next token is in the buffer.
         match self.peek_buffer {
             Token::Null => self.next_token_from_input(),
-            _ => std::mem::replace(&mut self.peek_buffer, Token::Null),
+            _ => {
+                self.token_position = self.peek_position;
+                std::mem::replace(&mut self.peek_buffer, Token::Null)
+            }
         }
     }
 
+    fn next_char(&mut self) -> Option<char> {
+        let ch = self.input.next();
+        match ch {
+            Some('\n') => {
+                self.next_position.line += 1;
+                self.next_position.column = 1;
+            }
+            Some(_) => self.next_position.column += 1,
+            None => {}
+        }
+        ch
+    }
+
+    // Lexes the next token from the input, and records where it starts as the position of the current token.
     fn next_token_from_input(&mut self) -> Token {
         self.skip_whitespace();
-        match self.input.next() {
+        self.token_position = self.next_position;
+        match self.next_char() {
             Some('=') => {
                 if let Some('=') = self.input.peek() {
-                    self.input.next();
+                    self.next_char();
                     return Token::Equal;
                 }
                 return Token::Assign;
@@ -82,13 +138,14 @@ impl<'a> Lexer<'a> {
             Some(':') => Token::Colon,
             Some('!') => {
                 if let Some('=') = self.input.peek() {
-                    let _ = self.input.next();
+                    let _ = self.next_char();
                     return Token::NotEqual;
                 }
                 return Token::Bang;
             }
             None => Token::EndOfFile,
             Some('"') => self.read_string(),
+            Some('\'') => self.read_char(),
             Some(a) => {
                 if is_valid_name_start_symbol(&a) {
                     return lookup_ident(self.read_identifier(a));
@@ -105,7 +162,7 @@ impl<'a> Lexer<'a> {
             if !ch.is_whitespace() {
                 return;
             }
-            self.input.next();
+            self.next_char();
         }
     }
 
@@ -116,7 +173,7 @@ impl<'a> Lexer<'a> {
             if !ch.is_numeric() {
                 break;
             }
-            if let Some(ch) = self.input.next() {
+            if let Some(ch) = self.next_char() {
                 ident.push(ch);
             }
         }
@@ -131,7 +188,7 @@ impl<'a> Lexer<'a> {
             if !is_valid_name_symbol(ch) {
                 break;
             }
-            if let Some(ch) = self.input.next() {
+            if let Some(ch) = self.next_char() {
                 ident.push(ch);
             }
         }
@@ -142,7 +199,7 @@ impl<'a> Lexer<'a> {
         // If the string is the final token of the input, the closing quote may be ignored.
         // TODO: Consider changing this to throw an error.
         let mut string = String::new();
-        while let Some(ch) = self.input.next() {
+        while let Some(ch) = self.next_char() {
             if ch == '"' {
                 break;
             }
@@ -150,6 +207,25 @@ impl<'a> Lexer<'a> {
         }
         return Token::Str(string);
     }
+
+    fn read_char(&mut self) -> Token {
+        // A character literal holds exactly one character between single quotes.
+        match (self.next_char(), self.next_char()) {
+            (Some(ch), Some('\'')) if ch != '\'' => Token::Char(ch),
+            _ => Token::Illegal,
+        }
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = (Token, Position);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_token() {
+            Token::EndOfFile => None,
+            token => Some((token, self.token_position)),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -184,6 +260,42 @@ mod tests {
         }
     }
 
+    #[test]
+    fn position_test() {
+        let mut lexer = Lexer::new("let x =\n  \"é\" + 'a';");
+        let tests = vec![
+            (Token::Let, 1, 1),
+            (Token::Ident(String::from("x")), 1, 5),
+            (Token::Assign, 1, 7),
+            (Token::Str(String::from("é")), 2, 3),
+            (Token::Plus, 2, 7),
+            (Token::Char('a'), 2, 9),
+            (Token::Semicolon, 2, 12),
+            (Token::EndOfFile, 2, 13),
+        ];
+        for (token, line, column) in tests {
+            assert_eq!(*lexer.peek_token(), token);
+            assert_eq!(lexer.position(), Position { line, column });
+            assert_eq!(lexer.next_token(), token);
+            assert_eq!(lexer.position(), Position { line, column });
+        }
+    }
+
+    #[test]
+    fn char_literal_test() {
+        let tests = vec![
+            ("'a'", Token::Char('a')),
+            ("' '", Token::Char(' ')),
+            ("'\"'", Token::Char('"')),
+            ("''", Token::Illegal),
+            ("'ab'", Token::Illegal),
+            ("'a", Token::Illegal),
+        ];
+        for (input, want) in tests {
+            assert_eq!(Lexer::new(input).next_token(), want);
+        }
+    }
+
     #[test]
     fn next_token_harder_test() {
         let sample_input = "let five = 5;
@@ -286,4 +398,36 @@ mod tests {
             assert_eq!(tok, t);
         }
     }
+
+    #[test]
+    fn iterator_test() {
+        assert_eq!(
+            tokenize("let x = 5;"),
+            vec![
+                Token::Let,
+                Token::Ident(String::from("x")),
+                Token::Assign,
+                Token::Integer(5),
+                Token::Semicolon,
+            ]
+        );
+        assert!(tokenize(" \n").is_empty());
+
+        let mut lexer = Lexer::new("a\n  + 1");
+        assert_eq!(lexer.peek_token(), &Token::Ident(String::from("a")));
+        // A peeked token is the first to be yielded.
+        let tokens: Vec<(Token, usize, usize)> = lexer
+            .by_ref()
+            .map(|(token, position)| (token, position.line, position.column))
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                (Token::Ident(String::from("a")), 1, 1),
+                (Token::Plus, 2, 3),
+                (Token::Integer(1), 2, 5),
+            ]
+        );
+        assert_eq!(lexer.next(), None);
+    }
 }
@@ -1,19 +1,43 @@
 //! Orangutan
 //!
 //! `orangutan` is a rust implementation of the Monkey language.
-//! The public interface consists only of the simple read-eval-print-loop in the `repl` module.
+//! The public interface consists only of the simple read-eval-print-loop in the `repl` module,
+//! plus the `benchmark`, `script` and `visualize` entry points used by the binary and the `error` type they return.
+//! Programs parsed with `parse_str` are syntax trees from the `ast` module, which tools can walk with its `Visitor`,
+//! and `tokenize` gives the `Token`s of source for tools which need no more, such as highlighters.
+//! Programs given as strings can be evaluated with `eval_str`, or compiled with `compile_str`, by code using the
+//! crate as a library; the `engine` module runs code a piece at a time, keeping what each piece defines.
+//! With the `wasm` feature, the `wasm` module exposes the interpreter to JavaScript.
 //!
 //! Documentation also exists for the private modules within the package (run `cargo doc --document-private-items`).
 extern crate num_enum;
 
-mod ast;
+pub mod ast;
 pub mod benchmark;
+#[cfg(all(test, feature = "book-suite"))]
+mod book_test;
 mod code;
+mod color;
 mod compiler;
+pub mod engine;
+pub mod error;
 mod evaluator;
+mod formatter;
 mod lexer;
+mod linter;
 mod object;
 mod parser;
+mod prelude;
 pub mod repl;
+pub mod script;
 mod token;
+pub mod visualize;
 mod vm;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use self::code::Bytecode;
+pub use self::lexer::tokenize;
+/// The value a Monkey program evaluates to.
+pub use self::object::Object as Value;
+pub use self::script::{compile_str, eval_str, parse_str};
@@ -0,0 +1,308 @@
+//! Linter
+//!
+//! `linter` looks over parsed Monkey programs for code which is legal but likely to be a mistake:
+//!
+//! * variables defined with `let` which are never used (names starting with `_` are exempt);
+//! * statements following a `return` in the same block, which can never run;
+//! * variables and parameters named after built-in functions, hiding them;
+//! * `==` and `!=` between values which are of different types, and so never equal.
+//!
+//! The syntax tree records where statements start but not where the expressions within them are, so warnings are
+//! placed by matching the nodes they concern with the tokens of the source: the `n`th `let` statement met, in source order, is the `n`th `let` token, and
+//! so on for `return`, `fn`, `==` and `!=`.
+#[cfg(test)]
+mod linter_test;
+
+use crate::ast::{
+    walk_expression, walk_statement, BlockStatement, Expression, Program, Statement, Visitor,
+};
+use crate::lexer::{Lexer, Position};
+use crate::object::BuiltIn;
+use crate::token::Token;
+use std::collections::HashMap;
+use std::fmt;
+
+/// A likely mistake found by the linter, and where in the source it is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    pub position: Position,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: warning: {}",
+            self.position.line, self.position.column, self.message
+        )
+    }
+}
+
+/// Returns the warnings for `program`, which was parsed from `source`, ordered by position.
+pub fn lint(source: &str, program: &Program) -> Vec<Warning> {
+    let mut linter = Linter {
+        locator: Locator::new(source),
+        builtins: BuiltIn::all().iter().map(|b| b.name()).collect(),
+        scopes: vec![HashMap::new()],
+        warnings: vec![],
+    };
+    linter.visit_program(program);
+    linter.leave_scope();
+    linter
+        .warnings
+        .sort_by_key(|w| (w.position.line, w.position.column));
+    linter.warnings
+}
+
+// A variable defined with `let`, and whether it has been used since.
+struct Binding {
+    position: Position,
+    used: bool,
+}
+
+struct Linter {
+    locator: Locator,
+    builtins: Vec<String>,
+    // Innermost last; function literals open a scope, as does the program as a whole.
+    scopes: Vec<HashMap<String, Binding>>,
+    warnings: Vec<Warning>,
+}
+
+impl Linter {
+    fn warn(&mut self, position: Position, message: String) {
+        self.warnings.push(Warning { position, message });
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        let mut returned = false;
+        for (idx, statement) in statements.iter().enumerate() {
+            if let Statement::Return(_) = statement {
+                if !returned && idx + 1 < statements.len() {
+                    let position = self.locator.peek(&Token::Return);
+                    self.warn(position, String::from("code after `return` is unreachable"));
+                }
+                returned = true;
+            }
+            self.visit_statement(statement);
+        }
+    }
+
+    fn define(&mut self, name: &str, position: Position) {
+        if self.builtins.iter().any(|builtin| builtin == name) {
+            self.warn(
+                position,
+                format!("`{}` hides the built-in function of the same name", name),
+            );
+        }
+        let binding = Binding {
+            position,
+            used: name.starts_with('_'),
+        };
+        let scope = self.scopes.last_mut().unwrap();
+        if let Some(replaced) = scope.insert(name.to_string(), binding) {
+            if !replaced.used {
+                self.warn(replaced.position, unused(name));
+            }
+        }
+    }
+
+    fn use_name(&mut self, name: &str) {
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(binding) = scope.get_mut(name) {
+                binding.used = true;
+                return;
+            }
+        }
+    }
+
+    fn leave_scope(&mut self) {
+        if let Some(scope) = self.scopes.pop() {
+            for (name, binding) in scope {
+                if !binding.used {
+                    self.warn(binding.position, unused(&name));
+                }
+            }
+        }
+    }
+}
+
+impl Visitor for Linter {
+    fn visit_program(&mut self, program: &Program) {
+        self.statements(&program.statements);
+    }
+
+    fn visit_block(&mut self, block: &BlockStatement) {
+        self.statements(&block.statements);
+    }
+
+    fn visit_statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Let(name, value) => {
+                let position = self.locator.next(&Token::Let);
+                self.visit_expression(value);
+                self.define(name, position);
+            }
+            Statement::Return(_) => {
+                self.locator.next(&Token::Return);
+                walk_statement(self, statement);
+            }
+            Statement::Assign(target, value) => {
+                // Assigning to a variable does not use it, but the parts of an index expression are used.
+                if let Expression::Index(target, index, _) = target {
+                    self.visit_expression(target);
+                    self.visit_expression(index);
+                }
+                self.visit_expression(value);
+            }
+            Statement::Expression(_) => walk_statement(self, statement),
+        }
+    }
+
+    fn visit_expression(&mut self, expr: &Expression) {
+        match expr {
+            Expression::Ident(name) => self.use_name(name),
+            Expression::Infix(left, token, right, _)
+                if *token == Token::Equal || *token == Token::NotEqual =>
+            {
+                self.visit_expression(left);
+                let position = self.locator.next(token);
+                if let (Some(left), Some(right)) = (static_type(left), static_type(right)) {
+                    if left != right {
+                        self.warn(
+                            position,
+                            format!(
+                                "`{}` compares values of different types ({} and {})",
+                                token, left, right
+                            ),
+                        );
+                    }
+                }
+                self.visit_expression(right);
+            }
+            Expression::FunctionLiteral(parameters, body, _) => {
+                let position = self.locator.next(&Token::Function);
+                let mut scope = HashMap::new();
+                for parameter in parameters {
+                    if let Some(default) = &parameter.default {
+                        self.visit_expression(default);
+                    }
+                    if self.builtins.contains(&parameter.name) {
+                        self.warn(
+                            position,
+                            format!(
+                                "parameter `{}` hides the built-in function of the same name",
+                                parameter.name
+                            ),
+                        );
+                    }
+                    // Parameters are often unused by design, as with callbacks, so they count as used.
+                    scope.insert(
+                        parameter.name.clone(),
+                        Binding {
+                            position,
+                            used: true,
+                        },
+                    );
+                }
+                self.scopes.push(scope);
+                self.visit_block(body);
+                self.leave_scope();
+            }
+            other => walk_expression(self, other),
+        }
+    }
+}
+
+pub(crate) fn unused(name: &str) -> String {
+    format!("variable `{}` is never used", name)
+}
+
+// Returns the type `expr` always evaluates to, where that is plain from the expression alone.
+fn static_type(expr: &Expression) -> Option<&'static str> {
+    match expr {
+        Expression::IntegerLiteral(_) => Some("INTEGER"),
+        Expression::BooleanLiteral(_) => Some("BOOLEAN"),
+        Expression::StringLiteral(_) => Some("STRING"),
+        Expression::CharLiteral(_) => Some("CHAR"),
+        Expression::ArrayLiteral(_) => Some("ARRAY"),
+        Expression::HashLiteral(_) => Some("HASH"),
+        Expression::FunctionLiteral(..) => Some("FUNCTION"),
+        Expression::Prefix(Token::Bang, ..) => Some("BOOLEAN"),
+        Expression::Prefix(Token::Minus, operand, _) => match static_type(operand) {
+            Some("INTEGER") => Some("INTEGER"),
+            _ => None,
+        },
+        Expression::Infix(left, token, right, _) => match token {
+            Token::Equal | Token::NotEqual | Token::LessThan | Token::GreaterThan => {
+                Some("BOOLEAN")
+            }
+            Token::Plus | Token::Minus | Token::Asterisk | Token::Slash => {
+                match (static_type(left), static_type(right)) {
+                    (Some("INTEGER"), Some("INTEGER")) => Some("INTEGER"),
+                    (Some("STRING"), Some("STRING")) if *token == Token::Plus => Some("STRING"),
+                    _ => None,
+                }
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// Finds the positions of the tokens which syntax tree nodes of interest start with, in source order.
+struct Locator {
+    tokens: HashMap<Kind, Vec<Position>>,
+    seen: HashMap<Kind, usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Kind {
+    Let,
+    Return,
+    Function,
+    Equality,
+}
+
+fn kind(token: &Token) -> Option<Kind> {
+    match token {
+        Token::Let => Some(Kind::Let),
+        Token::Return => Some(Kind::Return),
+        Token::Function => Some(Kind::Function),
+        Token::Equal | Token::NotEqual => Some(Kind::Equality),
+        _ => None,
+    }
+}
+
+impl Locator {
+    fn new(source: &str) -> Self {
+        let mut tokens: HashMap<Kind, Vec<Position>> = HashMap::new();
+        for (token, position) in Lexer::new(source) {
+            if let Some(kind) = kind(&token) {
+                tokens.entry(kind).or_default().push(position);
+            }
+        }
+        Locator {
+            tokens,
+            seen: HashMap::new(),
+        }
+    }
+
+    // Returns the position of the next token like `token`, without moving past it.
+    fn peek(&self, token: &Token) -> Position {
+        let kind = kind(token).expect("only some tokens are located");
+        let idx = self.seen.get(&kind).copied().unwrap_or(0);
+        self.tokens
+            .get(&kind)
+            .and_then(|positions| positions.get(idx))
+            .copied()
+            .unwrap_or(Position { line: 1, column: 1 })
+    }
+
+    // Returns the position of the next token like `token`, and moves past it.
+    fn next(&mut self, token: &Token) -> Position {
+        let position = self.peek(token);
+        *self.seen.entry(kind(token).unwrap()).or_default() += 1;
+        position
+    }
+}
@@ -0,0 +1,90 @@
+use super::*;
+
+use crate::parser::Parser;
+
+fn warnings(input: &str) -> Vec<String> {
+    let program = Parser::new(Lexer::new(input)).parse_program().unwrap();
+    lint(input, &program)
+        .iter()
+        .map(|warning| warning.to_string())
+        .collect()
+}
+
+#[test]
+fn unused_variable_test() {
+    let tests = vec![
+        ("let x = 1; x", vec![]),
+        (
+            "let x = 1;",
+            vec!["1:1: warning: variable `x` is never used"],
+        ),
+        ("let _x = 1;", vec![]),
+        (
+            "let f = fn(a, b) { let c = a;\n  b };\nf(1, 2)",
+            vec!["1:20: warning: variable `c` is never used"],
+        ),
+        // Closures use the variables of the functions enclosing them.
+        ("let f = fn() { let n = 1; fn() { n } }; f()", vec![]),
+        (
+            "let x = 1; let x = 2; x",
+            vec!["1:1: warning: variable `x` is never used"],
+        ),
+        // Assigning to a variable is not using it.
+        (
+            "let x = 1; x = 2;",
+            vec!["1:1: warning: variable `x` is never used"],
+        ),
+    ];
+    for (input, want) in tests {
+        assert_eq!(warnings(input), want, "linting {}", input);
+    }
+}
+
+#[test]
+fn unreachable_code_test() {
+    let tests = vec![
+        ("let f = fn() { return 1; }; f()", vec![]),
+        (
+            "let f = fn() {\n  return 1;\n  2\n}; f()",
+            vec!["2:3: warning: code after `return` is unreachable"],
+        ),
+    ];
+    for (input, want) in tests {
+        assert_eq!(warnings(input), want, "linting {}", input);
+    }
+}
+
+#[test]
+fn shadowed_builtin_test() {
+    let tests = vec![
+        (
+            "let len = 1; len",
+            vec!["1:1: warning: `len` hides the built-in function of the same name"],
+        ),
+        (
+            "let f = fn(puts) { puts }; f(1)",
+            vec!["1:9: warning: parameter `puts` hides the built-in function of the same name"],
+        ),
+    ];
+    for (input, want) in tests {
+        assert_eq!(warnings(input), want, "linting {}", input);
+    }
+}
+
+#[test]
+fn mismatched_comparison_test() {
+    let tests = vec![
+        ("1 == 2; \"a\" != \"b\"; x == 1", vec![]),
+        (
+            "1 == 2; 1 + 2 == \"3\"",
+            vec!["1:15: warning: `==` compares values of different types (INTEGER and STRING)"],
+        ),
+        (
+            "!x != -1",
+            vec!["1:4: warning: `!=` compares values of different types (BOOLEAN and INTEGER)"],
+        ),
+    ];
+    for (input, want) in tests {
+        assert_eq!(warnings(input), want, "linting {}", input);
+    }
+}
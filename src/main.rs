@@ -1,21 +1,267 @@
 extern crate orangutan;
 use std::env;
+use std::io::{self, IsTerminal};
+use std::path::Path;
+use std::process;
+use std::thread;
+use std::time::Duration;
 
-fn main() -> Result<(), std::io::Error> {
+use orangutan::error::Error;
+use orangutan::script::{Limits, Settings};
+
+// Flags which are not passed on to programs, along with the values following those in `OPTIONS`.
+const SWITCHES: [&str; 7] = [
+    "--compile",
+    "--no-prelude",
+    "--disassemble",
+    "--ast",
+    "--profile",
+    "--trace",
+    "--no-color",
+];
+// Prefixes the choice of how errors are printed: `human` (the default) or `json`.
+const ERROR_FORMAT: &str = "--error-format=";
+// Setting this environment variable to anything has the same effect as `--trace`.
+const TRACE_VARIABLE: &str = "ORANGUTAN_TRACE";
+const OPTIONS: [&str; 5] = [
+    "--stack-size",
+    "--max-frames",
+    "--globals-size",
+    "--fuel",
+    "--timeout",
+];
+// The size of the stack programs are run on. Parsing, compiling and evaluating expressions recurse into the ones
+// they contain, so deeply nested source code needs more room than the main thread has.
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+// Returns the command-line arguments from the `skip`th on, leaving out the flags above.
+fn operands(skip: usize) -> Vec<String> {
+    let mut operands = vec![];
+    let mut args = env::args().skip(skip);
+    while let Some(arg) = args.next() {
+        if OPTIONS.contains(&arg.as_str()) {
+            args.next();
+        } else if !SWITCHES.contains(&arg.as_str()) && !arg.starts_with(ERROR_FORMAT) {
+            operands.push(arg);
+        }
+    }
+    operands
+}
+
+// Returns the number given for the option `name`, or `default` if it is not given.
+fn size_option(name: &str, default: usize) -> Result<usize, Error> {
+    match env::args().position(|arg| arg == name) {
+        None => Ok(default),
+        Some(idx) => match env::args().nth(idx + 1).and_then(|arg| arg.parse().ok()) {
+            Some(size) if size > 0 => Ok(size),
+            _ => Err(Error::Usage(format!("{} takes a positive number", name))),
+        },
+    }
+}
+
+// Returns the limits the command line gives the virtual machine and, for calls, fuel and time, the interpreter.
+fn limits() -> Result<Limits, Error> {
+    let default = Limits::default();
+    Ok(Limits {
+        stack_size: size_option("--stack-size", default.stack_size)?,
+        max_frames: size_option("--max-frames", default.max_frames)?,
+        globals_size: size_option("--globals-size", default.globals_size)?,
+        fuel: match env::args().any(|arg| arg == "--fuel") {
+            true => Some(size_option("--fuel", 0)?),
+            false => None,
+        },
+        // Given in milliseconds.
+        timeout: match env::args().any(|arg| arg == "--timeout") {
+            true => Some(Duration::from_millis(size_option("--timeout", 0)? as u64)),
+            false => None,
+        },
+    })
+}
+
+// Returns the settings the command line gives, with `args` for the program to see.
+fn settings(args: Vec<String>) -> Result<Settings, Error> {
+    Ok(Settings {
+        compile: env::args().any(|arg| arg == "--compile"),
+        prelude: !env::args().any(|arg| arg == "--no-prelude"),
+        limits: limits()?,
+        args,
+        ..Settings::default()
+    })
+}
+
+// Starts the REPL, unless standard input is not a terminal, in which case it is run as a program instead.
+fn repl() -> Result<(), Error> {
+    if io::stdin().is_terminal() {
+        orangutan::repl::start(settings(vec![])?, color(io::stdout().is_terminal()))
+    } else {
+        orangutan::repl::run_stdin(settings(vec![])?)
+    }
+}
+
+fn usage(usage: &str) -> Result<(), Error> {
+    Err(Error::Usage(format!("Usage: {}", usage)))
+}
+
+// Returns whether errors are to be printed as JSON, for tools, rather than for people.
+fn json_errors() -> Result<bool, Error> {
+    let format = env::args().find_map(|arg| arg.strip_prefix(ERROR_FORMAT).map(String::from));
+    match format.as_deref() {
+        None | Some("human") => Ok(false),
+        Some("json") => Ok(true),
+        Some(other) => Err(Error::Usage(format!(
+            "Unknown error format {}; expected human or json",
+            other
+        ))),
+    }
+}
+
+// Returns whether output to a stream, which is a terminal if `terminal` is set, should be colored.
+//
+// Color is turned off by `--no-color`, or by setting NO_COLOR to anything but the empty string.
+fn color(terminal: bool) -> bool {
+    terminal
+        && !env::args().any(|arg| arg == "--no-color")
+        && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+}
+
+fn main() {
+    let runner = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(report)
+        .expect("could not start the thread to run on");
+    // A panic has been reported by the thread already.
+    if runner.join().is_err() {
+        process::exit(101);
+    }
+}
+
+// Errors are printed to standard error, and the process exits with the status matching their kind.
+fn report() {
+    let (json, result) = match json_errors() {
+        Ok(json) => (json, run()),
+        Err(error) => (false, Err(error)),
+    };
+    if let Err(error) = result {
+        if json {
+            eprintln!("{}", error.to_json());
+        } else if color(io::stderr().is_terminal()) {
+            eprintln!("{}", error.to_colored_string());
+        } else {
+            eprintln!("{}", error);
+        }
+        process::exit(error.exit_code());
+    }
+}
+
+fn run() -> Result<(), Error> {
     let compile = env::args().any(|arg| arg == "--compile");
+    let prelude = !env::args().any(|arg| arg == "--no-prelude");
+    if let Some(idx) = env::args().position(|arg| arg == "-e" || arg == "--eval") {
+        // The source follows the flag; any arguments after it, other than the flags above, go to the program.
+        let mut rest = operands(idx + 1).into_iter();
+        return match rest.next() {
+            Some(source) => orangutan::script::eval(&source, settings(rest.collect())?),
+            None => usage("orangutan -e <source> [--compile] [--no-prelude] [args...]"),
+        };
+    }
     let repl_or_benchmark = env::args().nth(1);
     match repl_or_benchmark {
         Some(repl_or_benchmark) => match repl_or_benchmark.as_ref() {
-            "repl" => orangutan::repl::start(compile),
+            "repl" => repl(),
             "bench" => {
-                orangutan::benchmark::start(compile);
+                match env::args().nth(2).as_deref() {
+                    Some("hash") => orangutan::benchmark::start_hash_display(),
+                    _ => orangutan::benchmark::start(
+                        compile,
+                        env::args().any(|arg| arg == "--profile"),
+                    ),
+                }
                 Ok(())
             }
-            _ => {
-                println!("Unrecognized input!");
-                Ok(())
+            "run" => {
+                // Everything after the path, other than the flags above, is passed on to the script.
+                let disassemble = env::args().any(|arg| arg == "--disassemble");
+                let ast = env::args().any(|arg| arg == "--ast");
+                let profile = env::args().any(|arg| arg == "--profile");
+                let trace = env::args().any(|arg| arg == "--trace")
+                    || env::var_os(TRACE_VARIABLE).is_some();
+                let mut rest = operands(2).into_iter();
+                match rest.next() {
+                    Some(path) if ast => orangutan::script::syntax_tree(&path),
+                    Some(path) if disassemble => orangutan::script::disassemble(&path, prelude),
+                    // Tracing and profiling watch the virtual machine, so they imply --compile.
+                    Some(path) if trace => {
+                        orangutan::script::trace(&path, settings(rest.collect())?)
+                    }
+                    Some(path) if profile => {
+                        orangutan::script::profile(&path, settings(rest.collect())?)
+                    }
+                    Some(path) => orangutan::script::start(&path, settings(rest.collect())?),
+                    None => usage("orangutan run <file> [--compile] [--no-prelude] [--disassemble] [--ast] [--profile] [--trace] [args...]"),
+                }
+            }
+            "build" => {
+                let path = env::args().nth(2).filter(|arg| !arg.starts_with("--"));
+                let output = match env::args().position(|arg| arg == "-o") {
+                    Some(idx) => env::args().nth(idx + 1),
+                    None => path
+                        .as_ref()
+                        .map(|path| Path::new(path).with_extension("mkc").display().to_string()),
+                };
+                match (path, output) {
+                    (Some(path), Some(output)) => orangutan::script::build(&path, &output, prelude),
+                    _ => usage("orangutan build <file> [-o <output>] [--no-prelude]"),
+                }
+            }
+            "fmt" => {
+                let width = match env::args().position(|arg| arg == "--width") {
+                    Some(idx) => env::args().nth(idx + 1).and_then(|arg| arg.parse().ok()),
+                    None => Some(orangutan::script::FORMAT_WIDTH),
+                };
+                match (env::args().nth(2), width) {
+                    (Some(path), Some(width)) if !path.starts_with("--") => {
+                        orangutan::script::format(&path, width)
+                    }
+                    _ => usage("orangutan fmt <file> [--width <columns>]"),
+                }
+            }
+            "lint" => match env::args().nth(2) {
+                Some(path) => {
+                    if !orangutan::script::lint(&path)? {
+                        process::exit(orangutan::error::RUNTIME_EXIT_CODE);
+                    }
+                    Ok(())
+                }
+                None => usage("orangutan lint <file>"),
+            },
+            "verify" => {
+                let mut rest = operands(2).into_iter();
+                match rest.next() {
+                    Some(path) => {
+                        if !orangutan::script::verify(&path, prelude, rest.collect(), limits()?)? {
+                            process::exit(orangutan::error::RUNTIME_EXIT_CODE);
+                        }
+                        Ok(())
+                    }
+                    None => usage("orangutan verify <file> [--no-prelude] [args...]"),
+                }
+            }
+            "exec" => {
+                let mut rest = operands(2).into_iter();
+                match rest.next() {
+                    Some(path) => orangutan::script::exec(&path, rest.collect(), limits()?),
+                    None => usage("orangutan exec <file> [args...]"),
+                }
+            }
+            "visualize" => {
+                let html = env::args().any(|arg| arg == "--html");
+                match operands(2).into_iter().find(|arg| arg != "--html") {
+                    Some(input) => orangutan::visualize::start(&input, html),
+                    None => usage("orangutan visualize <program> [--html]"),
+                }
             }
+            _ => Err(Error::Usage(String::from("Unrecognized input!"))),
         },
-        None => orangutan::repl::start(compile),
+        None => repl(),
     }
 }
@@ -4,18 +4,20 @@
 //! These types are used while interpreting Monkey programs.
 mod built_in_functions;
 mod environment;
+mod random;
 
 pub use self::built_in_functions::*;
 pub use self::environment::*;
-use crate::ast::BlockStatement;
+pub use self::random::*;
+use crate::ast::{BlockStatement, Parameter};
 use crate::code::{Closure, CompiledFunction};
 use crate::evaluator::EvalError;
+use indexmap::IndexMap;
 use std::cell::RefCell;
-use std::collections::HashMap;
 use std::fmt;
 use std::rc::Rc;
 
-pub type BuiltInFunction = fn(Vec<Object>) -> Result<Object, EvalError>;
+pub type BuiltInFunction = fn(&mut dyn Runtime, Vec<Object>) -> Result<Object, EvalError>;
 pub type SharedEnvironment = Rc<RefCell<Environment>>;
 
 // Represents an object that is of a hashable type.
@@ -24,6 +26,7 @@ pub enum HashableObject {
     Integer(i64),
     Boolean(bool),
     Str(String),
+    Char(char),
 }
 
 impl fmt::Display for HashableObject {
@@ -32,23 +35,29 @@ impl fmt::Display for HashableObject {
             HashableObject::Str(value) => write!(f, "\"{}\"", value),
             HashableObject::Integer(value) => write!(f, "{}", value),
             HashableObject::Boolean(value) => write!(f, "{}", value),
+            HashableObject::Char(value) => write!(f, "'{}'", value),
         }
     }
 }
 
 /// Represents any object in the Monkey language after evaluation.
 /// These types are specific to the interpreter implementation.
+///
+/// Hashes remember the order in which their keys were first inserted, which is also the order they are displayed in.
+/// The elements of arrays and hashes are shared, so that copying a collection does not copy what it holds; they are
+/// copied on write instead.
 #[derive(Clone, Debug)]
 pub enum Object {
     Null,
     Integer(i64),
     Boolean(bool),
     Str(String),
-    Return(Box<Object>),
-    Function(Vec<String>, BlockStatement, SharedEnvironment),
+    Char(char),
+    Return(Rc<Object>),
+    Function(Vec<Parameter>, BlockStatement, SharedEnvironment),
     BuiltIn(BuiltInFunction),
-    Array(Vec<Object>),
-    Hash(HashMap<HashableObject, Object>),
+    Array(Vec<Rc<Object>>),
+    Hash(IndexMap<HashableObject, Rc<Object>>),
     CompiledFunction(CompiledFunction),
     Closure(Closure),
 }
@@ -58,12 +67,20 @@ impl fmt::Display for Object {
         match self {
             Object::Null => write!(f, "null"),
             Object::Str(value) => write!(f, "\"{}\"", value),
+            Object::Char(value) => write!(f, "'{}'", value),
             Object::Integer(value) => write!(f, "{}", value),
             Object::Boolean(value) => write!(f, "{}", value),
-            Object::Return(boxed_object) => write!(f, "{}", **boxed_object),
-            Object::Function(parameters, body, _) => {
-                write!(f, "fn({}) {}", parameters.join(", "), body)
-            }
+            Object::Return(value) => write!(f, "{}", **value),
+            Object::Function(parameters, body, _) => write!(
+                f,
+                "fn({}) {}",
+                parameters
+                    .iter()
+                    .map(|x| x.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", "),
+                body
+            ),
             Object::BuiltIn(_) => write!(f, "Built-In function"),
             Object::Array(items) => write!(
                 f,
@@ -75,12 +92,15 @@ impl fmt::Display for Object {
                     .join(", ")
             ),
             Object::Hash(elements) => {
-                let mut formatted_elements = elements
-                    .iter()
-                    .map(|(x, y)| format!("{}: {}", x.to_string(), y.to_string()))
-                    .collect::<Vec<String>>();
-                formatted_elements.sort();
-                write!(f, "{{{}}}", formatted_elements.join(", "))
+                // Entries are kept in insertion order, so they can be written out directly.
+                write!(f, "{{")?;
+                for (i, (key, value)) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", key, value)?;
+                }
+                write!(f, "}}")
             }
             Object::CompiledFunction(func) => write!(f, "Compiled function {}", func),
             Object::Closure(cl) => write!(f, "Closure {:?}", cl),
@@ -89,6 +109,24 @@ impl fmt::Display for Object {
 }
 
 impl Object {
+    /// Returns the name of the type of this object, as reported by the `type` built-in and in error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Object::Null => "NULL",
+            Object::Integer(_) => "INTEGER",
+            Object::Boolean(_) => "BOOLEAN",
+            Object::Str(_) => "STRING",
+            Object::Char(_) => "CHAR",
+            Object::Return(_) => "RETURN_VALUE",
+            Object::Function(_, _, _) | Object::CompiledFunction(_) | Object::Closure(_) => {
+                "FUNCTION"
+            }
+            Object::BuiltIn(_) => "BUILTIN",
+            Object::Array(_) => "ARRAY",
+            Object::Hash(_) => "HASH",
+        }
+    }
+
     pub fn is_truthy(&self) -> bool {
         match self {
             Object::Boolean(value) => *value,
@@ -97,12 +135,102 @@ impl Object {
         }
     }
 
+    /// Returns whether two objects hold the same value.
+    ///
+    /// Arrays and hashes are compared element by element; functions are never equal to anything.
+    pub fn equals(&self, other: &Object) -> bool {
+        match (self, other) {
+            (Object::Null, Object::Null) => true,
+            (Object::Integer(left), Object::Integer(right)) => left == right,
+            (Object::Boolean(left), Object::Boolean(right)) => left == right,
+            (Object::Str(left), Object::Str(right)) => left == right,
+            (Object::Char(left), Object::Char(right)) => left == right,
+            (Object::Array(left), Object::Array(right)) => {
+                left.len() == right.len() && left.iter().zip(right).all(|(l, r)| l.equals(r))
+            }
+            (Object::Hash(left), Object::Hash(right)) => {
+                left.len() == right.len()
+                    && left.iter().all(|(key, l)| match right.get(key) {
+                        Some(r) => l.equals(r),
+                        None => false,
+                    })
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns a copy of the object that shares no arrays or hashes with the original.
+    ///
+    /// Functions keep referring to the environment they were defined in.
+    pub fn deep_copy(&self) -> Object {
+        match self {
+            Object::Array(elements) => {
+                Object::Array(elements.iter().map(|e| Rc::new(e.deep_copy())).collect())
+            }
+            Object::Hash(items) => Object::Hash(
+                items
+                    .iter()
+                    .map(|(key, value)| (key.clone(), Rc::new(value.deep_copy())))
+                    .collect(),
+            ),
+            Object::Return(value) => Object::Return(Rc::new(value.deep_copy())),
+            other => other.clone(),
+        }
+    }
+
+    /// Returns a mutable reference to the existing element of an array or hash at `index`, which is first copied if
+    /// it is shared.
+    pub fn index_mut(&mut self, index: Object) -> Result<&mut Object, EvalError> {
+        match (self, index) {
+            (Object::Array(elements), Object::Integer(idx)) => {
+                if idx < 0 || idx as usize >= elements.len() {
+                    return Err(EvalError::IndexOutOfBounds(Rc::new(Object::Integer(idx))));
+                }
+                Ok(Rc::make_mut(&mut elements[idx as usize]))
+            }
+            (Object::Hash(items), index) => {
+                let key = index.clone().to_hashable_object()?;
+                match items.get_mut(&key) {
+                    Some(value) => Ok(Rc::make_mut(value)),
+                    None => Err(EvalError::IndexOutOfBounds(Rc::new(index))),
+                }
+            }
+            (obj, index) => Err(EvalError::IndexTypeMismatch(
+                Rc::new(obj.clone()),
+                Rc::new(index),
+            )),
+        }
+    }
+
+    /// Replaces the element of an array at `index`, or inserts `value` into a hash under the key `index`.
+    pub fn set_index(&mut self, index: Object, value: Rc<Object>) -> Result<(), EvalError> {
+        match (self, index) {
+            (Object::Hash(items), index) => {
+                items.insert(index.to_hashable_object()?, value);
+            }
+            (Object::Array(elements), Object::Integer(idx)) => {
+                if idx < 0 || idx as usize >= elements.len() {
+                    return Err(EvalError::IndexOutOfBounds(Rc::new(Object::Integer(idx))));
+                }
+                elements[idx as usize] = value;
+            }
+            (obj, index) => {
+                return Err(EvalError::IndexTypeMismatch(
+                    Rc::new(obj.clone()),
+                    Rc::new(index),
+                ))
+            }
+        }
+        Ok(())
+    }
+
     pub fn to_hashable_object(self) -> Result<HashableObject, EvalError> {
         match self {
             Object::Boolean(value) => Ok(HashableObject::Boolean(value)),
             Object::Str(value) => Ok(HashableObject::Str(value)),
             Object::Integer(value) => Ok(HashableObject::Integer(value)),
-            other => Err(EvalError::HashError(other)),
+            Object::Char(value) => Ok(HashableObject::Char(value)),
+            other => Err(EvalError::HashError(Rc::new(other))),
         }
     }
 }
@@ -2,11 +2,56 @@
 //!
 //! `built_in_functions` contains the implementation of functions built-in to the Monkey language.
 use crate::evaluator::EvalError;
-use crate::object::Object;
+use crate::object::{HashableObject, Object, Rng};
+use indexmap::{IndexMap, IndexSet};
 use num_enum::{IntoPrimitive, TryFromPrimitive};
+use std::convert::TryFrom;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
 
 // TODO: Document.
 
+/// The engine running a built-in function.
+///
+/// Built-in functions receive the runtime so that they can call back into Monkey code, e.g. to apply a
+/// user-supplied closure. Calls made this way may themselves call built-in functions.
+pub trait Runtime {
+    /// Calls a Monkey function (or built-in function) with the given arguments and returns its result.
+    fn call(&mut self, func: &Object, args: Vec<Object>) -> Result<Object, EvalError>;
+
+    /// Returns the random number generator shared by all built-in functions of the running program.
+    fn rng(&mut self) -> &mut Rng;
+
+    /// Returns the reader from which the `input` built-in function reads lines.
+    fn input(&mut self) -> &mut dyn BufRead;
+
+    /// Returns the writer to which `puts`, `print` and the prompt of `input` write.
+    fn output(&mut self) -> &mut dyn Write;
+
+    /// Returns whether built-in functions that reach outside the program, like `env`, are disallowed.
+    fn sandboxed(&self) -> bool;
+
+    /// Returns the command line arguments given to the running script.
+    fn args(&self) -> &[String];
+
+    /// Runs Monkey source code in the context of the caller and returns the value it evaluates to.
+    fn eval_source(&mut self, source: &str) -> Result<Object, EvalError>;
+}
+
+/// Returns a reader over standard input, which is where engines read input from unless told otherwise.
+pub fn stdin_reader() -> Box<dyn BufRead + Send> {
+    Box::new(io::BufReader::new(io::stdin()))
+}
+
+/// Returns a writer to standard output, which is where engines write output to unless told otherwise.
+pub fn stdout_writer() -> Box<dyn Write + Send> {
+    Box::new(io::stdout())
+}
+
+fn io_error(error: io::Error) -> EvalError {
+    EvalError::IoError(error.to_string())
+}
+
 #[derive(IntoPrimitive, TryFromPrimitive, Debug, Eq, PartialEq, Clone)]
 #[repr(u8)]
 pub enum BuiltIn {
@@ -17,6 +62,54 @@ pub enum BuiltIn {
     Push,
     Puts,
     MagicNumber,
+    Str,
+    Contains,
+    HasKey,
+    Delete,
+    Type,
+    Split,
+    Join,
+    Slice,
+    Sort,
+    Map,
+    Filter,
+    Reduce,
+    Range,
+    Reverse,
+    Min,
+    Max,
+    Sum,
+    Abs,
+    Sqrt,
+    Pow,
+    Floor,
+    Ceil,
+    Round,
+    Rand,
+    RandInt,
+    Seed,
+    Input,
+    Assert,
+    Ord,
+    Chr,
+    CharAt,
+    Print,
+    DeepCopy,
+    Env,
+    Args,
+    Eval,
+    Replace,
+    ReplaceFirst,
+    IndexOf,
+    Find,
+    Flatten,
+    Unique,
+    Union,
+    Intersect,
+    Difference,
+    StartsWith,
+    EndsWith,
+    Chars,
 }
 
 impl BuiltIn {
@@ -29,6 +122,54 @@ impl BuiltIn {
             BuiltIn::Push,
             BuiltIn::Puts,
             BuiltIn::MagicNumber,
+            BuiltIn::Str,
+            BuiltIn::Contains,
+            BuiltIn::HasKey,
+            BuiltIn::Delete,
+            BuiltIn::Type,
+            BuiltIn::Split,
+            BuiltIn::Join,
+            BuiltIn::Slice,
+            BuiltIn::Sort,
+            BuiltIn::Map,
+            BuiltIn::Filter,
+            BuiltIn::Reduce,
+            BuiltIn::Range,
+            BuiltIn::Reverse,
+            BuiltIn::Min,
+            BuiltIn::Max,
+            BuiltIn::Sum,
+            BuiltIn::Abs,
+            BuiltIn::Sqrt,
+            BuiltIn::Pow,
+            BuiltIn::Floor,
+            BuiltIn::Ceil,
+            BuiltIn::Round,
+            BuiltIn::Rand,
+            BuiltIn::RandInt,
+            BuiltIn::Seed,
+            BuiltIn::Input,
+            BuiltIn::Assert,
+            BuiltIn::Ord,
+            BuiltIn::Chr,
+            BuiltIn::CharAt,
+            BuiltIn::Print,
+            BuiltIn::DeepCopy,
+            BuiltIn::Env,
+            BuiltIn::Args,
+            BuiltIn::Eval,
+            BuiltIn::Replace,
+            BuiltIn::ReplaceFirst,
+            BuiltIn::IndexOf,
+            BuiltIn::Find,
+            BuiltIn::Flatten,
+            BuiltIn::Unique,
+            BuiltIn::Union,
+            BuiltIn::Intersect,
+            BuiltIn::Difference,
+            BuiltIn::StartsWith,
+            BuiltIn::EndsWith,
+            BuiltIn::Chars,
         ]
     }
 
@@ -41,6 +182,54 @@ impl BuiltIn {
             BuiltIn::Push => "push",
             BuiltIn::Puts => "puts",
             BuiltIn::MagicNumber => "magic_number",
+            BuiltIn::Str => "str",
+            BuiltIn::Contains => "contains",
+            BuiltIn::HasKey => "has_key",
+            BuiltIn::Delete => "delete",
+            BuiltIn::Type => "type",
+            BuiltIn::Split => "split",
+            BuiltIn::Join => "join",
+            BuiltIn::Slice => "slice",
+            BuiltIn::Sort => "sort",
+            BuiltIn::Map => "map",
+            BuiltIn::Filter => "filter",
+            BuiltIn::Reduce => "reduce",
+            BuiltIn::Range => "range",
+            BuiltIn::Reverse => "reverse",
+            BuiltIn::Min => "min",
+            BuiltIn::Max => "max",
+            BuiltIn::Sum => "sum",
+            BuiltIn::Abs => "abs",
+            BuiltIn::Sqrt => "sqrt",
+            BuiltIn::Pow => "pow",
+            BuiltIn::Floor => "floor",
+            BuiltIn::Ceil => "ceil",
+            BuiltIn::Round => "round",
+            BuiltIn::Rand => "rand",
+            BuiltIn::RandInt => "rand_int",
+            BuiltIn::Seed => "seed",
+            BuiltIn::Input => "input",
+            BuiltIn::Assert => "assert",
+            BuiltIn::Ord => "ord",
+            BuiltIn::Chr => "chr",
+            BuiltIn::CharAt => "char_at",
+            BuiltIn::Print => "print",
+            BuiltIn::DeepCopy => "deep_copy",
+            BuiltIn::Env => "env",
+            BuiltIn::Args => "args",
+            BuiltIn::Eval => "eval",
+            BuiltIn::Replace => "replace",
+            BuiltIn::ReplaceFirst => "replace_first",
+            BuiltIn::IndexOf => "index_of",
+            BuiltIn::Find => "find",
+            BuiltIn::Flatten => "flatten",
+            BuiltIn::Unique => "unique",
+            BuiltIn::Union => "union",
+            BuiltIn::Intersect => "intersect",
+            BuiltIn::Difference => "difference",
+            BuiltIn::StartsWith => "starts_with",
+            BuiltIn::EndsWith => "ends_with",
+            BuiltIn::Chars => "chars",
         };
         String::from(raw)
     }
@@ -54,6 +243,54 @@ impl BuiltIn {
             BuiltIn::Push => push,
             BuiltIn::Puts => puts,
             BuiltIn::MagicNumber => magic_number,
+            BuiltIn::Str => str,
+            BuiltIn::Contains => contains,
+            BuiltIn::HasKey => has_key,
+            BuiltIn::Delete => delete,
+            BuiltIn::Type => type_of,
+            BuiltIn::Split => split,
+            BuiltIn::Join => join,
+            BuiltIn::Slice => slice,
+            BuiltIn::Sort => sort,
+            BuiltIn::Map => map,
+            BuiltIn::Filter => filter,
+            BuiltIn::Reduce => reduce,
+            BuiltIn::Range => range,
+            BuiltIn::Reverse => reverse,
+            BuiltIn::Min => min,
+            BuiltIn::Max => max,
+            BuiltIn::Sum => sum,
+            BuiltIn::Abs => abs,
+            BuiltIn::Sqrt => sqrt,
+            BuiltIn::Pow => pow,
+            BuiltIn::Floor => floor,
+            BuiltIn::Ceil => ceil,
+            BuiltIn::Round => round,
+            BuiltIn::Rand => rand,
+            BuiltIn::RandInt => rand_int,
+            BuiltIn::Seed => seed,
+            BuiltIn::Input => input,
+            BuiltIn::Assert => assert,
+            BuiltIn::Ord => ord,
+            BuiltIn::Chr => chr,
+            BuiltIn::CharAt => char_at,
+            BuiltIn::Print => print,
+            BuiltIn::DeepCopy => deep_copy,
+            BuiltIn::Env => env,
+            BuiltIn::Args => args,
+            BuiltIn::Eval => eval,
+            BuiltIn::Replace => replace,
+            BuiltIn::ReplaceFirst => replace_first,
+            BuiltIn::IndexOf => index_of,
+            BuiltIn::Find => find,
+            BuiltIn::Flatten => flatten,
+            BuiltIn::Unique => unique,
+            BuiltIn::Union => union,
+            BuiltIn::Intersect => intersect,
+            BuiltIn::Difference => difference,
+            BuiltIn::StartsWith => starts_with,
+            BuiltIn::EndsWith => ends_with,
+            BuiltIn::Chars => chars,
         };
         Object::BuiltIn(f)
     }
@@ -68,45 +305,49 @@ pub fn get_built_in(name: &str) -> Option<Object> {
     return None;
 }
 
-fn magic_number(_: Vec<Object>) -> Result<Object, EvalError> {
+fn magic_number(_: &mut dyn Runtime, _: Vec<Object>) -> Result<Object, EvalError> {
     // Doesn't care about parameters, just returns 42.
     Ok(Object::Integer(42))
 }
 
-fn puts(params: Vec<Object>) -> Result<Object, EvalError> {
+/// Writes each argument on a line of its own.
+fn puts(rt: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
     for param in &params {
-        match param {
-            // We do a silly match on the string to remove quotes from result.
-            Object::Str(string) => {
-                println!("{}", string);
-            }
-            _ => {
-                println!("{}", param);
-            }
-        };
+        // Strings and characters are written without their quotes.
+        writeln!(rt.output(), "{}", plain_string(param)).map_err(io_error)?;
     }
     Ok(Object::Null)
 }
 
-fn len(params: Vec<Object>) -> Result<Object, EvalError> {
+/// Writes the arguments separated by spaces, without starting a new line.
+fn print(rt: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    let parts: Vec<String> = params.iter().map(plain_string).collect();
+    let output = rt.output();
+    write!(output, "{}", parts.join(" ")).map_err(io_error)?;
+    output.flush().map_err(io_error)?;
+    Ok(Object::Null)
+}
+
+fn len(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
     if params.len() != 1 {
         return Err(EvalError::WrongNumberOfArguments(params.len() as u32, 1));
     }
     match &params[0] {
-        Object::Str(string) => Ok(Object::Integer(string.len() as i64)),
+        Object::Str(string) => Ok(Object::Integer(string.chars().count() as i64)),
         Object::Array(arr) => Ok(Object::Integer(arr.len() as i64)),
+        Object::Hash(items) => Ok(Object::Integer(items.len() as i64)),
         _ => Err(EvalError::UnsupportedInputToBuiltIn),
     }
 }
 
-fn first(params: Vec<Object>) -> Result<Object, EvalError> {
+fn first(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
     if params.len() != 1 {
         return Err(EvalError::WrongNumberOfArguments(params.len() as u32, 1));
     }
     match &params[0] {
         Object::Array(arr) => {
             if arr.len() > 0 {
-                Ok(arr[0].clone())
+                Ok((*arr[0]).clone())
             } else {
                 Ok(Object::Null)
             }
@@ -115,7 +356,7 @@ fn first(params: Vec<Object>) -> Result<Object, EvalError> {
     }
 }
 
-fn last(params: Vec<Object>) -> Result<Object, EvalError> {
+fn last(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
     if params.len() != 1 {
         return Err(EvalError::WrongNumberOfArguments(params.len() as u32, 1));
     }
@@ -123,7 +364,7 @@ fn last(params: Vec<Object>) -> Result<Object, EvalError> {
         Object::Array(arr) => {
             let ell = arr.len();
             if ell > 0 {
-                Ok(arr[ell - 1].clone())
+                Ok((*arr[ell - 1]).clone())
             } else {
                 Ok(Object::Null)
             }
@@ -132,7 +373,7 @@ fn last(params: Vec<Object>) -> Result<Object, EvalError> {
     }
 }
 
-fn rest(params: Vec<Object>) -> Result<Object, EvalError> {
+fn rest(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
     if params.len() != 1 {
         return Err(EvalError::WrongNumberOfArguments(params.len() as u32, 1));
     }
@@ -151,16 +392,874 @@ fn rest(params: Vec<Object>) -> Result<Object, EvalError> {
     }
 }
 
-fn push(params: Vec<Object>) -> Result<Object, EvalError> {
+fn push(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
     if params.len() != 2 {
         return Err(EvalError::WrongNumberOfArguments(params.len() as u32, 2));
     }
     match &params[0] {
         Object::Array(arr) => {
             let mut new_arr = arr.clone();
-            new_arr.push(params[1].clone());
+            new_arr.push(Rc::new(params[1].clone()));
             Ok(Object::Array(new_arr))
         }
         _ => Err(EvalError::UnsupportedInputToBuiltIn),
     }
 }
+
+/// Returns the text of an object, without the quotes used when displaying strings and characters.
+fn plain_string(obj: &Object) -> String {
+    match obj {
+        Object::Str(string) => string.clone(),
+        Object::Char(ch) => ch.to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// Converts any object to a string.
+fn str(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    if params.len() != 1 {
+        return Err(EvalError::WrongNumberOfArguments(params.len() as u32, 1));
+    }
+    Ok(Object::Str(plain_string(&params[0])))
+}
+
+/// Returns whether an array holds an element, a hash holds a key, or a string holds a substring or character.
+fn contains(rt: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    if params.len() != 2 {
+        return Err(EvalError::WrongNumberOfArguments(params.len() as u32, 2));
+    }
+    let found = match (&params[0], &params[1]) {
+        (Object::Array(arr), item) => arr.iter().any(|x| x.equals(item)),
+        (Object::Hash(_), _) => return has_key(rt, params),
+        (Object::Str(string), Object::Str(sub)) => string.contains(sub.as_str()),
+        (Object::Str(string), Object::Char(ch)) => string.contains(*ch),
+        _ => return Err(EvalError::UnsupportedInputToBuiltIn),
+    };
+    Ok(Object::Boolean(found))
+}
+
+fn has_key(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    if params.len() != 2 {
+        return Err(EvalError::WrongNumberOfArguments(params.len() as u32, 2));
+    }
+    match &params[0] {
+        Object::Hash(items) => {
+            let key = params[1].clone().to_hashable_object()?;
+            Ok(Object::Boolean(items.contains_key(&key)))
+        }
+        _ => Err(EvalError::UnsupportedInputToBuiltIn),
+    }
+}
+
+/// Returns a copy of a hash without the given key, keeping the order of the remaining entries.
+///
+/// Like `push`, this leaves its argument untouched; use `h = delete(h, k)` to remove the key from `h`.
+fn delete(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    if params.len() != 2 {
+        return Err(EvalError::WrongNumberOfArguments(params.len() as u32, 2));
+    }
+    match &params[0] {
+        Object::Hash(items) => {
+            let key = params[1].clone().to_hashable_object()?;
+            let mut new_items = items.clone();
+            new_items.shift_remove(&key);
+            Ok(Object::Hash(new_items))
+        }
+        _ => Err(EvalError::UnsupportedInputToBuiltIn),
+    }
+}
+
+fn type_of(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    if params.len() != 1 {
+        return Err(EvalError::WrongNumberOfArguments(params.len() as u32, 1));
+    }
+    Ok(Object::Str(params[0].type_name().to_string()))
+}
+
+/// Splits a string on every occurrence of a separator, or into its characters if the separator is empty.
+fn split(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    if params.len() != 2 {
+        return Err(EvalError::WrongNumberOfArguments(params.len() as u32, 2));
+    }
+    let parts: Vec<String> = match (&params[0], &params[1]) {
+        (Object::Str(string), Object::Str(sep)) if sep.is_empty() => {
+            string.chars().map(|ch| ch.to_string()).collect()
+        }
+        (Object::Str(string), Object::Str(sep)) => {
+            string.split(sep.as_str()).map(String::from).collect()
+        }
+        (Object::Str(string), Object::Char(sep)) => string.split(*sep).map(String::from).collect(),
+        _ => return Err(EvalError::UnsupportedInputToBuiltIn),
+    };
+    Ok(Object::Array(
+        parts
+            .into_iter()
+            .map(|part| Rc::new(Object::Str(part)))
+            .collect(),
+    ))
+}
+
+/// Concatenates the elements of an array into a string, with a separator between consecutive elements.
+fn join(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    if params.len() != 2 {
+        return Err(EvalError::WrongNumberOfArguments(params.len() as u32, 2));
+    }
+    match (&params[0], &params[1]) {
+        (Object::Array(arr), sep @ Object::Str(_))
+        | (Object::Array(arr), sep @ Object::Char(_)) => {
+            let parts: Vec<String> = arr.iter().map(|item| plain_string(item)).collect();
+            Ok(Object::Str(parts.join(&plain_string(sep))))
+        }
+        _ => Err(EvalError::UnsupportedInputToBuiltIn),
+    }
+}
+
+/// Converts a possibly negative index, counting from the end, into a position clamped to `0..=len`.
+fn clamp_index(idx: i64, len: usize) -> usize {
+    if idx < 0 {
+        (len as i64 + idx).max(0) as usize
+    } else {
+        (idx as usize).min(len)
+    }
+}
+
+/// Returns the characters of a string, or the elements of an array, from `start` up to (excluding) `end`.
+///
+/// Negative indices count from the end, `end` defaults to the length, and out-of-range indices are clamped,
+/// so `slice` never fails on bad bounds but may return an empty result.
+fn slice(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    if params.len() != 2 && params.len() != 3 {
+        return Err(EvalError::WrongNumberOfArguments(params.len() as u32, 3));
+    }
+    let start = match &params[1] {
+        Object::Integer(start) => *start,
+        _ => return Err(EvalError::UnsupportedInputToBuiltIn),
+    };
+    let end = match params.get(2) {
+        Some(Object::Integer(end)) => Some(*end),
+        None => None,
+        _ => return Err(EvalError::UnsupportedInputToBuiltIn),
+    };
+    let bounds = |len: usize| {
+        let start = clamp_index(start, len);
+        let end = end.map_or(len, |end| clamp_index(end, len));
+        (start, end.max(start))
+    };
+    match &params[0] {
+        Object::Str(string) => {
+            let (start, end) = bounds(string.chars().count());
+            Ok(Object::Str(
+                string.chars().skip(start).take(end - start).collect(),
+            ))
+        }
+        Object::Array(arr) => {
+            let (start, end) = bounds(arr.len());
+            Ok(Object::Array(arr[start..end].to_vec()))
+        }
+        _ => Err(EvalError::UnsupportedInputToBuiltIn),
+    }
+}
+
+/// Returns a sorted copy of an array.
+///
+/// Without a comparator, the elements must all be integers, all strings or all characters.
+/// A comparator `fn(a, b)` returns either whether `a` goes before `b`, or an integer which is negative
+/// exactly when `a` goes before `b`. The sort is stable.
+fn sort(rt: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    if params.len() != 1 && params.len() != 2 {
+        return Err(EvalError::WrongNumberOfArguments(params.len() as u32, 2));
+    }
+    let mut params = params.into_iter();
+    let arr = match params.next() {
+        Some(Object::Array(arr)) => arr,
+        _ => return Err(EvalError::UnsupportedInputToBuiltIn),
+    };
+    let sorted = match params.next() {
+        Some(comparator) => merge_sort(arr, &mut |a, b| match rt
+            .call(&comparator, vec![a.clone(), b.clone()])?
+        {
+            Object::Boolean(before) => Ok(before),
+            Object::Integer(order) => Ok(order < 0),
+            _ => Err(EvalError::UnsupportedInputToBuiltIn),
+        })?,
+        None => merge_sort(arr, &mut |a, b| match (a, b) {
+            (Object::Integer(a), Object::Integer(b)) => Ok(a < b),
+            (Object::Str(a), Object::Str(b)) => Ok(a < b),
+            (Object::Char(a), Object::Char(b)) => Ok(a < b),
+            _ => Err(EvalError::UnsupportedInputToBuiltIn),
+        })?,
+    };
+    Ok(Object::Array(sorted))
+}
+
+// A comparator may call back into Monkey code, so it can fail and need not be a consistent ordering.
+// Merge sort copes with both, unlike the standard library sorts.
+fn merge_sort(
+    mut arr: Vec<Rc<Object>>,
+    less: &mut dyn FnMut(&Object, &Object) -> Result<bool, EvalError>,
+) -> Result<Vec<Rc<Object>>, EvalError> {
+    if arr.len() <= 1 {
+        return Ok(arr);
+    }
+    let right = arr.split_off(arr.len() / 2);
+    let left = merge_sort(arr, less)?;
+    let right = merge_sort(right, less)?;
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+        // Taking from the left unless the right element goes strictly before keeps the sort stable.
+        if less(r, l)? {
+            merged.extend(right.next());
+        } else {
+            merged.extend(left.next());
+        }
+    }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(merged)
+}
+
+/// Returns the array of results of calling a function on each element of an array.
+fn map(rt: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    if params.len() != 2 {
+        return Err(EvalError::WrongNumberOfArguments(params.len() as u32, 2));
+    }
+    match &params[0] {
+        Object::Array(arr) => {
+            let mut mapped = Vec::with_capacity(arr.len());
+            for item in arr {
+                mapped.push(Rc::new(rt.call(&params[1], vec![(**item).clone()])?));
+            }
+            Ok(Object::Array(mapped))
+        }
+        _ => Err(EvalError::UnsupportedInputToBuiltIn),
+    }
+}
+
+/// Returns the elements of an array for which a function returns a truthy value.
+fn filter(rt: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    if params.len() != 2 {
+        return Err(EvalError::WrongNumberOfArguments(params.len() as u32, 2));
+    }
+    match &params[0] {
+        Object::Array(arr) => {
+            let mut kept = vec![];
+            for item in arr {
+                if rt.call(&params[1], vec![(**item).clone()])?.is_truthy() {
+                    kept.push(item.clone());
+                }
+            }
+            Ok(Object::Array(kept))
+        }
+        _ => Err(EvalError::UnsupportedInputToBuiltIn),
+    }
+}
+
+/// Combines the elements of an array from left to right, as in `reduce(arr, initial, fn(acc, x) { ... })`.
+fn reduce(rt: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    if params.len() != 3 {
+        return Err(EvalError::WrongNumberOfArguments(params.len() as u32, 3));
+    }
+    match &params[0] {
+        Object::Array(arr) => {
+            let mut acc = params[1].clone();
+            for item in arr {
+                acc = rt.call(&params[2], vec![acc, (**item).clone()])?;
+            }
+            Ok(acc)
+        }
+        _ => Err(EvalError::UnsupportedInputToBuiltIn),
+    }
+}
+
+/// The most integers `range` returns, so that a mistaken bound fails at once rather than using up memory.
+const MAX_RANGE_LENGTH: usize = 1 << 24;
+
+/// Returns the integers from `start` (default 0) up to, but excluding, `stop`, counting by `step` (default 1).
+///
+/// As in `range(5)`, `range(2, 5)` or `range(10, 0, -2)`. A step of zero is an error, as is a range of more than
+/// `MAX_RANGE_LENGTH` integers.
+fn range(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    if params.is_empty() || params.len() > 3 {
+        return Err(EvalError::WrongNumberOfArguments(params.len() as u32, 3));
+    }
+    let mut bounds = vec![];
+    for param in &params {
+        match param {
+            Object::Integer(value) => bounds.push(*value),
+            _ => return Err(EvalError::UnsupportedInputToBuiltIn),
+        }
+    }
+    let (start, stop, step) = match bounds[..] {
+        [stop] => (0, stop, 1),
+        [start, stop] => (start, stop, 1),
+        [start, stop, step] => (start, stop, step),
+        _ => unreachable!(),
+    };
+    if step == 0 {
+        return Err(EvalError::UnsupportedInputToBuiltIn);
+    }
+    // A distance left over short of a whole step still takes one.
+    let (distance, step_size) = (i128::from(stop) - i128::from(start), i128::from(step));
+    let length = match distance.signum() == step_size.signum() {
+        true => (distance.abs() + step_size.abs() - 1) / step_size.abs(),
+        false => 0,
+    };
+    if length > MAX_RANGE_LENGTH as i128 {
+        return Err(EvalError::RangeTooLarge(length as u64));
+    }
+    let mut items = Vec::with_capacity(length as usize);
+    let mut value = Some(start);
+    while let Some(current) =
+        value.filter(|&value| (step > 0 && value < stop) || (step < 0 && value > stop))
+    {
+        items.push(Rc::new(Object::Integer(current)));
+        value = current.checked_add(step);
+    }
+    Ok(Object::Array(items))
+}
+
+/// Returns a reversed copy of an array or string.
+fn reverse(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    if params.len() != 1 {
+        return Err(EvalError::WrongNumberOfArguments(params.len() as u32, 1));
+    }
+    match &params[0] {
+        Object::Array(arr) => Ok(Object::Array(arr.iter().rev().cloned().collect())),
+        Object::Str(string) => Ok(Object::Str(string.chars().rev().collect())),
+        _ => Err(EvalError::UnsupportedInputToBuiltIn),
+    }
+}
+
+/// Returns the integers passed to the built-in function `name`, either directly or as a single array.
+fn integer_arguments(name: &str, params: Vec<Object>) -> Result<Vec<i64>, EvalError> {
+    let items = match &params[..] {
+        [Object::Array(arr)] => arr.iter().map(|item| (**item).clone()).collect(),
+        _ => params,
+    };
+    let mut values = Vec::with_capacity(items.len());
+    for item in items {
+        match item {
+            Object::Integer(value) => values.push(value),
+            other => {
+                return Err(EvalError::UnsupportedArgument(
+                    name.to_string(),
+                    Rc::new(other),
+                ))
+            }
+        }
+    }
+    Ok(values)
+}
+
+/// Returns the smallest of some integers, or null if there are none.
+fn min(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    let values = integer_arguments("min", params)?;
+    Ok(values
+        .into_iter()
+        .min()
+        .map_or(Object::Null, Object::Integer))
+}
+
+/// Returns the largest of some integers, or null if there are none.
+fn max(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    let values = integer_arguments("max", params)?;
+    Ok(values
+        .into_iter()
+        .max()
+        .map_or(Object::Null, Object::Integer))
+}
+
+/// Returns the sum of some integers, which is zero if there are none.
+fn sum(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    let values = integer_arguments("sum", params)?;
+    values
+        .into_iter()
+        .try_fold(0i64, i64::checked_add)
+        .map(Object::Integer)
+        .ok_or(EvalError::IntegerOverflow)
+}
+
+fn abs(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    if params.len() != 1 {
+        return Err(EvalError::WrongNumberOfArguments(params.len() as u32, 1));
+    }
+    match &params[0] {
+        Object::Integer(value) => value
+            .checked_abs()
+            .map(Object::Integer)
+            .ok_or(EvalError::IntegerOverflow),
+        other => Err(EvalError::UnsupportedArgument(
+            String::from("abs"),
+            Rc::new(other.clone()),
+        )),
+    }
+}
+
+/// Returns the integer argument of the built-in function `name`, which takes exactly one.
+fn integer_argument(name: &str, params: Vec<Object>) -> Result<i64, EvalError> {
+    if params.len() != 1 {
+        return Err(EvalError::WrongNumberOfArguments(params.len() as u32, 1));
+    }
+    match &params[0] {
+        Object::Integer(value) => Ok(*value),
+        other => Err(EvalError::UnsupportedArgument(
+            name.to_string(),
+            Rc::new(other.clone()),
+        )),
+    }
+}
+
+/// Returns the integer square root of a non-negative integer, i.e., its square root rounded down.
+fn sqrt(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    let value = integer_argument("sqrt", params)?;
+    if value < 0 {
+        return Err(EvalError::UnsupportedArgument(
+            String::from("sqrt"),
+            Rc::new(Object::Integer(value)),
+        ));
+    }
+    // Start from the floating point estimate and correct it, since it may be off for large values.
+    let value = value as i128;
+    let mut root = (value as f64).sqrt() as i128;
+    while root * root > value {
+        root -= 1;
+    }
+    while (root + 1) * (root + 1) <= value {
+        root += 1;
+    }
+    Ok(Object::Integer(root as i64))
+}
+
+/// Raises an integer to a non-negative integer power.
+fn pow(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    if params.len() != 2 {
+        return Err(EvalError::WrongNumberOfArguments(params.len() as u32, 2));
+    }
+    match (&params[0], &params[1]) {
+        (Object::Integer(base), Object::Integer(exp)) if *exp >= 0 => {
+            let exp = match u32::try_from(*exp) {
+                Ok(exp) => exp,
+                // Only the powers of 0, 1 and -1 this high fit, and those depend only on whether `exp` is even.
+                Err(_) => u32::MAX - 1 + (*exp % 2) as u32,
+            };
+            base.checked_pow(exp)
+                .map(Object::Integer)
+                .ok_or(EvalError::IntegerOverflow)
+        }
+        (Object::Integer(_), other) | (other, _) => Err(EvalError::UnsupportedArgument(
+            String::from("pow"),
+            Rc::new(other.clone()),
+        )),
+    }
+}
+
+// Monkey only has integers, which `floor`, `ceil` and `round` leave unchanged.
+// They exist so that code written with rounding in mind keeps working should other numbers be added.
+
+fn floor(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    Ok(Object::Integer(integer_argument("floor", params)?))
+}
+
+fn ceil(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    Ok(Object::Integer(integer_argument("ceil", params)?))
+}
+
+fn round(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    Ok(Object::Integer(integer_argument("round", params)?))
+}
+
+/// Returns a random non-negative integer.
+fn rand(rt: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    if !params.is_empty() {
+        return Err(EvalError::WrongNumberOfArguments(params.len() as u32, 0));
+    }
+    Ok(Object::Integer(rt.rng().range(0, i64::MAX)))
+}
+
+/// Returns a random integer between `low` and `high`, both inclusive.
+fn rand_int(rt: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    if params.len() != 2 {
+        return Err(EvalError::WrongNumberOfArguments(params.len() as u32, 2));
+    }
+    match (&params[0], &params[1]) {
+        (Object::Integer(low), Object::Integer(high)) if low <= high => {
+            Ok(Object::Integer(rt.rng().range(*low, *high)))
+        }
+        (Object::Integer(_), other) | (other, _) => Err(EvalError::UnsupportedArgument(
+            String::from("rand_int"),
+            Rc::new(other.clone()),
+        )),
+    }
+}
+
+/// Restarts the random number generator from a seed, making the following random numbers reproducible.
+fn seed(rt: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    let seed = integer_argument("seed", params)?;
+    rt.rng().seed(seed as u64);
+    Ok(Object::Null)
+}
+
+/// Reads a line of input, without its line ending, after printing an optional prompt.
+///
+/// Returns null once the input is exhausted.
+fn input(rt: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    if params.len() > 1 {
+        return Err(EvalError::WrongNumberOfArguments(params.len() as u32, 1));
+    }
+    if let Some(prompt) = params.first() {
+        let output = rt.output();
+        write!(output, "{}", plain_string(prompt)).map_err(io_error)?;
+        output.flush().map_err(io_error)?;
+    }
+    let mut line = String::new();
+    match rt.input().read_line(&mut line) {
+        Ok(0) => Ok(Object::Null),
+        Ok(_) => {
+            if line.ends_with('\n') {
+                line.pop();
+                if line.ends_with('\r') {
+                    line.pop();
+                }
+            }
+            Ok(Object::Str(line))
+        }
+        Err(error) => Err(io_error(error)),
+    }
+}
+
+/// Fails with an assertion error, including the optional message, unless the condition is truthy.
+fn assert(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    if params.is_empty() || params.len() > 2 {
+        return Err(EvalError::WrongNumberOfArguments(params.len() as u32, 2));
+    }
+    if params[0].is_truthy() {
+        return Ok(Object::Null);
+    }
+    Err(EvalError::AssertionFailed(params.get(1).map(plain_string)))
+}
+
+/// Returns the Unicode code point of a character, or of a string holding exactly one character.
+fn ord(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    if params.len() != 1 {
+        return Err(EvalError::WrongNumberOfArguments(params.len() as u32, 1));
+    }
+    let ch = match &params[0] {
+        Object::Char(ch) => Some(*ch),
+        Object::Str(string) if string.chars().count() == 1 => string.chars().next(),
+        _ => None,
+    };
+    match ch {
+        Some(ch) => Ok(Object::Integer(ch as i64)),
+        None => Err(EvalError::UnsupportedArgument(
+            String::from("ord"),
+            Rc::new(params[0].clone()),
+        )),
+    }
+}
+
+/// Returns the character with the given Unicode code point.
+fn chr(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    let code = integer_argument("chr", params)?;
+    if code < 0 || code > u32::MAX as i64 {
+        return Err(EvalError::UnsupportedArgument(
+            String::from("chr"),
+            Rc::new(Object::Integer(code)),
+        ));
+    }
+    match std::char::from_u32(code as u32) {
+        Some(ch) => Ok(Object::Char(ch)),
+        None => Err(EvalError::UnsupportedArgument(
+            String::from("chr"),
+            Rc::new(Object::Integer(code)),
+        )),
+    }
+}
+
+/// Returns the character at a position of a string, or null if there is none, like indexing the string.
+fn char_at(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    if params.len() != 2 {
+        return Err(EvalError::WrongNumberOfArguments(params.len() as u32, 2));
+    }
+    match (&params[0], &params[1]) {
+        (Object::Str(string), Object::Integer(idx)) if *idx >= 0 => Ok(string
+            .chars()
+            .nth(*idx as usize)
+            .map_or(Object::Null, Object::Char)),
+        (Object::Str(_), Object::Integer(_)) => Ok(Object::Null),
+        _ => Err(EvalError::UnsupportedInputToBuiltIn),
+    }
+}
+
+fn deep_copy(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    match params.as_slice() {
+        [obj] => Ok(obj.deep_copy()),
+        _ => Err(EvalError::WrongNumberOfArguments(params.len() as u32, 1)),
+    }
+}
+
+/// Returns the value of an environment variable, or null if it is not set.
+/// Without arguments, returns a hash of all environment variables.
+fn env(rt: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    if rt.sandboxed() {
+        return Err(EvalError::Sandboxed(String::from("env")));
+    }
+    match params.as_slice() {
+        [] => Ok(Object::Hash(
+            std::env::vars_os()
+                .map(|(key, value)| {
+                    (
+                        HashableObject::Str(key.to_string_lossy().into_owned()),
+                        Rc::new(Object::Str(value.to_string_lossy().into_owned())),
+                    )
+                })
+                .collect::<IndexMap<_, _>>(),
+        )),
+        [Object::Str(name)] => Ok(std::env::var_os(name).map_or(Object::Null, |value| {
+            Object::Str(value.to_string_lossy().into_owned())
+        })),
+        [other] => Err(EvalError::UnsupportedArgument(
+            String::from("env"),
+            Rc::new(other.clone()),
+        )),
+        _ => Err(EvalError::WrongNumberOfArguments(params.len() as u32, 1)),
+    }
+}
+
+/// Returns the command line arguments given to the running script as an array of strings.
+fn args(rt: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    if !params.is_empty() {
+        return Err(EvalError::WrongNumberOfArguments(params.len() as u32, 0));
+    }
+    Ok(Object::Array(
+        rt.args()
+            .iter()
+            .map(|arg| Rc::new(Object::Str(arg.clone())))
+            .collect(),
+    ))
+}
+
+fn eval(rt: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    match params.as_slice() {
+        [Object::Str(source)] => rt.eval_source(source),
+        [other] => Err(EvalError::UnsupportedArgument(
+            String::from("eval"),
+            Rc::new(other.clone()),
+        )),
+        _ => Err(EvalError::WrongNumberOfArguments(params.len() as u32, 1)),
+    }
+}
+
+// Returns the string and the pattern and replacement of the string editing built-in function `name`.
+fn replace_arguments(
+    name: &str,
+    params: Vec<Object>,
+) -> Result<(String, String, String), EvalError> {
+    let text = |obj: &Object| match obj {
+        Object::Str(string) => Ok(string.clone()),
+        Object::Char(ch) => Ok(ch.to_string()),
+        other => Err(EvalError::UnsupportedArgument(
+            name.to_string(),
+            Rc::new(other.clone()),
+        )),
+    };
+    match params.as_slice() {
+        [Object::Str(string), from, to] => Ok((string.clone(), text(from)?, text(to)?)),
+        [other, _, _] => Err(EvalError::UnsupportedArgument(
+            name.to_string(),
+            Rc::new(other.clone()),
+        )),
+        _ => Err(EvalError::WrongNumberOfArguments(params.len() as u32, 3)),
+    }
+}
+
+/// Replaces every occurrence of a substring (or character) in a string.
+fn replace(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    let (string, from, to) = replace_arguments("replace", params)?;
+    Ok(Object::Str(string.replace(&from, &to)))
+}
+
+/// Replaces the first occurrence of a substring (or character) in a string.
+fn replace_first(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    let (string, from, to) = replace_arguments("replace_first", params)?;
+    Ok(Object::Str(string.replacen(&from, &to, 1)))
+}
+
+/// Returns the position of the first element of an array equal to a value, or of the first occurrence of a
+/// substring (or character) in a string, counted in characters. Returns null if there is none.
+fn index_of(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    if params.len() != 2 {
+        return Err(EvalError::WrongNumberOfArguments(params.len() as u32, 2));
+    }
+    let position = match (&params[0], &params[1]) {
+        (Object::Array(arr), item) => arr.iter().position(|x| x.equals(item)),
+        (Object::Str(string), Object::Str(sub)) => string
+            .find(sub.as_str())
+            .map(|byte| string[..byte].chars().count()),
+        (Object::Str(string), Object::Char(ch)) => string.chars().position(|x| x == *ch),
+        _ => return Err(EvalError::UnsupportedInputToBuiltIn),
+    };
+    Ok(position.map_or(Object::Null, |idx| Object::Integer(idx as i64)))
+}
+
+/// Returns the first element of an array for which a function returns a truthy value, or null if there is none.
+fn find(rt: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    if params.len() != 2 {
+        return Err(EvalError::WrongNumberOfArguments(params.len() as u32, 2));
+    }
+    match &params[0] {
+        Object::Array(arr) => {
+            for item in arr {
+                if rt.call(&params[1], vec![(**item).clone()])?.is_truthy() {
+                    return Ok((**item).clone());
+                }
+            }
+            Ok(Object::Null)
+        }
+        _ => Err(EvalError::UnsupportedInputToBuiltIn),
+    }
+}
+
+fn flatten_into(flat: &mut Vec<Rc<Object>>, arr: &[Rc<Object>], depth: i64) {
+    for item in arr {
+        match &**item {
+            Object::Array(inner) if depth > 0 => flatten_into(flat, inner, depth - 1),
+            _ => flat.push(Rc::clone(item)),
+        }
+    }
+}
+
+/// Splices nested arrays into their enclosing array, one level deep unless another depth is given.
+fn flatten(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    let (arr, depth) = match params.as_slice() {
+        [Object::Array(arr)] => (arr, 1),
+        [Object::Array(arr), Object::Integer(depth)] if *depth >= 0 => (arr, *depth),
+        [_] | [_, _] => return Err(EvalError::UnsupportedInputToBuiltIn),
+        _ => return Err(EvalError::WrongNumberOfArguments(params.len() as u32, 2)),
+    };
+    let mut flat = vec![];
+    flatten_into(&mut flat, arr, depth);
+    Ok(Object::Array(flat))
+}
+
+// Returns the elements for which `keep` holds, leaving out repeated ones. Elements must be hashable.
+fn distinct_elements(
+    arr: Vec<Rc<Object>>,
+    keep: impl Fn(&HashableObject) -> bool,
+) -> Result<Object, EvalError> {
+    let mut seen = IndexSet::new();
+    let mut kept = vec![];
+    for item in arr {
+        let key = (*item).clone().to_hashable_object()?;
+        if keep(&key) && seen.insert(key) {
+            kept.push(item);
+        }
+    }
+    Ok(Object::Array(kept))
+}
+
+// The elements of an array.
+type Elements = Vec<Rc<Object>>;
+
+// Returns the two arrays given to a set operation.
+fn set_operands(params: Vec<Object>) -> Result<(Elements, Elements), EvalError> {
+    if params.len() != 2 {
+        return Err(EvalError::WrongNumberOfArguments(params.len() as u32, 2));
+    }
+    let mut params = params.into_iter();
+    match (params.next(), params.next()) {
+        (Some(Object::Array(left)), Some(Object::Array(right))) => Ok((left, right)),
+        _ => Err(EvalError::UnsupportedInputToBuiltIn),
+    }
+}
+
+fn hashable_set(arr: Vec<Rc<Object>>) -> Result<IndexSet<HashableObject>, EvalError> {
+    arr.iter()
+        .map(|item| (**item).clone().to_hashable_object())
+        .collect()
+}
+
+/// Returns the elements of an array without repetitions, in order of first appearance.
+fn unique(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    match params.as_slice() {
+        [Object::Array(arr)] => distinct_elements(arr.clone(), |_| true),
+        [_] => Err(EvalError::UnsupportedInputToBuiltIn),
+        _ => Err(EvalError::WrongNumberOfArguments(params.len() as u32, 1)),
+    }
+}
+
+/// Returns the distinct elements found in either of two arrays.
+fn union(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    let (mut left, right) = set_operands(params)?;
+    left.extend(right);
+    distinct_elements(left, |_| true)
+}
+
+/// Returns the distinct elements of the first array that are also found in the second.
+fn intersect(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    let (left, right) = set_operands(params)?;
+    let right = hashable_set(right)?;
+    distinct_elements(left, |key| right.contains(key))
+}
+
+/// Returns the distinct elements of the first array that are not found in the second.
+fn difference(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    let (left, right) = set_operands(params)?;
+    let right = hashable_set(right)?;
+    distinct_elements(left, |key| !right.contains(key))
+}
+
+/// Returns whether a string begins with a substring (or character), or an array with the elements of another.
+fn starts_with(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    if params.len() != 2 {
+        return Err(EvalError::WrongNumberOfArguments(params.len() as u32, 2));
+    }
+    let found = match (&params[0], &params[1]) {
+        (Object::Str(string), Object::Str(prefix)) => string.starts_with(prefix.as_str()),
+        (Object::Str(string), Object::Char(ch)) => string.starts_with(*ch),
+        (Object::Array(arr), Object::Array(prefix)) => {
+            arr.len() >= prefix.len() && arr.iter().zip(prefix).all(|(x, y)| x.equals(y))
+        }
+        _ => return Err(EvalError::UnsupportedInputToBuiltIn),
+    };
+    Ok(Object::Boolean(found))
+}
+
+/// Returns whether a string ends with a substring (or character), or an array with the elements of another.
+fn ends_with(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    if params.len() != 2 {
+        return Err(EvalError::WrongNumberOfArguments(params.len() as u32, 2));
+    }
+    let found = match (&params[0], &params[1]) {
+        (Object::Str(string), Object::Str(suffix)) => string.ends_with(suffix.as_str()),
+        (Object::Str(string), Object::Char(ch)) => string.ends_with(*ch),
+        (Object::Array(arr), Object::Array(suffix)) => {
+            arr.len() >= suffix.len()
+                && arr
+                    .iter()
+                    .rev()
+                    .zip(suffix.iter().rev())
+                    .all(|(x, y)| x.equals(y))
+        }
+        _ => return Err(EvalError::UnsupportedInputToBuiltIn),
+    };
+    Ok(Object::Boolean(found))
+}
+
+/// Splits a string into an array of one-character strings.
+fn chars(_: &mut dyn Runtime, params: Vec<Object>) -> Result<Object, EvalError> {
+    match params.as_slice() {
+        [Object::Str(string)] => Ok(Object::Array(
+            string
+                .chars()
+                .map(|ch| Rc::new(Object::Str(ch.to_string())))
+                .collect(),
+        )),
+        [other] => Err(EvalError::UnsupportedArgument(
+            String::from("chars"),
+            Rc::new(other.clone()),
+        )),
+        _ => Err(EvalError::WrongNumberOfArguments(params.len() as u32, 1)),
+    }
+}
@@ -1,15 +1,22 @@
 //! Environment
 //!
 //! `environment` contains a simple struct representing the environment of the Monkey interpreter.
-use crate::object::Object;
+use crate::object::{Object, SharedEnvironment};
 use std::collections::HashMap;
+use std::rc::Rc;
 
 /// Represents the environment of objects already recognized by the interpreter.
 ///
 /// Such objects are known about due to the interpretation of prior statements.
+/// Environments are nested: names not found in an environment are looked up in its outer environment,
+/// which is shared with (and so sees changes made by) every closure created within it.
+///
+/// Objects are held shared, so that looking one up does not copy it; changing one in place copies it first if it is
+/// shared.
 #[derive(Default, Clone, Debug)]
 pub struct Environment {
-    store: HashMap<String, Object>,
+    store: HashMap<String, Rc<Object>>,
+    outer: Option<SharedEnvironment>,
 }
 
 impl Environment {
@@ -17,11 +24,56 @@ impl Environment {
         Default::default()
     }
 
-    pub fn get(&self, name: &str) -> Option<&Object> {
-        self.store.get(name)
+    /// Returns a new, empty environment enclosed by `outer`.
+    pub fn new_enclosed(outer: SharedEnvironment) -> Self {
+        Environment {
+            store: HashMap::new(),
+            outer: Some(outer),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<Rc<Object>> {
+        match self.store.get(name) {
+            Some(obj) => Some(Rc::clone(obj)),
+            None => match &self.outer {
+                Some(outer) => outer.borrow().get(name),
+                None => None,
+            },
+        }
+    }
+
+    /// Applies `f` to the object bound to `name` in the innermost environment defining it.
+    ///
+    /// Returns `None` if `name` is not defined.
+    pub fn update<T>(&mut self, name: &str, f: impl FnOnce(&mut Rc<Object>) -> T) -> Option<T> {
+        match self.store.get_mut(name) {
+            Some(obj) => Some(f(obj)),
+            None => match &self.outer {
+                Some(outer) => outer.borrow_mut().update(name, f),
+                None => None,
+            },
+        }
     }
 
-    pub fn set(&mut self, name: &str, val: Object) {
+    /// Defines `name` in this environment, shadowing any definition in an outer environment.
+    pub fn set(&mut self, name: &str, val: Rc<Object>) {
         self.store.insert(name.to_string(), val);
     }
+
+    /// Returns the names defined in this environment (but not its outer environments) with their objects,
+    /// ordered by name.
+    pub fn bindings(&self) -> Vec<(String, Object)> {
+        let mut bindings: Vec<(String, Object)> = self
+            .store
+            .iter()
+            .map(|(name, obj)| (name.clone(), (**obj).clone()))
+            .collect();
+        bindings.sort_by(|a, b| a.0.cmp(&b.0));
+        bindings
+    }
+
+    /// Rebinds `name` in the innermost environment defining it, returning whether such an environment exists.
+    pub fn assign(&mut self, name: &str, val: Rc<Object>) -> bool {
+        self.update(name, |obj| *obj = val).is_some()
+    }
 }
@@ -8,17 +8,29 @@ mod parser_test;
 mod precedence;
 
 pub use self::parse_error::*;
-use self::precedence::*;
+pub(crate) use self::precedence::*;
 
-use crate::ast::{BlockStatement, Expression, Program, Statement};
-use crate::lexer::Lexer;
-use crate::parser::{token_precedence, ParseError, Precedence};
+use crate::ast::{BlockStatement, Expression, Parameter, Program, Statement};
+use crate::lexer::{Lexer, Position};
 use crate::token::Token;
 
+/// Returns whether `target` may appear on the left of an assignment.
+///
+/// Only variables and indexing into variables (possibly repeatedly, as in `a[0][1]`) are assignable.
+fn is_assignable(target: &Expression) -> bool {
+    match target {
+        Expression::Ident(_) => true,
+        Expression::Index(left, ..) => is_assignable(left),
+        _ => false,
+    }
+}
+
 /// A struct handling the parsing of tokens from the wrapped `Lexer`.
 pub struct Parser<'a> {
     lexer: Lexer<'a>,
     errors: Vec<ParseError>,
+    error_position: Option<Position>,
+    positions: bool,
 }
 
 impl<'a> Parser<'a> {
@@ -26,10 +38,27 @@ impl<'a> Parser<'a> {
         Parser {
             lexer,
             errors: Vec::new(),
+            error_position: None,
+            positions: true,
         }
     }
 
+    /// Makes the parser record where each statement starts, or not.
+    ///
+    /// Positions are recorded unless turned off.
+    pub fn set_positions(&mut self, positions: bool) {
+        self.positions = positions;
+    }
+
+    /// Returns where in the input parsing failed, if it did.
+    ///
+    /// This is the start of the last token the parser looked at.
+    pub fn error_position(&self) -> Option<Position> {
+        self.error_position
+    }
+
     /// Prints the errors encountered during parsing to standard out.
+    #[cfg(test)]
     pub fn print_errors(self) {
         // TODO: Determine whether we want to fail immediately on an error in parsing.
         //   When we fast-fail, this function makes less sense.
@@ -41,17 +70,40 @@ impl<'a> Parser<'a> {
     /// Returns a `Program` of parsed expressions suitable for evaluation in the Monkey language.
     pub fn parse_program(&mut self) -> Result<Program, ParseError> {
         let mut statements = vec![];
+        let mut positions = vec![];
         while *self.lexer.peek_token() != Token::EndOfFile {
+            self.record_position(&mut positions);
             match self.parse_statement() {
                 Ok(statement) => statements.push(statement),
                 Err(error) => {
                     self.errors.push(error.clone());
+                    self.error_position = Some(self.lexer.position());
                     // For debugging, we can remove the error return.
                     return Err(error);
                 }
             }
         }
-        Ok(Program { statements })
+        Ok(Program {
+            statements,
+            positions,
+        })
+    }
+
+    // Records where the statement about to be parsed starts, if positions are being recorded.
+    fn record_position(&mut self, positions: &mut Vec<Position>) {
+        if self.positions {
+            self.lexer.peek_token();
+            positions.push(self.lexer.position());
+        }
+    }
+
+    // Returns where the token just read is, as the position of the expression it belongs to, if positions are being
+    // recorded.
+    fn expression_position(&self) -> Option<Position> {
+        match self.positions {
+            true => Some(self.lexer.position()),
+            false => None,
+        }
     }
 
     fn parse_statement(&mut self) -> Result<Statement, ParseError> {
@@ -115,11 +167,24 @@ impl<'a> Parser<'a> {
 
     fn parse_expression_statement(&mut self) -> Result<Statement, ParseError> {
         let expression = self.parse_expression(Precedence::Lowest)?;
+        let statement = match *self.lexer.peek_token() {
+            Token::Assign => self.parse_assign_statement(expression)?,
+            _ => Statement::Expression(expression),
+        };
         // Optional semicolon.
         if *self.lexer.peek_token() == Token::Semicolon {
             self.lexer.next_token();
         }
-        Ok(Statement::Expression(expression))
+        Ok(statement)
+    }
+
+    fn parse_assign_statement(&mut self, target: Expression) -> Result<Statement, ParseError> {
+        if !is_assignable(&target) {
+            return Err(ParseError::InvalidAssignmentTarget(target.to_string()));
+        }
+        self.expect_peek(Token::Assign)?;
+        let value = self.parse_expression(Precedence::Lowest)?;
+        Ok(Statement::Assign(target, value))
     }
 
     fn parse_boolean_literal(&mut self) -> Result<Expression, ParseError> {
@@ -140,14 +205,19 @@ impl<'a> Parser<'a> {
     fn parse_block_statement(&mut self) -> Result<BlockStatement, ParseError> {
         self.expect_peek(Token::LBrace)?;
         let mut statements = vec![];
+        let mut positions = vec![];
         while *self.lexer.peek_token() != Token::RBrace {
             if *self.lexer.peek_token() == Token::EndOfFile {
                 return Err(ParseError::UnexpectedToken(Token::EndOfFile));
             }
+            self.record_position(&mut positions);
             statements.push(self.parse_statement()?);
         }
         self.expect_peek(Token::RBrace)?;
-        Ok(BlockStatement { statements })
+        Ok(BlockStatement {
+            statements,
+            positions,
+        })
     }
 
     fn parse_if_expression(&mut self) -> Result<Expression, ParseError> {
@@ -168,18 +238,35 @@ impl<'a> Parser<'a> {
         ))
     }
 
-    fn parse_function_parameters(&mut self) -> Result<Vec<String>, ParseError> {
+    fn parse_function_parameters(&mut self) -> Result<Vec<Parameter>, ParseError> {
         let mut parameters = vec![];
         if *self.lexer.peek_token() != Token::RParen {
-            parameters.push(self.parse_identifier_string()?);
+            parameters.push(self.parse_function_parameter()?);
             while *self.lexer.peek_token() == Token::Comma {
                 self.lexer.next_token();
-                parameters.push(self.parse_identifier_string()?);
+                let parameter = self.parse_function_parameter()?;
+                // Parameters with default values must all come after those without.
+                if parameter.default.is_none() && parameters.iter().any(|p| p.default.is_some()) {
+                    return Err(ParseError::NonDefaultAfterDefault(parameter.name));
+                }
+                parameters.push(parameter);
             }
         }
         Ok(parameters)
     }
 
+    fn parse_function_parameter(&mut self) -> Result<Parameter, ParseError> {
+        let name = self.parse_identifier_string()?;
+        let default = match *self.lexer.peek_token() {
+            Token::Assign => {
+                self.lexer.next_token();
+                Some(self.parse_expression(Precedence::Lowest)?)
+            }
+            _ => None,
+        };
+        Ok(Parameter { name, default })
+    }
+
     fn parse_expression_list(&mut self, end_token: Token) -> Result<Vec<Expression>, ParseError> {
         let mut expressions = Vec::new();
 
@@ -216,12 +303,20 @@ impl<'a> Parser<'a> {
         }
     }
 
+    fn parse_char_literal(&mut self) -> Result<Expression, ParseError> {
+        match self.lexer.next_token() {
+            Token::Char(ch) => Ok(Expression::CharLiteral(ch)),
+            other => Err(ParseError::ExpectedChar(other)),
+        }
+    }
+
     fn parse_expression(&mut self, precedence: Precedence) -> Result<Expression, ParseError> {
         // Match left/primary expression.
         let mut expr = match *self.lexer.peek_token() {
             Token::Ident(_) => self.parse_identifier()?,
             Token::Integer(_) => self.parse_integer_literal()?,
             Token::Str(_) => self.parse_string_literal()?,
+            Token::Char(_) => self.parse_char_literal()?,
             Token::Bang | Token::Minus => self.parse_prefix_expression()?,
             Token::True | Token::False => self.parse_boolean_literal()?,
             Token::LParen => self.parse_grouped_expression()?,
@@ -281,9 +376,14 @@ impl<'a> Parser<'a> {
 
     fn parse_index_expression(&mut self, left_expr: Expression) -> Result<Expression, ParseError> {
         self.expect_peek(Token::LBracket)?;
+        let position = self.expression_position();
         let right_expr = self.parse_expression(Precedence::Lowest)?;
         self.expect_peek(Token::RBracket)?;
-        Ok(Expression::Index(Box::new(left_expr), Box::new(right_expr)))
+        Ok(Expression::Index(
+            Box::new(left_expr),
+            Box::new(right_expr),
+            position,
+        ))
     }
 
     fn parse_identifier_string(&mut self) -> Result<String, ParseError> {
@@ -307,8 +407,9 @@ impl<'a> Parser<'a> {
     fn parse_prefix_expression(&mut self) -> Result<Expression, ParseError> {
         match self.lexer.next_token() {
             prefix if (prefix == Token::Minus) | (prefix == Token::Bang) => {
+                let position = self.expression_position();
                 let expr = self.parse_expression(Precedence::Prefix)?;
-                Ok(Expression::Prefix(prefix, Box::new(expr)))
+                Ok(Expression::Prefix(prefix, Box::new(expr), position))
             }
             other => Err(ParseError::ExpectedPrefix(other)),
         }
@@ -316,18 +417,21 @@ impl<'a> Parser<'a> {
 
     fn parse_infix_expression(&mut self, left_expr: Expression) -> Result<Expression, ParseError> {
         let token = self.lexer.next_token();
+        let position = self.expression_position();
         let right_expr = self.parse_expression(token_precedence(&token))?;
         Ok(Expression::Infix(
             Box::new(left_expr),
             token,
             Box::new(right_expr),
+            position,
         ))
     }
 
     fn parse_call_expression(&mut self, left_expr: Expression) -> Result<Expression, ParseError> {
         self.expect_peek(Token::LParen)?;
+        let position = self.expression_position();
         let arguments = self.parse_expression_list(Token::RParen)?;
         self.expect_peek(Token::RParen)?;
-        Ok(Expression::Call(Box::new(left_expr), arguments))
+        Ok(Expression::Call(Box::new(left_expr), arguments, position))
     }
 }
@@ -20,6 +20,9 @@ pub enum ParseError {
     ExpectedRParen(Token),
     ExpectedSemicolon(Token),
     ExpectedStr(Token),
+    ExpectedChar(Token),
+    NonDefaultAfterDefault(String),
+    InvalidAssignmentTarget(String),
     UnknownError,
 }
 
@@ -32,6 +35,7 @@ impl fmt::Display for ParseError {
         match self {
             ParseError::ExpectedIdent(token) => expected_x_got_y(f, "identifier", token),
             ParseError::ExpectedStr(token) => expected_x_got_y(f, "string", token),
+            ParseError::ExpectedChar(token) => expected_x_got_y(f, "character", token),
             ParseError::ExpectedLet(token) => expected_x_got_y(f, "let", token),
             ParseError::ExpectedAssign(token) => expected_x_got_y(f, "assign", token),
             ParseError::ExpectedInteger(token) => expected_x_got_y(f, "integer", token),
@@ -42,6 +46,14 @@ impl fmt::Display for ParseError {
             ParseError::UnexpectedToken(token) => {
                 write!(f, "ParseError: UnexpectedToken `{}`!", token)
             }
+            ParseError::NonDefaultAfterDefault(name) => write!(
+                f,
+                "ParseError: parameter `{}` must have a default value!",
+                name
+            ),
+            ParseError::InvalidAssignmentTarget(target) => {
+                write!(f, "ParseError: cannot assign to `{}`!", target)
+            }
             ParseError::UnknownError => write!(f, "ParseError: UnknownError!"),
         }
     }
@@ -137,7 +137,7 @@ fn prefix_statement_test() -> Result<(), ParseError> {
             _ => panic!(),
         };
         let (prefix, tail_expression) = match expression {
-            Expression::Prefix(pref, tail_expr) => (pref, tail_expr),
+            Expression::Prefix(pref, tail_expr, _) => (pref, tail_expr),
             _ => panic!(),
         };
         assert_eq!(prefix, expected_prefix);
@@ -187,7 +187,7 @@ fn infix_statement_test() -> Result<(), ParseError> {
             _ => panic!(),
         };
         let (left, infix, right) = match expression {
-            Expression::Infix(left, infix, right) => (left, infix, right),
+            Expression::Infix(left, infix, right, _) => (left, infix, right),
             _ => panic!(),
         };
         assert_eq!(infix, expected_infix);
@@ -342,7 +342,14 @@ fn function_literal_statement_test() -> Result<(), ParseError> {
 
     if let Statement::Expression(expr) = &program.statements[0] {
         if let Expression::FunctionLiteral(parameters, body, _) = expr {
-            assert_eq!(parameters.join(", ").to_string(), "x, y");
+            assert_eq!(
+                parameters
+                    .iter()
+                    .map(|p| p.name.clone())
+                    .collect::<Vec<String>>()
+                    .join(", "),
+                "x, y"
+            );
             assert_eq!(body.to_string(), "{ return (x + y); }");
             Ok(())
         } else {
@@ -372,6 +379,30 @@ fn function_parameter_edge_case_test() -> Result<(), ParseError> {
     Ok(())
 }
 
+#[test]
+fn default_parameter_test() -> Result<(), ParseError> {
+    let input = "fn(x = 1){};
+    fn(x, y = x + 1, z = \"z\"){}";
+    let expected = ["fn(x = 1) {  };", "fn(x, y = (x + 1), z = \"z\") {  };"];
+
+    let mut parser = Parser::new(Lexer::new(input));
+    let program = parser.parse_program()?;
+
+    parser.print_errors();
+    assert_eq!(program.statements.len(), 2);
+
+    for (expected, statement) in expected.iter().zip(program.statements.iter()) {
+        assert_eq!(&statement.to_string(), expected);
+    }
+
+    let mut parser = Parser::new(Lexer::new("fn(x = 1, y){}"));
+    match parser.parse_program() {
+        Err(ParseError::NonDefaultAfterDefault(name)) => assert_eq!(name, "y"),
+        _ => panic!("Expected a parameter ordering error!"),
+    }
+    Ok(())
+}
+
 #[test]
 fn call_expression_test() -> Result<(), ParseError> {
     let input = "add(1, 2*3, 4+5+6)";
@@ -407,6 +438,24 @@ fn string_literal_statement_test() -> Result<(), ParseError> {
     Ok(())
 }
 
+#[test]
+fn char_literal_statement_test() -> Result<(), ParseError> {
+    let input = "'a'; 'b' == c";
+
+    let expected = ["'a';", "('b' == c);"];
+
+    let mut parser = Parser::new(Lexer::new(input));
+    let program = parser.parse_program()?;
+    parser.print_errors();
+    assert_eq!(program.statements.len(), expected.len());
+
+    for (expected, statement) in expected.iter().zip(program.statements.iter()) {
+        assert_eq!(&statement.to_string(), expected);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn array_literal_statement_test() -> Result<(), ParseError> {
     let input = "
@@ -464,3 +513,103 @@ fn hash_literal_statement_test() -> Result<(), ParseError> {
 
     Ok(())
 }
+
+#[test]
+fn assignment_statement_test() -> Result<(), ParseError> {
+    let input = "x = 5; y = x * 2";
+    let expected = ["x = 5;", "y = (x * 2);"];
+
+    let mut parser = Parser::new(Lexer::new(input));
+    let program = parser.parse_program()?;
+    parser.print_errors();
+    assert_eq!(program.statements.len(), expected.len());
+
+    for (expected, statement) in expected.iter().zip(program.statements.iter()) {
+        assert_eq!(&statement.to_string(), expected);
+    }
+    Ok(())
+}
+
+#[test]
+fn index_assignment_statement_test() -> Result<(), ParseError> {
+    let input = "
+    a[0] = 5;
+    h[\"k\"] = 1 + 2
+    a[0][i + 1] = [];";
+
+    let expected = [
+        "(a[0]) = 5;",
+        "(h[\"k\"]) = (1 + 2);",
+        "((a[0])[(i + 1)]) = [];",
+    ];
+
+    let mut parser = Parser::new(Lexer::new(input));
+    let program = parser.parse_program()?;
+    parser.print_errors();
+    assert_eq!(program.statements.len(), expected.len());
+
+    for (expected, statement) in expected.iter().zip(program.statements.iter()) {
+        assert_eq!(&statement.to_string(), expected);
+    }
+
+    let bad_targets = vec!["1 = 2;", "f()[0] = 2;", "[1][0] = 2;"];
+    for input in bad_targets {
+        let mut parser = Parser::new(Lexer::new(input));
+        match parser.parse_program() {
+            Err(ParseError::InvalidAssignmentTarget(_)) => {}
+            _ => panic!("Expected an invalid assignment target error for {}!", input),
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn positions_test() {
+    let input = "let x = 1;\n  if (x) {\n    x;\n    x = 2;\n  }";
+    let program = Parser::new(Lexer::new(input)).parse_program().unwrap();
+    assert_eq!(
+        program.positions,
+        vec![
+            Position { line: 1, column: 1 },
+            Position { line: 2, column: 3 }
+        ]
+    );
+    match &program.statements[1] {
+        Statement::Expression(Expression::If(_, consequence, _)) => assert_eq!(
+            consequence.positions,
+            vec![
+                Position { line: 3, column: 5 },
+                Position { line: 4, column: 5 }
+            ]
+        ),
+        other => panic!("Expected an if expression, got {:?}", other),
+    }
+
+    let mut parser = Parser::new(Lexer::new(input));
+    parser.set_positions(false);
+    assert!(parser.parse_program().unwrap().positions.is_empty());
+}
+
+#[test]
+fn error_position_test() {
+    let tests = vec![
+        ("let = 1;", 1, 5),
+        ("let x = 1;\nlet y = (2 + 3;", 2, 15),
+        ("1 +\n  ;", 2, 3),
+    ];
+    for (input, line, column) in tests {
+        let mut parser = Parser::new(Lexer::new(input));
+        assert!(parser.parse_program().is_err());
+        assert_eq!(
+            parser.error_position(),
+            Some(Position { line, column }),
+            "{}",
+            input
+        );
+    }
+
+    let mut parser = Parser::new(Lexer::new("let x = 1;"));
+    assert!(parser.parse_program().is_ok());
+    assert_eq!(parser.error_position(), None);
+}
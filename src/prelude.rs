@@ -0,0 +1,67 @@
+//! Prelude
+//!
+//! `prelude` holds a small standard library written in Monkey, which is loaded before user code.
+//! It complements the built-in functions with:
+//!
+//! * `identity(x)` and `compose(f, g)`;
+//! * `each(arr, f)` (which returns `arr`), `any(arr, f)`, `all(arr, f)` and `count(arr, f)`;
+//! * `min_by(arr, f)` and `max_by(arr, f)`, which compare elements by the integer `f` maps them to
+//!   and return null for an empty array;
+//! * `is_empty(x)`, `repeat(s, n)`, `pad_left(s, width, fill)` and `pad_right(s, width, fill)`.
+#[cfg(test)]
+mod prelude_test;
+
+use crate::ast::{Program, Statement};
+use crate::code::Constants;
+use crate::compiler::{Compiler, SymbolTable};
+use crate::evaluator::Interpreter;
+use crate::lexer::Lexer;
+use crate::object::{Object, SharedEnvironment};
+use crate::parser::Parser;
+use crate::vm::Vm;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// The Monkey source of the prelude.
+pub const SOURCE: &str = include_str!("prelude/prelude.monkey");
+
+/// Returns the prelude, parsed.
+///
+/// Where its statements are is not recorded, as errors are placed in the source of the program using the prelude:
+/// a failure within a function of the prelude is placed where the program called it.
+pub fn program() -> Program {
+    let mut parser = Parser::new(Lexer::new(SOURCE));
+    parser.set_positions(false);
+    parser.parse_program().expect("the prelude should parse")
+}
+
+/// Returns whether the prelude defines `name`.
+pub fn defines(name: &str) -> bool {
+    program()
+        .statements
+        .iter()
+        .any(|statement| matches!(statement, Statement::Let(defined, _) if defined == name))
+}
+
+/// Defines the functions of the prelude in `env`.
+pub fn load_interpreted(interpreter: &mut Interpreter, env: SharedEnvironment) {
+    interpreter
+        .eval(&program(), env)
+        .expect("the prelude should evaluate");
+}
+
+/// Defines the functions of the prelude as globals of a compiled session.
+///
+/// The symbol table, constants and globals are those the session compiles and runs all later input with.
+pub fn load_compiled(
+    symbol_table: &Rc<RefCell<SymbolTable>>,
+    constants: &Rc<RefCell<Constants>>,
+    globals: &Rc<RefCell<Vec<Rc<Object>>>>,
+) {
+    let bytecode = Compiler::new_with_state(Rc::clone(symbol_table), Rc::clone(constants))
+        .compile(&program())
+        .expect("the prelude should compile");
+    Vm::new_with_globals_store(&bytecode, Rc::clone(globals))
+        .run()
+        .expect("the prelude should run");
+}
@@ -0,0 +1,67 @@
+use super::*;
+
+use crate::object::Environment;
+
+const TESTS: [(&str, &str); 12] = [
+    ("compose(fn(x) { x + 1 }, fn(x) { x * 2 })(5)", "11"),
+    ("identity([1])", "[1]"),
+    (
+        "let seen = []; each([1, 2], fn(x) { seen = push(seen, x) }); seen",
+        "[1, 2]",
+    ),
+    (
+        "[any([1, 2, 3], fn(x) { x > 2 }), any([], fn(x) { true })]",
+        "[true, false]",
+    ),
+    (
+        "[all([1, 2, 3], fn(x) { x > 0 }), all([1, 2], fn(x) { x > 1 })]",
+        "[true, false]",
+    ),
+    ("count([1, 2, 3, 4], fn(x) { x > 2 })", "2"),
+    ("min_by([\"ccc\", \"a\", \"bb\"], len)", "\"a\""),
+    (
+        "[max_by([\"ccc\", \"a\", \"bb\"], len), max_by([], len)]",
+        "[\"ccc\", null]",
+    ),
+    ("[is_empty([]), is_empty(\"a\")]", "[true, false]"),
+    ("repeat(\"ab\", 3)", "\"ababab\""),
+    ("pad_left(7, 3, 0)", "\"007\""),
+    (
+        "pad_right(\"ab\", 1, \" \") + pad_right(\"c\", 3, '.')",
+        "\"abc..\"",
+    ),
+];
+
+fn parse(input: &str) -> Program {
+    Parser::new(Lexer::new(input)).parse_program().unwrap()
+}
+
+#[test]
+fn interpreted_prelude_test() {
+    for (input, want) in TESTS.iter() {
+        let env = Rc::new(RefCell::new(Environment::new()));
+        let mut interpreter = Interpreter::new();
+        load_interpreted(&mut interpreter, Rc::clone(&env));
+        match interpreter.eval(&parse(input), env) {
+            Ok(obj) => assert_eq!(obj.to_string(), *want),
+            Err(error) => panic!("Got error {} for {}!", error, input),
+        }
+    }
+}
+
+#[test]
+fn compiled_prelude_test() {
+    for (input, want) in TESTS.iter() {
+        let symbol_table = Rc::new(RefCell::new(SymbolTable::new_with_builtins()));
+        let constants = Rc::default();
+        let globals = Rc::new(RefCell::new(vec![]));
+        load_compiled(&symbol_table, &constants, &globals);
+        let bytecode = Compiler::new_with_state(symbol_table, constants)
+            .compile(&parse(input))
+            .unwrap();
+        match Vm::new_with_globals_store(&bytecode, globals).run() {
+            Ok(obj) => assert_eq!(obj.to_string(), *want),
+            Err(error) => panic!("VM error {:?} for {}!", error, input),
+        }
+    }
+}
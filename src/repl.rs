@@ -3,20 +3,68 @@
 //! `repl` implements a read-evaluate-print-loop for the Monkey language.
 //! The interface is bare-bones, consisting only of reading lines of input from
 //! standard in and evaluating them, line by line.
-use crate::code::Constant;
+//! Lines starting with `:` are commands to the REPL itself, such as `:load path.monkey`; `:help` lists them.
+#[cfg(not(target_arch = "wasm32"))]
+mod completion;
+#[cfg(test)]
+mod repl_test;
+
+#[cfg(not(target_arch = "wasm32"))]
+use self::completion::NameCompleter;
+use crate::ast::Program;
+use crate::code::bytecode_listing;
+use crate::color::{self, Style};
 use crate::compiler;
-use crate::evaluator;
+use crate::engine::{Engine, Settings};
+use crate::error::Error;
 use crate::lexer;
-use crate::object::Environment;
+use crate::object::BuiltIn;
 use crate::object::Object;
 use crate::parser;
-use crate::vm;
+#[cfg(not(target_arch = "wasm32"))]
+use rustyline::error::ReadlineError;
+#[cfg(not(target_arch = "wasm32"))]
+use rustyline::Editor;
+#[cfg(not(target_arch = "wasm32"))]
+use signal_hook::consts::SIGINT;
 use std::cell::RefCell;
+use std::fs;
 use std::io;
-use std::io::Write;
+use std::io::Read;
 use std::rc::Rc;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
 
+#[cfg(not(target_arch = "wasm32"))]
 const PROMPT: &str = ">>";
+/// The commands understood by the REPL, with their arguments and a description.
+const COMMANDS: [(&str, &str); 9] = [
+    (":help", "List the available commands"),
+    (
+        ":load <path>",
+        "Run a Monkey source file within the session",
+    ),
+    (":type <code>", "Run code and show the type of its value"),
+    (
+        ":ast <code>",
+        "Show the syntax tree code parses to, without running it",
+    ),
+    (
+        ":bytecode <code>",
+        "Show what code compiles to, without running it",
+    ),
+    (
+        ":mode <mode>",
+        "Switch to compile or interpret mode, keeping what has been defined",
+    ),
+    (
+        ":env",
+        "List the names defined during the session and their values",
+    ),
+    (":reset", "Forget everything defined during the session"),
+    (":quit", "Leave the REPL"),
+];
+#[cfg(not(target_arch = "wasm32"))]
 const MONKEY_FACE: &str = "            __,__
    .--.  .-\"     \"-.  .--.
   / .. \\/  .-. .-.  \\/ .. \\
@@ -32,85 +80,282 @@ const MONKEY_FACE: &str = "            __,__
 
 /// Starts the REPL.
 ///
-/// Input is read line-by-line in interactive form until the user terminates the process.
-pub fn start(compile: bool) -> io::Result<()> {
+/// Input is read line-by-line in interactive form until the user enters `:quit` or terminates the process.
+/// Lines are run as `settings` say; with the prelude, its functions are defined before the first line is read.
+/// With `color` set, errors and values are colored for the terminal.
+///
+/// Not available on WebAssembly, which has no terminal to read from.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn start(settings: Settings, color: bool) -> Result<(), Error> {
     println!("Welcome to the Monkey programming language!");
     println!("{}", MONKEY_FACE);
-    println!("Feel free to type in commands");
+    println!("Feel free to type in commands, or :help for commands to the REPL itself");
 
-    if compile {
+    if settings.compile {
         println!("(REPL is running in compiled mode)");
-        start_with_compiler()?;
     } else {
         println!("(REPL is running in interpreted mode)");
-        start_with_interpreter()?;
     }
-    Ok(())
-}
-
-fn start_with_interpreter() -> io::Result<()> {
-    let env = Rc::new(RefCell::new(Environment::new()));
+    let mut session = Session::new(settings);
+    session.color = color;
+    signal_hook::flag::register(SIGINT, Arc::clone(&session.interrupt))
+        .map_err(|error| Error::Io(String::from("Could not handle Ctrl-C"), error))?;
+    let mut editor = Editor::<NameCompleter>::new();
+    editor.set_helper(Some(NameCompleter::default()));
     loop {
-        print!("{}", PROMPT);
-        io::stdout().flush()?;
-        let mut input = String::new();
-        io::stdin().read_line(&mut input)?;
-
-        let mut p = parser::Parser::new(lexer::Lexer::new(&input));
-        let program = match p.parse_program() {
-            Ok(prog) => prog,
-            _ => {
-                println!("Error encountered while parsing the input!");
-                p.print_errors();
-                continue;
-            }
+        if let Some(completer) = editor.helper_mut() {
+            completer.names = session.names();
+        }
+        let input = match editor.readline(PROMPT) {
+            Ok(line) => line,
+            // Ctrl-C discards the line being typed, Ctrl-D leaves the REPL.
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => return Ok(()),
+            Err(ReadlineError::Io(error)) => return Err(read_error(error)),
+            Err(error) => return Err(read_error(io::Error::other(error.to_string()))),
         };
+        editor.add_history_entry(input.as_str());
 
-        match evaluator::eval(&program, Rc::clone(&env)) {
-            Ok(evaluated) => println!("{}", evaluated),
-            Err(error) => {
-                println!("Error encountered while evaluating the input!");
-                println!("{}", error)
+        if input.trim_start().starts_with(':') {
+            if !session.command(input.trim()) {
+                return Ok(());
             }
+        } else if let Some(obj) = session.run(&input) {
+            println!("{}", session.show(&obj));
         }
     }
 }
 
-fn start_with_compiler() -> io::Result<()> {
-    let constants: Rc<RefCell<Vec<Constant>>> = Rc::new(RefCell::new(vec![]));
-    let symbol_table = Rc::new(RefCell::new(compiler::SymbolTable::new_with_builtins()));
-    let globals: Rc<RefCell<Vec<Rc<Object>>>> = Rc::new(RefCell::new(vec![]));
+/// Runs all of standard input as one program, for when it is not a terminal but, say, a pipe.
+///
+/// Nothing but the program's own output is printed; the error which stopped the program, if any, is returned.
+pub fn run_stdin(settings: Settings) -> Result<(), Error> {
+    let mut source = String::new();
+    io::stdin()
+        .read_to_string(&mut source)
+        .map_err(read_error)?;
+    let mut session = Session::new(settings);
+    session.evaluate(&source).map(|_| ())
+}
 
-    loop {
-        print!("{}", PROMPT);
-        io::stdout().flush()?;
-        let mut input = String::new();
-        io::stdin().read_line(&mut input)?;
-
-        let mut p = parser::Parser::new(lexer::Lexer::new(&input));
-        let program = match p.parse_program() {
-            Ok(prog) => prog,
-            _ => {
-                println!("Error encountered while parsing the input!");
-                p.print_errors();
-                continue;
-            }
+fn read_error(error: io::Error) -> Error {
+    Error::Io(String::from("Could not read standard input"), error)
+}
+
+/// The state an interactive session keeps between lines.
+struct Session {
+    engine: Engine,
+    // Raised by Ctrl-C to stop the code being run, without ending the session.
+    interrupt: Arc<AtomicBool>,
+    // Whether errors and values are colored for the terminal.
+    color: bool,
+}
+
+impl Session {
+    fn new(settings: Settings) -> Self {
+        let interrupt = Arc::new(AtomicBool::new(false));
+        let mut engine = Engine::new(settings);
+        engine.set_interrupt_flag(Arc::clone(&interrupt));
+        Session {
+            engine,
+            interrupt,
+            color: false,
+        }
+    }
+
+    /// Carries out a command to the REPL, given as a line starting with `:`.
+    ///
+    /// Returns false if the session should end.
+    fn command(&mut self, line: &str) -> bool {
+        let (name, argument) = match line.find(char::is_whitespace) {
+            Some(idx) => (&line[..idx], line[idx..].trim()),
+            None => (line, ""),
         };
+        match name {
+            ":help" => {
+                for (usage, description) in COMMANDS.iter() {
+                    println!("{:<18}{}", usage, description);
+                }
+            }
+            ":load" => {
+                self.load(argument);
+            }
+            ":type" => {
+                if let Some(obj) = self.run(argument) {
+                    println!("{}", obj.type_name());
+                }
+            }
+            ":ast" => {
+                if let Some(tree) = self.report(syntax_tree(argument)) {
+                    println!("{}", tree);
+                }
+            }
+            ":bytecode" => {
+                if let Some(listing) = self.bytecode(argument) {
+                    println!("{}", listing);
+                }
+            }
+            ":env" => {
+                let bindings = self.bindings();
+                if bindings.is_empty() {
+                    println!("Nothing has been defined yet");
+                }
+                for (name, obj) in bindings {
+                    println!("{} = {}", name, self.show(&obj));
+                }
+            }
+            ":mode" => match argument {
+                "compile" | "compiled" => self.switch_mode(true),
+                "interpret" | "interpreted" => self.switch_mode(false),
+                _ => eprintln!("Usage: :mode compile|interpret"),
+            },
+            ":reset" => {
+                self.engine.reset();
+                println!("Session reset");
+            }
+            ":quit" => return false,
+            _ => eprintln!(
+                "Unknown command {}; enter :help for a list of commands",
+                name
+            ),
+        }
+        true
+    }
+
+    /// Makes the session run code compiled or interpreted from now on, according to `compile`.
+    fn switch_mode(&mut self, compile: bool) {
+        let mode = if compile { "compiled" } else { "interpreted" };
+        if self.engine.is_compiled() == compile {
+            println!("Already in {} mode", mode);
+            return;
+        }
+        let left_behind = self.engine.migrate(compile);
+        println!("Switched to {} mode", mode);
+        if !left_behind.is_empty() {
+            println!(
+                "Functions do not carry over, so these are no longer defined: {}",
+                left_behind.join(", ")
+            );
+        }
+    }
+
+    /// Runs `source` within the session and returns the value it evaluates to.
+    ///
+    /// Errors are reported to the user, in which case nothing is returned.
+    fn run(&mut self, source: &str) -> Option<Object> {
+        let result = self.evaluate(source);
+        self.report(result)
+    }
 
-        let mut compiler =
-            compiler::Compiler::new_with_state(symbol_table.clone(), constants.clone());
-        let bytecode = match compiler.compile(&program) {
-            Ok(bc) => bc,
-            _ => {
-                println!("Error encountered during compilation!");
-                continue;
+    /// Returns the value of `result`, or reports its error to the user and returns nothing.
+    fn report<T>(&self, result: Result<T, Error>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(error) if self.color => {
+                eprintln!("{}", error.to_colored_string());
+                None
+            }
+            Err(error) => {
+                eprintln!("{}", error);
+                None
+            }
+        }
+    }
+
+    /// Returns `obj` as shown to the user.
+    fn show(&self, obj: &Object) -> String {
+        match self.color {
+            true => color::paint(&obj.to_string(), Style::Value),
+            false => obj.to_string(),
+        }
+    }
+
+    /// Runs `source` within the session and returns the value it evaluates to, or the error which stopped it.
+    ///
+    /// What the compiler warns of is reported to the user either way.
+    fn evaluate(&mut self, source: &str) -> Result<Object, Error> {
+        let result = self.engine.eval(source);
+        for warning in self.engine.warnings() {
+            eprintln!("{}", warning);
+        }
+        result
+    }
+
+    /// Returns a listing of the bytecode `source` compiles to within the session, or nothing if it does not compile.
+    ///
+    /// The session itself is left unchanged, so definitions in `source` do not take effect.
+    fn bytecode(&mut self, source: &str) -> Option<String> {
+        let (symbol_table, constants) = match self.engine.compiler_state() {
+            Some(state) => state,
+            None => {
+                eprintln!("The :bytecode command is only available in compiled mode!");
+                return None;
             }
         };
+        let program = self.report(parse(source))?;
+        let symbol_table = Rc::new(RefCell::new(symbol_table));
+        let mut compiler = compiler::Compiler::new_with_state(
+            Rc::clone(&symbol_table),
+            Rc::new(RefCell::new(constants)),
+        );
+        let bytecode = self.report(compiler.compile(&program).map_err(Error::from))?;
+        let listing = bytecode_listing(&bytecode, &symbol_table.borrow());
+        Some(listing)
+    }
+
+    /// Returns the names the user might want to complete: commands, built-in functions and defined names.
+    #[cfg(any(test, not(target_arch = "wasm32")))]
+    fn names(&self) -> Vec<String> {
+        let commands = COMMANDS
+            .iter()
+            .map(|(usage, _)| usage.split(' ').next().unwrap_or(usage).to_string());
+        let built_ins = BuiltIn::all().into_iter().map(|b| b.name());
+        let defined = self.bindings().into_iter().map(|(name, _)| name);
+        commands.chain(built_ins).chain(defined).collect()
+    }
 
-        let mut vm = vm::Vm::new_with_globals_store(&bytecode, globals.clone());
-        match vm.run() {
-            Ok(obj) => println!("{}", obj),
-            _ => println!("Error executing bytecode!"),
+    /// Returns the names defined at the top level of the session with their current values, ordered by name.
+    fn bindings(&self) -> Vec<(String, Object)> {
+        self.engine.bindings()
+    }
+
+    /// Runs the Monkey source file at `path` within the session, so that what it defines can be used afterwards.
+    ///
+    /// Returns whether the file ran successfully; errors are reported to the user.
+    fn load(&mut self, path: &str) -> bool {
+        if path.is_empty() {
+            eprintln!("Usage: :load <path>");
+            return false;
+        }
+        match fs::read_to_string(path) {
+            Ok(source) => {
+                let loaded = self.run(&source).is_some();
+                if loaded {
+                    println!("Loaded {}", path);
+                }
+                loaded
+            }
+            Err(error) => {
+                self.report::<()>(Err(Error::Io(format!("Could not read `{}`", path), error)));
+                false
+            }
         }
     }
 }
+
+/// Returns the syntax tree of each statement of `source`, or the error if it does not parse.
+fn syntax_tree(source: &str) -> Result<String, Error> {
+    let trees: Vec<String> = parse(source)?
+        .statements
+        .iter()
+        .map(|statement| format!("{:#?}", statement))
+        .collect();
+    Ok(trees.join("\n"))
+}
+
+/// Returns the program `source` parses to, or the error pointing out where in `source` it does not parse.
+fn parse(source: &str) -> Result<Program, Error> {
+    let mut p = parser::Parser::new(lexer::Lexer::new(source));
+    p.parse_program()
+        .map_err(|error| Error::from(error).at(None, source, p.error_position()))
+}
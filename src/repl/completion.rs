@@ -0,0 +1,63 @@
+//! Completion
+//!
+//! `completion` lets the line editor of the REPL complete names when the user presses Tab.
+use rustyline::completion::Completer;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+
+/// Completes the word before the cursor from a list of names, which the REPL keeps up to date.
+#[derive(Default)]
+pub struct NameCompleter {
+    pub names: Vec<String>,
+}
+
+impl Completer for NameCompleter {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(complete_word(line, pos, &self.names))
+    }
+}
+
+impl Hinter for NameCompleter {
+    type Hint = String;
+}
+
+impl Highlighter for NameCompleter {}
+
+impl Validator for NameCompleter {}
+
+impl Helper for NameCompleter {}
+
+/// Returns where the word ending at `pos` in `line` starts, and the names it could be completed to in sorted order.
+///
+/// Words consist of the characters of identifiers, except that a word at the start of the line may begin with `:`
+/// so that commands to the REPL can be completed too.
+pub fn complete_word(line: &str, pos: usize, names: &[String]) -> (usize, Vec<String>) {
+    let before = &line[..pos];
+    let mut start = before
+        .rfind(|ch: char| !(ch.is_alphanumeric() || ch == '_'))
+        .map_or(0, |idx| idx + 1);
+    if start == 1 && before.starts_with(':') {
+        start = 0;
+    }
+    let word = &before[start..];
+    if word.is_empty() {
+        return (pos, vec![]);
+    }
+    let mut candidates: Vec<String> = names
+        .iter()
+        .filter(|name| name.starts_with(word))
+        .cloned()
+        .collect();
+    candidates.sort();
+    candidates.dedup();
+    (start, candidates)
+}
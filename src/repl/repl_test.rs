@@ -0,0 +1,216 @@
+use super::*;
+
+use std::env;
+
+fn new_session(compile: bool, prelude: bool) -> Session {
+    Session::new(Settings {
+        compile,
+        prelude,
+        ..Settings::default()
+    })
+}
+
+fn sessions() -> Vec<Session> {
+    vec![new_session(false, false), new_session(true, false)]
+}
+
+#[test]
+fn run_test() {
+    for mut session in sessions() {
+        assert!(session.run("let x = 20;").is_some());
+        match session.run("x + 22") {
+            Some(obj) => assert_eq!(obj.to_string(), "42"),
+            None => panic!("Expected a result!"),
+        }
+        assert!(session.run("let = 1").is_none());
+        assert!(session.run("-true").is_none());
+    }
+}
+
+#[test]
+fn error_excerpt_test() {
+    for (mut session, caret) in sessions().into_iter().zip(vec!["    ^", "  ^"]) {
+        assert!(session.run("let f = fn(x) {\n  x + true\n};").is_some());
+        assert!(session.run("let y = 1;").is_some());
+        // The error is shown in the entry defining the function which failed, not the one calling it.
+        let error = session.evaluate("f(y)").unwrap_err().to_string();
+        assert!(
+            error.contains(&format!("\n  x + true\n{}", caret)),
+            "{}",
+            error
+        );
+        let error = session.evaluate("let = 1;").unwrap_err().to_string();
+        assert!(error.ends_with("\nlet = 1;\n    ^"), "{}", error);
+    }
+}
+
+#[test]
+fn load_test() {
+    let path = env::temp_dir().join(format!("orangutan_load_test_{}.monkey", std::process::id()));
+    fs::write(&path, "let double = fn(x) { x * 2 };\nlet y = double(4);\n").unwrap();
+    for mut session in sessions() {
+        assert!(session.load(path.to_str().unwrap()));
+        match session.run("double(y)") {
+            Some(obj) => assert_eq!(obj.to_string(), "16"),
+            None => panic!("Expected a result!"),
+        }
+        assert!(!session.load(""));
+        assert!(!session.load("this/file/does/not/exist.monkey"));
+    }
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn command_test() {
+    for mut session in sessions() {
+        assert!(session.command(":help"));
+        assert!(session.command(":nonsense"));
+        assert!(session.run("let x = 1;").is_some());
+        assert!(session.command(":reset"));
+        assert!(session.run("x").is_none());
+        assert!(!session.command(":quit"));
+    }
+}
+
+#[test]
+fn bytecode_test() {
+    let mut session = new_session(true, false);
+    assert!(session.run("let x = 1;").is_some());
+    let want = "0000 OpGetGlobal 0            ; x
+0003 OpConstant 1             ; 2
+0006 OpAdd
+0007 OpPop";
+    assert_eq!(session.bytecode("x + 2").unwrap(), want);
+    let want = "0000 OpClosure 1 0            ; function of 1 parameter
+    0000 OpGetLocal 0
+    0002 OpReturnValue
+0004 OpPop";
+    assert_eq!(session.bytecode("fn(a) { a }").unwrap(), want);
+    // Definitions shown as bytecode do not take effect.
+    assert!(session.bytecode("let y = 1;").is_some());
+    assert!(session.run("y").is_none());
+    assert!(session.bytecode("z").is_none());
+
+    assert!(new_session(false, false).bytecode("1").is_none());
+}
+
+#[test]
+fn syntax_tree_test() {
+    let want = "Expression(
+    Infix(
+        IntegerLiteral(
+            1,
+        ),
+        Plus,
+        Ident(
+            \"x\",
+        ),
+        Some(
+            Position {
+                line: 1,
+                column: 3,
+            },
+        ),
+    ),
+)";
+    assert_eq!(syntax_tree("1 + x").unwrap(), want);
+    assert_eq!(syntax_tree("1; 2").unwrap().lines().count(), 10);
+    assert!(syntax_tree("let = 1").is_err());
+}
+
+#[test]
+fn bindings_test() {
+    for mut session in sessions() {
+        assert!(session.bindings().is_empty());
+        assert!(session
+            .run("let b = [1]; let a = 2; let b = \"b\";")
+            .is_some());
+        let bindings: Vec<String> = session
+            .bindings()
+            .iter()
+            .map(|(name, obj)| format!("{} = {}", name, obj))
+            .collect();
+        assert_eq!(bindings, vec!["a = 2", "b = \"b\""]);
+    }
+}
+
+#[test]
+fn switch_mode_test() {
+    let mut session = new_session(false, true);
+    let definitions =
+        "let n = 1; let a = [1, {\"k\": 'c'}]; let f = fn() { 1 }; let g = [len, fn() { 2 }];";
+    assert!(session.run(definitions).is_some());
+    assert_eq!(session.engine.migrate(true), vec!["f", "g"]);
+    assert!(session.engine.is_compiled());
+    match session.run("[n, a, repeat(\"x\", 2)]") {
+        Some(obj) => assert_eq!(obj.to_string(), "[1, [1, {\"k\": 'c'}], \"xx\"]"),
+        None => panic!("Expected a result!"),
+    }
+    assert!(session.run("f").is_none());
+
+    assert!(session.run("let m = n + 1; let h = fn() { 3 };").is_some());
+    assert_eq!(session.engine.migrate(false), vec!["h"]);
+    assert!(!session.engine.is_compiled());
+    match session.run("[m, n]") {
+        Some(obj) => assert_eq!(obj.to_string(), "[2, 1]"),
+        None => panic!("Expected a result!"),
+    }
+}
+
+#[test]
+fn completion_test() {
+    use super::completion::complete_word;
+
+    let mut session = new_session(true, false);
+    assert!(session.run("let rest_of_it = 1; let mine = 2;").is_some());
+    let names = session.names();
+    let tests = vec![
+        (
+            "re",
+            2,
+            0,
+            vec![
+                "reduce",
+                "replace",
+                "replace_first",
+                "rest",
+                "rest_of_it",
+                "reverse",
+            ],
+        ),
+        ("1 + mi", 6, 4, vec!["min", "min_by", "mine"]),
+        ("puts(mi) + 1", 7, 5, vec!["min", "min_by", "mine"]),
+        (":lo", 3, 0, vec![":load"]),
+        ("let x = ", 8, 8, vec![]),
+        ("zzz", 3, 0, vec![]),
+    ];
+    // Without the prelude, `min_by` is not defined.
+    let tests: Vec<_> = tests
+        .into_iter()
+        .map(|(line, pos, start, want)| {
+            let want: Vec<&str> = want.into_iter().filter(|name| *name != "min_by").collect();
+            (line, pos, start, want)
+        })
+        .collect();
+    for (line, pos, start, want) in tests {
+        assert_eq!(
+            complete_word(line, pos, &names),
+            (start, want.iter().map(|s| s.to_string()).collect()),
+            "{}",
+            line
+        );
+    }
+}
+
+#[test]
+fn type_test() {
+    for mut session in sessions() {
+        assert!(session.command(":type 1 + 1"));
+        assert!(session.command(":type let = 1"));
+        let types: Vec<&str> = vec!["{}", "fn(x) { x }", "'c'", "len"]
+            .into_iter()
+            .map(|source| session.run(source).unwrap().type_name())
+            .collect();
+        assert_eq!(types, vec!["HASH", "FUNCTION", "CHAR", "BUILTIN"]);
+    }
+}
@@ -0,0 +1,351 @@
+//! Script
+//!
+//! `script` runs a Monkey program read from a file, interpreted or compiled, as the `run` subcommand of the binary.
+//! It also runs programs given on the command line with `-e`, compiles programs ahead of time to bytecode files
+//! (`build`) which can be run later (`exec`), and backs the `fmt`, `lint` and `verify` subcommands.
+//!
+//! `eval_str` and `compile_str` do the same for programs given as strings by code using the crate as a library.
+#[cfg(test)]
+mod script_test;
+
+use crate::ast::{Program, Statement};
+use crate::code::bytecode_listing;
+use crate::code::Bytecode;
+use crate::compiler;
+use crate::engine::holds_function;
+use crate::engine::CapturedOutput;
+use crate::error::Error;
+use crate::evaluator;
+use crate::formatter;
+use crate::lexer;
+use crate::linter;
+use crate::object::{Environment, Object};
+use crate::parser;
+use crate::prelude;
+use crate::vm;
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::rc::Rc;
+
+pub use crate::engine::Settings;
+pub use crate::vm::Limits;
+
+/// The width `format` lays programs out in, unless told otherwise.
+pub const FORMAT_WIDTH: usize = formatter::DEFAULT_WIDTH;
+
+/// Runs the program in the file at `path` as `settings` say.
+///
+/// When compiled, the program runs on a virtual machine of the size given by the limits; otherwise, it may nest calls
+/// as deep as the machine's frames would allow. Either way, it is stopped once it has used up the fuel in the
+/// limits, if any: instructions executed when compiled, statements evaluated otherwise. Likewise, it is stopped once
+/// it has run for the timeout in the limits, if any.
+pub fn start(path: &str, settings: Settings) -> Result<(), Error> {
+    let source = read(path)?;
+    run(Some(path), &source, settings)
+}
+
+/// Runs `source` as a program, like `start` does the contents of a file.
+pub fn eval(source: &str, settings: Settings) -> Result<(), Error> {
+    run(None, source, settings)
+}
+
+/// Returns the syntax tree of the program `source`.
+pub fn parse_str(source: &str) -> Result<Program, Error> {
+    parse(None, source)
+}
+
+/// Returns the value of the program `source`, evaluated by the interpreter after the prelude.
+///
+/// Built-in functions such as `puts` use standard input and output, as they do when the program is run from a file.
+pub fn eval_str(source: &str) -> Result<Object, Error> {
+    let mut program = parse(None, source)?;
+    evaluator::optimize(&mut program);
+    let env = Rc::new(RefCell::new(Environment::new()));
+    let mut interpreter = evaluator::Interpreter::new();
+    prelude::load_interpreted(&mut interpreter, Rc::clone(&env));
+    interpreter
+        .eval(&program, env)
+        .map_err(|error| Error::from(error).at(None, source, interpreter.error_position()))
+}
+
+/// Returns the bytecode of the program `source`, compiled after the prelude as `build` does by default.
+pub fn compile_str(source: &str) -> Result<Bytecode, Error> {
+    compile(&parse(None, source)?, true)
+}
+
+/// Compiles the program in the file at `path`, preceded by the prelude if `prelude` is set, and writes the
+/// bytecode to the file at `output`.
+pub fn build(path: &str, output: &str, prelude: bool) -> Result<(), Error> {
+    let source = read(path)?;
+    let program = parse(Some(path), &source)?;
+    let bytes = compile(&program, prelude)?.serialize()?;
+    fs::write(output, bytes)
+        .map_err(|error| Error::Io(format!("Could not write `{}`", output), error))
+}
+
+// Returns the bytecode of `program`, preceded by the prelude if `prelude` is set.
+fn compile(program: &Program, prelude: bool) -> Result<Bytecode, Error> {
+    let mut compiler = compiler::Compiler::new();
+    if prelude {
+        // The prelude is compiled into the bytecode, so that running it needs nothing else.
+        compiler
+            .compile(&prelude::program())
+            .expect("the prelude should compile");
+    }
+    Ok(compiler.compile(program)?)
+}
+
+/// Runs the bytecode in the file at `path`, as written by `build`, which sees `args` through the `args`
+/// built-in function.
+pub fn exec(path: &str, args: Vec<String>, limits: Limits) -> Result<(), Error> {
+    let bytes =
+        fs::read(path).map_err(|error| Error::Io(format!("Could not read `{}`", path), error))?;
+    let bytecode = Bytecode::deserialize(&bytes)?;
+    let mut vm = vm::Vm::new_with_limits(&bytecode, Rc::new(RefCell::new(vec![])), limits);
+    vm.set_args(args);
+    vm.run()?;
+    Ok(())
+}
+
+/// Runs the program in the file at `path` compiled, whatever `settings` say, like `start` does, then prints to
+/// standard error how many times each opcode was executed and how busy each function was.
+///
+/// The counts are printed even if the program fails, as far as it got.
+pub fn profile(path: &str, settings: Settings) -> Result<(), Error> {
+    let source = read(path)?;
+    let program = parse(Some(path), &source)?;
+    let mut vm = compiled_vm(&program, settings)?;
+    vm.enable_profiling();
+    let result = vm
+        .run()
+        .map_err(|error| Error::vm(error, &vm, Some(path), &source));
+    if let Some(profile) = vm.take_profile() {
+        eprintln!("{}", profile);
+    }
+    result?;
+    Ok(())
+}
+
+/// Runs the program in the file at `path` compiled, whatever `settings` say, like `start` does, printing each
+/// instruction to standard error before it is executed, along with the instruction pointer and the top of the stack.
+pub fn trace(path: &str, settings: Settings) -> Result<(), Error> {
+    let source = read(path)?;
+    let program = parse(Some(path), &source)?;
+    let mut vm = compiled_vm(&program, settings)?;
+    vm.set_tracer(Box::new(vm::Printer::new(Box::new(io::stderr()))));
+    vm.run()
+        .map_err(|error| Error::vm(error, &vm, Some(path), &source))?;
+    Ok(())
+}
+
+/// Runs the program in the file at `path` both interpreted and compiled, and reports on standard error where the
+/// two runs differ: in what they print, in the value they end with, or in whether they fail.
+///
+/// Both runs failing counts as agreement, as each engine words its errors its own way. Values are compared only
+/// for programs ending with an expression, which have a value, and only if they hold no functions, which each
+/// engine represents its own way. Returns whether the runs agree.
+pub fn verify(path: &str, prelude: bool, args: Vec<String>, limits: Limits) -> Result<bool, Error> {
+    let source = read(path)?;
+    let program = parse(Some(path), &source)?;
+
+    let interpreted_output = CapturedOutput::default();
+    let env = Rc::new(RefCell::new(Environment::new()));
+    let mut interpreter = evaluator::Interpreter::new();
+    if prelude {
+        prelude::load_interpreted(&mut interpreter, Rc::clone(&env));
+    }
+    interpreter.set_args(args.clone());
+    interpreter.set_max_depth(limits.max_frames);
+    if let Some(fuel) = limits.fuel {
+        interpreter.set_fuel(fuel);
+    }
+    if let Some(timeout) = limits.timeout {
+        interpreter.set_timeout(timeout);
+    }
+    interpreter.set_output(Box::new(interpreted_output.clone()));
+    let interpreted = interpreter.eval(&program, env).map_err(Error::from);
+
+    let compiled_output = CapturedOutput::default();
+    let settings = Settings {
+        prelude,
+        args,
+        limits,
+        ..Settings::default()
+    };
+    let compiled = compiled_vm(&program, settings).and_then(|mut vm| {
+        vm.set_output(Box::new(compiled_output.clone()));
+        Ok(vm.run()?)
+    });
+
+    let mut agree = true;
+    let mut diverge = |what: &str, interpreted: &str, compiled: &str| {
+        eprintln!("{}: the runs {}", path, what);
+        eprintln!("  interpreted: {}", interpreted);
+        eprintln!("  compiled:    {}", compiled);
+        agree = false;
+    };
+    let (interpreted_output, compiled_output) = (interpreted_output.text(), compiled_output.text());
+    if interpreted_output != compiled_output {
+        diverge(
+            "print different output",
+            &format!("{:?}", interpreted_output),
+            &format!("{:?}", compiled_output),
+        );
+    }
+    let has_value = matches!(program.statements.last(), Some(Statement::Expression(_)));
+    match (&interpreted, &compiled) {
+        (Ok(a), Ok(b))
+            if has_value
+                && !holds_function(a)
+                && !holds_function(b)
+                && a.to_string() != b.to_string() =>
+        {
+            diverge("end with different values", &a.to_string(), &b.to_string())
+        }
+        (Ok(a), Err(b)) => diverge("disagree on failing", &a.to_string(), &b.to_string()),
+        (Err(a), Ok(b)) => diverge("disagree on failing", &a.to_string(), &b.to_string()),
+        _ => {}
+    }
+    if agree {
+        println!("{}: the interpreted and compiled runs agree", path);
+    }
+    Ok(agree)
+}
+
+/// Compiles the program in the file at `path` and prints its disassembly, including the body of each function,
+/// without running it.
+///
+/// With `prelude` set, the program is compiled as it would be run, after the prelude, whose own bytecode is left out
+/// but whose globals are named.
+pub fn disassemble(path: &str, prelude: bool) -> Result<(), Error> {
+    let source = read(path)?;
+    let program = parse(Some(path), &source)?;
+    let symbol_table = Rc::new(RefCell::new(compiler::SymbolTable::new_with_builtins()));
+    let constants = Rc::default();
+    if prelude {
+        compiler::Compiler::new_with_state(Rc::clone(&symbol_table), Rc::clone(&constants))
+            .compile(&prelude::program())
+            .expect("the prelude should compile");
+    }
+    let bytecode = compiler::Compiler::new_with_state(Rc::clone(&symbol_table), constants)
+        .compile(&program)?;
+    println!("{}", bytecode_listing(&bytecode, &symbol_table.borrow()));
+    Ok(())
+}
+
+/// Parses the program in the file at `path` and prints the syntax tree of each statement, without running it.
+pub fn syntax_tree(path: &str) -> Result<(), Error> {
+    let source = read(path)?;
+    for statement in &parse(Some(path), &source)?.statements {
+        println!("{:#?}", statement);
+    }
+    Ok(())
+}
+
+/// Prints the program in the file at `path` in canonical layout, within `width` columns where possible.
+pub fn format(path: &str, width: usize) -> Result<(), Error> {
+    let source = read(path)?;
+    let program = parse(Some(path), &source)?;
+    print!("{}", formatter::format_program(&program, width));
+    Ok(())
+}
+
+/// Checks the program in the file at `path` for likely mistakes, printing a warning for each to standard error.
+/// Those of the linter are joined by those the compiler gives, such as for unused parameters, if it compiles.
+///
+/// Returns whether the program is free of warnings.
+pub fn lint(path: &str) -> Result<bool, Error> {
+    let source = read(path)?;
+    let program = parse(Some(path), &source)?;
+    let mut warnings = linter::lint(&source, &program);
+    for warning in compiler_warnings(&program) {
+        if !warnings.contains(&warning) {
+            warnings.push(warning);
+        }
+    }
+    warnings.sort_by_key(|w| (w.position.line, w.position.column));
+    for warning in &warnings {
+        eprintln!("{}:{}", path, warning);
+    }
+    Ok(warnings.is_empty())
+}
+
+// Returns the warnings the compiler gives for `program`, compiled after the prelude, or none if it does not compile.
+fn compiler_warnings(program: &Program) -> Vec<linter::Warning> {
+    let symbol_table = Rc::new(RefCell::new(compiler::SymbolTable::new_with_builtins()));
+    let constants = Rc::default();
+    compiler::Compiler::new_with_state(Rc::clone(&symbol_table), Rc::clone(&constants))
+        .compile(&prelude::program())
+        .expect("the prelude should compile");
+    let mut compiler = compiler::Compiler::new_with_state(symbol_table, constants);
+    match compiler.compile(program) {
+        Ok(_) => compiler.warnings().to_vec(),
+        Err(_) => vec![],
+    }
+}
+
+fn read(path: &str) -> Result<String, Error> {
+    fs::read_to_string(path).map_err(|error| Error::Io(format!("Could not read `{}`", path), error))
+}
+
+// Parses `source`, whose errors are said to be in `name` if given.
+fn parse(name: Option<&str>, source: &str) -> Result<Program, Error> {
+    let mut p = parser::Parser::new(lexer::Lexer::new(source));
+    p.parse_program()
+        .map_err(|error| Error::from(error).at(name, source, p.error_position()))
+}
+
+// Runs `source` as `settings` say, with its errors said to be in `name` if given.
+fn run(name: Option<&str>, source: &str, settings: Settings) -> Result<(), Error> {
+    let mut program = parse(name, source)?;
+
+    if !settings.compile {
+        evaluator::optimize(&mut program);
+        let env = Rc::new(RefCell::new(Environment::new()));
+        let mut interpreter = evaluator::Interpreter::new();
+        if settings.prelude {
+            prelude::load_interpreted(&mut interpreter, Rc::clone(&env));
+        }
+        let limits = settings.limits;
+        interpreter.set_sandboxed(settings.sandboxed);
+        interpreter.set_args(settings.args);
+        interpreter.set_input(settings.input);
+        interpreter.set_output(settings.output);
+        interpreter.set_max_depth(limits.max_frames);
+        if let Some(fuel) = limits.fuel {
+            interpreter.set_fuel(fuel);
+        }
+        if let Some(timeout) = limits.timeout {
+            interpreter.set_timeout(timeout);
+        }
+        interpreter
+            .eval(&program, env)
+            .map_err(|error| Error::from(error).at(name, source, interpreter.error_position()))?;
+        return Ok(());
+    }
+
+    let mut vm = compiled_vm(&program, settings)?;
+    vm.run()
+        .map_err(|error| Error::vm(error, &vm, name, source))?;
+    Ok(())
+}
+
+// Returns a machine ready to run `program` as `settings` say, compiled whether or not they ask for it.
+fn compiled_vm(program: &Program, settings: Settings) -> Result<vm::Vm, Error> {
+    let symbol_table = Rc::new(RefCell::new(compiler::SymbolTable::new_with_builtins()));
+    let constants = Rc::default();
+    let globals = Rc::new(RefCell::new(vec![]));
+    if settings.prelude {
+        prelude::load_compiled(&symbol_table, &constants, &globals);
+    }
+    let bytecode =
+        compiler::Compiler::new_with_state(Rc::clone(&symbol_table), constants).compile(program)?;
+    let mut vm = vm::Vm::new_with_limits(&bytecode, globals, settings.limits);
+    vm.set_symbol_table(symbol_table);
+    vm.set_sandboxed(settings.sandboxed);
+    vm.set_args(settings.args);
+    vm.set_input(settings.input);
+    vm.set_output(settings.output);
+    Ok(vm)
+}
@@ -0,0 +1,201 @@
+use super::*;
+
+use crate::error::{COMPILE_EXIT_CODE, IO_EXIT_CODE, PARSE_EXIT_CODE, RUNTIME_EXIT_CODE};
+use std::env;
+
+fn settings(compile: bool, prelude: bool) -> Settings {
+    Settings {
+        compile,
+        prelude,
+        ..Settings::default()
+    }
+}
+
+#[test]
+fn start_test() {
+    let tests = vec![
+        ("ok", "let total = fn(xs) { reduce(xs, 0, fn(a, b) { a + b }) };\nassert(total([1, 2, 3]) == 6);\nassert(len(args()) == 2);\nassert(starts_with(last(args()), \"b\"));\n", 0),
+        ("parse", "let = 1;\n", PARSE_EXIT_CODE),
+        ("eval", "1 + true;\n", RUNTIME_EXIT_CODE),
+    ];
+    for (name, source, want) in tests {
+        let path = env::temp_dir().join(format!(
+            "orangutan_script_test_{}_{}.monkey",
+            name,
+            std::process::id()
+        ));
+        fs::write(&path, source).unwrap();
+        for compile in [false, true] {
+            let args = vec!["a".to_string(), "b".to_string()];
+            let settings = Settings {
+                args,
+                ..settings(compile, true)
+            };
+            let result = start(path.to_str().unwrap(), settings);
+            assert_eq!(result.map_or_else(|error| error.exit_code(), |_| 0), want);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+    let error = start("/does/not/exist.monkey", settings(false, false)).unwrap_err();
+    assert_eq!(error.exit_code(), IO_EXIT_CODE);
+}
+
+#[test]
+fn eval_test() {
+    for compile in [false, true] {
+        assert!(eval("let x = 1 + 2; assert(x == 3)", settings(compile, false)).is_ok());
+        assert!(eval("assert(1 == 2)", settings(compile, false)).is_err());
+        assert!(eval("let", settings(compile, false)).is_err());
+        let sandboxed = Settings {
+            sandboxed: true,
+            ..settings(compile, false)
+        };
+        assert!(eval("env(\"HOME\")", sandboxed).is_err());
+    }
+}
+
+#[test]
+fn parse_str_test() {
+    match parse_str("let x = 1;\nx + 2") {
+        Ok(program) => assert_eq!(program.to_string(), "Program:let x = 1;(x + 2);"),
+        Err(error) => panic!("Got error {}!", error),
+    }
+    assert_eq!(parse_str("let").unwrap_err().exit_code(), PARSE_EXIT_CODE);
+}
+
+#[test]
+fn eval_str_test() {
+    match eval_str("let total = fn(xs) { reduce(xs, 0, fn(a, b) { a + b }) };\ntotal([1, 2, 3])") {
+        Ok(value) => assert_eq!(value.to_string(), "6"),
+        Err(error) => panic!("Got error {}!", error),
+    }
+    let error = eval_str("let a = 1;\na + true").unwrap_err();
+    assert_eq!(error.exit_code(), RUNTIME_EXIT_CODE);
+    // The error is shown with the line it is on.
+    assert!(error.to_string().ends_with("\na + true\n  ^"), "{}", error);
+    assert_eq!(eval_str("let").unwrap_err().exit_code(), PARSE_EXIT_CODE);
+}
+
+#[test]
+fn compile_str_test() {
+    // The bytecode holds the prelude, so it runs on a machine of its own.
+    let bytecode = compile_str("map([1, 2], fn(x) { x * 2 })").unwrap();
+    let mut vm = vm::Vm::new_with_limits(&bytecode, Rc::default(), Limits::default());
+    assert_eq!(vm.run().unwrap().to_string(), "[2, 4]");
+    match compile_str("undefined") {
+        Err(error) => assert_eq!(error.exit_code(), COMPILE_EXIT_CODE),
+        Ok(_) => panic!("Expected an unknown variable not to compile!"),
+    }
+}
+
+#[test]
+fn build_exec_test() {
+    let dir = env::temp_dir();
+    let source = dir.join(format!(
+        "orangutan_build_test_{}.monkey",
+        std::process::id()
+    ));
+    let output = dir.join(format!("orangutan_build_test_{}.mkc", std::process::id()));
+    fs::write(
+        &source,
+        "let counts = map([[1], [2, 3]], len);\nassert(any(counts, fn(n) { n == 2 }));\nassert(len(args()) == 1);\n",
+    )
+    .unwrap();
+    assert!(build(source.to_str().unwrap(), output.to_str().unwrap(), true).is_ok());
+    assert!(exec(
+        output.to_str().unwrap(),
+        vec!["a".to_string()],
+        Limits::default()
+    )
+    .is_ok());
+    assert!(exec(output.to_str().unwrap(), vec![], Limits::default()).is_err());
+    // Without the prelude, `any` is unknown.
+    assert!(build(source.to_str().unwrap(), output.to_str().unwrap(), false).is_err());
+    assert!(exec(source.to_str().unwrap(), vec![], Limits::default()).is_err());
+    fs::remove_file(&source).unwrap();
+    fs::remove_file(&output).unwrap();
+}
+
+#[test]
+fn disassemble_test() {
+    let path = env::temp_dir().join(format!(
+        "orangutan_disassemble_test_{}.monkey",
+        std::process::id()
+    ));
+    fs::write(&path, "let f = fn(x) { x + 1 };\nf(1)\n").unwrap();
+    assert!(disassemble(path.to_str().unwrap(), true).is_ok());
+    fs::write(&path, "undefined_name\n").unwrap();
+    assert!(disassemble(path.to_str().unwrap(), false).is_err());
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn syntax_tree_test() {
+    let path = env::temp_dir().join(format!(
+        "orangutan_syntax_tree_test_{}.monkey",
+        std::process::id()
+    ));
+    fs::write(&path, "let x = [1, 2];\n").unwrap();
+    assert!(syntax_tree(path.to_str().unwrap()).is_ok());
+    fs::write(&path, "let x = [1, 2;\n").unwrap();
+    assert!(syntax_tree(path.to_str().unwrap()).is_err());
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn profile_test() {
+    let path = env::temp_dir().join(format!(
+        "orangutan_profile_test_{}.monkey",
+        std::process::id()
+    ));
+    fs::write(
+        &path,
+        "let xs = map([1, 2], fn(x) { x + 1 });\nassert(len(xs) == 2);\n",
+    )
+    .unwrap();
+    assert!(profile(path.to_str().unwrap(), settings(true, true)).is_ok());
+    fs::write(&path, "assert(false);\n").unwrap();
+    assert!(profile(path.to_str().unwrap(), settings(true, true)).is_err());
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn trace_test() {
+    let path = env::temp_dir().join(format!(
+        "orangutan_trace_test_{}.monkey",
+        std::process::id()
+    ));
+    fs::write(&path, "let x = 1 + 2;\nassert(x == 3);\n").unwrap();
+    assert!(trace(path.to_str().unwrap(), settings(true, false)).is_ok());
+    fs::write(&path, "assert(false);\n").unwrap();
+    assert!(trace(path.to_str().unwrap(), settings(true, false)).is_err());
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn verify_test() {
+    let path = env::temp_dir().join(format!(
+        "orangutan_verify_test_{}.monkey",
+        std::process::id()
+    ));
+    let tests = vec![
+        ("puts(map([1, 2], fn(x) { x * 2 }));\nlet y = 3;\n", true),
+        ("let f = fn(x) { if (x > 1) { x } };\nf(0)\n", true),
+        ("1 + true\n", true),
+        ("args()\n", true),
+    ];
+    for (source, want) in tests {
+        fs::write(&path, source).unwrap();
+        let agree = verify(
+            path.to_str().unwrap(),
+            true,
+            vec!["a".to_string()],
+            Limits::default(),
+        );
+        assert_eq!(agree.unwrap(), want, "{}", source);
+    }
+    fs::write(&path, "let = 1;\n").unwrap();
+    let error = verify(path.to_str().unwrap(), true, vec![], Limits::default()).unwrap_err();
+    assert_eq!(error.exit_code(), PARSE_EXIT_CODE);
+    fs::remove_file(&path).unwrap();
+}
@@ -16,6 +16,7 @@ pub enum Token {
     Ident(String),
     Integer(i64),
     Str(String),
+    Char(char),
     // Operators
     Assign,
     Plus,
@@ -90,6 +91,7 @@ impl fmt::Display for Token {
             Token::Illegal => write!(f, "illegal"),
             Token::EndOfFile => write!(f, "EOF"),
             Token::Str(s) => write!(f, "{}", s),
+            Token::Char(c) => write!(f, "{}", c),
             Token::Comma => write!(f, ","),
             Token::Semicolon => write!(f, ";"),
             Token::Function => write!(f, "fn"),
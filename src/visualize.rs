@@ -0,0 +1,36 @@
+//! Visualize
+//!
+//! `visualize` runs a small Monkey program on the virtual machine while recording the state of the stack and
+//! frames before every instruction, printing the recorded trace for rendering as an animation.
+use crate::compiler;
+use crate::error::Error;
+use crate::lexer;
+use crate::parser;
+use crate::vm;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Compiles and runs `input`, printing the step-by-step trace as JSON, or as an HTML page if `html` is set.
+pub fn start(input: &str, html: bool) -> Result<(), Error> {
+    let mut p = parser::Parser::new(lexer::Lexer::new(input));
+    let program = p
+        .parse_program()
+        .map_err(|error| Error::from(error).at(None, input, p.error_position()))?;
+
+    let mut compiler = compiler::Compiler::new();
+    let bytecode = compiler.compile(&program)?;
+
+    let recorder = Rc::new(RefCell::new(vm::Recorder::new()));
+    let mut vm = vm::Vm::new(&bytecode);
+    vm.set_tracer(Box::new(recorder.clone()));
+    vm.run()
+        .map_err(|error| Error::vm(error, &vm, None, input))?;
+
+    let recorder = recorder.borrow();
+    if html {
+        println!("{}", recorder.to_html());
+    } else {
+        println!("{}", recorder.to_json());
+    }
+    Ok(())
+}
@@ -1,107 +1,507 @@
+mod backtrace;
+mod debugger;
 mod frame;
+mod gc;
+mod profile;
+mod trace;
 #[cfg(test)]
 mod vm_test;
 
-use crate::code::{read_uint16, Bytecode, Closure, CompiledFunction, Constant, OpCode};
-use crate::object::{BuiltIn, Object};
+pub use self::backtrace::*;
+pub use self::debugger::*;
+pub use self::gc::Collection;
+pub use self::profile::*;
+pub use self::trace::*;
+use crate::code::{
+    decode, disassemble_instruction, Bytecode, Capture, Closure, CompiledFunction, Constant,
+    Constants, DecodedOp, Instructions, OpCode, Upvalue,
+};
+use crate::compiler::{CompileError, Compiler, SymbolTable};
+use crate::evaluator::EvalError;
+use crate::lexer::{Lexer, Position};
+use crate::object::{stdin_reader, stdout_writer, BuiltIn, Object, Rng, Runtime};
+use crate::parser::Parser;
 use crate::vm::frame::Frame;
+use crate::vm::gc::Collector;
+use indexmap::IndexMap;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::convert::TryFrom;
+use std::fmt;
+use std::io::{BufRead, Write};
+use std::mem;
+use std::ops::RangeInclusive;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-const STACK_SIZE: usize = 2048;
-const MAX_FRAMES: usize = 1024;
-const GLOBALS_SIZE: usize = 65536;
+// The integers every machine keeps an object for, to share between all the results equal to them rather than
+// allocating one per result. Small integers are by far the most common results of arithmetic.
+pub(crate) const SMALL_INTEGERS: RangeInclusive<i64> = -128..=256;
+// How many instructions are executed between looks at the clock, which take far longer than most instructions.
+const CLOCK_INTERVAL: usize = 1024;
+
+// A compiled function ready to make closures of, with its instructions decoded.
+type Function = (Rc<CompiledFunction>, Rc<[DecodedOp]>);
+
+/// The sizes of the stack, call frames and globals a machine is allowed, and how many instructions it may execute
+/// and for how long.
+///
+/// A machine without `fuel` may execute any number of instructions; one with it stops with `VmError::OutOfFuel`
+/// once it has executed that many. Likewise, one with a `timeout` stops with `VmError::TimedOut` once a run has
+/// taken that long, as checked every so many instructions.
+///
+/// The defaults suit most programs, so limits are usually given as changes to them, such as
+/// `Limits { fuel: Some(100_000), ..Limits::default() }`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// The number of values the stack holds; 2048 by default.
+    pub stack_size: usize,
+    /// The depth calls may be nested to, counting the main program; 1024 by default.
+    pub max_frames: usize,
+    /// The number of globals; 65536 by default.
+    pub globals_size: usize,
+    /// The number of instructions a machine may execute; unlimited by default.
+    pub fuel: Option<usize>,
+    /// How long each run may take; unlimited by default.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            stack_size: 2048,
+            max_frames: 1024,
+            globals_size: 65536,
+            fuel: None,
+            timeout: None,
+        }
+    }
+}
 
 #[derive(Debug)]
 pub enum VmError {
-    UnknownError,
-    BadOpCode,
+    // The byte does not encode any opcode.
+    BadOpCode(u8),
+    // The opcode was handed to an operation which does not carry it out, such as comparing with `OpAdd`.
+    UnexpectedOpCode(OpCode),
     StackOverflow,
     StackUnderflow,
-    UnsupportedOperands,
-    CallingNonFunction,
-    WrongNumberOfArgs,
+    // The operation, and the types of the operands it was given.
+    UnsupportedOperands(OpCode, Vec<&'static str>),
+    // The type of the value called.
+    CallingNonFunction(&'static str),
+    // The number of arguments the function takes, at least and at most, and the number it was given.
+    WrongNumberOfArgs { min: usize, max: usize, got: usize },
+    // The index, which is past the end of the array assigned into.
+    IndexOutOfBounds(Object),
+    DivisionByZero,
+    // The result of an operation on integers does not fit in one.
+    IntegerOverflow,
+    FrameOverflow,
+    // The index of the global, which is beyond the globals store.
+    GlobalsOverflow(usize),
+    // The index of the constant, which does not exist or is not the function a closure is to be made from.
+    BadConstant(usize),
+    // The index of the local, which the function running does not have.
+    BadLocal(usize),
+    // The index of the free variable, which the closure running does not have.
+    BadFree(usize),
+    // The index of the built-in function, which does not exist.
+    BadBuiltIn(u8),
+    // A return was met outside any function, with nothing to return to.
+    NoFrame,
+    // A built-in function failed, for reasons the interpreter would report the same way.
+    BuiltIn(Box<EvalError>),
+    // Source code given to the `eval` built-in function could not be compiled.
+    CompileError(CompileError),
+    // The interrupt flag was raised, say by Ctrl-C, while the program was running.
+    Interrupted,
+    // The number of instructions the machine was allowed to execute was used up.
+    OutOfFuel,
+    // The run took longer than the machine was allowed.
+    TimedOut,
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VmError::BadOpCode(byte) => write!(f, "VmError: Bad opcode {}", byte),
+            VmError::UnexpectedOpCode(op) => {
+                write!(f, "VmError: Unexpected opcode {}", op.definition().name)
+            }
+            VmError::StackOverflow => write!(f, "VmError: Stack overflow"),
+            VmError::StackUnderflow => write!(f, "VmError: Stack underflow"),
+            VmError::UnsupportedOperands(op, types) => write!(
+                f,
+                "VmError: Unsupported operands for {}: {}",
+                op.definition().name,
+                types.join(" and ")
+            ),
+            VmError::CallingNonFunction(type_name) => {
+                write!(f, "VmError: Calling non-function of type {}", type_name)
+            }
+            VmError::WrongNumberOfArgs { min, max, got } if min == max => write!(
+                f,
+                "VmError: Wrong number of arguments: expected {}, got {}",
+                max, got
+            ),
+            VmError::WrongNumberOfArgs { min, max, got } => write!(
+                f,
+                "VmError: Wrong number of arguments: expected {} to {}, got {}",
+                min, max, got
+            ),
+            VmError::IndexOutOfBounds(index) => {
+                write!(f, "VmError: Index `{}` is out of bounds", index)
+            }
+            VmError::DivisionByZero => write!(f, "VmError: Division by zero"),
+            VmError::IntegerOverflow => write!(f, "VmError: Integer overflow"),
+            VmError::FrameOverflow => write!(f, "VmError: Too many nested calls"),
+            VmError::GlobalsOverflow(idx) => {
+                write!(f, "VmError: Too many globals for global {}", idx)
+            }
+            VmError::BadConstant(idx) => write!(f, "VmError: Bad constant {}", idx),
+            VmError::BadLocal(idx) => write!(f, "VmError: Bad local {}", idx),
+            VmError::BadFree(idx) => write!(f, "VmError: Bad free variable {}", idx),
+            VmError::BadBuiltIn(idx) => write!(f, "VmError: Bad built-in function {}", idx),
+            VmError::NoFrame => write!(f, "VmError: Returned outside a function"),
+            VmError::BuiltIn(error) => write!(f, "{}", error),
+            VmError::CompileError(error) => {
+                write!(f, "VmError: Could not compile evaluated source: {}", error)
+            }
+            VmError::Interrupted => write!(f, "VmError: Interrupted"),
+            VmError::OutOfFuel => write!(f, "VmError: Out of fuel"),
+            VmError::TimedOut => write!(f, "VmError: Timed out"),
+        }
+    }
 }
 
 pub struct Vm {
-    constants: Vec<Rc<Constant>>,
+    constants: Constants,
+    // The compiled functions among the constants, indexed like the constants, each decoded when it is first made
+    // into a closure. Each is kept with its constant, so that it is only used for the same one once others are
+    // loaded.
+    functions: Vec<Option<(Rc<Constant>, Function)>>,
     globals: Rc<RefCell<Vec<Rc<Object>>>>,
     stack: Vec<Rc<Object>>, // TODO: Check type
     sp: usize,
     frames: Vec<Frame>,
     frames_index: usize,
+    // Takes the place of the closures of frames no longer in use, which would otherwise keep them alive.
+    idle_closure: Closure,
     // TODO: Determine a better way to have these constants.
     true_obj: Rc<Object>,
     false_obj: Rc<Object>,
     null_obj: Rc<Object>,
+    // The objects for `SMALL_INTEGERS`, in order.
+    small_integers: Vec<Rc<Object>>,
+    tracer: Option<Box<dyn Tracer>>,
+    profile: Option<Profile>,
+    steps: usize,
+    // An error raised by Monkey code called back from a built-in function, reported once the built-in returns.
+    callback_error: Option<VmError>,
+    rng: Rng,
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+    sandboxed: bool,
+    args: Vec<String>,
+    // Resolves the names of globals for code compiled by `eval`; without it, such code gets globals of its own.
+    symbol_table: Option<Rc<RefCell<SymbolTable>>>,
+    interrupt: Option<Arc<AtomicBool>>,
+    limits: Limits,
+    // The number of instructions left to execute, if limited.
+    fuel: Option<usize>,
+    // When the current run must stop by, if limited, and the instructions executed until the clock is next checked.
+    deadline: Option<Instant>,
+    until_clock: usize,
+    // Where in the source the last run failed, if it did and that is known.
+    error_position: Option<Position>,
+    // The calls active when the last run failed, if it did.
+    backtrace: Option<Backtrace>,
+    collector: Collector,
+    last_collection: Option<Collection>,
+    // Where to pause, once a debugger has been asked for.
+    debug: Option<DebugState>,
+}
+
+// Returns a closure running the main program of `bytecode`.
+fn main_closure(bytecode: &Bytecode) -> Closure {
+    let main_function = CompiledFunction {
+        instructions: bytecode.instructions.clone(),
+        num_locals: 0,
+        num_parameters: 0,
+        num_defaults: 0,
+        captures: vec![],
+        positions: bytecode.positions.clone(),
+    };
+    Closure {
+        code: decode(&main_function.instructions).into(),
+        compiled_function: Rc::new(main_function),
+        free: Rc::new([]),
+    }
 }
 
 impl Vm {
+    /// Returns a machine ready to run `bytecode`, with globals of its own and the default `Limits`.
     pub fn new(bytecode: &Bytecode) -> Self {
-        // TODO: Would be nice to make this the same reference as in new_with_globals_store.
-        let null_ref = Rc::new(Object::Null);
-        Vm::new_with_globals_store(
-            bytecode,
-            Rc::new(RefCell::new(vec![null_ref.clone(); GLOBALS_SIZE])),
-        )
+        // The store is grown to the size of the globals, filled with the machine's own null.
+        Vm::new_with_globals_store(bytecode, Rc::new(RefCell::new(vec![])))
     }
 
     fn current_frame(&mut self) -> &mut Frame {
         &mut self.frames[self.frames_index - 1]
     }
 
-    fn push_frame(&mut self, frame: Frame) {
+    // Frames are kept once allocated, and those above `frames_index` are reused by later calls.
+    fn push_frame(
+        &mut self,
+        cl: Closure,
+        base_pointer: usize,
+        num_args: usize,
+    ) -> Result<(), VmError> {
+        if self.frames_index >= self.limits.max_frames {
+            return Err(VmError::FrameOverflow);
+        }
+        if let Some(profile) = &mut self.profile {
+            profile.enter(&cl.compiled_function.instructions);
+        }
+        match self.frames.get_mut(self.frames_index) {
+            Some(frame) => frame.reuse(cl, base_pointer, num_args),
+            None => self.frames.push(Frame::new(cl, base_pointer, num_args)),
+        }
         self.frames_index += 1;
-        self.frames.push(frame);
+        Ok(())
     }
 
-    fn pop_frame(&mut self) -> Result<Frame, VmError> {
-        self.frames_index -= 1;
-        match self.frames.pop() {
-            None => Err(VmError::UnknownError),
-            Some(other) => Ok(other),
+    // Returns the base pointer of the innermost frame, which is popped. The main program's frame is never popped, as
+    // there is nothing for it to return to.
+    fn pop_frame(&mut self) -> Result<usize, VmError> {
+        if self.frames_index <= 1 {
+            return Err(VmError::NoFrame);
         }
+        if let Some(profile) = &mut self.profile {
+            profile.leave();
+        }
+        self.frames_index -= 1;
+        let frame = &mut self.frames[self.frames_index];
+        // Upvalues would otherwise keep the values they hold alive until the frame is reused.
+        frame.cells.clear();
+        frame.cl = self.idle_closure.clone();
+        Ok(frame.bp)
     }
 
     pub fn new_with_globals_store(
         bytecode: &Bytecode,
         store: Rc<RefCell<Vec<Rc<Object>>>>,
     ) -> Self {
-        let mut ref_counted_constants = vec![];
-        for constant in &bytecode.constants {
-            ref_counted_constants.push(Rc::new(constant.clone()));
-        }
-        let main_function = CompiledFunction {
-            instructions: bytecode.instructions.clone(),
-            num_locals: 0,
-            num_parameters: 0,
-        };
-        let main_closure = Closure {
-            compiled_function: main_function,
-            free: vec![],
-        };
+        Vm::new_with_limits(bytecode, store, Limits::default())
+    }
+
+    /// Returns a machine like `new_with_globals_store` does, but which may only grow as large as `limits` allow.
+    ///
+    /// The globals store is grown to the size of the globals, but never shrunk.
+    pub fn new_with_limits(
+        bytecode: &Bytecode,
+        store: Rc<RefCell<Vec<Rc<Object>>>>,
+        limits: Limits,
+    ) -> Self {
         let null_ref = Rc::new(Object::Null);
-        let mut frames = Vec::with_capacity(MAX_FRAMES);
-        frames.push(Frame::new(main_closure, 0));
-        let deficit = GLOBALS_SIZE - store.borrow().len();
+        let mut frames = Vec::with_capacity(limits.max_frames);
+        frames.push(Frame::new(main_closure(bytecode), 0, 0));
+        let deficit = limits.globals_size.saturating_sub(store.borrow().len());
         store
             .borrow_mut()
             .append(&mut vec![null_ref.clone(); deficit]);
         Vm {
-            constants: ref_counted_constants,
+            constants: Rc::clone(&bytecode.constants),
+            functions: vec![],
             globals: store,
-            stack: vec![null_ref.clone(); STACK_SIZE],
+            stack: vec![null_ref.clone(); limits.stack_size],
             sp: 0,
             frames,
             frames_index: 1,
+            idle_closure: main_closure(&Bytecode::new(vec![], vec![])),
             true_obj: Rc::new(Object::Boolean(true)),
             false_obj: Rc::new(Object::Boolean(false)),
             null_obj: null_ref.clone(),
+            small_integers: SMALL_INTEGERS
+                .map(|value| Rc::new(Object::Integer(value)))
+                .collect(),
+            tracer: None,
+            profile: None,
+            steps: 0,
+            callback_error: None,
+            rng: Rng::default(),
+            input: stdin_reader(),
+            output: stdout_writer(),
+            sandboxed: false,
+            args: vec![],
+            symbol_table: None,
+            interrupt: None,
+            limits,
+            fuel: limits.fuel,
+            deadline: None,
+            until_clock: CLOCK_INTERVAL,
+            error_position: None,
+            backtrace: None,
+            collector: Collector::new(),
+            last_collection: None,
+            debug: None,
+        }
+    }
+
+    // Returns `obj` ready to push, reusing the machine's own object for it if it has one.
+    fn share(&self, obj: Object) -> Rc<Object> {
+        match obj {
+            Object::Integer(value) => self.integer(value),
+            Object::Boolean(true) => Rc::clone(&self.true_obj),
+            Object::Boolean(false) => Rc::clone(&self.false_obj),
+            Object::Null => Rc::clone(&self.null_obj),
+            other => Rc::new(other),
+        }
+    }
+
+    // Returns an object for the integer `value`, which is shared if the integer is small.
+    fn integer(&self, value: i64) -> Rc<Object> {
+        match SMALL_INTEGERS.contains(&value) {
+            true => Rc::clone(&self.small_integers[(value - SMALL_INTEGERS.start()) as usize]),
+            false => Rc::new(Object::Integer(value)),
         }
     }
 
+    /// Installs a tracer which is shown the state of the machine before each instruction executes.
+    pub fn set_tracer(&mut self, tracer: Box<dyn Tracer>) {
+        self.tracer = Some(tracer);
+    }
+
+    /// Makes the machine count the opcodes and functions it executes, and time the functions, from now on, as
+    /// returned by `take_profile`.
+    pub fn enable_profiling(&mut self) {
+        self.profile = Some(Profile::new(&self.constants));
+    }
+
+    /// Returns the counts gathered since `enable_profiling` was called, if it was, and stops counting.
+    ///
+    /// Functions held by globals are named after them, given the symbol table set by `set_symbol_table`.
+    pub fn take_profile(&mut self) -> Option<Profile> {
+        let mut profile = self.profile.take()?;
+        profile.finish();
+        for (instructions, name) in self.global_names() {
+            profile.name(&instructions, &name);
+        }
+        Some(profile)
+    }
+
+    /// Makes the `input` built-in function read from `input` instead of standard input.
+    pub fn set_input(&mut self, input: Box<dyn BufRead>) {
+        self.input = input;
+    }
+
+    /// Makes `puts`, `print` and `input` write to `output` instead of standard output.
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = output;
+    }
+
+    /// Exchanges the reader and writer built-in functions use with `input` and `output`, so that an engine driving
+    /// this one can lend it its own for a while.
+    pub(crate) fn swap_io(&mut self, input: &mut Box<dyn BufRead>, output: &mut Box<dyn Write>) {
+        mem::swap(&mut self.input, input);
+        mem::swap(&mut self.output, output);
+    }
+
+    /// Disallows built-in functions that reach outside the program, such as `env`.
+    pub fn set_sandboxed(&mut self, sandboxed: bool) {
+        self.sandboxed = sandboxed;
+    }
+
+    /// Sets the script arguments returned by the `args` built-in function.
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.args = args;
+    }
+
+    /// Makes the machine stop with `VmError::Interrupted` once `flag` is raised, lowering it again.
+    pub fn set_interrupt_flag(&mut self, flag: Arc<AtomicBool>) {
+        self.interrupt = Some(flag);
+    }
+
+    /// Lets code run by the `eval` built-in function use the globals named in `symbol_table`.
+    ///
+    /// This should be the symbol table the running bytecode was compiled with.
+    pub fn set_symbol_table(&mut self, symbol_table: Rc<RefCell<SymbolTable>>) {
+        self.symbol_table = Some(symbol_table);
+    }
+
+    // Compiles and runs `source` on a child machine which shares this machine's globals (when their names are known),
+    // as well as its random number generator, input and output.
+    fn run_source(&mut self, source: &str) -> Result<Object, VmError> {
+        let program = Parser::new(Lexer::new(source))
+            .parse_program()
+            .map_err(|error| VmError::BuiltIn(Box::new(EvalError::ParseError(error))))?;
+        let (symbol_table, globals) = match &self.symbol_table {
+            Some(symbol_table) => (Rc::clone(symbol_table), Rc::clone(&self.globals)),
+            None => (
+                Rc::new(RefCell::new(SymbolTable::new_with_builtins())),
+                Rc::new(RefCell::new(vec![])),
+            ),
+        };
+        let bytecode = Compiler::new_with_state(Rc::clone(&symbol_table), Rc::default())
+            .compile(&program)
+            .map_err(VmError::CompileError)?;
+        // The child draws on what is left of this machine's fuel and time.
+        let limits = Limits {
+            fuel: self.fuel,
+            timeout: self
+                .deadline
+                .map(|deadline| deadline.saturating_duration_since(Instant::now())),
+            ..self.limits
+        };
+        let mut child = Vm::new_with_limits(&bytecode, globals, limits);
+        child.symbol_table = Some(symbol_table);
+        child.sandboxed = self.sandboxed;
+        child.args = self.args.clone();
+        child.interrupt = self.interrupt.clone();
+        mem::swap(&mut child.rng, &mut self.rng);
+        mem::swap(&mut child.input, &mut self.input);
+        mem::swap(&mut child.output, &mut self.output);
+        let result = child.run();
+        self.fuel = child.fuel;
+        mem::swap(&mut child.rng, &mut self.rng);
+        mem::swap(&mut child.input, &mut self.input);
+        mem::swap(&mut child.output, &mut self.output);
+        result
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        let frame = &self.frames[self.frames_index - 1];
+        let (instruction, _) = disassemble_instruction(frame.instructions(), frame.offset());
+        Snapshot {
+            step: self.steps,
+            ip: frame.offset(),
+            instruction,
+            stack: self.stack[..self.sp]
+                .iter()
+                .map(|x| x.to_string())
+                .collect(),
+            frames: self.frames[..self.frames_index]
+                .iter()
+                .map(|x| FrameSnapshot {
+                    ip: x.offset(),
+                    bp: x.bp,
+                    num_locals: x.cl.compiled_function.num_locals,
+                })
+                .collect(),
+        }
+    }
+
+    fn trace(&mut self) {
+        if let Some(mut tracer) = self.tracer.take() {
+            tracer.trace(&self.snapshot());
+            self.tracer = Some(tracer);
+        }
+        self.steps += 1;
+    }
+
     fn increment_ip(&mut self, val: usize) {
         self.current_frame().ip += val;
     }
@@ -111,16 +511,28 @@ impl Vm {
     }
 
     fn call_closure(&mut self, num_args: usize, closure: Closure) -> Result<(), VmError> {
-        if closure.compiled_function.num_parameters != num_args {
-            return Err(VmError::WrongNumberOfArgs);
+        let num_parameters = closure.compiled_function.num_parameters;
+        let num_required = num_parameters - closure.compiled_function.num_defaults;
+        if num_args < num_required || num_args > num_parameters {
+            return Err(VmError::WrongNumberOfArgs {
+                min: num_required,
+                max: num_parameters,
+                got: num_args,
+            });
         }
         let num_locals = closure.compiled_function.num_locals;
-        self.push_frame(Frame::new(closure, self.sp - num_args));
+        if self.sp + num_locals > self.stack.len() {
+            return Err(VmError::StackOverflow);
+        }
+        self.push_frame(closure, self.sp - num_args, num_args)?;
         self.sp += num_locals;
         Ok(())
     }
 
     fn call_function(&mut self, num_args: usize) -> Result<(), VmError> {
+        if num_args >= self.sp {
+            return Err(VmError::StackUnderflow);
+        }
         let func = (*self.stack[self.sp - 1 - num_args]).clone();
         match func {
             Object::Closure(cl) => self.call_closure(num_args, cl),
@@ -132,86 +544,336 @@ impl Vm {
                 args.reverse();
                 // Remove the function itself from the stack.
                 self.pop()?;
-                match func(args) {
+                let result = func(self, args);
+                let callback_error = self.callback_error.take();
+                match result {
                     Ok(obj) => {
-                        self.push(Rc::new(obj))?;
+                        let obj = self.share(obj);
+                        self.push(obj)?;
                         self.increment_ip(1);
                         Ok(())
                     }
-                    Err(_) => Err(VmError::UnknownError),
+                    Err(error) => {
+                        Err(callback_error.unwrap_or_else(|| VmError::BuiltIn(Box::new(error))))
+                    }
                 }
             }
-            _ => Err(VmError::CallingNonFunction),
+            other => Err(VmError::CallingNonFunction(other.type_name())),
+        }
+    }
+
+    fn call_nested(&mut self, closure: Closure, args: Vec<Object>) -> Result<Object, VmError> {
+        let num_args = args.len();
+        self.push(Rc::new(Object::Closure(closure.clone())))?;
+        for arg in args {
+            let arg = self.share(arg);
+            self.push(arg)?;
+        }
+        self.call_closure(num_args, closure)?;
+        self.execute(self.frames_index)?;
+        Ok((*self.pop()?).clone())
+    }
+
+    fn push_closure(&mut self, idx: usize) -> Result<(), VmError> {
+        let (func, code) = self.function(idx)?;
+        let mut free_vars = Vec::with_capacity(func.captures.len());
+        for capture in &func.captures {
+            free_vars.push(self.capture(*capture)?);
+        }
+        self.push(Rc::new(Object::Closure(Closure {
+            compiled_function: func,
+            free: free_vars.into(),
+            code,
+        })))
+    }
+
+    // Returns the compiled function which is constant `idx`, decoding it the first time.
+    fn function(&mut self, idx: usize) -> Result<Function, VmError> {
+        let constant = match self.constants.get(idx) {
+            Some(constant) => constant,
+            None => return Err(VmError::BadConstant(idx)),
+        };
+        if let Some(Some((decoded_from, (func, code)))) = self.functions.get(idx) {
+            if Rc::ptr_eq(decoded_from, constant) {
+                return Ok((Rc::clone(func), Rc::clone(code)));
+            }
         }
+        let function = match &**constant {
+            Constant::CompiledFunction(function) => function,
+            _ => return Err(VmError::BadConstant(idx)),
+        };
+        let decoded: Function = (
+            Rc::new(function.clone()),
+            decode(&function.instructions).into(),
+        );
+        if self.functions.len() <= idx {
+            self.functions.resize(idx + 1, None);
+        }
+        self.functions[idx] = Some((Rc::clone(constant), decoded.clone()));
+        Ok(decoded)
     }
 
-    fn push_closure(&mut self, idx: u16, num_free: u8) -> Result<(), VmError> {
-        match (*self.constants[idx as usize]).clone() {
-            Object::CompiledFunction(func) => {
-                let mut free_vars = Vec::with_capacity(num_free as usize);
-                for _ in 0..num_free {
-                    free_vars.push(self.pop()?);
+    /// Returns the upvalue through which a new closure shares a variable of the current frame.
+    fn capture(&mut self, capture: Capture) -> Result<Upvalue, VmError> {
+        let frame = &mut self.frames[self.frames_index - 1];
+        match capture {
+            Capture::Local(idx) => {
+                if let Some(cell) = frame.cell(idx) {
+                    return Ok(cell.clone());
+                }
+                if idx >= frame.cl.compiled_function.num_locals {
+                    return Err(VmError::BadLocal(idx));
                 }
-                free_vars.reverse();
-                self.push(Rc::new(Object::Closure(Closure {
-                    compiled_function: func,
-                    free: free_vars,
-                })))
+                // Move the local into an upvalue, which is used in place of its stack slot from now on.
+                if frame.cells.len() <= idx {
+                    frame.cells.resize(idx + 1, None);
+                }
+                let cell = Rc::new(RefCell::new(self.stack[frame.bp + idx].clone()));
+                frame.cells[idx] = Some(cell.clone());
+                self.collector.track(&cell);
+                Ok(cell)
+            }
+            Capture::Free(idx) => frame.cl.free.get(idx).cloned().ok_or(VmError::BadFree(idx)),
+            Capture::CurrentClosure => {
+                let cell = Rc::new(RefCell::new(Rc::new(Object::Closure(frame.cl.clone()))));
+                self.collector.track(&cell);
+                Ok(cell)
             }
-            _ => return Err(VmError::UnknownError),
         }
     }
 
     pub fn run(&mut self) -> Result<Object, VmError> {
-        while self.current_frame().ip < self.current_frame().instructions().len() {
-            let ip = self.current_frame().ip;
-            let ins = self.current_frame().instructions();
-            let op = match OpCode::try_from(ins[ip]) {
+        self.deadline = self.limits.timeout.map(|timeout| Instant::now() + timeout);
+        self.run_main()
+    }
+
+    /// Returns a debugger for running the program a little at a time instead of all at once with `run`.
+    ///
+    /// Breakpoints are kept by the machine, so they remain when the debugger is dropped and another made. The
+    /// machine's timeout does not apply to running it with a debugger.
+    pub fn debugger(&mut self) -> Debugger<'_> {
+        Debugger::new(self)
+    }
+
+    /// Makes the machine run `bytecode` next, from its start, like `reset` does for the program it has run.
+    ///
+    /// The globals are kept, and so are the settings of the machine, such as its limits and where its built-in
+    /// functions write to, and the functions it has decoded which are still among the constants.
+    pub fn load(&mut self, bytecode: &Bytecode) {
+        self.reset();
+        self.constants = Rc::clone(&bytecode.constants);
+        self.frames[0].reuse(main_closure(bytecode), 0, 0);
+    }
+
+    /// Clears what the last run left behind, whether it ended, failed or was paused: the values on the stack, the
+    /// calls in progress and where it failed. The fuel is refilled and breakpoints are removed.
+    ///
+    /// The program itself is let go of too, so that the constants can be added to without being copied, and one
+    /// must be loaded with `load` before running the machine again.
+    pub fn reset(&mut self) {
+        for slot in self.stack.iter_mut() {
+            *slot = Rc::clone(&self.null_obj);
+        }
+        self.sp = 0;
+        for frame in &mut self.frames[..self.frames_index] {
+            frame.cells.clear();
+            frame.cl = self.idle_closure.clone();
+        }
+        self.frames_index = 1;
+        self.frames[0].reuse(self.idle_closure.clone(), 0, 0);
+        self.constants = Rc::default();
+        self.callback_error = None;
+        self.fuel = self.limits.fuel;
+        self.error_position = None;
+        self.backtrace = None;
+        self.debug = None;
+    }
+
+    // Executes the main program from where it is until it ends, or a debugger pauses it.
+    fn run_main(&mut self) -> Result<Object, VmError> {
+        self.error_position = None;
+        self.backtrace = None;
+        if let Err(error) = self.execute(1) {
+            self.error_position = self.position();
+            self.backtrace = Some(self.trace_back());
+            return Err(error);
+        }
+        let result = &*self.last_top();
+        Ok(result.clone())
+    }
+
+    /// Returns where in the source the last call to `run` failed, if it did.
+    ///
+    /// This is the start of the statement the failing instruction was compiled from, in the innermost function
+    /// whose positions are known (those of the prelude are not, so its failures are placed where it was called).
+    pub fn error_position(&self) -> Option<Position> {
+        self.error_position
+    }
+
+    /// Returns the calls which were active when the last call to `run` failed, if it did.
+    ///
+    /// Functions are named after the globals holding them, given the symbol table set by `set_symbol_table`.
+    pub fn backtrace(&self) -> Option<&Backtrace> {
+        self.backtrace.as_ref()
+    }
+
+    /// Breaks the reference cycles among closures and the variables they captured which can no longer be reached,
+    /// and returns what was found. Collections are also made automatically, as captured variables are created.
+    ///
+    /// Anything referenced from outside the cycles is kept, whether by the machine or by values it has handed out,
+    /// such as those returned by `run`.
+    pub fn collect_garbage(&mut self) -> Collection {
+        let collection = self.collector.collect(&self.null_obj);
+        self.last_collection = Some(collection);
+        collection
+    }
+
+    /// Returns what the last garbage collection found, if one has been made.
+    // Not used within the crate outside of tests yet.
+    #[allow(dead_code)]
+    pub fn last_collection(&self) -> Option<Collection> {
+        self.last_collection
+    }
+
+    fn trace_back(&self) -> Backtrace {
+        let names = self.global_names();
+        let frames = self.frames[..self.frames_index]
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(depth, frame)| {
+                let function = &frame.cl.compiled_function;
+                // The main program is always the outermost frame, and is not among the constants.
+                let idx = match depth {
+                    0 => None,
+                    _ => self
+                        .constants
+                        .iter()
+                        .position(|constant| match &**constant {
+                            Object::CompiledFunction(candidate) => {
+                                candidate.instructions == function.instructions
+                            }
+                            _ => false,
+                        }),
+                };
+                BacktraceFrame {
+                    function: idx,
+                    name: idx.and_then(|_| names.get(&function.instructions).cloned()),
+                    ip: frame.offset(),
+                    position: function.position(frame.offset()),
+                }
+            })
+            .collect();
+        Backtrace { frames }
+    }
+
+    // Returns the names of the globals holding functions, keyed by the instructions of the function.
+    fn global_names(&self) -> HashMap<Instructions, String> {
+        let mut names = HashMap::new();
+        if let Some(symbol_table) = &self.symbol_table {
+            let globals = self.globals.borrow();
+            for symbol in symbol_table.borrow().global_symbols() {
+                if let Some(Object::Closure(closure)) =
+                    globals.get(symbol.index as usize).map(|obj| &**obj)
+                {
+                    names
+                        .entry(closure.compiled_function.instructions.clone())
+                        .or_insert(symbol.name);
+                }
+            }
+        }
+        names
+    }
+
+    // Returns where the innermost frame whose positions are known is in the source, if any is.
+    fn position(&self) -> Option<Position> {
+        self.frames[..self.frames_index]
+            .iter()
+            .rev()
+            .find_map(|frame| frame.cl.compiled_function.position(frame.offset()))
+    }
+
+    /// Executes instructions until the end of the program, or until the frame at `depth` returns.
+    fn execute(&mut self, depth: usize) -> Result<(), VmError> {
+        while self.current_frame().ip < self.current_frame().code().len() {
+            // Only the outermost run can return to the debugger without losing its place.
+            if depth == 1 && self.debug.is_some() && self.pause_here() {
+                return Ok(());
+            }
+            if let Some(flag) = &self.interrupt {
+                if flag.swap(false, Ordering::Relaxed) {
+                    return Err(VmError::Interrupted);
+                }
+            }
+            if let Some(fuel) = &mut self.fuel {
+                if *fuel == 0 {
+                    return Err(VmError::OutOfFuel);
+                }
+                *fuel -= 1;
+            }
+            if let Some(deadline) = self.deadline {
+                self.until_clock -= 1;
+                if self.until_clock == 0 {
+                    self.until_clock = CLOCK_INTERVAL;
+                    if Instant::now() >= deadline {
+                        return Err(VmError::TimedOut);
+                    }
+                }
+            }
+            if self.collector.due() {
+                self.collect_garbage();
+            }
+            if self.tracer.is_some() {
+                self.trace();
+            }
+            let frame = &self.frames[self.frames_index - 1];
+            let instruction = frame.code()[frame.ip];
+            if let Some(profile) = &mut self.profile {
+                profile.record(instruction.byte());
+            }
+            let op = match instruction.op {
                 Ok(op) => op,
-                _ => return Err(VmError::BadOpCode),
+                Err(byte) => return Err(VmError::BadOpCode(byte)),
             };
+            let [operand, second_operand] = instruction.operands;
             match op {
                 OpCode::CurrentClosure => {
                     let curr = self.current_frame().cl.clone();
                     self.push(Rc::new(Object::Closure(curr)))?;
                 }
                 OpCode::GetFree => {
-                    let free_idx = ins[ip + 1];
-                    self.increment_ip(1);
-                    let free = self.current_frame().cl.free[free_idx as usize].clone();
+                    let free = self.free(operand)?.borrow().clone();
                     self.push(free)?;
                 }
-                OpCode::Closure => {
-                    let idx = read_uint16(ins[ip + 1], ins[ip + 2]);
-                    let num_free = ins[ip + 3];
-                    self.increment_ip(3);
-                    self.push_closure(idx, num_free)?
+                OpCode::SetFree => {
+                    let element = self.pop()?;
+                    *self.free(operand)?.borrow_mut() = element;
                 }
+                OpCode::Closure | OpCode::ClosureWide => self.push_closure(operand)?,
                 OpCode::GetBuiltin => {
-                    // TODO: Clean this up.
-                    let idx = ins[ip + 1];
-                    self.increment_ip(1);
-                    let b = match BuiltIn::try_from(idx) {
+                    let b = match BuiltIn::try_from(operand as u8) {
                         Ok(built_in) => built_in,
-                        Err(_) => return Err(VmError::UnknownError),
+                        Err(_) => return Err(VmError::BadBuiltIn(operand as u8)),
                     };
                     self.push(Rc::new(b.func()))?;
                 }
                 OpCode::Return => {
-                    let frame = self.pop_frame()?;
-                    self.sp = frame.bp - 1;
+                    self.sp = self.pop_frame()? - 1;
                     self.push(self.null_obj.clone())?;
+                    if self.frames_index < depth {
+                        return Ok(());
+                    }
                 }
                 OpCode::ReturnValue => {
                     let return_value = self.pop()?;
-                    let frame = self.pop_frame()?;
-                    self.sp = frame.bp - 1;
+                    self.sp = self.pop_frame()? - 1;
                     self.push(return_value)?;
+                    if self.frames_index < depth {
+                        return Ok(());
+                    }
                 }
-                OpCode::Call => {
-                    let num_args = ins[ip + 1];
-                    self.increment_ip(1);
-                    self.call_function(num_args as usize)?;
+                OpCode::Call | OpCode::CallWide => {
+                    self.call_function(operand)?;
                     continue;
                 }
                 OpCode::Index => {
@@ -219,61 +881,78 @@ impl Vm {
                     let left = self.pop()?;
                     self.index_expression(left, index)?;
                 }
-                OpCode::Hash => {
-                    let num_elements = read_uint16(ins[ip + 1], ins[ip + 2]);
-                    self.increment_ip(2);
-                    let mut hash_map = HashMap::new();
-                    for _ in 0..num_elements / 2 {
-                        // TODO: Stop the cloning...
-                        let value = (*self.pop()?).clone();
-                        if let Ok(key) = (*self.pop()?).clone().to_hashable_object() {
-                            hash_map.insert(key, value);
-                        } else {
-                            return Err(VmError::UnsupportedOperands);
+                OpCode::SetIndex => {
+                    let index = self.pop()?;
+                    let mut collection = self.pop()?;
+                    let value = self.pop()?;
+                    // Only copies the collection if it is still referenced elsewhere.
+                    let result = Rc::make_mut(&mut collection).set_index((*index).clone(), value);
+                    match result {
+                        Ok(()) => self.push(collection)?,
+                        Err(EvalError::IndexOutOfBounds(index)) => {
+                            return Err(VmError::IndexOutOfBounds((*index).clone()))
+                        }
+                        Err(_) => {
+                            return Err(VmError::UnsupportedOperands(
+                                op,
+                                vec![collection.type_name(), index.type_name()],
+                            ))
                         }
                     }
+                }
+                OpCode::Hash => {
+                    let num_elements = operand;
+                    let mut hash_map = IndexMap::with_capacity(num_elements / 2);
+                    // Keys and values are inserted in source order, so read them from the bottom up.
+                    if num_elements > self.sp {
+                        return Err(VmError::StackUnderflow);
+                    }
+                    let first = self.sp - num_elements;
+                    for i in (first..self.sp).step_by(2) {
+                        let value = Rc::clone(&self.stack[i + 1]);
+                        let key = (*self.stack[i]).clone();
+                        let type_name = key.type_name();
+                        match key.to_hashable_object() {
+                            Ok(key) => hash_map.insert(key, value),
+                            Err(_) => {
+                                return Err(VmError::UnsupportedOperands(op, vec![type_name]))
+                            }
+                        };
+                    }
+                    self.sp = first;
                     let hash = Rc::new(Object::Hash(hash_map));
                     self.push(hash)?;
                 }
                 OpCode::Array => {
-                    let num_elements = read_uint16(ins[ip + 1], ins[ip + 2]);
-                    self.increment_ip(2);
-                    let mut elements = Vec::with_capacity(num_elements as usize);
-                    for _ in 0..num_elements {
-                        // TODO: If we modify the array class to hold Rc elements, we don't have to clone here.
-                        elements.push((*self.pop()?).clone());
+                    let mut elements = Vec::with_capacity(operand);
+                    for _ in 0..operand {
+                        elements.push(self.pop()?);
                     }
                     elements.reverse();
                     let array = Rc::new(Object::Array(elements));
                     self.push(array)?;
                 }
                 OpCode::SetGlobal => {
-                    let global_idx = read_uint16(ins[ip + 1], ins[ip + 2]);
-                    self.increment_ip(2);
                     let element = self.pop()?;
-                    self.globals.borrow_mut()[global_idx as usize] = element;
+                    match self.globals.borrow_mut().get_mut(operand) {
+                        Some(global) => *global = element,
+                        None => return Err(VmError::GlobalsOverflow(operand)),
+                    }
                 }
                 OpCode::GetGlobal => {
-                    let global_idx = read_uint16(ins[ip + 1], ins[ip + 2]);
-                    self.increment_ip(2);
-                    let element = match self.globals.borrow().get(global_idx as usize) {
-                        Some(elem) => elem.clone(),
-                        _ => return Err(VmError::UnknownError),
-                    };
+                    let element = self.global(operand)?;
                     self.push(element)?;
                 }
-                OpCode::SetLocal => {
-                    let local_idx = ins[ip + 1];
-                    self.increment_ip(1);
+                OpCode::SetLocal | OpCode::SetLocalWide => {
                     let element = self.pop()?;
-                    let idx = self.current_frame().bp + local_idx as usize;
-                    self.stack[idx] = element;
-                }
-                OpCode::GetLocal => {
-                    let local_idx = ins[ip + 1];
-                    self.increment_ip(1);
-                    let idx = self.current_frame().bp + local_idx as usize;
-                    let element = self.stack[idx].clone();
+                    let slot = self.local_slot(operand)?;
+                    match self.current_frame().cell(operand) {
+                        Some(cell) => *cell.borrow_mut() = element,
+                        None => self.stack[slot] = element,
+                    }
+                }
+                OpCode::GetLocal | OpCode::GetLocalWide => {
+                    let element = self.local(operand)?;
                     self.push(element)?;
                 }
                 OpCode::True => self.push(self.true_obj.clone())?,
@@ -282,10 +961,9 @@ impl Vm {
                 OpCode::Pop => {
                     self.pop()?;
                 }
-                OpCode::Constant => {
-                    let const_idx = read_uint16(ins[ip + 1], ins[ip + 2]);
-                    self.increment_ip(2);
-                    self.push(self.constants[const_idx as usize].clone())?;
+                OpCode::Constant | OpCode::ConstantWide => {
+                    let constant = self.constant(operand)?;
+                    self.push(constant)?;
                 }
                 OpCode::Bang => {
                     let result = match &*self.pop()? {
@@ -303,27 +981,129 @@ impl Vm {
                 OpCode::Minus => {
                     let value = match &*self.pop()? {
                         Object::Integer(val) => *val,
-                        _ => return Err(VmError::UnsupportedOperands),
+                        other => {
+                            return Err(VmError::UnsupportedOperands(op, vec![other.type_name()]))
+                        }
                     };
-                    self.push(Rc::new(Object::Integer(-value)))?;
+                    let value = value.checked_neg().ok_or(VmError::IntegerOverflow)?;
+                    self.push(self.integer(value))?;
                 }
                 OpCode::Jump => {
-                    let jump_pos = read_uint16(ins[ip + 1], ins[ip + 2]);
-                    self.set_ip((jump_pos - 1) as usize);
+                    self.set_ip(operand);
+                    continue;
+                }
+                OpCode::JumpIfPassed => {
+                    if self.current_frame().num_args > second_operand {
+                        self.set_ip(operand);
+                        continue;
+                    }
                 }
                 OpCode::JumpNotTruthy => {
-                    let jump_pos = read_uint16(ins[ip + 1], ins[ip + 2]);
-                    self.increment_ip(2);
                     let value = &*self.pop()?;
                     if !value.is_truthy() {
-                        self.set_ip((jump_pos - 1) as usize);
+                        self.set_ip(operand);
+                        continue;
                     }
                 }
+                OpCode::GetLocalGetLocalAdd => {
+                    let (left, right) = (self.local(operand)?, self.local(second_operand)?);
+                    self.push(left)?;
+                    self.push(right)?;
+                    self.binary_op(OpCode::Add)?;
+                }
+                OpCode::ConstantCall => {
+                    let constant = self.constant(operand)?;
+                    self.push(constant)?;
+                    self.call_function(second_operand)?;
+                    continue;
+                }
+                OpCode::GetGlobalCall => {
+                    let element = self.global(operand)?;
+                    self.push(element)?;
+                    self.call_function(second_operand)?;
+                    continue;
+                }
             }
             self.increment_ip(1);
         }
-        let result = &*self.last_top();
-        Ok(result.clone())
+        Ok(())
+    }
+
+    // Returns whether the debugger is to pause before the current instruction.
+    fn pause_here(&mut self) -> bool {
+        let frame = &self.frames[self.frames_index - 1];
+        let (function, offset) = (&frame.cl.compiled_function, frame.offset());
+        let main = self.frames_index == 1;
+        let functions = &self.functions;
+        let here = |breakpoint: &Breakpoint| match *breakpoint {
+            Breakpoint::Instruction {
+                function: None,
+                offset: at,
+            } => main && at == offset,
+            Breakpoint::Instruction {
+                function: Some(idx),
+                offset: at,
+            } => {
+                at == offset
+                    && matches!(functions.get(idx), Some(Some((_, (candidate, _)))) if Rc::ptr_eq(candidate, function))
+            }
+            Breakpoint::Line(line) => function
+                .positions
+                .iter()
+                .any(|(start, position)| *start == offset && position.line == line),
+        };
+        match &mut self.debug {
+            Some(debug) => debug.pause(here),
+            None => false,
+        }
+    }
+
+    // Returns the local variable at `idx` of the current frame.
+    fn local(&self, idx: usize) -> Result<Rc<Object>, VmError> {
+        let slot = self.local_slot(idx)?;
+        match self.frames[self.frames_index - 1].cell(idx) {
+            Some(cell) => Ok(cell.borrow().clone()),
+            None => Ok(self.stack[slot].clone()),
+        }
+    }
+
+    // Returns where on the stack the local variable at `idx` of the current frame is kept, unless it is captured.
+    fn local_slot(&self, idx: usize) -> Result<usize, VmError> {
+        let frame = &self.frames[self.frames_index - 1];
+        match idx < frame.cl.compiled_function.num_locals {
+            true => Ok(frame.bp + idx),
+            false => Err(VmError::BadLocal(idx)),
+        }
+    }
+
+    // Returns the free variable at `idx` of the closure running in the current frame.
+    fn free(&self, idx: usize) -> Result<&Upvalue, VmError> {
+        self.frames[self.frames_index - 1]
+            .cl
+            .free
+            .get(idx)
+            .ok_or(VmError::BadFree(idx))
+    }
+
+    fn global(&self, idx: usize) -> Result<Rc<Object>, VmError> {
+        match self.globals.borrow().get(idx) {
+            Some(elem) => Ok(elem.clone()),
+            _ => Err(VmError::GlobalsOverflow(idx)),
+        }
+    }
+
+    fn constant(&self, idx: usize) -> Result<Rc<Object>, VmError> {
+        match self.constants.get(idx) {
+            // The constants are shared with the compiler, so small integers among them are swapped for the
+            // machine's own objects here.
+            Some(constant) => match **constant {
+                Object::Integer(value) if SMALL_INTEGERS.contains(&value) => {
+                    Ok(self.integer(value))
+                }
+                _ => Ok(constant.clone()),
+            },
+            None => Err(VmError::BadConstant(idx)),
+        }
     }
 
     fn comparison_op(&mut self, op: OpCode) -> Result<(), VmError> {
@@ -336,7 +1116,16 @@ impl Vm {
             (Object::Integer(left), Object::Integer(right)) => {
                 self.comparison_integer_op(*left, op, *right)?;
             }
-            _ => return Err(VmError::UnsupportedOperands),
+            // Characters compare by their code points.
+            (Object::Char(left), Object::Char(right)) => {
+                self.comparison_integer_op(*left as i64, op, *right as i64)?;
+            }
+            (left, right) => {
+                return Err(VmError::UnsupportedOperands(
+                    op,
+                    vec![left.type_name(), right.type_name()],
+                ))
+            }
         }
         Ok(())
     }
@@ -350,7 +1139,7 @@ impl Vm {
         let result = match op {
             OpCode::Equal => left == right,
             OpCode::NotEqual => left != right,
-            _ => return Err(VmError::BadOpCode),
+            _ => return Err(VmError::UnexpectedOpCode(op)),
         };
         if result {
             self.push(self.true_obj.clone())?;
@@ -365,7 +1154,7 @@ impl Vm {
             OpCode::Equal => left == right,
             OpCode::NotEqual => left != right,
             OpCode::GreaterThan => left > right,
-            _ => return Err(VmError::BadOpCode),
+            _ => return Err(VmError::UnexpectedOpCode(op)),
         };
         if result {
             self.push(self.true_obj.clone())?;
@@ -385,20 +1174,27 @@ impl Vm {
             (Object::Str(left), Object::Str(right)) => {
                 self.binary_string_op(left, op, right)?;
             }
-            _ => return Err(VmError::UnsupportedOperands),
+            (left, right) => {
+                return Err(VmError::UnsupportedOperands(
+                    op,
+                    vec![left.type_name(), right.type_name()],
+                ))
+            }
         }
         Ok(())
     }
 
     fn binary_integer_op(&mut self, left: i64, op: OpCode, right: i64) -> Result<(), VmError> {
         let result = match op {
-            OpCode::Add => left + right,
-            OpCode::Sub => left - right,
-            OpCode::Mul => left * right,
-            OpCode::Div => left / right,
-            _ => return Err(VmError::BadOpCode),
+            OpCode::Add => left.checked_add(right),
+            OpCode::Sub => left.checked_sub(right),
+            OpCode::Mul => left.checked_mul(right),
+            OpCode::Div if right == 0 => return Err(VmError::DivisionByZero),
+            OpCode::Div => left.checked_div(right),
+            _ => return Err(VmError::UnexpectedOpCode(op)),
         };
-        self.push(Rc::new(Object::Integer(result)))?;
+        let result = result.ok_or(VmError::IntegerOverflow)?;
+        self.push(self.integer(result))?;
         Ok(())
     }
 
@@ -410,7 +1206,7 @@ impl Vm {
     ) -> Result<(), VmError> {
         let result = match op {
             OpCode::Add => format!("{}{}", left, right),
-            _ => return Err(VmError::BadOpCode),
+            _ => return Err(VmError::UnexpectedOpCode(op)),
         };
         self.push(Rc::new(Object::Str(result)))?;
         Ok(())
@@ -420,23 +1216,39 @@ impl Vm {
         match (&*left, &*index) {
             (Object::Array(elements), Object::Integer(idx)) => match elements.get(*idx as usize) {
                 Some(thing) => {
-                    self.push(Rc::new(thing.clone()))?;
+                    self.push(Rc::clone(thing))?;
                 }
                 None => {
                     self.push(self.null_obj.clone())?;
                 }
             },
+            (Object::Str(string), Object::Integer(idx)) => {
+                match string.chars().nth(*idx as usize) {
+                    Some(ch) => self.push(Rc::new(Object::Char(ch)))?,
+                    None => self.push(self.null_obj.clone())?,
+                }
+            }
             (Object::Hash(keys_and_values), _) => match (*index).clone().to_hashable_object() {
                 Ok(key) => {
                     let obj = match keys_and_values.get(&key) {
-                        Some(elem) => Rc::new(elem.clone()),
+                        Some(elem) => Rc::clone(elem),
                         _ => self.null_obj.clone(),
                     };
                     self.push(obj)?;
                 }
-                _ => return Err(VmError::UnsupportedOperands),
+                _ => {
+                    return Err(VmError::UnsupportedOperands(
+                        OpCode::Index,
+                        vec![left.type_name(), index.type_name()],
+                    ))
+                }
             },
-            _ => return Err(VmError::UnsupportedOperands),
+            _ => {
+                return Err(VmError::UnsupportedOperands(
+                    OpCode::Index,
+                    vec![left.type_name(), index.type_name()],
+                ))
+            }
         }
         Ok(())
     }
@@ -446,7 +1258,7 @@ impl Vm {
     }
 
     fn push(&mut self, obj: Rc<Object>) -> Result<(), VmError> {
-        if self.sp >= STACK_SIZE {
+        if self.sp >= self.stack.len() {
             return Err(VmError::StackOverflow);
         }
         self.stack[self.sp] = obj;
@@ -463,3 +1275,45 @@ impl Vm {
         Ok(obj)
     }
 }
+
+// Built-in functions call Monkey functions by running them to completion on top of the current stack.
+impl Runtime for Vm {
+    fn call(&mut self, func: &Object, args: Vec<Object>) -> Result<Object, EvalError> {
+        let result = match func {
+            Object::Closure(cl) => self.call_nested(cl.clone(), args),
+            Object::BuiltIn(built_in) => return built_in(self, args),
+            other => Err(VmError::CallingNonFunction(other.type_name())),
+        };
+        result.map_err(|error| {
+            self.callback_error = Some(error);
+            EvalError::UnknownError
+        })
+    }
+
+    fn rng(&mut self) -> &mut Rng {
+        &mut self.rng
+    }
+
+    fn input(&mut self) -> &mut dyn BufRead {
+        &mut *self.input
+    }
+
+    fn output(&mut self) -> &mut dyn Write {
+        &mut *self.output
+    }
+
+    fn sandboxed(&self) -> bool {
+        self.sandboxed
+    }
+
+    fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    fn eval_source(&mut self, source: &str) -> Result<Object, EvalError> {
+        self.run_source(source).map_err(|error| {
+            self.callback_error = Some(error);
+            EvalError::UnknownError
+        })
+    }
+}
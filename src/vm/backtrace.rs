@@ -0,0 +1,74 @@
+//! Backtrace
+//!
+//! `backtrace` describes the calls the virtual machine was in the middle of when it failed, innermost first, so
+//! that the chain of calls leading to an error can be followed back to the main program.
+use crate::lexer::Position;
+use std::fmt;
+
+/// One of the calls active when the machine failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BacktraceFrame {
+    /// The index of the function among the constants, or nothing for the main program.
+    pub function: Option<usize>,
+    /// The name of the global holding the function, if it is held by one and the names of globals are known.
+    pub name: Option<String>,
+    /// The offset of the instruction the call was executing.
+    pub ip: usize,
+    /// Where in the source the statement being executed starts, if known.
+    pub position: Option<Position>,
+}
+
+/// The calls active when the machine failed, innermost first; the last is the main program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Backtrace {
+    pub frames: Vec<BacktraceFrame>,
+}
+
+impl BacktraceFrame {
+    // Returns what the call is of, as `add` or, without its name, `constant 3`.
+    fn callee(&self) -> String {
+        match (&self.name, self.function) {
+            (Some(name), Some(_)) => name.clone(),
+            (None, Some(idx)) => format!("constant {}", idx),
+            (_, None) => String::from("<main>"),
+        }
+    }
+}
+
+impl fmt::Display for BacktraceFrame {
+    /// Writes the frame as, for example, `add (constant 3) at 2:5, ip 4`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.name, self.function) {
+            (Some(name), Some(idx)) => write!(f, "{} (constant {})", name, idx)?,
+            _ => write!(f, "{}", self.callee())?,
+        }
+        if let Some(position) = self.position {
+            write!(f, " at {}:{}", position.line, position.column)?;
+        }
+        write!(f, ", ip {}", self.ip)
+    }
+}
+
+impl fmt::Display for Backtrace {
+    /// Writes the frames one to a line, except that a run of identical frames, as deep recursion leaves, is written
+    /// as its first and the number of the others.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Backtrace (innermost call first):")?;
+        let mut idx = 0;
+        while idx < self.frames.len() {
+            let frame = &self.frames[idx];
+            write!(f, "\n  {}: {}", idx, frame)?;
+            let repeats = self.frames[idx + 1..]
+                .iter()
+                .take_while(|other| *other == frame)
+                .count();
+            // A single repeat takes no more room written out.
+            if repeats > 1 {
+                write!(f, "\n  ... {} more calls of {}", repeats, frame.callee())?;
+                idx += repeats;
+            }
+            idx += 1;
+        }
+        Ok(())
+    }
+}
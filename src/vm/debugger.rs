@@ -0,0 +1,171 @@
+//! Debugger
+//!
+//! `debugger` lets a program be run a little at a time: until it reaches a breakpoint, or one instruction at a
+//! time, with the stack, the locals of each call and the globals open to inspection whenever it is paused. It is
+//! meant as the backend of debuggers for people, such as one in the REPL or one speaking a debug protocol.
+//!
+//! The machine can only pause between the instructions of Monkey code called from Monkey code. Functions called
+//! back by built-in functions, such as the function given to `map`, run to completion without pausing.
+use crate::lexer::Position;
+use crate::object::Object;
+use crate::vm::{Backtrace, Vm, VmError};
+use std::mem;
+
+/// Where the machine is to pause, before executing the instruction there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// The instruction at `offset` among those of the function which is constant `function`, or of the main
+    /// program if there is none.
+    Instruction {
+        function: Option<usize>,
+        offset: usize,
+    },
+    /// The first instruction of each statement starting on the line, once the positions of statements are known.
+    Line(usize),
+}
+
+/// Why the machine stopped running.
+#[derive(Debug, Clone)]
+pub enum Stop {
+    /// It reached a breakpoint.
+    Breakpoint,
+    /// It executed the instructions it was asked to.
+    Step,
+    /// The program ended, with its result.
+    Finished(Object),
+}
+
+// What the machine must know, as it runs, to pause where it is asked to.
+#[derive(Default)]
+pub(crate) struct DebugState {
+    breakpoints: Vec<Breakpoint>,
+    // The number of instructions to execute before pausing, if it is to pause after so many.
+    steps: Option<usize>,
+    // Whether no instruction has been executed since the machine was told to run, in which case it does not pause
+    // at a breakpoint it is already at.
+    resuming: bool,
+    paused: bool,
+}
+
+impl DebugState {
+    // Returns whether to pause before the next instruction, where `here` tells whether a breakpoint is on it.
+    pub(crate) fn pause(&mut self, here: impl Fn(&Breakpoint) -> bool) -> bool {
+        let resuming = mem::replace(&mut self.resuming, false);
+        if !resuming && (self.steps == Some(0) || self.breakpoints.iter().any(here)) {
+            self.paused = true;
+            return true;
+        }
+        if let Some(steps) = &mut self.steps {
+            *steps -= 1;
+        }
+        false
+    }
+}
+
+/// Runs a machine a little at a time, and inspects it in between.
+pub struct Debugger<'a> {
+    vm: &'a mut Vm,
+}
+
+impl<'a> Debugger<'a> {
+    pub(crate) fn new(vm: &'a mut Vm) -> Self {
+        Debugger { vm }
+    }
+
+    /// Adds a breakpoint; adding one already there has no effect.
+    pub fn set_breakpoint(&mut self, breakpoint: Breakpoint) {
+        let breakpoints = &mut self.state().breakpoints;
+        if !breakpoints.contains(&breakpoint) {
+            breakpoints.push(breakpoint);
+        }
+    }
+
+    /// Removes a breakpoint, returning whether it was there.
+    pub fn clear_breakpoint(&mut self, breakpoint: Breakpoint) -> bool {
+        let breakpoints = &mut self.state().breakpoints;
+        let len = breakpoints.len();
+        breakpoints.retain(|other| *other != breakpoint);
+        breakpoints.len() < len
+    }
+
+    /// Returns the breakpoints, in the order they were added.
+    pub fn breakpoints(&mut self) -> &[Breakpoint] {
+        &self.state().breakpoints
+    }
+
+    /// Runs the program from where it is until it reaches a breakpoint or ends.
+    ///
+    /// A breakpoint on the instruction the program is paused at does not stop it again straight away.
+    pub fn resume(&mut self) -> Result<Stop, VmError> {
+        self.run(None)
+    }
+
+    /// Executes the next instruction of the program, which may enter or return from a call, and pauses again.
+    pub fn step(&mut self) -> Result<Stop, VmError> {
+        self.run(Some(1))
+    }
+
+    fn run(&mut self, steps: Option<usize>) -> Result<Stop, VmError> {
+        let state = self.state();
+        state.steps = steps;
+        state.resuming = true;
+        state.paused = false;
+        let result = self.vm.run_main()?;
+        let state = self.state();
+        Ok(match (state.paused, state.steps) {
+            (false, _) => Stop::Finished(result),
+            (true, Some(0)) => Stop::Step,
+            (true, _) => Stop::Breakpoint,
+        })
+    }
+
+    /// Returns the calls the program is in the middle of, innermost first, with where each is.
+    pub fn frames(&self) -> Backtrace {
+        self.vm.trace_back()
+    }
+
+    /// Returns where in the source the innermost call whose positions are known is, if any is.
+    pub fn position(&self) -> Option<Position> {
+        self.vm.position()
+    }
+
+    /// Returns the values on the stack, from the bottom up.
+    pub fn stack(&self) -> Vec<Object> {
+        let stack = &self.vm.stack[..self.vm.sp];
+        stack.iter().map(|obj| (**obj).clone()).collect()
+    }
+
+    /// Returns the values of the locals of call `frame`, counted like `frames` from the innermost, or nothing if
+    /// there is no such call. The parameters of a function are its first locals.
+    pub fn locals(&self, frame: usize) -> Option<Vec<Object>> {
+        let idx = self.vm.frames_index.checked_sub(frame + 1)?;
+        let frame = &self.vm.frames[idx];
+        let locals = (0..frame.cl.compiled_function.num_locals).map(|idx| match frame.cell(idx) {
+            Some(cell) => (**cell.borrow()).clone(),
+            None => (*self.vm.stack[frame.bp + idx]).clone(),
+        });
+        Some(locals.collect())
+    }
+
+    /// Returns the globals named by the symbol table set by `Vm::set_symbol_table`, with their names and ordered by
+    /// them. Without a symbol table, which globals are defined is not known and none are returned.
+    pub fn globals(&self) -> Vec<(String, Object)> {
+        let symbol_table = match &self.vm.symbol_table {
+            Some(symbol_table) => symbol_table.borrow(),
+            None => return vec![],
+        };
+        let globals = self.vm.globals.borrow();
+        symbol_table
+            .global_symbols()
+            .into_iter()
+            .filter_map(|symbol| {
+                let value = globals.get(symbol.index as usize)?;
+                Some((symbol.name, (**value).clone()))
+            })
+            .collect()
+    }
+
+    fn state(&mut self) -> &mut DebugState {
+        self.vm.debug.get_or_insert_with(DebugState::default)
+    }
+}
@@ -1,21 +1,59 @@
-use crate::code::{Closure, Instructions};
+use crate::code::{Closure, DecodedOp, Instructions, Upvalue};
 
 pub struct Frame {
     pub cl: Closure,
+    /// The index of the current instruction among the decoded ones.
     pub ip: usize,
     pub bp: usize,
+    pub num_args: usize,
+    /// Upvalues for the locals captured by closures created in this frame, indexed like the locals.
+    /// Captured locals are read and written through their upvalue rather than the stack.
+    pub cells: Vec<Option<Upvalue>>,
 }
 
 impl Frame {
-    pub fn new(cl: Closure, base_pointer: usize) -> Self {
+    pub fn new(cl: Closure, base_pointer: usize, num_args: usize) -> Self {
         Frame {
             cl,
             ip: 0,
             bp: base_pointer,
+            num_args,
+            cells: vec![],
         }
     }
 
+    /// Makes the frame, which is no longer in use, that of a new call to `cl`.
+    ///
+    /// The space allocated for upvalues is kept for the new call.
+    pub fn reuse(&mut self, cl: Closure, base_pointer: usize, num_args: usize) {
+        self.cl = cl;
+        self.ip = 0;
+        self.bp = base_pointer;
+        self.num_args = num_args;
+        self.cells.clear();
+    }
+
     pub fn instructions(&self) -> &Instructions {
         &self.cl.compiled_function.instructions
     }
+
+    pub fn code(&self) -> &[DecodedOp] {
+        &self.cl.code
+    }
+
+    /// Returns where the current instruction starts among the bytes of the instructions.
+    pub fn offset(&self) -> usize {
+        match self.code().get(self.ip) {
+            Some(instruction) => instruction.offset,
+            None => self.instructions().len(),
+        }
+    }
+
+    /// Returns the upvalue for local `idx`, if a closure has captured it.
+    pub fn cell(&self, idx: usize) -> Option<&Upvalue> {
+        match self.cells.get(idx) {
+            Some(Some(cell)) => Some(cell),
+            _ => None,
+        }
+    }
 }
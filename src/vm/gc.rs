@@ -0,0 +1,218 @@
+//! GC
+//!
+//! `gc` finds the variables captured by closures which can no longer be reached, so that the reference cycles
+//! they may be part of can be broken. Objects are reference counted, so everything else is freed as soon as it is
+//! unreachable, but a closure can be stored in a variable it has captured itself, as in
+//! `let f = 0; f = fn() { f };`, and then the two keep each other alive.
+//!
+//! Captured variables are the only objects which can be changed once created, so every cycle passes through one.
+//! A collection looks over everything reachable from them and takes away the references found along the way from
+//! the counts of what they point to. What still has references left is held from elsewhere: by the machine, or by
+//! whoever was handed a value by it, which the collector need not know about. Whatever can be reached from those
+//! is kept, and the captured variables left over are emptied, which frees whatever they alone were keeping alive.
+use crate::code::Upvalue;
+use crate::object::Object;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::{Rc, Weak};
+
+// The number of captured variables there must be before the first collection is made automatically.
+const FIRST_COLLECTION: usize = 1024;
+
+/// What a garbage collection found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Collection {
+    /// The number of captured variables still alive before the collection.
+    pub tracked: usize,
+    /// The number of those which could not be reached, and were emptied.
+    pub freed: usize,
+}
+
+impl fmt::Display for Collection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Freed {} of {} captured variables",
+            self.freed, self.tracked
+        )
+    }
+}
+
+/// Keeps track of the captured variables a machine has created, and decides when to collect them.
+pub(crate) struct Collector {
+    upvalues: Vec<Weak<RefCell<Rc<Object>>>>,
+    // The number of captured variables at which the next collection is made automatically.
+    next_collection: usize,
+}
+
+impl Collector {
+    pub(crate) fn new() -> Self {
+        Collector {
+            upvalues: vec![],
+            next_collection: FIRST_COLLECTION,
+        }
+    }
+
+    /// Starts keeping track of a newly created captured variable.
+    pub(crate) fn track(&mut self, upvalue: &Upvalue) {
+        self.upvalues.push(Rc::downgrade(upvalue));
+    }
+
+    /// Returns whether enough captured variables have been created since the last collection to make another.
+    pub(crate) fn due(&self) -> bool {
+        self.upvalues.len() >= self.next_collection
+    }
+
+    /// Empties the captured variables which can only be reached from one another, replacing their values with
+    /// `null`.
+    pub(crate) fn collect(&mut self, null: &Rc<Object>) -> Collection {
+        let upvalues: Vec<Upvalue> = self
+            .upvalues
+            .drain(..)
+            .filter_map(|weak| weak.upgrade())
+            .collect();
+        let mut graph = Graph::default();
+        for upvalue in &upvalues {
+            // Leave out the references in `upvalues` and the graph.
+            graph.add(Node::Upvalue(Rc::clone(upvalue)), 2);
+        }
+        graph.scan();
+        let live = graph.live();
+
+        let mut collection = Collection {
+            tracked: upvalues.len(),
+            freed: 0,
+        };
+        for (upvalue, live) in upvalues.iter().zip(live) {
+            if live {
+                self.upvalues.push(Rc::downgrade(upvalue));
+            } else {
+                collection.freed += 1;
+                *upvalue.borrow_mut() = Rc::clone(null);
+            }
+        }
+        // Collections grow rarer as more captured variables stay alive, so that they take little time overall.
+        self.next_collection = FIRST_COLLECTION.max(2 * self.upvalues.len());
+        collection
+    }
+}
+
+// Something shared on the way from a captured variable to another.
+enum Node {
+    Object(Rc<Object>),
+    // The captured variables of a closure, shared by its copies.
+    Free(Rc<[Upvalue]>),
+    Upvalue(Upvalue),
+}
+
+impl Node {
+    fn address(&self) -> *const () {
+        match self {
+            Node::Object(obj) => Rc::as_ptr(obj) as *const (),
+            Node::Free(free) => Rc::as_ptr(free) as *const (),
+            Node::Upvalue(upvalue) => Rc::as_ptr(upvalue) as *const (),
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            Node::Object(obj) => Rc::strong_count(obj),
+            Node::Free(free) => Rc::strong_count(free),
+            Node::Upvalue(upvalue) => Rc::strong_count(upvalue),
+        }
+    }
+}
+
+// The nodes reachable from the captured variables, and the references among them.
+#[derive(Default)]
+struct Graph {
+    nodes: Vec<Node>,
+    // For each node, the references to it not yet found to come from another node.
+    outside: Vec<usize>,
+    // For each node, the nodes it refers to.
+    edges: Vec<Vec<usize>>,
+    indices: HashMap<*const (), usize>,
+}
+
+impl Graph {
+    // Adds `node`, unless it is there already, and returns its index. `held` is the number of references to it the
+    // collection itself holds, `node` included.
+    fn add(&mut self, node: Node, held: usize) -> usize {
+        let address = node.address();
+        if let Some(&index) = self.indices.get(&address) {
+            return index;
+        }
+        let index = self.nodes.len();
+        self.outside.push(node.strong_count() - held);
+        self.edges.push(vec![]);
+        self.nodes.push(node);
+        self.indices.insert(address, index);
+        index
+    }
+
+    // Adds the node `child` refers to, with the reference from `parent`.
+    fn refer(&mut self, parent: usize, child: Node) {
+        let index = self.add(child, 1);
+        self.outside[index] -= 1;
+        self.edges[parent].push(index);
+    }
+
+    // Follows the references from every node, adding the nodes they lead to.
+    fn scan(&mut self) {
+        let mut index = 0;
+        while index < self.nodes.len() {
+            match &self.nodes[index] {
+                Node::Upvalue(upvalue) => {
+                    match upvalue.try_borrow().map(|obj| Rc::clone(&obj)) {
+                        Ok(obj) => self.refer(index, Node::Object(obj)),
+                        // It is being changed, so it is in use.
+                        Err(_) => self.outside[index] += 1,
+                    }
+                }
+                Node::Object(obj) => {
+                    let obj = Rc::clone(obj);
+                    match &*obj {
+                        Object::Array(elements) => {
+                            for element in elements {
+                                self.refer(index, Node::Object(Rc::clone(element)));
+                            }
+                        }
+                        Object::Hash(items) => {
+                            for value in items.values() {
+                                self.refer(index, Node::Object(Rc::clone(value)));
+                            }
+                        }
+                        Object::Closure(closure) => {
+                            self.refer(index, Node::Free(Rc::clone(&closure.free)))
+                        }
+                        Object::Return(value) => self.refer(index, Node::Object(Rc::clone(value))),
+                        _ => {}
+                    }
+                }
+                Node::Free(free) => {
+                    let free = Rc::clone(free);
+                    for upvalue in free.iter() {
+                        self.refer(index, Node::Upvalue(Rc::clone(upvalue)));
+                    }
+                }
+            }
+            index += 1;
+        }
+    }
+
+    // Returns, for each node, whether it can be reached from a reference held outside the graph.
+    fn live(&self) -> Vec<bool> {
+        let mut live = vec![false; self.nodes.len()];
+        let mut pending: Vec<usize> = (0..self.nodes.len())
+            .filter(|&index| self.outside[index] > 0)
+            .collect();
+        while let Some(index) = pending.pop() {
+            if !live[index] {
+                live[index] = true;
+                pending.extend(&self.edges[index]);
+            }
+        }
+        live
+    }
+}
@@ -0,0 +1,213 @@
+//! Profile
+//!
+//! `profile` counts what the virtual machine does while it runs a program: how many times each opcode is
+//! executed, and how many times each compiled function is called, how many instructions it executes and how
+//! long it runs for. The counts can be printed as a summary table, to see where a program spends its time.
+//!
+//! Time is measured from when a call is made to when it returns, so it includes the time taken by the machine to
+//! dispatch instructions and by the built-in functions called, as well as that taken to measure it.
+use crate::code::{Constant, Instructions, OpCode};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// The counts for a single function: the main program, or a compiled function among the constants.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FunctionProfile {
+    pub calls: usize,
+    pub instructions: usize,
+    /// The time spent running the function itself, leaving out the functions it calls.
+    pub self_time: Duration,
+    /// The time spent in calls to the function, including the functions it calls. Time spent in a recursive call
+    /// is only counted once, for the outermost call.
+    pub total_time: Duration,
+}
+
+/// The counts gathered while a program runs.
+#[derive(Debug)]
+pub struct Profile {
+    // Indexed by the byte each opcode is encoded as.
+    opcodes: Vec<usize>,
+    // Keyed by the index of the function among the constants; the main program has none.
+    functions: HashMap<Option<usize>, FunctionProfile>,
+    // The function each frame is running, with when it was called, innermost last.
+    running: Vec<(Option<usize>, Instant)>,
+    // When the time since was last added to that of the innermost function.
+    since: Instant,
+    // Closures carry a copy of their function rather than its index, so functions are recognized by their
+    // instructions. Functions compiled to the same instructions are counted as one.
+    indices: HashMap<Instructions, usize>,
+    // The names functions are known by, such as that of the global holding them, keyed like `functions`.
+    names: HashMap<usize, String>,
+}
+
+impl Profile {
+    /// Returns an empty profile for a program with `constants`, which is already running its main program.
+    pub(crate) fn new(constants: &[Rc<Constant>]) -> Self {
+        let mut indices = HashMap::new();
+        for (idx, constant) in constants.iter().enumerate() {
+            if let Constant::CompiledFunction(function) = &**constant {
+                indices.entry(function.instructions.clone()).or_insert(idx);
+            }
+        }
+        let mut functions = HashMap::new();
+        functions.insert(
+            None,
+            FunctionProfile {
+                calls: 1,
+                ..FunctionProfile::default()
+            },
+        );
+        let now = Instant::now();
+        Profile {
+            opcodes: vec![0; 256],
+            functions,
+            running: vec![(None, now)],
+            since: now,
+            indices,
+            names: HashMap::new(),
+        }
+    }
+
+    /// Counts a call to the function with `instructions`.
+    pub(crate) fn enter(&mut self, instructions: &Instructions) {
+        let now = self.lap();
+        let function = self.indices.get(instructions).copied();
+        self.functions.entry(function).or_default().calls += 1;
+        self.running.push((function, now));
+    }
+
+    /// Counts a return from the innermost function.
+    pub(crate) fn leave(&mut self) {
+        let now = self.lap();
+        if let Some((function, called)) = self.running.pop() {
+            // The time of a recursive call is already part of that of the call it was made from.
+            if self.running.iter().all(|(outer, _)| *outer != function) {
+                self.functions.entry(function).or_default().total_time += now - called;
+            }
+        }
+    }
+
+    /// Counts returns from the functions still running, as if the program stopped now.
+    pub(crate) fn finish(&mut self) {
+        while !self.running.is_empty() {
+            self.leave();
+        }
+    }
+
+    /// Counts the execution of the instruction starting with byte `op` by the innermost function.
+    pub(crate) fn record(&mut self, op: u8) {
+        self.opcodes[op as usize] += 1;
+        let function = self.running.last().and_then(|(function, _)| *function);
+        self.functions.entry(function).or_default().instructions += 1;
+    }
+
+    // Adds the time since the last lap to that of the innermost function, and returns the time now.
+    fn lap(&mut self) -> Instant {
+        let now = Instant::now();
+        if let Some((function, _)) = self.running.last() {
+            self.functions.entry(*function).or_default().self_time += now - self.since;
+        }
+        self.since = now;
+        now
+    }
+
+    /// Makes the table refer to the function with `instructions` as `name`, unless it already has a name.
+    pub(crate) fn name(&mut self, instructions: &Instructions, name: &str) {
+        if let Some(idx) = self.indices.get(instructions) {
+            self.names.entry(*idx).or_insert_with(|| name.to_string());
+        }
+    }
+
+    /// Returns the number of times `op` was executed.
+    // Not used within the crate outside of tests yet.
+    #[allow(dead_code)]
+    pub fn opcode_count(&self, op: OpCode) -> usize {
+        self.opcodes[u8::from(op) as usize]
+    }
+
+    /// Returns the counts for the function at index `idx` among the constants, or for the main program if `None`.
+    // Not used within the crate outside of tests yet.
+    #[allow(dead_code)]
+    pub fn function(&self, idx: Option<usize>) -> Option<&FunctionProfile> {
+        self.functions.get(&idx)
+    }
+
+    /// Returns the number of instructions executed in all.
+    pub fn total(&self) -> usize {
+        self.opcodes.iter().sum()
+    }
+
+    /// Returns the functions called with their names and counts, those which took the most time themselves first.
+    pub fn hottest(&self) -> Vec<(String, &FunctionProfile)> {
+        let mut functions: Vec<(&Option<usize>, &FunctionProfile)> =
+            self.functions.iter().collect();
+        functions.sort_by(|a, b| {
+            (b.1.self_time, b.1.instructions)
+                .cmp(&(a.1.self_time, a.1.instructions))
+                .then(a.0.cmp(b.0))
+        });
+        functions
+            .into_iter()
+            .map(|(idx, counts)| (self.function_name(*idx), counts))
+            .collect()
+    }
+
+    // Returns the name the table refers to the function at index `idx` among the constants by.
+    fn function_name(&self, idx: Option<usize>) -> String {
+        match idx {
+            Some(idx) => match self.names.get(&idx) {
+                Some(name) => name.clone(),
+                None => format!("constant {}", idx),
+            },
+            None => String::from("<main>"),
+        }
+    }
+}
+
+impl fmt::Display for Profile {
+    /// Writes a table of the opcodes executed, then one of the functions called, each busiest first.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let total = self.total().max(1);
+        let mut opcodes: Vec<(String, usize)> = self
+            .opcodes
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(byte, count)| {
+                let name = match OpCode::try_from(byte as u8) {
+                    Ok(op) => op.definition().name,
+                    Err(_) => format!("Op{}", byte),
+                };
+                (name, *count)
+            })
+            .collect();
+        opcodes.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        writeln!(f, "{:<20}{:>12}{:>8}", "Opcode", "Count", "%")?;
+        for (name, count) in opcodes {
+            let share = 100.0 * count as f64 / total as f64;
+            writeln!(f, "{:<20}{:>12}{:>8.1}", name, count, share)?;
+        }
+
+        writeln!(f)?;
+        write!(
+            f,
+            "{:<20}{:>12}{:>14}{:>12}{:>12}",
+            "Function", "Calls", "Instructions", "Self ms", "Total ms"
+        )?;
+        for (name, counts) in self.hottest() {
+            write!(
+                f,
+                "\n{:<20}{:>12}{:>14}{:>12.3}{:>12.3}",
+                name,
+                counts.calls,
+                counts.instructions,
+                counts.self_time.as_secs_f64() * 1000.0,
+                counts.total_time.as_secs_f64() * 1000.0
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,208 @@
+//! Trace
+//!
+//! `trace` contains hooks for observing the virtual machine as it executes instructions.
+//! The `Recorder` tracer keeps a snapshot of the stack and frames before every instruction, which can be
+//! rendered as JSON or as a self-contained HTML page stepping through the execution.
+//! The `Printer` tracer instead writes a line per instruction as it goes, for debugging the machine itself.
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+
+/// Represents the state of a single call frame at some point during execution.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameSnapshot {
+    pub ip: usize,
+    pub bp: usize,
+    pub num_locals: usize,
+}
+
+/// Represents the state of the virtual machine just before an instruction is executed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub step: usize,
+    pub ip: usize,
+    pub instruction: String,
+    pub stack: Vec<String>,
+    pub frames: Vec<FrameSnapshot>,
+}
+
+/// Observes the virtual machine one instruction at a time.
+pub trait Tracer {
+    fn trace(&mut self, snapshot: &Snapshot);
+}
+
+// Allows the caller to keep a handle on a tracer while the virtual machine owns it.
+impl<T: Tracer> Tracer for Rc<RefCell<T>> {
+    fn trace(&mut self, snapshot: &Snapshot) {
+        self.borrow_mut().trace(snapshot)
+    }
+}
+
+/// A tracer which records every snapshot for later visualization.
+#[derive(Default, Debug)]
+pub struct Recorder {
+    pub snapshots: Vec<Snapshot>,
+}
+
+impl Tracer for Recorder {
+    fn trace(&mut self, snapshot: &Snapshot) {
+        self.snapshots.push(snapshot.clone());
+    }
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Returns the recorded snapshots as a JSON array with one object per executed instruction.
+    pub fn to_json(&self) -> String {
+        let steps = self
+            .snapshots
+            .iter()
+            .map(snapshot_to_json)
+            .collect::<Vec<String>>();
+        format!("[{}]", steps.join(",\n"))
+    }
+
+    /// Returns a standalone HTML page which animates the recorded snapshots.
+    pub fn to_html(&self) -> String {
+        // Escape closing tags so that program strings cannot terminate the embedding script early.
+        HTML_TEMPLATE.replace("{{STEPS}}", &self.to_json().replace("</", "<\\/"))
+    }
+}
+
+/// A tracer which writes each instruction, the instruction pointer and the top of the stack as it executes.
+///
+/// Lines are indented by the depth of the call, so that the instructions of each function stand out.
+pub struct Printer {
+    out: Box<dyn Write>,
+}
+
+impl Printer {
+    pub fn new(out: Box<dyn Write>) -> Self {
+        Printer { out }
+    }
+}
+
+impl Tracer for Printer {
+    fn trace(&mut self, snapshot: &Snapshot) {
+        let indent = "  ".repeat(snapshot.frames.len().saturating_sub(1));
+        let top = snapshot.stack.last().map_or("-", |top| top.as_str());
+        // Tracing is a debugging aid, so a failure to write it should not stop the program.
+        let _ = writeln!(
+            self.out,
+            "{}{:04} {:<24} top: {}",
+            indent, snapshot.ip, snapshot.instruction, top
+        );
+    }
+}
+
+fn snapshot_to_json(snapshot: &Snapshot) -> String {
+    let stack = snapshot
+        .stack
+        .iter()
+        .map(|x| json_string(x))
+        .collect::<Vec<String>>();
+    let frames = snapshot
+        .frames
+        .iter()
+        .map(|x| {
+            format!(
+                "{{\"ip\": {}, \"bp\": {}, \"num_locals\": {}}}",
+                x.ip, x.bp, x.num_locals
+            )
+        })
+        .collect::<Vec<String>>();
+    format!(
+        "{{\"step\": {}, \"ip\": {}, \"instruction\": {}, \"stack\": [{}], \"frames\": [{}]}}",
+        snapshot.step,
+        snapshot.ip,
+        json_string(&snapshot.instruction),
+        stack.join(", "),
+        frames.join(", ")
+    )
+}
+
+/// Returns `s` as a quoted JSON string literal.
+pub fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            ch if (ch as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => out.push(ch),
+        }
+    }
+    out.push('"');
+    out
+}
+
+const HTML_TEMPLATE: &str = "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>Monkey VM</title>
+<style>
+body { font-family: monospace; }
+.column { display: inline-block; vertical-align: top; margin-right: 3em; }
+.slot { border: 1px solid black; padding: 0.2em 1em; min-width: 8em; }
+</style>
+</head>
+<body>
+<button onclick=\"show(current - 1)\">&lt; previous</button>
+<button onclick=\"show(current + 1)\">next &gt;</button>
+<span id=\"step\"></span>
+<h3 id=\"instruction\"></h3>
+<div class=\"column\"><h4>Stack</h4><div id=\"stack\"></div></div>
+<div class=\"column\"><h4>Frames</h4><div id=\"frames\"></div></div>
+<script>
+const steps = {{STEPS}};
+let current = 0;
+function render(id, items) {
+  const column = document.getElementById(id);
+  column.innerHTML = '';
+  items.forEach(x => {
+    const slot = document.createElement('div');
+    slot.className = 'slot';
+    slot.textContent = x;
+    column.appendChild(slot);
+  });
+}
+function show(i) {
+  if (i < 0 || i >= steps.length) { return; }
+  current = i;
+  const s = steps[i];
+  document.getElementById('step').textContent = 'step ' + (i + 1) + ' / ' + steps.length;
+  document.getElementById('instruction').textContent = s.ip + ': ' + s.instruction;
+  render('stack', s.stack.slice().reverse());
+  render('frames', s.frames.slice().reverse()
+    .map(f => 'ip ' + f.ip + ', bp ' + f.bp + ', locals ' + f.num_locals));
+}
+show(0);
+</script>
+</body>
+</html>
+";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_string_test() {
+        let tests = vec![
+            ("abc", "\"abc\""),
+            ("\"quoted\"", "\"\\\"quoted\\\"\""),
+            ("a\nb", "\"a\\nb\""),
+        ];
+        for (input, want) in tests {
+            assert_eq!(json_string(input), want);
+        }
+    }
+}
@@ -4,6 +4,8 @@ use crate::compiler::Compiler;
 use crate::lexer::Lexer;
 use crate::object::Object;
 use crate::parser::Parser;
+use std::cell::RefCell;
+use std::rc::Rc;
 
 fn run(input: &str) -> Result<Object, VmError> {
     let mut p = Parser::new(Lexer::new(input));
@@ -154,6 +156,30 @@ fn string_expression_test() {
     }
 }
 
+#[test]
+fn char_test() {
+    let tests = vec![
+        ("'a'", "'a'"),
+        ("'a' == 'a'", "true"),
+        ("'a' != 'a'", "false"),
+        ("'a' < 'b'", "true"),
+        ("'b' > 'c'", "false"),
+        ("\"abc\"[1]", "'b'"),
+        ("\"abc\"[0] == 'a'", "true"),
+        ("\"abc\"[3]", "null"),
+        ("let s = \"héllo\"; s[len(s) - 1]", "'o'"),
+        ("{'a': 1}['a']", "1"),
+        ("str('a') + \"b\"", "\"ab\""),
+        ("str(12)", "\"12\""),
+    ];
+    for (test_input, expected) in tests {
+        match run(test_input) {
+            Ok(obj) => assert_eq!(obj.to_string(), expected.to_string()),
+            Err(error) => panic!("VM error on input {}! {:?}", test_input, error),
+        }
+    }
+}
+
 #[test]
 fn array_literal_test() {
     let tests = vec![
@@ -176,6 +202,11 @@ fn hash_literal_test() {
         ("{}", "{}"),
         ("{1: 2, 3: 4}", "{1: 2, 3: 4}"),
         ("{1+1: 2+2, 3*3: 4*4}", "{2: 4, 9: 16}"),
+        (
+            "{\"b\": 1, \"a\": 2, true: 3}",
+            "{\"b\": 1, \"a\": 2, true: 3}",
+        ),
+        ("{3: 1, 2: 2, 3: 3}", "{3: 3, 2: 2}"),
     ];
     for (test_input, expected) in tests {
         if let Ok(obj) = run(test_input) {
@@ -317,11 +348,51 @@ fn calling_functions_with_arguments_and_bindings_test() {
     }
 }
 
+#[test]
+fn default_parameters_test() {
+    let tests = vec![
+        ("let add = fn(x, y = 10) { x + y; }; add(5);", 15),
+        ("let add = fn(x, y = 10) { x + y; }; add(5, 1);", 6),
+        ("let f = fn(x = 1, y = x * 2) { x + y; }; f();", 3),
+        ("let f = fn(x = 1, y = x * 2) { x + y; }; f(2);", 6),
+        (
+            "let outer = fn(a) { let inner = fn(b = a) { b }; inner() + inner(1) }; outer(5);",
+            6,
+        ),
+    ];
+    for (test_input, expected) in tests {
+        match run(test_input) {
+            Ok(obj) => assert_eq!(obj.to_string(), expected.to_string()),
+            Err(error) => panic!("VM error on input {}! {:?}", test_input, error),
+        }
+    }
+
+    let errors = vec![
+        ("let add = fn(x, y = 10) { x + y; }; add();", 0),
+        ("let add = fn(x, y = 10) { x + y; }; add(1, 2, 3);", 3),
+    ];
+    for (test_input, count) in errors {
+        match run(test_input) {
+            Err(VmError::WrongNumberOfArgs {
+                min: 1,
+                max: 2,
+                got,
+            }) if got == count => {}
+            other => panic!(
+                "Expected WrongNumberOfArgs on input {}, got {:?}",
+                test_input, other
+            ),
+        }
+    }
+}
+
 #[test]
 fn builtin_functions_test() {
     let tests = vec![
         ("len(\"\")", 0),
         ("len(\"four\")", 4),
+        ("len(\"héllo\")", 5),
+        ("len({1: 2})", 1),
         ("let array = [1,2,3]; first(rest(array))", 2),
     ];
     for (test_input, expected) in tests {
@@ -332,6 +403,995 @@ fn builtin_functions_test() {
     }
 }
 
+#[test]
+fn membership_test() {
+    let tests = vec![
+        ("contains([1, 2, 3], 2)", "true"),
+        ("contains([1, 2, 3], 4)", "false"),
+        ("contains([[1], \"a\"], [1])", "true"),
+        ("contains({\"a\": 1}, \"a\")", "true"),
+        ("contains({\"a\": 1}, 1)", "false"),
+        ("contains(\"monkey\", \"key\")", "true"),
+        ("contains(\"monkey\", 'z')", "false"),
+        ("has_key({1: 2, true: 3}, true)", "true"),
+        ("has_key({}, \"a\")", "false"),
+    ];
+    for (test_input, expected) in tests {
+        match run(test_input) {
+            Ok(obj) => assert_eq!(obj.to_string(), expected.to_string()),
+            Err(error) => panic!("VM error on input {}! {:?}", test_input, error),
+        }
+    }
+}
+
+#[test]
+fn delete_test() {
+    let tests = vec![
+        (
+            "delete({\"a\": 1, \"b\": 2, \"c\": 3}, \"b\")",
+            "{\"a\": 1, \"c\": 3}",
+        ),
+        ("delete({1: 2}, 3)", "{1: 2}"),
+        (
+            "let h = {1: 2}; let g = delete(h, 1); [h, g]",
+            "[{1: 2}, {}]",
+        ),
+        ("let h = {1: 2, 3: 4}; h = delete(h, 1); h", "{3: 4}"),
+    ];
+    for (test_input, expected) in tests {
+        match run(test_input) {
+            Ok(obj) => assert_eq!(obj.to_string(), expected.to_string()),
+            Err(error) => panic!("VM error on input {}! {:?}", test_input, error),
+        }
+    }
+}
+
+#[test]
+fn type_test() {
+    let tests = vec![
+        ("type(1)", "\"INTEGER\""),
+        ("type(true)", "\"BOOLEAN\""),
+        ("type(\"a\")", "\"STRING\""),
+        ("type('a')", "\"CHAR\""),
+        ("type([])", "\"ARRAY\""),
+        ("type({})", "\"HASH\""),
+        ("type(fn() {})", "\"FUNCTION\""),
+        ("type(len)", "\"BUILTIN\""),
+        ("type(if (false) { 1 })", "\"NULL\""),
+    ];
+    for (test_input, expected) in tests {
+        match run(test_input) {
+            Ok(obj) => assert_eq!(obj.to_string(), expected.to_string()),
+            Err(error) => panic!("VM error on input {}! {:?}", test_input, error),
+        }
+    }
+}
+
+#[test]
+fn split_join_test() {
+    let tests = vec![
+        ("split(\"a,b,c\", \",\")", "[\"a\", \"b\", \"c\"]"),
+        ("split(\"a, b\", \", \")", "[\"a\", \"b\"]"),
+        ("split(\"a-b\", '-')", "[\"a\", \"b\"]"),
+        ("split(\"abc\", \"\")", "[\"a\", \"b\", \"c\"]"),
+        ("split(\"\", \",\")", "[\"\"]"),
+        ("join([\"a\", \"b\"], \"-\")", "\"a-b\""),
+        ("join([1, 'x', true], \", \")", "\"1, x, true\""),
+        ("join([], \",\")", "\"\""),
+        ("join(split(\"a b c\", \" \"), \"\")", "\"abc\""),
+    ];
+    for (test_input, expected) in tests {
+        match run(test_input) {
+            Ok(obj) => assert_eq!(obj.to_string(), expected.to_string()),
+            Err(error) => panic!("VM error on input {}! {:?}", test_input, error),
+        }
+    }
+}
+
+#[test]
+fn slice_test() {
+    let tests = vec![
+        ("slice(\"monkey\", 1, 3)", "\"on\""),
+        ("slice(\"monkey\", 3)", "\"key\""),
+        ("slice(\"monkey\", -3)", "\"key\""),
+        ("slice(\"monkey\", 0, -3)", "\"mon\""),
+        ("slice(\"monkey\", -100, 100)", "\"monkey\""),
+        ("slice(\"monkey\", 4, 2)", "\"\""),
+        ("slice([1, 2, 3, 4], 1, 3)", "[2, 3]"),
+        ("slice([1, 2, 3, 4], -1)", "[4]"),
+    ];
+    for (test_input, expected) in tests {
+        match run(test_input) {
+            Ok(obj) => assert_eq!(obj.to_string(), expected.to_string()),
+            Err(error) => panic!("VM error on input {}! {:?}", test_input, error),
+        }
+    }
+}
+
+#[test]
+fn sort_test() {
+    let tests = vec![
+        ("sort([3, 1, 2])", "[1, 2, 3]"),
+        ("sort([])", "[]"),
+        ("sort([\"b\", \"c\", \"a\"])", "[\"a\", \"b\", \"c\"]"),
+        ("sort(['b', 'a'])", "['a', 'b']"),
+        ("sort([3, 1, 2], fn(a, b) { a > b })", "[3, 2, 1]"),
+        ("sort([3, 1, 2], fn(a, b) { b - a })", "[3, 2, 1]"),
+        (
+            "sort([[2, \"x\"], [1, \"y\"], [2, \"a\"], [1, \"b\"]], fn(a, b) { a[0] < b[0] })",
+            "[[1, \"y\"], [1, \"b\"], [2, \"x\"], [2, \"a\"]]",
+        ),
+        (
+            "let by = fn(k) { fn(a, b) { a[k] < b[k] } }; sort([{\"n\": 2}, {\"n\": 1}], by(\"n\"))",
+            "[{\"n\": 1}, {\"n\": 2}]",
+        ),
+        ("sort([[3, 1], [2]], fn(a, b) { len(sort(a)) < len(b) })", "[[2], [3, 1]]"),
+    ];
+    for (test_input, expected) in tests {
+        match run(test_input) {
+            Ok(obj) => assert_eq!(obj.to_string(), expected.to_string()),
+            Err(error) => panic!("VM error on input {}! {:?}", test_input, error),
+        }
+    }
+
+    // Errors raised by the comparator surface as the original virtual machine error.
+    let errors = vec![
+        (
+            "sort([1, 2], fn(a) { true })",
+            "WrongNumberOfArgs { min: 1, max: 1, got: 2 }",
+        ),
+        (
+            "sort([1, 2], fn(a, b) { a + true })",
+            "UnsupportedOperands(Add, [\"INTEGER\", \"BOOLEAN\"])",
+        ),
+        ("sort([1, \"a\"])", "BuiltIn(UnsupportedInputToBuiltIn)"),
+    ];
+    for (test_input, expected) in errors {
+        match run(test_input) {
+            Err(error) => assert_eq!(format!("{:?}", error), expected),
+            Ok(obj) => panic!("Expected an error on input {}, got {}!", test_input, obj),
+        }
+    }
+}
+
+#[test]
+fn higher_order_builtin_test() {
+    let tests = vec![
+        ("map([1, 2, 3], fn(x) { x * 2 })", "[2, 4, 6]"),
+        ("map([], fn(x) { x })", "[]"),
+        ("map([\"a\"], len)", "[1]"),
+        ("filter([1, 2, 3, 4], fn(x) { x > 2 })", "[3, 4]"),
+        ("filter([1, 2], fn(x) { if (x == 1) { 0 } })", "[1]"),
+        ("reduce([1, 2, 3, 4], 0, fn(acc, x) { acc + x })", "10"),
+        ("reduce([], 5, fn(acc, x) { acc + x })", "5"),
+        (
+            "let total = 0; map([1, 2], fn(x) { total = total + x }); total",
+            "3",
+        ),
+        (
+            "reduce(map(filter([1, 2, 3], fn(x) { x != 2 }), fn(x) { x * 10 }), [], push)",
+            "[10, 30]",
+        ),
+    ];
+    for (test_input, expected) in tests {
+        match run(test_input) {
+            Ok(obj) => assert_eq!(obj.to_string(), expected.to_string()),
+            Err(error) => panic!("VM error on input {}! {:?}", test_input, error),
+        }
+    }
+}
+
+#[test]
+fn range_test() {
+    let tests = vec![
+        ("range(5)", "[0, 1, 2, 3, 4]"),
+        ("range(0)", "[]"),
+        ("range(-3)", "[]"),
+        ("range(2, 5)", "[2, 3, 4]"),
+        ("range(2, 10, 2)", "[2, 4, 6, 8]"),
+        ("range(2, 9, 2)", "[2, 4, 6, 8]"),
+        ("range(5, 0, -2)", "[5, 3, 1]"),
+        ("range(5, 0)", "[]"),
+        (
+            "range(9223372036854775800, 9223372036854775807, 10)",
+            "[9223372036854775800]",
+        ),
+    ];
+    for (test_input, expected) in tests {
+        match run(test_input) {
+            Ok(obj) => assert_eq!(obj.to_string(), expected.to_string()),
+            Err(error) => panic!("VM error on input {}! {:?}", test_input, error),
+        }
+    }
+
+    match run("range(1000000000000)") {
+        Err(error) => assert!(error
+            .to_string()
+            .ends_with("A range of 1000000000000 integers is too large")),
+        Ok(obj) => panic!("Expected an error, got {}!", obj),
+    }
+}
+
+#[test]
+fn reverse_test() {
+    let tests = vec![
+        ("reverse([1, 2, 3])", "[3, 2, 1]"),
+        ("reverse([])", "[]"),
+        ("reverse(\"monkey\")", "\"yeknom\""),
+        ("let a = [1, 2]; reverse(a); a", "[1, 2]"),
+    ];
+    for (test_input, expected) in tests {
+        match run(test_input) {
+            Ok(obj) => assert_eq!(obj.to_string(), expected.to_string()),
+            Err(error) => panic!("VM error on input {}! {:?}", test_input, error),
+        }
+    }
+}
+
+#[test]
+fn numeric_builtin_test() {
+    let tests = vec![
+        ("min(3, 1, 2)", "1"),
+        ("min([3, 1, 2])", "1"),
+        ("min(7)", "7"),
+        ("min([])", "null"),
+        ("max(3, 1, 2)", "3"),
+        ("max([-3, -1])", "-1"),
+        ("sum([1, 2, 3])", "6"),
+        ("sum(1, 2)", "3"),
+        ("sum([])", "0"),
+        ("abs(-5)", "5"),
+        ("abs(5)", "5"),
+    ];
+    for (test_input, expected) in tests {
+        match run(test_input) {
+            Ok(obj) => assert_eq!(obj.to_string(), expected.to_string()),
+            Err(error) => panic!("VM error on input {}! {:?}", test_input, error),
+        }
+    }
+}
+
+#[test]
+fn math_builtin_test() {
+    let tests = vec![
+        ("sqrt(16)", "4"),
+        ("sqrt(17)", "4"),
+        ("sqrt(0)", "0"),
+        ("sqrt(9223372036854775807)", "3037000499"),
+        ("pow(2, 10)", "1024"),
+        ("pow(-3, 3)", "-27"),
+        ("pow(5, 0)", "1"),
+        ("pow(1, 4294967296)", "1"),
+        ("pow(-1, 4294967297)", "-1"),
+        ("floor(7)", "7"),
+        ("ceil(-7)", "-7"),
+        ("round(7)", "7"),
+    ];
+    for (test_input, expected) in tests {
+        match run(test_input) {
+            Ok(obj) => assert_eq!(obj.to_string(), expected.to_string()),
+            Err(error) => panic!("VM error on input {}! {:?}", test_input, error),
+        }
+    }
+}
+
+#[test]
+fn random_test() {
+    let tests = vec![
+        ("seed(7); let a = rand(); seed(7); a == rand()", "true"),
+        (
+            "seed(7); let a = rand_int(1, 6); seed(7); a == rand_int(1, 6)",
+            "true",
+        ),
+        ("rand_int(3, 3)", "3"),
+    ];
+    for (test_input, expected) in tests {
+        match run(test_input) {
+            Ok(obj) => assert_eq!(obj.to_string(), expected.to_string()),
+            Err(error) => panic!("VM error on input {}! {:?}", test_input, error),
+        }
+    }
+
+    let mut rng = Rng::new(42);
+    let expected = format!("[{}, {}]", rng.range(0, 1000), rng.range(0, 1000));
+    match run("seed(42); [rand_int(0, 1000), rand_int(0, 1000)]") {
+        Ok(obj) => assert_eq!(obj.to_string(), expected),
+        Err(error) => panic!("VM error! {:?}", error),
+    }
+}
+
+#[test]
+fn input_test() {
+    let program = Parser::new(Lexer::new("[input(), input()]"))
+        .parse_program()
+        .unwrap();
+    let bytecode = Compiler::new().compile(&program).unwrap();
+    let mut vm = Vm::new(&bytecode);
+    vm.set_input(Box::new("only line".as_bytes()));
+    match vm.run() {
+        Ok(obj) => assert_eq!(obj.to_string(), "[\"only line\", null]"),
+        Err(error) => panic!("VM error! {:?}", error),
+    }
+}
+
+#[test]
+fn assert_test() {
+    match run("assert(1 < 2, \"fine\"); 7") {
+        Ok(obj) => assert_eq!(obj.to_string(), "7"),
+        Err(error) => panic!("VM error! {:?}", error),
+    }
+    match run("let f = fn(x) { assert(x > 0, \"positive\") }; f(1); f(-1); 7") {
+        Err(VmError::BuiltIn(error)) => {
+            assert_eq!(error.to_string(), "EvalError: Assertion failed: positive")
+        }
+        other => panic!("Expected an assertion failure, got {:?}", other),
+    }
+}
+
+#[test]
+fn code_point_test() {
+    let tests = vec![
+        ("ord('a')", "97"),
+        ("ord(\"A\")", "65"),
+        ("chr(98)", "'b'"),
+        ("chr(ord('x') + 1)", "'y'"),
+        ("char_at(\"monkey\", 2)", "'n'"),
+        ("char_at(\"monkey\", 6)", "null"),
+        ("char_at(\"monkey\", -1)", "null"),
+        (
+            "let shift = fn(s) { join(map(split(s, \"\"), fn(c) { chr(ord(c) + 1) }), \"\") }; shift(\"HAL\")",
+            "\"IBM\"",
+        ),
+    ];
+    for (test_input, expected) in tests {
+        match run(test_input) {
+            Ok(obj) => assert_eq!(obj.to_string(), expected.to_string()),
+            Err(error) => panic!("VM error on input {}! {:?}", test_input, error),
+        }
+    }
+}
+
+/// A writer whose contents remain readable after it has been handed to an engine.
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl std::io::Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn print_test() {
+    let program = Parser::new(Lexer::new("puts(\"a\"); print(\"b\", 'c', [2]); print(3)"))
+        .parse_program()
+        .unwrap();
+    let bytecode = Compiler::new().compile(&program).unwrap();
+    let output = SharedBuffer::default();
+    let mut vm = Vm::new(&bytecode);
+    vm.set_output(Box::new(output.clone()));
+    match vm.run() {
+        Ok(obj) => assert_eq!(obj.to_string(), "null"),
+        Err(error) => panic!("VM error! {:?}", error),
+    }
+    assert_eq!(String::from_utf8_lossy(&output.0.borrow()), "a\nb c [2]3");
+}
+
+#[test]
+fn deep_copy_test() {
+    match run("let a = [[1], {1: [2]}]; let b = deep_copy(a); b[0][0] = 3; b[1][1][0] = 4; [a, b]")
+    {
+        Ok(obj) => assert_eq!(obj.to_string(), "[[[1], {1: [2]}], [[3], {1: [4]}]]"),
+        Err(error) => panic!("VM error! {:?}", error),
+    }
+}
+
+#[test]
+fn env_test() {
+    std::env::set_var("ORANGUTAN_VM_ENV_TEST", "monkey");
+    match run("[env(\"ORANGUTAN_VM_ENV_TEST\"), env(\"ORANGUTAN_VM_ENV_TEST_UNSET\")]") {
+        Ok(obj) => assert_eq!(obj.to_string(), "[\"monkey\", null]"),
+        Err(error) => panic!("VM error! {:?}", error),
+    }
+
+    let program = Parser::new(Lexer::new("env()")).parse_program().unwrap();
+    let bytecode = Compiler::new().compile(&program).unwrap();
+    let mut vm = Vm::new(&bytecode);
+    vm.set_sandboxed(true);
+    match vm.run() {
+        Err(VmError::BuiltIn(error)) => assert_eq!(
+            error.to_string(),
+            "EvalError: Built-in function `env` is not available in a sandbox"
+        ),
+        other => panic!("Expected sandbox error, got {:?}!", other),
+    }
+}
+
+#[test]
+fn args_test() {
+    let program = Parser::new(Lexer::new("args()")).parse_program().unwrap();
+    let bytecode = Compiler::new().compile(&program).unwrap();
+    let mut vm = Vm::new(&bytecode);
+    vm.set_args(vec![String::from("one"), String::from("two")]);
+    match vm.run() {
+        Ok(obj) => assert_eq!(obj.to_string(), "[\"one\", \"two\"]"),
+        Err(error) => panic!("VM error! {:?}", error),
+    }
+}
+
+#[test]
+fn eval_builtin_test() {
+    // Without the symbol table, evaluated code has globals of its own.
+    match run("let x = 1; [eval(\"let x = 2; x + 40\"), x]") {
+        Ok(obj) => assert_eq!(obj.to_string(), "[42, 1]"),
+        Err(error) => panic!("VM error! {:?}", error),
+    }
+    match run("eval(\"let = 1\")") {
+        Err(VmError::BuiltIn(error)) => match *error {
+            EvalError::ParseError(_) => {}
+            other => panic!("Expected parse error, got {:?}!", other),
+        },
+        other => panic!("Expected parse error, got {:?}!", other),
+    }
+    match run("eval(\"nope\")") {
+        Err(VmError::CompileError(_)) => {}
+        other => panic!("Expected compile error, got {:?}!", other),
+    }
+
+    let symbol_table = Rc::new(RefCell::new(SymbolTable::new_with_builtins()));
+    let program = Parser::new(Lexer::new(
+        "let x = 5; eval(\"let y = x * 2;\"); eval(\"y + 1\")",
+    ))
+    .parse_program()
+    .unwrap();
+    let bytecode = Compiler::new_with_state(Rc::clone(&symbol_table), Rc::default())
+        .compile(&program)
+        .unwrap();
+    let mut vm = Vm::new(&bytecode);
+    vm.set_symbol_table(symbol_table);
+    match vm.run() {
+        Ok(obj) => assert_eq!(obj.to_string(), "11"),
+        Err(error) => panic!("VM error! {:?}", error),
+    }
+}
+
+#[test]
+fn replace_test() {
+    match run("[replace(\"a.b.c\", \".\", \"/\"), replace_first(\"a.b.c\", '.', '/')]") {
+        Ok(obj) => assert_eq!(obj.to_string(), "[\"a/b/c\", \"a/b.c\"]"),
+        Err(error) => panic!("VM error! {:?}", error),
+    }
+}
+
+#[test]
+fn index_of_test() {
+    match run("[index_of([1, 2, 3], 3), index_of(\"abc\", 'b'), find([1, 2, 3], fn(x) { x > 1 })]")
+    {
+        Ok(obj) => assert_eq!(obj.to_string(), "[2, 1, 2]"),
+        Err(error) => panic!("VM error! {:?}", error),
+    }
+}
+
+#[test]
+fn flatten_test() {
+    match run("[flatten([[1], [[2]]]), flatten([[1], [[2]]], 5)]") {
+        Ok(obj) => assert_eq!(obj.to_string(), "[[1, [2]], [1, 2]]"),
+        Err(error) => panic!("VM error! {:?}", error),
+    }
+}
+
+#[test]
+fn set_operations_test() {
+    match run("let a = [1, 2, 2, 3]; let b = [3, 4]; [unique(a), union(a, b), intersect(a, b), difference(a, b)]") {
+        Ok(obj) => assert_eq!(obj.to_string(), "[[1, 2, 3], [1, 2, 3, 4], [3], [1, 2]]"),
+        Err(error) => panic!("VM error! {:?}", error),
+    }
+}
+
+#[test]
+fn starts_with_test() {
+    match run("[starts_with(\"abc\", \"ab\"), ends_with(\"abc\", 'b'), ends_with([1, 2], [2])]") {
+        Ok(obj) => assert_eq!(obj.to_string(), "[true, false, true]"),
+        Err(error) => panic!("VM error! {:?}", error),
+    }
+}
+
+#[test]
+fn chars_test() {
+    match run("chars(\"hé\")") {
+        Ok(obj) => assert_eq!(obj.to_string(), "[\"h\", \"é\"]"),
+        Err(error) => panic!("VM error! {:?}", error),
+    }
+}
+
+#[test]
+fn interrupt_test() {
+    let program = Parser::new(Lexer::new("1 + 2")).parse_program().unwrap();
+    let bytecode = Compiler::new().compile(&program).unwrap();
+    let flag = Arc::new(AtomicBool::new(true));
+    let mut vm = Vm::new(&bytecode);
+    vm.set_interrupt_flag(Arc::clone(&flag));
+    match vm.run() {
+        Err(VmError::Interrupted) => {}
+        other => panic!("Expected interruption, got {:?}!", other),
+    }
+    assert!(!flag.load(Ordering::Relaxed));
+}
+
+#[test]
+fn limits_test() {
+    let run_with = |input: &str, limits: Limits| {
+        let program = Parser::new(Lexer::new(input)).parse_program().unwrap();
+        let bytecode = Compiler::new().compile(&program).unwrap();
+        Vm::new_with_limits(&bytecode, Rc::new(RefCell::new(vec![])), limits).run()
+    };
+    let countdown = "let f = fn(n) { if (n == 0) { 0 } else { f(n - 1) } }; f(100)";
+    let default = Limits::default();
+    assert!(run_with(countdown, default).is_ok());
+    match run_with(
+        countdown,
+        Limits {
+            max_frames: 50,
+            ..default
+        },
+    ) {
+        Err(VmError::FrameOverflow) => {}
+        other => panic!("Expected frame overflow, got {:?}!", other),
+    }
+    match run_with(
+        countdown,
+        Limits {
+            stack_size: 64,
+            ..default
+        },
+    ) {
+        Err(VmError::StackOverflow) => {}
+        other => panic!("Expected stack overflow, got {:?}!", other),
+    }
+    match run_with(
+        "let a = 1; let b = 2;",
+        Limits {
+            globals_size: 1,
+            ..default
+        },
+    ) {
+        Err(VmError::GlobalsOverflow(1)) => {}
+        other => panic!("Expected globals overflow, got {:?}!", other),
+    }
+}
+
+#[test]
+fn fuel_test() {
+    let run_with = |input: &str, fuel: usize| {
+        let program = Parser::new(Lexer::new(input)).parse_program().unwrap();
+        let bytecode = Compiler::new().compile(&program).unwrap();
+        let limits = Limits {
+            fuel: Some(fuel),
+            ..Limits::default()
+        };
+        Vm::new_with_limits(&bytecode, Rc::new(RefCell::new(vec![])), limits).run()
+    };
+    let countdown = "let f = fn(n) { if (n == 0) { 0 } else { f(n - 1) } }; f(100)";
+    assert!(run_with(countdown, 10_000).is_ok());
+    match run_with(countdown, 100) {
+        Err(VmError::OutOfFuel) => {}
+        other => panic!("Expected to run out of fuel, got {:?}!", other),
+    }
+    // Source run by `eval` draws on the same fuel.
+    let evaluated = "eval(\"let f = fn(n) { if (n == 0) { 0 } else { f(n - 1) } }; f(100)\")";
+    assert!(run_with(evaluated, 10_000).is_ok());
+    match run_with(evaluated, 100) {
+        Err(VmError::OutOfFuel) => {}
+        other => panic!("Expected to run out of fuel, got {:?}!", other),
+    }
+}
+
+#[test]
+fn timeout_test() {
+    // Makes some two billion calls, so only stops in time if stopped.
+    let input = "let f = fn(n) { if (n == 0) { 0 } else { f(n - 1) + f(n - 1) } }; f(30)";
+    let program = Parser::new(Lexer::new(input)).parse_program().unwrap();
+    let bytecode = Compiler::new().compile(&program).unwrap();
+    let limits = Limits {
+        timeout: Some(Duration::from_millis(10)),
+        ..Limits::default()
+    };
+    match Vm::new_with_limits(&bytecode, Rc::new(RefCell::new(vec![])), limits).run() {
+        Err(VmError::TimedOut) => {}
+        other => panic!("Expected to time out, got {:?}!", other),
+    }
+}
+
+#[test]
+fn small_integers_test() {
+    let program = Parser::new(Lexer::new(
+        "let a = 1 + 2; let b = 5 - 2; let c = 999 + 1; let d = 1000;",
+    ))
+    .parse_program()
+    .unwrap();
+    let bytecode = Compiler::new().compile(&program).unwrap();
+    let globals = Rc::new(RefCell::new(vec![]));
+    let mut vm = Vm::new_with_globals_store(&bytecode, Rc::clone(&globals));
+    vm.run().unwrap();
+    let globals = globals.borrow();
+    // Small integers share one object, however they were arrived at; others each get their own.
+    assert!(Rc::ptr_eq(&globals[0], &globals[1]));
+    assert!(Rc::ptr_eq(&globals[0], &vm.integer(3)));
+    assert!(!Rc::ptr_eq(&globals[2], &globals[3]));
+    assert_eq!(globals[2].to_string(), globals[3].to_string());
+}
+
+#[test]
+fn gc_test() {
+    let run_vm = |input| {
+        let program = Parser::new(Lexer::new(input)).parse_program().unwrap();
+        let bytecode = Compiler::new().compile(&program).unwrap();
+        let mut vm = Vm::new(&bytecode);
+        let result = vm.run().unwrap().clone();
+        (vm, result)
+    };
+    // Each call leaves a closure stored in the variable it captured, which nothing else can reach.
+    let (mut vm, _) = run_vm(
+        "let make = fn() { let f = 0; f = fn() { f }; 1 };
+        make(); make(); make();
+        let mk = fn() { let f = 0; f = fn() { f }; f };
+        let keep = mk();",
+    );
+    assert_eq!(
+        vm.collect_garbage(),
+        Collection {
+            tracked: 4,
+            freed: 3
+        }
+    );
+    assert_eq!(vm.last_collection().unwrap().freed, 3);
+    // Nothing more is freed, and what was kept still works.
+    assert_eq!(vm.collect_garbage().freed, 0);
+
+    // A closure which has returned is not kept alive by the frame it ran in.
+    let (mut vm, _) =
+        run_vm("let make = fn() { let f = 0; f = fn() { if (false) { f } }; f(); 1 }; make();");
+    assert_eq!(vm.collect_garbage().freed, 1);
+
+    let (vm, result) = run_vm(
+        "let mk = fn() { let f = 0; f = fn() { f }; f };
+        let keep = mk();
+        let make = fn() { let f = 0; f = fn() { f }; 1 };
+        map(range(3000), fn(i) { make() });
+        map(range(3000), fn(i) { make() });
+        type(keep()()())",
+    );
+    assert!(vm.last_collection().unwrap().freed > 0);
+    assert_eq!(result.to_string(), "\"FUNCTION\"");
+}
+
+#[test]
+fn many_locals_test() {
+    let names: Vec<String> = (0..300).map(|idx| format!("a{}", idx)).collect();
+    let input = format!(
+        "let f = fn({}) {{ let b = a299 - a1; b = b * 2; b }}; f({})",
+        names.join(", "),
+        (0..300)
+            .map(|idx| idx.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    assert_eq!(run(&input).unwrap().to_string(), "596");
+}
+
+#[test]
+fn many_constants_test() {
+    let literals: Vec<String> = (0..70000).map(|idx| format!("\"{}\"", idx)).collect();
+    let input = format!("{}; let f = fn() {{ \"wide\" }}; f()", literals.join("; "));
+    assert_eq!(run(&input).unwrap().to_string(), "\"wide\"");
+}
+
+#[test]
+fn debugger_test() {
+    let input = "let add = fn(a, b) {
+    let c = a + b;
+    c
+};
+let x = add(1, 2);
+x * 10";
+    let program = Parser::new(Lexer::new(input)).parse_program().unwrap();
+    let symbol_table = Rc::new(RefCell::new(SymbolTable::new_with_builtins()));
+    let constants = Rc::default();
+    let bytecode = Compiler::new_with_state(Rc::clone(&symbol_table), constants)
+        .compile(&program)
+        .unwrap();
+    let strings = |objects: Vec<Object>| -> Vec<String> {
+        objects.iter().map(|obj| obj.to_string()).collect()
+    };
+    let mut vm = Vm::new(&bytecode);
+    vm.set_symbol_table(symbol_table);
+    let mut debugger = vm.debugger();
+    debugger.set_breakpoint(Breakpoint::Line(2));
+    debugger.set_breakpoint(Breakpoint::Line(6));
+    debugger.set_breakpoint(Breakpoint::Line(6));
+    assert_eq!(debugger.breakpoints().len(), 2);
+
+    assert!(matches!(debugger.resume(), Ok(Stop::Breakpoint)));
+    assert_eq!(debugger.position().unwrap().line, 2);
+    assert_eq!(debugger.frames().frames.len(), 2);
+    assert_eq!(strings(debugger.locals(0).unwrap()), vec!["1", "2", "null"]);
+    assert!(debugger.locals(2).is_none());
+    // Each step executes a single instruction, here adding and then storing the sum.
+    assert!(matches!(debugger.step(), Ok(Stop::Step)));
+    assert_eq!(strings(debugger.stack()).last().unwrap(), "3");
+    assert!(matches!(debugger.step(), Ok(Stop::Step)));
+    assert_eq!(strings(debugger.locals(0).unwrap()), vec!["1", "2", "3"]);
+
+    assert!(matches!(debugger.resume(), Ok(Stop::Breakpoint)));
+    assert_eq!(debugger.position().unwrap().line, 6);
+    let globals = debugger.globals();
+    assert_eq!(globals.len(), 2);
+    assert_eq!(
+        (globals[1].0.as_str(), globals[1].1.to_string()),
+        ("x", String::from("3"))
+    );
+    assert!(debugger.clear_breakpoint(Breakpoint::Line(2)));
+    assert!(!debugger.clear_breakpoint(Breakpoint::Line(2)));
+    match debugger.resume() {
+        Ok(Stop::Finished(result)) => assert_eq!(result.to_string(), "30"),
+        other => panic!("Expected the program to finish, got {:?}!", other),
+    }
+
+    // Breakpoints can be placed on the instructions of a function, and are met on every call.
+    let program = Parser::new(Lexer::new("let f = fn(x) { x }; f(1) + f(2)"))
+        .parse_program()
+        .unwrap();
+    let bytecode = Compiler::new().compile(&program).unwrap();
+    let function = bytecode
+        .constants
+        .iter()
+        .position(|constant| matches!(**constant, Constant::CompiledFunction(_)));
+    let mut vm = Vm::new(&bytecode);
+    let mut debugger = vm.debugger();
+    debugger.set_breakpoint(Breakpoint::Instruction {
+        function,
+        offset: 0,
+    });
+    for arg in &["1", "2"] {
+        assert!(matches!(debugger.resume(), Ok(Stop::Breakpoint)));
+        assert_eq!(strings(debugger.locals(0).unwrap()), vec![*arg]);
+    }
+    assert!(matches!(debugger.resume(), Ok(Stop::Finished(_))));
+}
+
+#[test]
+fn reset_load_test() {
+    let symbol_table = Rc::new(RefCell::new(SymbolTable::new_with_builtins()));
+    let constants: Rc<RefCell<Constants>> = Rc::default();
+    let compile = |input: &str| {
+        let program = Parser::new(Lexer::new(input)).parse_program().unwrap();
+        Compiler::new_with_state(Rc::clone(&symbol_table), Rc::clone(&constants))
+            .compile(&program)
+            .unwrap()
+    };
+    let first = compile("let add = fn(a, b) { a + b }; let big = \"big\"; [1][5]");
+    let mut vm = Vm::new(&first);
+    drop(first);
+    assert_eq!(vm.run().unwrap().to_string(), "null");
+
+    // Globals are kept for the next program, and the machine no longer holds the constants once reset.
+    vm.reset();
+    assert_eq!(Rc::strong_count(&constants.borrow()), 1);
+    vm.load(&compile("add(len(big), 1)"));
+    assert_eq!(vm.run().unwrap().to_string(), "4");
+
+    vm.load(&compile("add(1)"));
+    assert!(vm.run().is_err());
+    assert!(vm.backtrace().is_some());
+    vm.load(&compile("add(big, big)"));
+    assert!(vm.backtrace().is_none());
+    assert_eq!(vm.run().unwrap().to_string(), "\"bigbig\"");
+    // Without a program loaded, the machine runs none.
+    vm.reset();
+    assert_eq!(vm.run().unwrap().to_string(), "null");
+}
+
+#[test]
+fn error_position_test() {
+    let tests = vec![
+        ("let a = 1;\nlet b = a + true;", 2, 1),
+        ("let f = fn(x) {\n  let y = x;\n  y + true\n};\nf(1)", 3, 3),
+        ("let t = true;\nmap([t], fn(x) {\n    -x\n})", 3, 5),
+        ("if (true) { 1 } else { 2 } + true", 1, 1),
+    ];
+    for (input, line, column) in tests {
+        let program = Parser::new(Lexer::new(input)).parse_program().unwrap();
+        let bytecode = Compiler::new().compile(&program).unwrap();
+        let mut vm = Vm::new(&bytecode);
+        assert!(vm.run().is_err(), "{}", input);
+        assert_eq!(
+            vm.error_position(),
+            Some(Position { line, column }),
+            "{}",
+            input
+        );
+    }
+
+    let program = Parser::new(Lexer::new("let x = 1;"))
+        .parse_program()
+        .unwrap();
+    let mut vm = Vm::new(&Compiler::new().compile(&program).unwrap());
+    assert!(vm.run().is_ok());
+    assert_eq!(vm.error_position(), None);
+}
+
+#[test]
+fn backtrace_repeats_test() {
+    let input = "let f = fn(n) {\n  if (n == 0) { n + true } else { f(n - 1) }\n};\nf(100);";
+    let program = Parser::new(Lexer::new(input)).parse_program().unwrap();
+    let symbol_table = Rc::new(RefCell::new(SymbolTable::new_with_builtins()));
+    let bytecode = Compiler::new_with_state(Rc::clone(&symbol_table), Rc::default())
+        .compile(&program)
+        .unwrap();
+    let mut vm = Vm::new(&bytecode);
+    vm.set_symbol_table(symbol_table);
+    assert!(vm.run().is_err());
+
+    let backtrace = vm.backtrace().unwrap();
+    assert_eq!(backtrace.frames.len(), 102);
+    // The calls on the way down are written as one.
+    let text = backtrace.to_string();
+    let lines: Vec<&str> = text.lines().skip(2).collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("  1: f (constant 2) at 2:35, ip "));
+    assert_eq!(lines[1], "  ... 99 more calls of f");
+    assert!(lines[2].starts_with("  101: <main> at 4:1, ip "));
+}
+
+#[test]
+fn backtrace_test() {
+    let input = "let inner = fn(x) { x + true };\nlet outer = fn(x) { inner(x) };\nouter(1);";
+    let program = Parser::new(Lexer::new(input)).parse_program().unwrap();
+    let symbol_table = Rc::new(RefCell::new(SymbolTable::new_with_builtins()));
+    let constants = Rc::default();
+    let bytecode = Compiler::new_with_state(Rc::clone(&symbol_table), constants)
+        .compile(&program)
+        .unwrap();
+    let mut vm = Vm::new(&bytecode);
+    vm.set_symbol_table(symbol_table);
+    assert!(vm.run().is_err());
+
+    let backtrace = vm.backtrace().unwrap();
+    let summary: Vec<(Option<usize>, Option<&str>, Option<Position>)> = backtrace
+        .frames
+        .iter()
+        .map(|frame| (frame.function, frame.name.as_deref(), frame.position))
+        .collect();
+    let at = |line, column| Some(Position { line, column });
+    assert_eq!(
+        summary,
+        vec![
+            (Some(0), Some("inner"), at(1, 21)),
+            (Some(1), Some("outer"), at(2, 21)),
+            (None, None, at(3, 1)),
+        ]
+    );
+    let text = backtrace.to_string();
+    assert!(
+        text.starts_with("Backtrace (innermost call first):\n  0: inner (constant 0) at 1:21, ip ")
+    );
+    assert!(text.contains("\n  2: <main> at 3:1, ip "));
+
+    // Without the symbol table, functions are known only by their index.
+    let mut vm = Vm::new(&bytecode);
+    assert!(vm.run().is_err());
+    assert!(vm
+        .backtrace()
+        .unwrap()
+        .to_string()
+        .contains("\n  0: constant 0 at 1:21, ip "));
+
+    let mut vm = Vm::new(
+        &Compiler::new()
+            .compile(&Parser::new(Lexer::new("1")).parse_program().unwrap())
+            .unwrap(),
+    );
+    assert!(vm.run().is_ok());
+    assert!(vm.backtrace().is_none());
+}
+
+#[test]
+fn error_display_test() {
+    let tests = vec![
+        (
+            "1 + \"a\"",
+            "VmError: Unsupported operands for OpAdd: INTEGER and STRING",
+        ),
+        (
+            "-true",
+            "VmError: Unsupported operands for OpMinus: BOOLEAN",
+        ),
+        (
+            "[1][true]",
+            "VmError: Unsupported operands for OpIndex: ARRAY and BOOLEAN",
+        ),
+        (
+            "{[1]: 2}",
+            "VmError: Unsupported operands for OpHash: ARRAY",
+        ),
+        ("5(1)", "VmError: Calling non-function of type INTEGER"),
+        (
+            "fn(x) { x }()",
+            "VmError: Wrong number of arguments: expected 1, got 0",
+        ),
+        (
+            "fn(x, y = 1) { x }(1, 2, 3)",
+            "VmError: Wrong number of arguments: expected 1 to 2, got 3",
+        ),
+        (
+            "let a = [1]; a[3] = 2;",
+            "VmError: Index `3` is out of bounds",
+        ),
+        ("5 / 0", "VmError: Division by zero"),
+        ("let z = 1; 5 / (z - 1)", "VmError: Division by zero"),
+        (
+            "let m = -9223372036854775807 - 1; m / -1",
+            "VmError: Integer overflow",
+        ),
+        (
+            "let x = 9223372036854775807; x + 1",
+            "VmError: Integer overflow",
+        ),
+        ("-9223372036854775807 - 2", "VmError: Integer overflow"),
+        (
+            "let x = 4611686018427387904; x * 2",
+            "VmError: Integer overflow",
+        ),
+        (
+            "let m = -9223372036854775807 - 1; -m",
+            "VmError: Integer overflow",
+        ),
+        (
+            "fn(a, b) { a + b }(9223372036854775807, 1)",
+            "VmError: Integer overflow",
+        ),
+        ("pow(2, 64)", "EvalError: Integer overflow"),
+    ];
+    for (input, want) in tests {
+        match run(input) {
+            Err(error) => assert_eq!(error.to_string(), want, "{}", input),
+            Ok(obj) => panic!("Expected an error on input {}, got {}!", input, obj),
+        }
+    }
+
+    let bytecode = Bytecode::new(vec![255], vec![]);
+    match Vm::new(&bytecode).run() {
+        Err(error) => assert_eq!(error.to_string(), "VmError: Bad opcode 255"),
+        Ok(obj) => panic!("Expected a bad opcode, got {}!", obj),
+    }
+    let bytecode = Bytecode::new(OpCode::Constant.make_u16(7), vec![]);
+    match Vm::new(&bytecode).run() {
+        Err(error) => assert_eq!(error.to_string(), "VmError: Bad constant 7"),
+        Ok(obj) => panic!("Expected a bad constant, got {}!", obj),
+    }
+
+    // Instructions the compiler never emits, as a damaged file may hold, fail rather than panic.
+    let tests = [
+        (OpCode::GetLocal.make_u8(0), "VmError: Bad local 0"),
+        (OpCode::GetFree.make_u8(3), "VmError: Bad free variable 3"),
+        (
+            OpCode::Return.make(),
+            "VmError: Returned outside a function",
+        ),
+        (OpCode::Call.make_u8(2), "VmError: Stack underflow"),
+    ];
+    for (instructions, want) in tests {
+        match Vm::new(&Bytecode::new(instructions, vec![])).run() {
+            Err(error) => assert_eq!(error.to_string(), want),
+            Ok(obj) => panic!("Expected an error, got {}!", obj),
+        }
+    }
+}
+
 #[test]
 fn closures_test() {
     let tests = vec![
@@ -395,6 +1455,41 @@ fn closures_test() {
                 closure();",
             99,
         ),
+        // The frame of `identity` is the one `newClosure` had, but not the upvalue for `a`.
+        (
+            "let newClosure = fn(a) { fn() { a } };
+            let identity = fn(b) { b };
+            let closure = newClosure(1);
+            identity(2) + closure();",
+            3,
+        ),
+        // Variables are captured through functions between the one defining and the one using them, which do not
+        // use them themselves.
+        (
+            "let f = fn(a) { fn() { fn() { fn() { a } } } }; f(7)()()()",
+            7,
+        ),
+        (
+            "let f = fn(a) { let b = 2; fn(c) { fn() { fn() { a + b + c } } } }; f(1)(3)()()",
+            6,
+        ),
+        (
+            "let f = fn(a) { fn() { let g = fn() { a }; fn() { g() + a } } }; f(5)()()",
+            10,
+        ),
+        (
+            "let f = fn(a) { fn() { fn() { a = a + 1; a } } }; let g = f(1); g()(); g()()",
+            3,
+        ),
+        // A function refers to itself by name from within closures it creates.
+        (
+            "let f = fn(n) {
+                let g = fn(k) { fn() { fn() { if (k == 0) { n } else { g(k - 1)()() } } } };
+                g(3)()()
+            };
+            f(8)",
+            8,
+        ),
     ];
     for (test_input, expected) in tests {
         match run(test_input) {
@@ -469,3 +1564,195 @@ fn recursive_functions_test() {
         }
     }
 }
+
+#[test]
+fn recorder_test() {
+    let mut p = Parser::new(Lexer::new("1 + 2"));
+    let program = p.parse_program().unwrap();
+    let mut compiler = Compiler::new();
+    // Keep the addition, rather than its folded result, for there to be more than one step.
+    compiler.set_optimize(false);
+    let bytecode = compiler.compile(&program).unwrap();
+    let recorder = Rc::new(RefCell::new(Recorder::new()));
+    let mut vm = Vm::new(&bytecode);
+    vm.set_tracer(Box::new(recorder.clone()));
+    vm.run().unwrap();
+
+    let want = vec![
+        (0, "OpConstant 0", vec![]),
+        (3, "OpConstant 1", vec!["1"]),
+        (6, "OpAdd", vec!["1", "2"]),
+        (7, "OpPop", vec!["3"]),
+    ];
+    let snapshots = &recorder.borrow().snapshots;
+    assert_eq!(snapshots.len(), want.len());
+    for (snapshot, (ip, instruction, stack)) in snapshots.iter().zip(want) {
+        assert_eq!(snapshot.ip, ip);
+        assert_eq!(snapshot.instruction, instruction);
+        assert_eq!(snapshot.stack, stack);
+        assert_eq!(snapshot.frames.len(), 1);
+    }
+    assert!(recorder
+        .borrow()
+        .to_json()
+        .starts_with("[{\"step\": 0, \"ip\": 0"));
+}
+
+#[test]
+fn profile_test() {
+    let input = "let double = fn(x) { x * 2 }; double(1) + double(2)";
+    let program = Parser::new(Lexer::new(input)).parse_program().unwrap();
+    let symbol_table = Rc::new(RefCell::new(SymbolTable::new_with_builtins()));
+    let constants = Rc::default();
+    let bytecode = Compiler::new_with_state(Rc::clone(&symbol_table), constants)
+        .compile(&program)
+        .unwrap();
+    let function = bytecode
+        .constants
+        .iter()
+        .position(|constant| matches!(**constant, Constant::CompiledFunction(_)));
+    let mut vm = Vm::new(&bytecode);
+    vm.set_symbol_table(symbol_table);
+    vm.enable_profiling();
+    vm.run().unwrap();
+
+    let profile = vm.take_profile().unwrap();
+    // Each call passes a constant, so is made by a single superinstruction.
+    assert_eq!(profile.opcode_count(OpCode::ConstantCall), 2);
+    assert_eq!(profile.opcode_count(OpCode::Call), 0);
+    assert_eq!(profile.opcode_count(OpCode::Mul), 2);
+    assert_eq!(profile.opcode_count(OpCode::Sub), 0);
+    let double = profile.function(function).unwrap();
+    assert_eq!(double.calls, 2);
+    assert_eq!(double.instructions, 8);
+    let main = profile.function(None).unwrap();
+    assert_eq!(main.calls, 1);
+    assert_eq!(main.instructions + double.instructions, profile.total());
+    let table = profile.to_string();
+    assert!(table.starts_with("Opcode"));
+    assert!(table.contains("\ndouble "));
+    assert!(table.contains("Self ms"));
+    // Each call is timed, and the main program runs throughout.
+    assert!(main.total_time >= double.total_time);
+    assert!(double.total_time >= double.self_time);
+    let hottest = profile.hottest();
+    assert_eq!(hottest.len(), 2);
+    assert!(hottest[0].1.self_time >= hottest[1].1.self_time);
+    assert!(vm.take_profile().is_none());
+}
+
+#[test]
+fn trace_printer_test() {
+    let program = Parser::new(Lexer::new("let f = fn() { 1 }; f()"))
+        .parse_program()
+        .unwrap();
+    let bytecode = Compiler::new().compile(&program).unwrap();
+    let output = SharedBuffer::default();
+    let mut vm = Vm::new(&bytecode);
+    vm.set_tracer(Box::new(Printer::new(Box::new(output.clone()))));
+    vm.run().unwrap();
+    let trace = String::from_utf8_lossy(&output.0.borrow()).to_string();
+    let lines: Vec<&str> = trace.lines().collect();
+    assert_eq!(lines.len(), 6);
+    assert_eq!(lines[0], format!("0000 {:<24} top: -", "OpClosure 1 0"));
+    assert_eq!(
+        lines[2],
+        format!("0007 {:<24} top: -", "OpGetGlobalCall 0 0")
+    );
+    // The body of `f` is indented, as it runs in a frame of its own.
+    assert_eq!(lines[4], format!("  0003 {:<24} top: 1", "OpReturnValue"));
+    assert_eq!(lines[5], format!("0011 {:<24} top: 1", "OpPop"));
+}
+
+#[test]
+fn mutable_closure_test() {
+    let tests = vec![
+        (
+            "let counter = fn() { let count = 0; fn() { count = count + 1; count } };
+            let c = counter(); c(); c(); c()",
+            "3",
+        ),
+        (
+            "let make = fn() { let n = 0; [fn() { n = n + 1 }, fn() { n }] };
+            let fs = make(); fs[0](); fs[0](); fs[1]()",
+            "2",
+        ),
+        (
+            "let f = fn() { let x = 1; let set = fn(v) { x = v }; set(5); x }; f()",
+            "5",
+        ),
+        (
+            "let f = fn(a) { fn() { fn() { a = a * 2; a } } }; let g = f(3)(); g(); g()",
+            "12",
+        ),
+        (
+            "let f = fn() { let a = [1]; let g = fn() { a[0] = 2 }; g(); a }; f()",
+            "[2]",
+        ),
+        ("let x = 1; let f = fn() { x = 2 }; f(); x", "2"),
+        ("let x = 1; x = x + 1; x", "2"),
+    ];
+    for (test_input, expected) in tests {
+        match run(test_input) {
+            Ok(obj) => assert_eq!(obj.to_string(), expected.to_string()),
+            Err(error) => panic!("VM error on input {}! {:?}", test_input, error),
+        }
+    }
+}
+
+#[test]
+fn index_assignment_test() {
+    let tests = vec![
+        ("let a = [1, 2, 3]; a[0] = 5; a", "[5, 2, 3]"),
+        (
+            "let a = [1, 2, 3]; let i = 1; a[i + 1] = a[i]; a",
+            "[1, 2, 2]",
+        ),
+        ("let a = [[1, 2], [3]]; a[0][1] = 7; a", "[[1, 7], [3]]"),
+        (
+            "let h = {\"a\": 1}; h[\"a\"] = 2; h[\"b\"] = 3; h",
+            "{\"a\": 2, \"b\": 3}",
+        ),
+        ("let h = {\"a\": [1]}; h[\"a\"][0] = 2; h", "{\"a\": [2]}"),
+        ("let a = [1]; let b = a; b[0] = 2; a", "[1]"),
+        // Elements are shared between copies, but copied before they are changed.
+        ("let a = [[1], 2]; let b = a; b[0][0] = 5; a", "[[1], 2]"),
+        ("let a = [[1], 2]; let c = a[0]; c[0] = 7; a", "[[1], 2]"),
+        (
+            "let h = {\"x\": [1]}; let g = h; g[\"x\"][0] = 3; h",
+            "{\"x\": [1]}",
+        ),
+        (
+            "let f = fn() { let a = [0, 0]; a[1] = 4; a }; f()",
+            "[0, 4]",
+        ),
+    ];
+    for (test_input, expected) in tests {
+        match run(test_input) {
+            Ok(obj) => assert_eq!(obj.to_string(), expected.to_string()),
+            Err(error) => panic!("VM error on input {}! {:?}", test_input, error),
+        }
+    }
+
+    let errors = vec![
+        ("let a = [1]; a[1] = 2;", "1"),
+        ("let a = [1]; a[-1] = 2;", "-1"),
+    ];
+    for (test_input, index) in errors {
+        match run(test_input) {
+            Err(VmError::IndexOutOfBounds(got)) if got.to_string() == index => {}
+            other => panic!(
+                "Expected IndexOutOfBounds on input {}, got {:?}",
+                test_input, other
+            ),
+        }
+    }
+
+    // Missing hash keys read as null, which cannot be indexed into.
+    match run("let h = {}; h[1][2] = 2;") {
+        Err(VmError::UnsupportedOperands(OpCode::SetIndex, types)) => {
+            assert_eq!(types, vec!["NULL", "INTEGER"])
+        }
+        other => panic!("Expected UnsupportedOperands, got {:?}", other),
+    }
+}
@@ -0,0 +1,119 @@
+//! Wasm
+//!
+//! `wasm` exposes the interpreter to JavaScript through `wasm-bindgen`, for a playground running in the browser.
+//! There is no standard input or output there, so programs read nothing and what they print is handed back instead.
+use crate::engine::{Engine, Settings};
+use crate::error::Error;
+use crate::object::Object;
+use std::io;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+/// Runs `source` interpreted after the prelude, and returns what it printed followed by the value it ends with,
+/// unless that is null.
+///
+/// Exported to JavaScript as `evaluate`, as modules cannot declare a function named `eval`. The error it throws is a
+/// `MonkeyError`.
+#[wasm_bindgen(js_name = evaluate)]
+pub fn eval(source: &str) -> Result<String, MonkeyError> {
+    let mut engine = Engine::new(Settings {
+        // Built-in functions reaching outside the program, such as `env`, would find nothing in a browser.
+        sandboxed: true,
+        input: Box::new(io::empty()),
+        output: Box::new(io::sink()),
+        ..Settings::default()
+    });
+    let output = engine.capture_output();
+    match engine.eval(source) {
+        Ok(Object::Null) => Ok(output.text()),
+        Ok(value) => Ok(format!("{}{}\n", output.text(), value)),
+        Err(error) => Err(MonkeyError::new(&error, output.text())),
+    }
+}
+
+/// An error which stopped a program, with its parts for JavaScript to read one by one.
+#[wasm_bindgen]
+pub struct MonkeyError {
+    kind: String,
+    message: String,
+    line: Option<u32>,
+    column: Option<u32>,
+    text: String,
+    output: String,
+}
+
+impl MonkeyError {
+    fn new(error: &Error, output: String) -> Self {
+        let position = error.position();
+        MonkeyError {
+            kind: error.kind().to_string(),
+            message: error.message(),
+            line: position.map(|position| position.line as u32),
+            column: position.map(|position| position.column as u32),
+            text: error.to_string(),
+            output,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl MonkeyError {
+    /// The kind of error, as `Error::kind` names it: `parse`, `compile` or `runtime`.
+    #[wasm_bindgen(getter)]
+    pub fn kind(&self) -> String {
+        self.kind.clone()
+    }
+
+    /// What went wrong, without saying where.
+    #[wasm_bindgen(getter)]
+    pub fn message(&self) -> String {
+        self.message.clone()
+    }
+
+    /// The line the error is on, counting from 1, if known.
+    #[wasm_bindgen(getter)]
+    pub fn line(&self) -> Option<u32> {
+        self.line
+    }
+
+    /// The column the error is at, counting from 1, if known.
+    #[wasm_bindgen(getter)]
+    pub fn column(&self) -> Option<u32> {
+        self.column
+    }
+
+    /// The error as the command line shows it, with the offending line of source and a caret underneath.
+    #[wasm_bindgen(getter)]
+    pub fn text(&self) -> String {
+        self.text.clone()
+    }
+
+    /// What the program printed before it failed.
+    #[wasm_bindgen(getter)]
+    pub fn output(&self) -> String {
+        self.output.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eval_test() {
+        assert_eq!(
+            eval("puts(\"hi\"); 1 + 2").ok(),
+            Some(String::from("hi\n3\n"))
+        );
+        assert_eq!(eval("puts(1);").ok(), Some(String::from("1\n")));
+
+        let error = eval("puts(1);\nlet x = 1 + true;").err().unwrap();
+        assert_eq!(error.kind(), "runtime");
+        assert_eq!(
+            (error.line(), error.output()),
+            (Some(2), String::from("1\n"))
+        );
+        assert!(error.text().ends_with("let x = 1 + true;\n          ^"));
+        // Nothing outside the program is reached.
+        assert!(eval("env(\"HOME\")").is_err());
+    }
+}